@@ -3,16 +3,16 @@ use std::{sync::Arc, time::Duration};
 use calloop::EventLoop;
 use smithay_ecs::{
     compositor::{Compositor, CompositorHandler, RegionData, Role},
-    shm::Shm,
+    shm::{Shm, ShmPoolData},
     wayland_protocols::xdg::shell::server::{
         xdg_surface::XdgSurface, xdg_toplevel::XdgToplevel, xdg_wm_base::XdgWmBase,
     },
     wayland_server::{
         delegate_dispatch, delegate_global_dispatch,
         protocol::{
-            wl_callback::WlCallback, wl_compositor::WlCompositor, wl_region::WlRegion,
-            wl_shm::WlShm, wl_shm_pool::WlShmPool, wl_subcompositor::WlSubcompositor,
-            wl_subsurface::WlSubsurface, wl_surface::WlSurface,
+            wl_buffer::WlBuffer, wl_callback::WlCallback, wl_compositor::WlCompositor,
+            wl_region::WlRegion, wl_shm::WlShm, wl_shm_pool::WlShmPool,
+            wl_subcompositor::WlSubcompositor, wl_subsurface::WlSubsurface, wl_surface::WlSurface,
         },
         Display, ListeningSocket,
     },
@@ -100,6 +100,7 @@ delegate_dispatch!(SmallvilEcs: [WlCompositor: ()] => Compositor);
 delegate_dispatch!(SmallvilEcs: [WlRegion: RegionData] => Compositor);
 delegate_dispatch!(SmallvilEcs: [WlSurface: EntityData] => Compositor);
 delegate_dispatch!(SmallvilEcs: [WlCallback: ()] => Compositor);
+delegate_dispatch!(SmallvilEcs: [WlBuffer: EntityData] => Compositor);
 
 delegate_global_dispatch!(SmallvilEcs: [WlSubcompositor: ()] => Compositor);
 delegate_dispatch!(SmallvilEcs: [WlSubcompositor: ()] => Compositor);
@@ -107,7 +108,7 @@ delegate_dispatch!(SmallvilEcs: [WlSubsurface: EntityData] => Compositor);
 
 delegate_global_dispatch!(SmallvilEcs: [WlShm: ()] => Shm);
 delegate_dispatch!(SmallvilEcs: [WlShm: ()] => Shm);
-delegate_dispatch!(SmallvilEcs: [WlShmPool: ()] => Shm);
+delegate_dispatch!(SmallvilEcs: [WlShmPool: ShmPoolData] => Shm);
 
 delegate_global_dispatch!(SmallvilEcs: [XdgWmBase: ()] => XdgShell);
 delegate_dispatch!(SmallvilEcs: [XdgWmBase: ()] => XdgShell);
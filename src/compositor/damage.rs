@@ -0,0 +1,420 @@
+use std::collections::VecDeque;
+
+use smithay::utils::{Buffer as BufferCoords, Logical, Rectangle, Size};
+use wayland_server::protocol::wl_output;
+
+use crate::viewporter::Viewport;
+
+use super::{Damage, Region};
+
+/// Number of commits of damage which are kept for consumers which are behind.
+///
+/// A consumer which last saw a commit older than this will be told to redraw the whole buffer.
+const MAX_DAMAGE_HISTORY: usize = 10;
+
+/// Identifies a commit of a surface.
+///
+/// A renderer or output keeps the counter of the last commit it has drawn and passes it to
+/// [`SurfaceDamage::damage_since`] to get the damage accumulated since then.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CommitCounter(usize);
+
+impl CommitCounter {
+    /// The number of commits between this commit and an older commit.
+    pub fn distance(&self, older: CommitCounter) -> Option<usize> {
+        self.0.checked_sub(older.0)
+    }
+}
+
+/// The damage of a [`WlSurface`](wayland_server::protocol::wl_surface::WlSurface), in buffer coordinates.
+///
+/// All damage sent by the client is converted into buffer coordinates on commit using the committed buffer
+/// scale, transform and [`Viewport`], clamped to the size of the buffer and merged into non-overlapping rectangles.
+///
+/// This can always be queried if the surface is alive.
+#[derive(Debug, Default)]
+pub struct SurfaceDamage {
+    commit: CommitCounter,
+
+    /// Damage of the most recent commits, newest last.
+    ///
+    /// [`None`] means the whole buffer was damaged.
+    history: VecDeque<Option<Region<BufferCoords>>>,
+}
+
+impl SurfaceDamage {
+    /// The counter of the most recent commit.
+    pub fn current_commit(&self) -> CommitCounter {
+        self.commit
+    }
+
+    /// The damage of the most recent commit.
+    ///
+    /// Returns [`None`] if the whole buffer should be considered damaged.
+    pub fn last_damage(&self) -> Option<&[Rectangle<i32, BufferCoords>]> {
+        match self.history.back() {
+            Some(Some(region)) => Some(region.rects()),
+            Some(None) => None,
+            // No commit has happened yet, so nothing was damaged.
+            None => Some(&[]),
+        }
+    }
+
+    /// The damage accumulated in all commits after the specified commit.
+    ///
+    /// Passing [`None`] indicates the consumer has never seen this surface.
+    ///
+    /// Returns [`None`] if the whole buffer should be redrawn. This happens if the consumer has never seen the
+    /// surface, if the consumer is too many commits behind or if a commit damaged the whole buffer.
+    pub fn damage_since(
+        &self,
+        commit: Option<CommitCounter>,
+    ) -> Option<Vec<Rectangle<i32, BufferCoords>>> {
+        let distance = self.commit.distance(commit?)?;
+
+        if distance > self.history.len() {
+            return None;
+        }
+
+        let mut damage = Region::new();
+        for region in self.history.iter().skip(self.history.len() - distance) {
+            damage = damage.union(region.as_ref()?);
+        }
+
+        Some(damage.rects().to_vec())
+    }
+
+    /// Records the damage of a commit.
+    ///
+    /// `buffer_size` is the size of the committed buffer if known. If the size is unknown, damage can only be
+    /// converted when the transform is normal and no viewport is set; otherwise the whole buffer is damaged.
+    pub(super) fn commit(
+        &mut self,
+        damage: impl IntoIterator<Item = Damage>,
+        scale: i32,
+        transform: wl_output::Transform,
+        viewport: &Viewport,
+        buffer_size: Option<Size<i32, BufferCoords>>,
+    ) {
+        self.commit.0 += 1;
+
+        let mut rects = Vec::new();
+        let mut full = false;
+
+        for damage in damage {
+            let rect = match damage {
+                Damage::Buffer(rect) => rect,
+                Damage::Surface(rect) => {
+                    match surface_to_buffer(rect, scale, transform, viewport, buffer_size) {
+                        Some(rect) => rect,
+                        None => {
+                            full = true;
+                            break;
+                        }
+                    }
+                }
+            };
+
+            rects.push(rect);
+        }
+
+        let damage = (!full).then(|| {
+            let region = Region::from_rects(rects);
+
+            match buffer_size {
+                Some(size) => {
+                    region.intersect(&Region::from_rects([Rectangle::from_loc_and_size(
+                        (0, 0),
+                        size,
+                    )]))
+                }
+                None => region,
+            }
+        });
+
+        if self.history.len() == MAX_DAMAGE_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(damage);
+    }
+}
+
+/// Converts a rectangle in surface coordinates to buffer coordinates.
+///
+/// Returns [`None`] if the conversion requires the size of the buffer which is not known.
+fn surface_to_buffer(
+    rect: Rectangle<i32, Logical>,
+    scale: i32,
+    transform: wl_output::Transform,
+    viewport: &Viewport,
+    buffer_size: Option<Size<i32, BufferCoords>>,
+) -> Option<Rectangle<i32, BufferCoords>> {
+    // The corners of the rectangle in buffer pixels, before the buffer transform is applied.
+    let (x1, y1, x2, y2) = if viewport.is_set() {
+        let logical_size = buffer_size_to_logical(buffer_size?, scale, transform);
+        let ((x1, y1), (x2, y2)) = viewport.surface_to_source(rect, logical_size);
+        let scale = scale as f64;
+
+        // Round outwards so partially damaged pixels are redrawn.
+        (
+            (x1 * scale).floor() as i32,
+            (y1 * scale).floor() as i32,
+            (x2 * scale).ceil() as i32,
+            (y2 * scale).ceil() as i32,
+        )
+    } else {
+        let (x1, y1) = (rect.loc.x * scale, rect.loc.y * scale);
+        (x1, y1, x1 + rect.size.w * scale, y1 + rect.size.h * scale)
+    };
+
+    if transform == wl_output::Transform::Normal {
+        return Some(Rectangle::from_loc_and_size((x1, y1), (x2 - x1, y2 - y1)));
+    }
+
+    // The size of the surface in buffer pixels, before the buffer transform is applied.
+    let buffer_size = buffer_size?;
+    let (width, height) = if transform_swaps_axes(transform) {
+        (buffer_size.h, buffer_size.w)
+    } else {
+        (buffer_size.w, buffer_size.h)
+    };
+
+    let (ax, ay) = transform_point(transform, width, height, x1, y1);
+    let (bx, by) = transform_point(transform, width, height, x2, y2);
+
+    Some(Rectangle::from_loc_and_size(
+        (ax.min(bx), ay.min(by)),
+        ((ax - bx).abs(), (ay - by).abs()),
+    ))
+}
+
+/// Converts the size of a buffer into surface-local coordinates using the buffer scale and transform.
+pub(super) fn buffer_size_to_logical(
+    size: Size<i32, BufferCoords>,
+    scale: i32,
+    transform: wl_output::Transform,
+) -> Size<i32, Logical> {
+    let (w, h) = if transform_swaps_axes(transform) {
+        (size.h, size.w)
+    } else {
+        (size.w, size.h)
+    };
+
+    (w / scale, h / scale).into()
+}
+
+/// Whether the transform rotates the buffer by 90 or 270 degrees.
+pub(super) fn transform_swaps_axes(transform: wl_output::Transform) -> bool {
+    matches!(
+        transform,
+        wl_output::Transform::_90
+            | wl_output::Transform::_270
+            | wl_output::Transform::Flipped90
+            | wl_output::Transform::Flipped270
+    )
+}
+
+/// Maps a point on a surface of the specified size into the transformed buffer.
+fn transform_point(
+    transform: wl_output::Transform,
+    width: i32,
+    height: i32,
+    x: i32,
+    y: i32,
+) -> (i32, i32) {
+    match transform {
+        wl_output::Transform::Flipped => (width - x, y),
+        wl_output::Transform::_90 => (y, width - x),
+        wl_output::Transform::Flipped90 => (y, x),
+        wl_output::Transform::_180 => (width - x, height - y),
+        wl_output::Transform::Flipped180 => (x, height - y),
+        wl_output::Transform::_270 => (height - y, x),
+        wl_output::Transform::Flipped270 => (height - y, width - x),
+        _ => (x, y),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::utils::{Buffer as BufferCoords, Rectangle};
+    use wayland_server::protocol::wl_output::Transform;
+
+    use crate::viewporter::Viewport;
+
+    use super::{transform_point, CommitCounter, Damage, SurfaceDamage, MAX_DAMAGE_HISTORY};
+
+    fn buffer_rect(x: i32, y: i32, w: i32, h: i32) -> Rectangle<i32, BufferCoords> {
+        Rectangle::from_loc_and_size((x, y), (w, h))
+    }
+
+    /// Commits surface damage on a buffer of 20x10 pixels and returns the damage in buffer coordinates.
+    fn convert(
+        rect: (i32, i32, i32, i32),
+        scale: i32,
+        transform: Transform,
+    ) -> Option<Vec<Rectangle<i32, BufferCoords>>> {
+        let (x, y, w, h) = rect;
+        let mut damage = SurfaceDamage::default();
+        damage.commit(
+            [Damage::Surface(Rectangle::from_loc_and_size(
+                (x, y),
+                (w, h),
+            ))],
+            scale,
+            transform,
+            &Viewport::default(),
+            Some((20 * scale, 10 * scale).into()),
+        );
+        damage.last_damage().map(<[_]>::to_vec)
+    }
+
+    #[test]
+    fn rotated_damage_is_converted() {
+        // A rotated 20x10 buffer is shown on a 10x20 surface.
+        assert_eq!(
+            convert((0, 0, 2, 3), 1, Transform::_90),
+            Some(vec![buffer_rect(0, 8, 3, 2)])
+        );
+        assert_eq!(
+            convert((0, 0, 4, 3), 1, Transform::_180),
+            Some(vec![buffer_rect(16, 7, 4, 3)])
+        );
+        assert_eq!(
+            convert((0, 0, 2, 3), 1, Transform::_270),
+            Some(vec![buffer_rect(17, 0, 3, 2)])
+        );
+    }
+
+    #[test]
+    fn flipped_damage_is_converted() {
+        assert_eq!(
+            convert((0, 0, 4, 3), 1, Transform::Flipped),
+            Some(vec![buffer_rect(16, 0, 4, 3)])
+        );
+        assert_eq!(
+            convert((0, 0, 2, 3), 1, Transform::Flipped90),
+            Some(vec![buffer_rect(0, 0, 3, 2)])
+        );
+        assert_eq!(
+            convert((0, 0, 4, 3), 1, Transform::Flipped180),
+            Some(vec![buffer_rect(0, 7, 4, 3)])
+        );
+        assert_eq!(
+            convert((0, 0, 2, 3), 1, Transform::Flipped270),
+            Some(vec![buffer_rect(17, 8, 3, 2)])
+        );
+    }
+
+    #[test]
+    fn scaled_and_rotated_damage_is_converted() {
+        assert_eq!(
+            convert((1, 1, 2, 3), 2, Transform::Normal),
+            Some(vec![buffer_rect(2, 2, 4, 6)])
+        );
+        // The 40x20 buffer is shown on a 10x20 surface.
+        assert_eq!(
+            convert((1, 1, 2, 3), 2, Transform::_90),
+            Some(vec![buffer_rect(2, 14, 6, 4)])
+        );
+    }
+
+    #[test]
+    fn transform_point_maps_corners() {
+        // The corners of a 4x2 surface, which is a 2x4 buffer if the transform swaps the axes.
+        let corners = |transform| {
+            [(0, 0), (4, 0), (0, 2), (4, 2)].map(|(x, y)| transform_point(transform, 4, 2, x, y))
+        };
+        assert_eq!(corners(Transform::Normal), [(0, 0), (4, 0), (0, 2), (4, 2)]);
+        assert_eq!(corners(Transform::_90), [(0, 4), (0, 0), (2, 4), (2, 0)]);
+        assert_eq!(corners(Transform::_180), [(4, 2), (0, 2), (4, 0), (0, 0)]);
+        assert_eq!(corners(Transform::_270), [(2, 0), (2, 4), (0, 0), (0, 4)]);
+        assert_eq!(
+            corners(Transform::Flipped),
+            [(4, 0), (0, 0), (4, 2), (0, 2)]
+        );
+        assert_eq!(
+            corners(Transform::Flipped90),
+            [(0, 0), (0, 4), (2, 0), (2, 4)]
+        );
+        assert_eq!(
+            corners(Transform::Flipped180),
+            [(0, 2), (4, 2), (0, 0), (4, 0)]
+        );
+        assert_eq!(
+            corners(Transform::Flipped270),
+            [(2, 4), (2, 0), (0, 4), (0, 0)]
+        );
+    }
+
+    #[test]
+    fn damage_is_clamped_to_buffer() {
+        assert_eq!(
+            convert((15, 5, 10, 10), 1, Transform::Normal),
+            Some(vec![buffer_rect(15, 5, 5, 5)])
+        );
+        assert_eq!(
+            convert((-5, -5, 10, 10), 1, Transform::_180),
+            Some(vec![buffer_rect(15, 5, 5, 5)])
+        );
+        assert_eq!(convert((30, 0, 10, 10), 1, Transform::Normal), Some(vec![]));
+
+        let mut damage = SurfaceDamage::default();
+        damage.commit(
+            [Damage::Buffer(buffer_rect(-10, 0, 100, 100))],
+            1,
+            Transform::Normal,
+            &Viewport::default(),
+            Some((20, 10).into()),
+        );
+        assert_eq!(damage.last_damage(), Some(&[buffer_rect(0, 0, 20, 10)][..]));
+    }
+
+    #[test]
+    fn transformed_damage_without_buffer_size_is_full() {
+        let mut damage = SurfaceDamage::default();
+        damage.commit(
+            [Damage::Surface(Rectangle::from_loc_and_size(
+                (0, 0),
+                (1, 1),
+            ))],
+            1,
+            Transform::_90,
+            &Viewport::default(),
+            None,
+        );
+        assert_eq!(damage.last_damage(), None);
+    }
+
+    #[test]
+    fn damage_since_overflowed_history_is_full() {
+        let mut damage = SurfaceDamage::default();
+        let mut counters = Vec::new();
+        for i in 0..MAX_DAMAGE_HISTORY as i32 + 2 {
+            damage.commit(
+                [Damage::Buffer(buffer_rect(i, 0, 1, 1))],
+                1,
+                Transform::Normal,
+                &Viewport::default(),
+                Some((100, 100).into()),
+            );
+            counters.push(damage.current_commit());
+        }
+
+        // The damage of the first two commits was dropped from the history.
+        assert_eq!(damage.damage_since(Some(CommitCounter::default())), None);
+        assert_eq!(damage.damage_since(Some(counters[0])), None);
+        assert_eq!(
+            damage.damage_since(Some(counters[1])),
+            Some(vec![buffer_rect(2, 0, MAX_DAMAGE_HISTORY as i32, 1)])
+        );
+        assert_eq!(
+            damage.damage_since(Some(counters[MAX_DAMAGE_HISTORY])),
+            Some(vec![buffer_rect(MAX_DAMAGE_HISTORY as i32 + 1, 0, 1, 1)])
+        );
+        assert_eq!(
+            damage.damage_since(damage.current_commit().into()),
+            Some(vec![])
+        );
+        assert_eq!(damage.damage_since(None), None);
+    }
+}
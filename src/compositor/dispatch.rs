@@ -1,9 +1,11 @@
-use std::sync::Mutex;
+use std::{mem, sync::Mutex};
 
+use hecs::Entity;
 use smithay::utils::Rectangle;
 use wayland_backend::server::{ClientId, ObjectId};
 use wayland_server::{
     protocol::{
+        wl_buffer::{self, WlBuffer},
         wl_callback::WlCallback,
         wl_compositor::{self, WlCompositor},
         wl_region::{self, WlRegion},
@@ -16,12 +18,14 @@ use wayland_server::{
 
 use crate::{
     compositor::{AlreadyHasRole, Subsurface},
-    EntityData,
+    viewporter::Viewport,
+    EcsAccess, EntityData,
 };
 
 use super::{
-    Buffer, BufferAssignment, Compositor, CompositorHandler, Damage, Internal, RectangleKind,
-    RegionAttributes, RegionData, Role,
+    geometry, transaction, Buffer, BufferAssignment, BufferDimensions, Compositor, CompositorEvent,
+    CompositorHandler, Damage, FrameCallbacks, InputRegion, Internal, OpaqueRegion, RectangleKind,
+    RegionAttributes, RegionData, Role, SubsurfaceChildren, SurfaceDamage, SurfaceGeometry,
 };
 
 impl<State> GlobalDispatch<WlCompositor, (), State> for Compositor
@@ -69,10 +73,17 @@ where
                     .insert(
                         entity,
                         (
+                            surface.clone(),
                             Internal::<State>::default(),
                             Role::default(),
                             Buffer::default(),
-                            // TODO: Input and opaque regions
+                            SurfaceDamage::default(),
+                            FrameCallbacks::default(),
+                            Viewport::default(),
+                            SurfaceGeometry::default(),
+                            SubsurfaceChildren::default(),
+                            OpaqueRegion::default(),
+                            InputRegion::default(),
                         ),
                     )
                     .expect("Entity was reserved");
@@ -81,6 +92,10 @@ where
                     .compositor()
                     .surfaces
                     .insert(surface.id(), surface.clone());
+                state.ecs().push_event(CompositorEvent::SurfaceCreated {
+                    surface: surface.clone(),
+                    entity,
+                });
                 state.new_surface(surface);
             }
 
@@ -203,6 +218,33 @@ where
             }
 
             wl_surface::Request::Commit => {
+                let internal = state
+                    .ecs()
+                    .world()
+                    .query_one_mut::<&mut Internal<State>>(data.0)
+                    .expect("Surface must be a valid entity if dispatched");
+
+                // The buffer which will be attached after this commit must have a size which is a multiple of
+                // the buffer scale. Earlier commits may still be waiting in a transaction, so the buffer is taken
+                // from the newest queued state rather than the applied state.
+                let scale = internal.pending.scale;
+                let attached = internal.attached_buffer();
+
+                if let Some(buffer) = attached {
+                    if let Some(size) = BufferDimensions::query(state.ecs(), &buffer) {
+                        if size.w % scale != 0 || size.h % scale != 0 {
+                            surface.post_error(
+                                wl_surface::Error::InvalidSize,
+                                format!(
+                                    "Buffer size ({}x{}) is not divisible by the buffer scale ({})",
+                                    size.w, size.h, scale
+                                ),
+                            );
+                            return;
+                        }
+                    }
+                }
+
                 let internal = state
                     .ecs()
                     .world()
@@ -217,39 +259,49 @@ where
                     system(state, surface)
                 }
 
-                let (internal, buffer) = state
+                let internal = state
                     .ecs()
                     .world()
-                    .query_one_mut::<(&mut Internal<State>, &mut Buffer)>(data.0)
+                    .query_one_mut::<&mut Internal<State>>(data.0)
                     .expect("Surface must be a valid entity if dispatched");
-                let post_commit_systems = internal.post_commit_systems.clone();
-
-                // TODO: Apply current state
-                buffer.delta = internal.pending.delta;
-                buffer.buffer = internal.pending.buffer.clone();
-                buffer.scale = internal.pending.scale;
-                buffer.transform = internal.pending.transform;
-                buffer.damage.extend(internal.pending.damage.drain(..));
-
-                for system in post_commit_systems {
-                    system(state, surface)
+                if mem::take(&mut internal.discard) {
+                    // Drop the state which the discarded commit would have consumed.
+                    internal.pending.take_commit();
+                    return;
                 }
 
-                state.commit(surface);
+                transaction::commit(state, surface, data.0);
             }
 
             wl_surface::Request::SetBufferTransform { transform } => {
-                if let WEnum::Value(transform) = transform {
-                    let internal = state
-                        .ecs()
-                        .world()
-                        .query_one_mut::<&mut Internal<State>>(data.0)
-                        .expect("Surface must be a valid entity if dispatched");
-                    internal.pending.transform = transform;
-                }
+                let transform = match transform {
+                    WEnum::Value(transform) => transform,
+                    WEnum::Unknown(transform) => {
+                        surface.post_error(
+                            wl_surface::Error::InvalidTransform,
+                            format!("Unknown buffer transform {transform}"),
+                        );
+                        return;
+                    }
+                };
+
+                let internal = state
+                    .ecs()
+                    .world()
+                    .query_one_mut::<&mut Internal<State>>(data.0)
+                    .expect("Surface must be a valid entity if dispatched");
+                internal.pending.transform = transform;
             }
 
             wl_surface::Request::SetBufferScale { scale } => {
+                if scale < 1 {
+                    surface.post_error(
+                        wl_surface::Error::InvalidScale,
+                        format!("Buffer scale must be positive, got {scale}"),
+                    );
+                    return;
+                }
+
                 let internal = state
                     .ecs()
                     .world()
@@ -293,6 +345,10 @@ where
 
     fn destroyed(state: &mut State, _client: ClientId, resource: ObjectId, data: &EntityData) {
         if let Some(surface) = state.compositor().surfaces.remove(&resource) {
+            state.ecs().push_event(CompositorEvent::SurfaceDestroyed {
+                surface: surface.clone(),
+                entity: data.0,
+            });
             state.destroy(&surface);
 
             let internal = state
@@ -411,6 +467,12 @@ where
                     return;
                 }
 
+                state.ecs().push_event(CompositorEvent::RoleAssigned {
+                    surface: surface.clone(),
+                    entity,
+                    role: Subsurface::ROLE,
+                });
+
                 data_init.init(id, EntityData(entity));
                 state
                     .ecs()
@@ -422,11 +484,20 @@ where
                             // Quoting wl_subsurface:
                             // > A sub-surface is initially in the synchronized mode.
                             sync: true,
+                            position: (0, 0).into(),
+                            pending_position: None,
                         },
                     )
                     .unwrap();
 
-                // TODO: Setup relations to other subsurfaces.
+                // The new subsurface is placed on top of its siblings.
+                let parent = parent.data::<EntityData>().unwrap().0;
+                state
+                    .ecs()
+                    .world()
+                    .query_one_mut::<&mut SubsurfaceChildren>(parent)
+                    .expect("Parent surface must be a valid entity")
+                    .add(entity);
             }
 
             _ => unreachable!(),
@@ -448,15 +519,119 @@ where
         data_init: &mut DataInit<'_, State>,
     ) {
         match request {
-            wl_subsurface::Request::Destroy => todo!(),
-            wl_subsurface::Request::SetPosition { x, y } => todo!(),
-            wl_subsurface::Request::PlaceAbove { sibling } => todo!(),
-            wl_subsurface::Request::PlaceBelow { sibling } => todo!(),
-            wl_subsurface::Request::SetSync => todo!(),
-            wl_subsurface::Request::SetDesync => todo!(),
-            _ => todo!(),
+            wl_subsurface::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            wl_subsurface::Request::SetPosition { x, y } => {
+                // Quoting wl_subsurface::set_position:
+                // > The scheduled coordinates will take effect whenever the state of the parent surface is
+                // > applied.
+                if let Ok(subsurface) = state.ecs().world().query_one_mut::<&mut Subsurface>(data.0)
+                {
+                    subsurface.pending_position = Some((x, y).into());
+                }
+            }
+
+            wl_subsurface::Request::PlaceAbove { sibling } => {
+                place_subsurface(state, resource, data.0, &sibling, true)
+            }
+
+            wl_subsurface::Request::PlaceBelow { sibling } => {
+                place_subsurface(state, resource, data.0, &sibling, false)
+            }
+
+            wl_subsurface::Request::SetSync => {
+                if let Ok(subsurface) = state.ecs().world().query_one_mut::<&mut Subsurface>(data.0)
+                {
+                    subsurface.sync = true;
+                }
+            }
+
+            wl_subsurface::Request::SetDesync => {
+                if let Ok(subsurface) = state.ecs().world().query_one_mut::<&mut Subsurface>(data.0)
+                {
+                    subsurface.sync = false;
+                    // Cached state is applied as soon as the subsurface is no longer synchronized, rather than
+                    // waiting for its next commit.
+                    transaction::desync(state, data.0);
+                }
+            }
+
+            _ => unreachable!(),
         }
     }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        // The surface is no longer a subsurface and is unmapped from its parent.
+        let subsurface = match state.ecs().world().remove_one::<Subsurface>(data.0) {
+            Ok(subsurface) => subsurface,
+            Err(_) => return,
+        };
+
+        let parent = match subsurface.parent.upgrade() {
+            Ok(parent) => parent.data::<EntityData>().unwrap().0,
+            Err(_) => return,
+        };
+
+        if let Ok(children) = state
+            .ecs()
+            .world()
+            .query_one_mut::<&mut SubsurfaceChildren>(parent)
+        {
+            children.remove(data.0);
+        }
+
+        geometry::update_bounding_boxes(state.ecs(), parent);
+    }
+}
+
+/// Moves a subsurface above or below a sibling, or its parent, in the pending stacking order of the parent.
+fn place_subsurface<State>(
+    state: &mut State,
+    resource: &WlSubsurface,
+    entity: Entity,
+    sibling: &WlSurface,
+    above: bool,
+) where
+    State: CompositorHandler,
+{
+    // The subsurface is inert once its parent is destroyed.
+    let parent = match state
+        .ecs()
+        .world()
+        .query_one_mut::<&Subsurface>(entity)
+        .ok()
+        .and_then(Subsurface::parent)
+    {
+        Some(parent) => parent,
+        None => return,
+    };
+
+    let sibling = if *sibling == parent {
+        None
+    } else {
+        Some(sibling.data::<EntityData>().unwrap().0)
+    };
+
+    let children = state
+        .ecs()
+        .world()
+        .query_one_mut::<&mut SubsurfaceChildren>(parent.data::<EntityData>().unwrap().0)
+        .expect("Parent surface must be a valid entity if alive");
+
+    // Quoting wl_subsurface::place_above:
+    // > The reference surface must be one of the sibling surfaces, or the parent surface. Using any other
+    // > surface, including this sub-surface, will cause a protocol error.
+    if !children.is_sibling(entity, sibling) {
+        resource.post_error(
+            wl_subsurface::Error::BadSurface,
+            "Surface is neither a sibling nor the parent",
+        );
+        return;
+    }
+
+    children.place(entity, sibling, above);
 }
 
 impl<State> Dispatch<WlCallback, (), State> for Compositor
@@ -475,3 +650,31 @@ where
         unreachable!("no requests")
     }
 }
+
+impl<State> Dispatch<WlBuffer, EntityData, State> for Compositor
+where
+    State: Dispatch<WlBuffer, EntityData> + EcsAccess,
+{
+    fn request(
+        _state: &mut State,
+        _client: &Client,
+        _resource: &WlBuffer,
+        request: wl_buffer::Request,
+        _data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            wl_buffer::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        // The buffer entity only exists for the lifetime of the wl_buffer.
+        let _ = state.ecs().world().despawn(data.0);
+    }
+}
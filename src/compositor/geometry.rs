@@ -0,0 +1,168 @@
+use hecs::Entity;
+use smithay::utils::{Buffer as BufferCoords, Logical, Point, Rectangle, Scale, Size};
+use wayland_server::{protocol::wl_surface::WlSurface, Resource};
+
+use crate::{Ecs, EntityData};
+
+use super::{Buffer, BufferDimensions, Compositor, Subsurface, SubsurfaceChildren};
+
+/// The size and extents of a [`WlSurface`], derived from its committed state.
+///
+/// This is recomputed whenever a commit of the surface or of one of its subsurfaces is applied, so window
+/// management and rendering code can rely on it instead of calculating sizes themselves.
+///
+/// This can always be queried if the surface is alive.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SurfaceGeometry {
+    buffer_size: Option<Size<i32, BufferCoords>>,
+    size: Option<Size<i32, Logical>>,
+    scale: Option<Scale<f64>>,
+    bounding_box: Rectangle<i32, Logical>,
+    window_geometry: Option<Rectangle<i32, Logical>>,
+}
+
+impl SurfaceGeometry {
+    /// The size of the attached buffer in pixels.
+    ///
+    /// Returns [`None`] if no buffer is attached or the size of the buffer is unknown.
+    pub fn buffer_size(&self) -> Option<Size<i32, BufferCoords>> {
+        self.buffer_size
+    }
+
+    /// The size of the surface in surface-local coordinates.
+    ///
+    /// This is the size of the buffer after the buffer scale, transform and viewport are applied. See
+    /// [`Compositor::surface_size`].
+    pub fn size(&self) -> Option<Size<i32, Logical>> {
+        self.size
+    }
+
+    /// The number of buffer pixels per unit of the surface-local coordinate space.
+    ///
+    /// This combines the buffer scale with the scaling applied by the viewport. A client using fractional
+    /// scaling attaches a buffer at its preferred scale and sets the viewport destination to the logical size, so
+    /// this is the fractional scale the content was rendered at. Returns [`None`] if the surface is not mapped.
+    pub fn scale(&self) -> Option<Scale<f64>> {
+        self.scale
+    }
+
+    /// Returns whether the surface has a buffer of known size attached.
+    pub fn is_mapped(&self) -> bool {
+        self.size.is_some()
+    }
+
+    /// The rectangle which contains the surface and all of its mapped subsurfaces, relative to the surface.
+    pub fn bounding_box(&self) -> Rectangle<i32, Logical> {
+        self.bounding_box
+    }
+
+    /// The window geometry of an xdg_surface, relative to the surface.
+    ///
+    /// If the client did not set a window geometry, this is the [bounding box](Self::bounding_box). Otherwise
+    /// the window geometry set by the client is clamped to the bounding box.
+    pub fn window_geometry(&self) -> Rectangle<i32, Logical> {
+        match self.window_geometry {
+            Some(geometry) => intersection(geometry, self.bounding_box),
+            None => self.bounding_box,
+        }
+    }
+}
+
+/// Recomputes the geometry of a surface after a commit was applied.
+///
+/// The bounding boxes of all ancestors are updated as well, since they include this surface.
+pub(super) fn update(
+    ecs: &mut Ecs,
+    surface: &WlSurface,
+    entity: Entity,
+    window_geometry: Option<Rectangle<i32, Logical>>,
+) {
+    let buffer = ecs
+        .world
+        .query_one_mut::<&Buffer>(entity)
+        .expect("Surface must be a valid entity if alive")
+        .current
+        .clone();
+    let buffer_size = match buffer {
+        Some(buffer) => BufferDimensions::query(ecs, &buffer),
+        None => None,
+    };
+    let size = Compositor::surface_size(ecs, surface);
+    let scale = Compositor::surface_scale(ecs, surface);
+
+    let geometry = ecs
+        .world
+        .query_one_mut::<&mut SurfaceGeometry>(entity)
+        .expect("Surface must be a valid entity if alive");
+    geometry.buffer_size = buffer_size;
+    geometry.size = size;
+    geometry.scale = scale;
+    geometry.window_geometry = window_geometry;
+
+    update_bounding_boxes(ecs, entity);
+}
+
+/// Recomputes the bounding box of a surface and all of its ancestors.
+pub(super) fn update_bounding_boxes(ecs: &mut Ecs, entity: Entity) {
+    let mut current = Some(entity);
+
+    while let Some(entity) = current {
+        let bounding_box = bounding_box(ecs, entity);
+
+        if let Ok(geometry) = ecs.world.query_one_mut::<&mut SurfaceGeometry>(entity) {
+            geometry.bounding_box = bounding_box;
+        }
+
+        current = ecs
+            .world
+            .query_one_mut::<&Subsurface>(entity)
+            .ok()
+            .and_then(|subsurface| subsurface.parent.upgrade().ok())
+            .and_then(|parent| parent.data::<EntityData>().map(|data| data.0));
+    }
+}
+
+/// Computes the bounding box of a surface from its size and the bounding boxes of its children.
+fn bounding_box(ecs: &mut Ecs, entity: Entity) -> Rectangle<i32, Logical> {
+    let (geometry, children) = match ecs
+        .world
+        .query_one_mut::<(&SurfaceGeometry, &SubsurfaceChildren)>(entity)
+    {
+        Ok((geometry, children)) => (*geometry, children.children.clone()),
+        Err(_) => return Rectangle::default(),
+    };
+
+    let size = match geometry.size {
+        Some(size) => size,
+        // Subsurfaces of an unmapped surface are hidden too.
+        None => return Rectangle::default(),
+    };
+
+    let mut bounding_box = Rectangle::from_loc_and_size((0, 0), size);
+
+    for child in children {
+        let child = ecs
+            .world
+            .query_one_mut::<(&Subsurface, &SurfaceGeometry)>(child)
+            .ok()
+            .filter(|(_, geometry)| geometry.is_mapped())
+            .map(|(subsurface, geometry)| (subsurface.position, geometry.bounding_box));
+
+        if let Some((position, mut child_box)) = child {
+            child_box.loc += position;
+            bounding_box = bounding_box.merge(child_box);
+        }
+    }
+
+    bounding_box
+}
+
+fn intersection(a: Rectangle<i32, Logical>, b: Rectangle<i32, Logical>) -> Rectangle<i32, Logical> {
+    let loc = Point::from((a.loc.x.max(b.loc.x), a.loc.y.max(b.loc.y)));
+    let end = Point::<i32, Logical>::from((
+        (a.loc.x + a.size.w).min(b.loc.x + b.size.w),
+        (a.loc.y + a.size.h).min(b.loc.y + b.size.h),
+    ));
+
+    Rectangle::from_loc_and_size(loc, ((end.x - loc.x).max(0), (end.y - loc.y).max(0)))
+}
@@ -1,5 +1,10 @@
 //! Protocol implementations for surfaces, subsurfaces and regions.
 //!
+//! # Surface entities
+//!
+//! Every [`WlSurface`] is an entity. The [`WlSurface`] itself is a component of that entity, so protocol
+//! objects which share the entity of a surface can find the surface they extend.
+//!
 //! # Surface roles
 //!
 //! The wayland protocol specifies that a surface needs to be assigned a role before displaying the surface.
@@ -13,22 +18,74 @@
 //! When a buffer is commited the surface may become visible. The attached buffer and related data is stored in
 //! a [`Buffer`] which can be queried from a [`WlSurface`].
 //!
+//! The size of a surface depends on the buffer scale, transform and [`Viewport`] and is calculated by
+//! [`Compositor::surface_size`]. Since the viewport may scale the buffer, the number of buffer pixels per
+//! surface-local unit is calculated by [`Compositor::surface_scale`], which is how the content of clients using
+//! [fractional scaling](crate::fractional_scale) is rendered at the right density. The size, the bounding box
+//! including subsurfaces and the window geometry are kept up to date in a [`SurfaceGeometry`] which can be
+//! queried from a [`WlSurface`].
+//!
+//! Damage is accumulated in a [`SurfaceDamage`], which converts the damage into buffer coordinates. Renderers
+//! remember the [`CommitCounter`] of the last commit they have drawn and use [`SurfaceDamage::damage_since`]
+//! to find out which parts of the buffer need to be redrawn.
+//!
+//! Buffer protocols such as [`linux-dmabuf`](crate::dmabuf) create every [`WlBuffer`](wl_buffer::WlBuffer)
+//! as an entity which is despawned when the buffer is destroyed. The [`Compositor`] implements the dispatch of
+//! those buffers.
+//!
 //! # Subsurfaces
 //!
 //! This module provides an implementation for [`WlSubsurface`]. A subsurface has a [`role`](Subsurface::ROLE)
-//! and a [`Subsurface`] can be queried from a [`WlSurface`] if the surface is a subsurface.
+//! and a [`Subsurface`] can be queried from a [`WlSurface`] if the surface is a subsurface. The subsurfaces of
+//! a surface are listed in its [`SubsurfaceChildren`].
+//!
+//! # Transactions
+//!
+//! Committed state is not applied immediately. Every commit creates a [`Transaction`] entity, which is applied
+//! once all of its [`Blocker`]s are released. Commits of synchronized subsurfaces are added to the transaction
+//! of their parent, so the state of a whole surface tree changes atomically. The [`Buffer`] and other committed
+//! state of a surface only changes when its transaction is applied.
 //!
-//! TODO: Query to get the next subsurface for a surface with some child subsurfaces.
+//! # Events
+//!
+//! Besides calling [`CompositorHandler`] synchronously, the compositor pushes a [`CompositorEvent`] into the
+//! event queue of the [`Ecs`] whenever a surface is created, committed, assigned a role or destroyed. Enable
+//! the queue using [`Ecs::enable_events`] and drain it once per frame with [`Ecs::drain_events`] to process
+//! changes in batches.
+//!
+//! # Regions
+//!
+//! Clients describe regions as a sequence of rectangles to add or subtract, which is stored as
+//! [`RegionAttributes`]. [`RegionAttributes::to_region`] evaluates that sequence into a [`Region`], a set of
+//! non-overlapping rectangles which supports set operations and clipping damage. The committed opaque and input
+//! regions of a surface are stored in an [`OpaqueRegion`] and an [`InputRegion`].
 //!
 //! [^1]: Some surface roles, such as xdg-surface can be turned into an xdg-popup or xdg-toplevel.
 //! [`Role::replace_role`] is available for those types of surface roles.
 
+mod damage;
 mod dispatch;
-
-use std::{collections::HashMap, sync::Mutex};
+mod geometry;
+mod region;
+mod transaction;
+
+#[cfg(test)]
+mod tests;
+
+use self::damage::buffer_size_to_logical;
+pub use self::damage::{CommitCounter, SurfaceDamage};
+pub use self::geometry::SurfaceGeometry;
+pub use self::region::Region;
+pub use self::transaction::{Blocker, ManualBlocker, Transaction};
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::Mutex,
+};
 
 use hecs::Entity;
-use smithay::utils::{Logical, Point, Rectangle};
+use smithay::utils::{Logical, Point, Rectangle, Scale, Size};
 use wayland_backend::server::ObjectId;
 use wayland_server::{
     protocol::{
@@ -38,13 +95,19 @@ use wayland_server::{
     DisplayHandle, GlobalDispatch, Resource, Weak,
 };
 
-use crate::{Ecs, EcsAccess, EntityData};
+use crate::{viewporter::Viewport, Ecs, EcsAccess, EntityData};
 
 // TODO: Way to allow components to be notified that a surface was pre and post committed. This kind of acts
 // like a system. But it's per object type.
 
 pub struct Compositor {
     surfaces: HashMap<ObjectId, WlSurface>,
+    /// Entities of transactions which have not been applied yet, oldest first.
+    transactions: Vec<Entity>,
+    /// Whether [`Compositor::apply_transactions`] is running.
+    applying: bool,
+    /// Whether more transactions may have become ready while transactions were being applied.
+    dirty: bool,
 }
 
 impl Compositor {
@@ -59,6 +122,9 @@ impl Compositor {
 
         Self {
             surfaces: HashMap::new(),
+            transactions: Vec::new(),
+            applying: false,
+            dirty: false,
         }
     }
 
@@ -92,6 +158,207 @@ impl Compositor {
         internal.post_commit_systems.push(handler);
     }
 
+    /// Get the buffer assignment of the pending state of a surface.
+    ///
+    /// This is intended for [`SurfacePreCommit`] systems which need to validate the buffer being committed.
+    pub fn pending_buffer<State>(ecs: &mut Ecs, surface: &WlSurface) -> Option<BufferAssignment>
+    where
+        State: EcsAccess,
+    {
+        let data = surface.data::<EntityData>().unwrap();
+        let internal = ecs
+            .world
+            .query_one_mut::<&Internal<State>>(data.0)
+            .expect("State type did not match");
+        internal.pending.buffer.clone()
+    }
+
+    /// Get the buffer which will be attached once the pending state of a surface is committed.
+    ///
+    /// Unlike [`Buffer::current_buffer`], this takes the pending attach and commits which are still waiting in a
+    /// [`Transaction`] into account. This is intended for role requests which must be sent before a buffer is
+    /// attached.
+    pub fn attached_buffer<State>(ecs: &mut Ecs, surface: &WlSurface) -> Option<wl_buffer::WlBuffer>
+    where
+        State: EcsAccess,
+    {
+        let data = surface.data::<EntityData>().unwrap();
+        let internal = ecs
+            .world
+            .query_one_mut::<&Internal<State>>(data.0)
+            .expect("State type did not match");
+        internal.attached_buffer()
+    }
+
+    /// Get the size of the buffer which will be attached once the pending state of a surface is committed.
+    ///
+    /// The size is in surface-local coordinates, after the pending buffer scale and transform are applied but
+    /// before the [`Viewport`]. Returns [`None`] if no buffer will be attached or the size of the buffer is
+    /// unknown.
+    pub fn pending_buffer_size<State>(
+        ecs: &mut Ecs,
+        surface: &WlSurface,
+    ) -> Option<Size<i32, Logical>>
+    where
+        State: EcsAccess,
+    {
+        let data = surface.data::<EntityData>().unwrap();
+        let internal = ecs
+            .world
+            .query_one_mut::<&Internal<State>>(data.0)
+            .expect("State type did not match");
+
+        let scale = internal.pending.scale;
+        let transform = internal.pending.transform;
+        let attached = internal.attached_buffer()?;
+
+        let size = BufferDimensions::query(ecs, &attached)?;
+        Some(buffer_size_to_logical(size, scale, transform))
+    }
+
+    /// Get the size of a surface in surface-local coordinates.
+    ///
+    /// This applies the committed buffer scale, transform and [`Viewport`] to the size of the attached buffer.
+    /// Returns [`None`] if no buffer is attached or the size of the buffer is unknown.
+    pub fn surface_size(ecs: &mut Ecs, surface: &WlSurface) -> Option<Size<i32, Logical>> {
+        let data = surface.data::<EntityData>().unwrap();
+        let (buffer, viewport) = ecs
+            .world
+            .query_one_mut::<(&Buffer, &Viewport)>(data.0)
+            .ok()?;
+
+        let scale = buffer.scale;
+        let transform = buffer.transform;
+        let viewport = *viewport;
+        let attached = buffer.current.clone()?;
+
+        let size = BufferDimensions::query(ecs, &attached)?;
+        Some(viewport.surface_size(buffer_size_to_logical(size, scale, transform)))
+    }
+
+    /// Get the number of buffer pixels per unit of the surface-local coordinate space.
+    ///
+    /// The part of the buffer shown by the [`Viewport`] is scaled to the [surface size](Self::surface_size), so
+    /// the scale of a surface using fractional scaling is the ratio of the two rather than the integer buffer
+    /// scale. Returns [`None`] if no buffer is attached or the size of the buffer is unknown.
+    pub fn surface_scale(ecs: &mut Ecs, surface: &WlSurface) -> Option<Scale<f64>> {
+        let data = surface.data::<EntityData>().unwrap();
+        let (buffer, viewport) = ecs
+            .world
+            .query_one_mut::<(&Buffer, &Viewport)>(data.0)
+            .ok()?;
+
+        let scale = buffer.scale;
+        let transform = buffer.transform;
+        let viewport = *viewport;
+        let attached = buffer.current.clone()?;
+
+        let size = BufferDimensions::query(ecs, &attached)?;
+        let buffer_size = buffer_size_to_logical(size, scale, transform);
+        let surface_size = viewport.surface_size(buffer_size);
+
+        // The source rectangle is in surface-local coordinates before the viewport, where a unit is `scale`
+        // pixels of the buffer.
+        let (source_w, source_h) = match viewport.source {
+            Some(source) => (source.size.w, source.size.h),
+            None => (buffer_size.w as f64, buffer_size.h as f64),
+        };
+
+        Some(Scale {
+            x: source_w * scale as f64 / surface_size.w as f64,
+            y: source_h * scale as f64 / surface_size.h as f64,
+        })
+    }
+
+    /// Get the xdg window geometry of the pending state of a surface.
+    pub(crate) fn pending_window_geometry<'a, State>(
+        ecs: &'a mut Ecs,
+        surface: &WlSurface,
+    ) -> &'a mut Option<Rectangle<i32, Logical>>
+    where
+        State: EcsAccess,
+    {
+        let data = surface.data::<EntityData>().unwrap();
+        let internal = ecs
+            .world
+            .query_one_mut::<&mut Internal<State>>(data.0)
+            .expect("State type did not match");
+        &mut internal.pending.window_geometry
+    }
+
+    /// Get the viewport of the pending state of a surface.
+    pub(crate) fn pending_viewport<'a, State>(
+        ecs: &'a mut Ecs,
+        surface: &WlSurface,
+    ) -> &'a mut Viewport
+    where
+        State: EcsAccess,
+    {
+        let data = surface.data::<EntityData>().unwrap();
+        let internal = ecs
+            .world
+            .query_one_mut::<&mut Internal<State>>(data.0)
+            .expect("State type did not match");
+        &mut internal.pending.viewport
+    }
+
+    /// Stores extension state in the pending state of a surface.
+    ///
+    /// This is intended for [`SurfacePreCommit`] systems. The value becomes part of the commit and is handed to
+    /// [`Compositor::take_commit_state`] once that commit is applied, however long the commit waits in a
+    /// [`Transaction`]. If the commit is never applied, for example because the surface is destroyed, the value
+    /// is dropped. A value of the same type which was stored earlier is replaced.
+    pub fn insert_commit_state<State, T>(ecs: &mut Ecs, surface: &WlSurface, value: T)
+    where
+        State: EcsAccess,
+        T: Send + Sync + 'static,
+    {
+        let data = surface.data::<EntityData>().unwrap();
+        let internal = ecs
+            .world
+            .query_one_mut::<&mut Internal<State>>(data.0)
+            .expect("State type did not match");
+        internal
+            .pending
+            .commit_state
+            .0
+            .insert(TypeId::of::<T>(), Box::new(value));
+    }
+
+    /// Takes the extension state of the commit which is being applied.
+    ///
+    /// This is intended for [`SurfacePostCommit`] systems. Returns [`None`] if the commit carries no value of
+    /// the type. Values which no system takes are dropped once the commit was applied.
+    pub fn take_commit_state<State, T>(ecs: &mut Ecs, surface: &WlSurface) -> Option<T>
+    where
+        State: EcsAccess,
+        T: Send + Sync + 'static,
+    {
+        let data = surface.data::<EntityData>().unwrap();
+        let internal = ecs
+            .world
+            .query_one_mut::<&mut Internal<State>>(data.0)
+            .expect("State type did not match");
+        let value = internal.applying.0.remove(&TypeId::of::<T>())?;
+        Some(*value.downcast().expect("Commit state is keyed by type"))
+    }
+
+    /// Discards the commit which is being processed.
+    ///
+    /// This is intended for [`SurfacePreCommit`] systems which post a protocol error because the committed
+    /// state is invalid. The commit is not queued in a [`Transaction`], so none of its state is applied.
+    pub fn discard_commit<State>(ecs: &mut Ecs, surface: &WlSurface)
+    where
+        State: EcsAccess,
+    {
+        let data = surface.data::<EntityData>().unwrap();
+        let internal = ecs
+            .world
+            .query_one_mut::<&mut Internal<State>>(data.0)
+            .expect("State type did not match");
+        internal.discard = true;
+    }
+
     pub fn add_destroy<State>(ecs: &mut Ecs, surface: &WlSurface, handler: SurfaceDestroy<State>)
     where
         State: EcsAccess,
@@ -118,10 +385,39 @@ pub trait CompositorHandler: EcsAccess {
     }
 }
 
+/// An event describing a change to a surface.
+///
+/// See the [module documentation](self#events) for how to receive these events.
+#[derive(Debug, Clone)]
+pub enum CompositorEvent {
+    /// A surface was created.
+    SurfaceCreated { surface: WlSurface, entity: Entity },
+
+    /// The state of a surface was committed.
+    SurfaceCommitted {
+        surface: WlSurface,
+        entity: Entity,
+        commit: CommitCounter,
+    },
+
+    /// A surface was assigned a role.
+    ///
+    /// This is also emitted when a role is replaced, such as an xdg_surface becoming an xdg_toplevel.
+    RoleAssigned {
+        surface: WlSurface,
+        entity: Entity,
+        role: &'static str,
+    },
+
+    /// A surface was destroyed.
+    SurfaceDestroyed { surface: WlSurface, entity: Entity },
+}
+
 /// A system that is run before applying a pending surface state.
 ///
 /// This is typically used by protocol extensions that add state to a surface and need to check on commit that
-/// the client did not request an illegal state before it is applied on commit.
+/// the client did not request an illegal state before it is applied on commit. A system which posts a protocol
+/// error should discard the commit using [`Compositor::discard_commit`].
 pub type SurfacePreCommit<State> = fn(state: &mut State, surface: &WlSurface);
 
 /// A system that is run after commiting the current surface state.
@@ -184,33 +480,46 @@ impl Role {
 
 /// The buffer attached to a [`WlSurface`].
 ///
+/// The attached buffer and offset are consumed by each commit: [`Buffer::buffer`] and [`Buffer::delta`] only
+/// describe what changed in the most recent commit, while [`Buffer::current_buffer`] is the buffer which is
+/// currently attached. The buffer scale and transform persist until the client changes them.
+///
 /// This can always be queried if the surface is alive.
 #[derive(Debug)]
 pub struct Buffer {
     buffer: Option<BufferAssignment>,
+    current: Option<wl_buffer::WlBuffer>,
     delta: Option<Point<i32, Logical>>,
     scale: i32,
     transform: wl_output::Transform,
-    damage: Vec<Damage>,
 }
 
 impl Default for Buffer {
     fn default() -> Self {
         Self {
             buffer: None,
+            current: None,
             delta: None,
             scale: 1,
             transform: wl_output::Transform::Normal,
-            damage: Vec::new(),
         }
     }
 }
 
 impl Buffer {
+    /// The buffer assignment of the most recent commit.
+    ///
+    /// Returns [`None`] if the most recent commit did not attach a buffer.
     pub fn buffer(&self) -> Option<BufferAssignment> {
         self.buffer.clone()
     }
 
+    /// The buffer which is currently attached to the surface.
+    pub fn current_buffer(&self) -> Option<&wl_buffer::WlBuffer> {
+        self.current.as_ref()
+    }
+
+    /// The offset of the most recent commit, relative to the previous buffer.
     pub fn delta(&self) -> Option<Point<i32, Logical>> {
         self.delta
     }
@@ -222,10 +531,6 @@ impl Buffer {
     pub fn transform(&self) -> wl_output::Transform {
         self.transform
     }
-
-    pub fn damage(&mut self) -> &mut Vec<Damage> {
-        &mut self.damage
-    }
 }
 
 #[derive(Debug, Clone)]
@@ -234,6 +539,61 @@ pub enum BufferAssignment {
     Removed,
 }
 
+/// The frame callbacks of a [`WlSurface`] which were committed and are waiting to be sent.
+///
+/// This can always be queried if the surface is alive.
+#[derive(Debug, Default)]
+pub struct FrameCallbacks {
+    callbacks: Vec<WlCallback>,
+}
+
+impl FrameCallbacks {
+    /// Sends all committed frame callbacks with the specified timestamp in milliseconds.
+    pub fn send_frame(&mut self, time: u32) {
+        for callback in self.callbacks.drain(..) {
+            callback.done(time);
+        }
+    }
+
+    /// Returns whether there are committed frame callbacks waiting to be sent.
+    pub fn is_empty(&self) -> bool {
+        self.callbacks.is_empty()
+    }
+}
+
+/// The size of a [`WlBuffer`](wl_buffer::WlBuffer) in pixels.
+///
+/// Protocols which create buffers should insert this on the entity of the buffer. Buffers without this
+/// component are treated as having an unknown size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferDimensions {
+    size: Size<i32, smithay::utils::Buffer>,
+}
+
+impl BufferDimensions {
+    pub fn new(size: Size<i32, smithay::utils::Buffer>) -> Self {
+        Self { size }
+    }
+
+    pub fn size(&self) -> Size<i32, smithay::utils::Buffer> {
+        self.size
+    }
+
+    /// Get the size of a buffer.
+    ///
+    /// Returns [`None`] if the buffer is not an entity or the size of the buffer is unknown.
+    pub fn query(
+        ecs: &mut Ecs,
+        buffer: &wl_buffer::WlBuffer,
+    ) -> Option<Size<i32, smithay::utils::Buffer>> {
+        let entity = buffer.data::<EntityData>()?.0;
+        ecs.world
+            .query_one_mut::<&BufferDimensions>(entity)
+            .ok()
+            .map(BufferDimensions::size)
+    }
+}
+
 #[derive(Debug)]
 pub struct AlreadyHasRole;
 
@@ -249,16 +609,168 @@ pub struct Subsurface {
     /// Note that a subsurface is sync if it's parent subsurface is sync, regardless of whether this subsurface
     /// is sync or not.
     sync: bool,
+
+    /// The position relative to the parent surface.
+    position: Point<i32, Logical>,
+
+    /// The position which is applied with the next commit of the parent surface.
+    pending_position: Option<Point<i32, Logical>>,
 }
 
 impl Subsurface {
     pub const ROLE: &str = "subsurface";
+
+    /// The parent surface of the subsurface, or [`None`] if it was destroyed.
+    pub fn parent(&self) -> Option<WlSurface> {
+        self.parent.upgrade().ok()
+    }
+
+    /// The position of the subsurface relative to its parent surface.
+    pub fn position(&self) -> Point<i32, Logical> {
+        self.position
+    }
+}
+
+/// The subsurfaces of a [`WlSurface`], as entities.
+///
+/// This can always be queried if the surface is alive.
+#[derive(Debug, Default)]
+pub struct SubsurfaceChildren {
+    /// The children in stacking order, bottom first.
+    children: Vec<Entity>,
+    /// The number of children which are stacked below the parent.
+    below: usize,
+    /// The stacking order which is applied with the next commit of the parent, where [`None`] is the parent.
+    pending: Option<Vec<Option<Entity>>>,
+}
+
+impl SubsurfaceChildren {
+    /// The subsurfaces in stacking order, bottom first.
+    pub fn children(&self) -> &[Entity] {
+        &self.children
+    }
+
+    /// The subsurfaces which are stacked below the parent surface, bottom first.
+    pub fn below_parent(&self) -> &[Entity] {
+        &self.children[..self.below]
+    }
+
+    /// The subsurfaces which are stacked above the parent surface, bottom first.
+    pub fn above_parent(&self) -> &[Entity] {
+        &self.children[self.below..]
+    }
+
+    /// Adds a new subsurface on top of the stack.
+    fn add(&mut self, child: Entity) {
+        self.children.push(child);
+        if let Some(pending) = &mut self.pending {
+            pending.push(Some(child));
+        }
+    }
+
+    /// Removes a subsurface immediately.
+    fn remove(&mut self, child: Entity) {
+        if let Some(index) = self.children.iter().position(|&c| c == child) {
+            self.children.remove(index);
+            if index < self.below {
+                self.below -= 1;
+            }
+        }
+
+        if let Some(pending) = &mut self.pending {
+            pending.retain(|&c| c != Some(child));
+        }
+    }
+
+    /// Returns whether a surface may be used as the sibling of a stacking request, where [`None`] is the parent.
+    fn is_sibling(&self, child: Entity, sibling: Option<Entity>) -> bool {
+        match sibling {
+            None => true,
+            Some(sibling) => sibling != child && self.children.contains(&sibling),
+        }
+    }
+
+    /// Moves a subsurface directly above or below a sibling in the pending stacking order.
+    fn place(&mut self, child: Entity, sibling: Option<Entity>, above: bool) {
+        let children = &self.children;
+        let below = self.below;
+        let pending = self.pending.get_or_insert_with(|| {
+            let mut order = children.iter().copied().map(Some).collect::<Vec<_>>();
+            order.insert(below, None);
+            order
+        });
+
+        pending.retain(|&c| c != Some(child));
+        let index = pending
+            .iter()
+            .position(|&c| c == sibling)
+            .expect("Sibling must be in the stacking order");
+        let index = if above { index + 1 } else { index };
+        pending.insert(index, Some(child));
+    }
+
+    /// Applies the pending stacking order.
+    fn apply_pending(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            self.below = pending
+                .iter()
+                .position(Option::is_none)
+                .expect("Parent must be in the stacking order");
+            self.children = pending.into_iter().flatten().collect();
+        }
+    }
 }
 
 pub struct RegionData {
     inner: Mutex<RegionAttributes>,
 }
 
+impl RegionData {
+    /// The current contents of the region.
+    pub(crate) fn attributes(&self) -> RegionAttributes {
+        self.inner.lock().unwrap().clone()
+    }
+}
+
+/// The committed opaque region of a [`WlSurface`], in surface-local coordinates.
+///
+/// Content behind the opaque region is hidden by the surface and does not need to be drawn. The region is empty
+/// until the client sets one.
+///
+/// This can always be queried if the surface is alive.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OpaqueRegion(Region);
+
+impl OpaqueRegion {
+    pub fn region(&self) -> &Region {
+        &self.0
+    }
+}
+
+/// The committed input region of a [`WlSurface`], in surface-local coordinates.
+///
+/// Until the client sets an input region, the whole surface accepts input. The input region is not clipped to
+/// the size of the surface.
+///
+/// This can always be queried if the surface is alive.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct InputRegion(Option<Region>);
+
+impl InputRegion {
+    /// The input region, or [`None`] if the whole surface accepts input.
+    pub fn region(&self) -> Option<&Region> {
+        self.0.as_ref()
+    }
+
+    /// Checks whether the input region contains a point.
+    pub fn contains<P: Into<Point<i32, Logical>>>(&self, point: P) -> bool {
+        match &self.0 {
+            Some(region) => region.contains(point),
+            None => true,
+        }
+    }
+}
+
 /// Kind of a rectangle part of a region
 #[derive(Copy, Clone, Debug)]
 pub enum RectangleKind {
@@ -298,6 +810,19 @@ impl RegionAttributes {
         }
         contains
     }
+
+    /// Evaluates the list of rectangles into a set of non-overlapping rectangles.
+    pub fn to_region(&self) -> Region {
+        self.rects
+            .iter()
+            .fold(Region::new(), |region, (kind, rect)| {
+                let rect = Region::from_rects([*rect]);
+                match kind {
+                    RectangleKind::Add => region.union(&rect),
+                    RectangleKind::Subtract => region.subtract(&rect),
+                }
+            })
+    }
 }
 
 /// Internal component for data assoicated with a [`WlSurface`].
@@ -309,6 +834,16 @@ struct Internal<State: EcsAccess> {
     destroy_systems: Vec<SurfaceDestroy<State>>,
 
     pending: Pending,
+    /// The buffer which is attached once every commit which was queued so far is applied.
+    queued_buffer: Option<wl_buffer::WlBuffer>,
+    /// Blockers for the next commit.
+    blockers: Vec<Box<dyn Blocker>>,
+    /// Commits of synchronized child subsurfaces waiting for the next commit of this surface.
+    cached: Vec<transaction::SurfaceCommit>,
+    /// Extension state of the commit which is being applied.
+    applying: CommitState,
+    /// Whether a pre-commit system discarded the commit which is being processed.
+    discard: bool,
 }
 
 impl<State: EcsAccess> Default for Internal<State> {
@@ -326,7 +861,29 @@ impl<State: EcsAccess> Default for Internal<State> {
                 buffer: None,
                 opaque_region: None,
                 input_region: None,
+                viewport: Viewport::default(),
+                window_geometry: None,
+                commit_state: CommitState::default(),
             },
+            queued_buffer: None,
+            blockers: Vec::new(),
+            cached: Vec::new(),
+            applying: CommitState::default(),
+            discard: false,
+        }
+    }
+}
+
+impl<State: EcsAccess> Internal<State> {
+    /// The buffer which will be attached once the pending state is applied.
+    ///
+    /// Commits which are still waiting in a transaction are taken into account, so this may differ from
+    /// [`Buffer::current_buffer`].
+    fn attached_buffer(&self) -> Option<wl_buffer::WlBuffer> {
+        match &self.pending.buffer {
+            Some(BufferAssignment::NewBuffer(buffer)) => Some(buffer.clone()),
+            Some(BufferAssignment::Removed) => None,
+            None => self.queued_buffer.clone(),
         }
     }
 }
@@ -340,4 +897,34 @@ struct Pending {
     buffer: Option<BufferAssignment>,
     opaque_region: Option<RegionAttributes>,
     input_region: Option<RegionAttributes>,
+    viewport: Viewport,
+    window_geometry: Option<Rectangle<i32, Logical>>,
+    commit_state: CommitState,
+}
+
+/// Extension state carried by a single commit, keyed by type.
+///
+/// See [`Compositor::insert_commit_state`].
+#[derive(Default)]
+struct CommitState(HashMap<TypeId, Box<dyn Any + Send + Sync>>);
+
+impl Pending {
+    /// Takes the state of a commit.
+    ///
+    /// State which is consumed by a commit is reset, while the state which persists between commits is copied.
+    fn take_commit(&mut self) -> Pending {
+        Pending {
+            damage: std::mem::take(&mut self.damage),
+            frame_callbacks: std::mem::take(&mut self.frame_callbacks),
+            transform: self.transform,
+            scale: self.scale,
+            delta: self.delta.take(),
+            buffer: self.buffer.take(),
+            opaque_region: self.opaque_region.clone(),
+            input_region: self.input_region.clone(),
+            viewport: self.viewport,
+            window_geometry: self.window_geometry,
+            commit_state: std::mem::take(&mut self.commit_state),
+        }
+    }
 }
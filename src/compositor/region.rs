@@ -0,0 +1,419 @@
+use std::fmt;
+
+use smithay::utils::{Logical, Point, Rectangle};
+
+/// A region represented as a canonical set of non-overlapping rectangles.
+///
+/// A [`RegionAttributes`](super::RegionAttributes) is the list of add and subtract requests sent by a client,
+/// while a [`Region`] is the evaluated form of that list.
+///
+/// The rectangles are stored in a canonical banded order: they are sorted top to bottom and then left to
+/// right, rectangles in the same band share the same vertical extent, and vertically adjacent bands covering
+/// the same horizontal spans are coalesced. Two regions covering the same area therefore always contain the
+/// same rectangles.
+pub struct Region<Kind = Logical> {
+    rects: Vec<Rectangle<i32, Kind>>,
+}
+
+impl<Kind> Region<Kind> {
+    /// Creates an empty region.
+    pub fn new() -> Self {
+        Self { rects: Vec::new() }
+    }
+
+    /// Creates a region which is the union of the specified rectangles.
+    ///
+    /// The rectangles may overlap.
+    pub fn from_rects<I>(rects: I) -> Self
+    where
+        I: IntoIterator<Item = Rectangle<i32, Kind>>,
+    {
+        let rects = rects
+            .into_iter()
+            .filter(|rect| rect.size.w > 0 && rect.size.h > 0)
+            .collect::<Vec<_>>();
+        Self {
+            rects: combine(&rects, &[], |a, _| a),
+        }
+    }
+
+    /// The non-overlapping rectangles which make up this region, in canonical order.
+    pub fn rects(&self) -> &[Rectangle<i32, Kind>] {
+        &self.rects
+    }
+
+    /// Returns whether the region contains no area.
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    /// Checks whether given point is inside the region.
+    pub fn contains<P: Into<Point<i32, Kind>>>(&self, point: P) -> bool {
+        let point = point.into();
+        self.rects.iter().any(|rect| rect.contains(point))
+    }
+
+    /// The smallest rectangle containing the whole region.
+    ///
+    /// Returns [`None`] if the region is empty.
+    pub fn bounding_box(&self) -> Option<Rectangle<i32, Kind>> {
+        let first = self.rects.first()?;
+        let (mut x1, mut y1) = (first.loc.x, first.loc.y);
+        let (mut x2, mut y2) = (first.loc.x + first.size.w, first.loc.y + first.size.h);
+
+        for rect in &self.rects[1..] {
+            x1 = x1.min(rect.loc.x);
+            y1 = y1.min(rect.loc.y);
+            x2 = x2.max(rect.loc.x + rect.size.w);
+            y2 = y2.max(rect.loc.y + rect.size.h);
+        }
+
+        Some(Rectangle::from_loc_and_size((x1, y1), (x2 - x1, y2 - y1)))
+    }
+
+    /// The union of this region and another region.
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            rects: combine(&self.rects, &other.rects, |a, b| a || b),
+        }
+    }
+
+    /// The intersection of this region and another region.
+    pub fn intersect(&self, other: &Self) -> Self {
+        Self {
+            rects: combine(&self.rects, &other.rects, |a, b| a && b),
+        }
+    }
+
+    /// This region with the area of another region removed.
+    pub fn subtract(&self, other: &Self) -> Self {
+        Self {
+            rects: combine(&self.rects, &other.rects, |a, b| a && !b),
+        }
+    }
+
+    /// Moves every rectangle of the region by the specified offset.
+    pub fn translate<P: Into<Point<i32, Kind>>>(&self, offset: P) -> Self {
+        let offset = offset.into();
+        Self {
+            rects: self
+                .rects
+                .iter()
+                .map(|rect| Rectangle::from_loc_and_size(rect.loc + offset, rect.size))
+                .collect(),
+        }
+    }
+
+    /// Multiplies the position and size of every rectangle in the region by an integer factor.
+    ///
+    /// The factor must be positive.
+    pub fn scale(&self, factor: i32) -> Self {
+        assert!(factor > 0, "Region scale factor must be positive");
+        Self {
+            rects: self
+                .rects
+                .iter()
+                .map(|rect| {
+                    Rectangle::from_loc_and_size(
+                        (rect.loc.x * factor, rect.loc.y * factor),
+                        (rect.size.w * factor, rect.size.h * factor),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Converts the region to another coordinate space by applying a function to every rectangle.
+    ///
+    /// The resulting rectangles are flattened again, so the function does not need to preserve ordering.
+    pub fn map<F, Other>(&self, f: F) -> Region<Other>
+    where
+        F: FnMut(Rectangle<i32, Kind>) -> Rectangle<i32, Other>,
+    {
+        Region::from_rects(self.rects.iter().copied().map(f))
+    }
+
+    /// Clips a list of damage rectangles to the region.
+    ///
+    /// The returned rectangles do not overlap.
+    pub fn intersect_damage<I>(&self, damage: I) -> Vec<Rectangle<i32, Kind>>
+    where
+        I: IntoIterator<Item = Rectangle<i32, Kind>>,
+    {
+        Region::from_rects(damage).intersect(self).rects
+    }
+
+    /// Removes the area of the region from a list of damage rectangles.
+    ///
+    /// This is useful for occlusion culling: damage behind an opaque region does not need to be redrawn.
+    pub fn subtract_from_damage<I>(&self, damage: I) -> Vec<Rectangle<i32, Kind>>
+    where
+        I: IntoIterator<Item = Rectangle<i32, Kind>>,
+    {
+        Region::from_rects(damage).subtract(self).rects
+    }
+}
+
+impl<Kind> Default for Region<Kind> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Kind> Clone for Region<Kind> {
+    fn clone(&self) -> Self {
+        Self {
+            rects: self.rects.clone(),
+        }
+    }
+}
+
+impl<Kind> PartialEq for Region<Kind> {
+    fn eq(&self, other: &Self) -> bool {
+        // Regions are always stored in canonical form, so the same area means the same rectangles.
+        self.rects == other.rects
+    }
+}
+
+impl<Kind> Eq for Region<Kind> {}
+
+impl<Kind> fmt::Debug for Region<Kind> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Region")
+            .field("rects", &self.rects)
+            .finish()
+    }
+}
+
+/// Combines two sets of rectangles using a boolean operation, producing rectangles in canonical banded form.
+///
+/// The input rectangles may overlap.
+fn combine<Kind>(
+    a: &[Rectangle<i32, Kind>],
+    b: &[Rectangle<i32, Kind>],
+    op: fn(bool, bool) -> bool,
+) -> Vec<Rectangle<i32, Kind>> {
+    let mut ys = a
+        .iter()
+        .chain(b.iter())
+        .flat_map(|rect| [rect.loc.y, rect.loc.y + rect.size.h])
+        .collect::<Vec<_>>();
+    ys.sort_unstable();
+    ys.dedup();
+
+    let mut rects: Vec<Rectangle<i32, Kind>> = Vec::new();
+    // Start index in `rects` and spans of the previously emitted band, used to coalesce bands.
+    let mut previous: Option<(usize, i32, Vec<(i32, i32)>)> = None;
+
+    for band in ys.windows(2) {
+        let (y1, y2) = (band[0], band[1]);
+        let spans = combine_spans(&spans_in_band(a, y1, y2), &spans_in_band(b, y1, y2), op);
+
+        if spans.is_empty() {
+            previous = None;
+            continue;
+        }
+
+        // If the band directly above covers the same spans, grow those rectangles instead.
+        match previous.take() {
+            Some((start, end, previous_spans)) if end == y1 && previous_spans == spans => {
+                for rect in &mut rects[start..] {
+                    rect.size.h += y2 - y1;
+                }
+                previous = Some((start, y2, spans));
+            }
+
+            _ => {
+                let start = rects.len();
+                rects.extend(
+                    spans.iter().map(|&(x1, x2)| {
+                        Rectangle::from_loc_and_size((x1, y1), (x2 - x1, y2 - y1))
+                    }),
+                );
+                previous = Some((start, y2, spans));
+            }
+        }
+    }
+
+    rects
+}
+
+/// Sorted, merged horizontal spans of all rectangles which fully cover the band from `y1` to `y2`.
+fn spans_in_band<Kind>(rects: &[Rectangle<i32, Kind>], y1: i32, y2: i32) -> Vec<(i32, i32)> {
+    let mut spans = rects
+        .iter()
+        .filter(|rect| rect.loc.y <= y1 && rect.loc.y + rect.size.h >= y2)
+        .map(|rect| (rect.loc.x, rect.loc.x + rect.size.w))
+        .collect::<Vec<_>>();
+    spans.sort_unstable();
+
+    let mut merged: Vec<(i32, i32)> = Vec::with_capacity(spans.len());
+    for (x1, x2) in spans {
+        match merged.last_mut() {
+            Some(last) if last.1 >= x1 => last.1 = last.1.max(x2),
+            _ => merged.push((x1, x2)),
+        }
+    }
+
+    merged
+}
+
+/// Combines two lists of sorted, non-overlapping spans using a boolean operation.
+fn combine_spans(
+    a: &[(i32, i32)],
+    b: &[(i32, i32)],
+    op: fn(bool, bool) -> bool,
+) -> Vec<(i32, i32)> {
+    let mut xs = a
+        .iter()
+        .chain(b.iter())
+        .flat_map(|&(x1, x2)| [x1, x2])
+        .collect::<Vec<_>>();
+    xs.sort_unstable();
+    xs.dedup();
+
+    let inside = |spans: &[(i32, i32)], x: i32| spans.iter().any(|&(x1, x2)| x1 <= x && x < x2);
+
+    let mut result: Vec<(i32, i32)> = Vec::new();
+    for segment in xs.windows(2) {
+        let (x1, x2) = (segment[0], segment[1]);
+
+        if !op(inside(a, x1), inside(b, x1)) {
+            continue;
+        }
+
+        match result.last_mut() {
+            Some(last) if last.1 == x1 => last.1 = x2,
+            _ => result.push((x1, x2)),
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::utils::{Logical, Rectangle};
+
+    use super::Region;
+
+    fn rect(x: i32, y: i32, w: i32, h: i32) -> Rectangle<i32, Logical> {
+        Rectangle::from_loc_and_size((x, y), (w, h))
+    }
+
+    fn region(rects: &[(i32, i32, i32, i32)]) -> Region {
+        Region::from_rects(rects.iter().map(|&(x, y, w, h)| rect(x, y, w, h)))
+    }
+
+    #[test]
+    fn union_of_overlapping_rects_is_banded() {
+        let union = region(&[(0, 0, 10, 10)]).union(&region(&[(5, 5, 10, 10)]));
+        assert_eq!(
+            union.rects(),
+            [rect(0, 0, 10, 5), rect(0, 5, 15, 5), rect(5, 10, 10, 5)]
+        );
+        assert_eq!(union.bounding_box(), Some(rect(0, 0, 15, 15)));
+    }
+
+    #[test]
+    fn union_of_adjacent_rects_is_coalesced() {
+        let horizontal = region(&[(0, 0, 10, 10)]).union(&region(&[(10, 0, 10, 10)]));
+        assert_eq!(horizontal.rects(), [rect(0, 0, 20, 10)]);
+
+        let vertical = region(&[(0, 0, 10, 5)]).union(&region(&[(0, 5, 10, 5)]));
+        assert_eq!(vertical.rects(), [rect(0, 0, 10, 10)]);
+
+        // Bands touching at a corner only are kept apart.
+        let diagonal = region(&[(0, 0, 5, 5), (5, 5, 5, 5)]);
+        assert_eq!(diagonal.rects(), [rect(0, 0, 5, 5), rect(5, 5, 5, 5)]);
+    }
+
+    #[test]
+    fn intersect() {
+        let a = region(&[(0, 0, 10, 10)]);
+        assert_eq!(
+            a.intersect(&region(&[(5, 5, 10, 10)])).rects(),
+            [rect(5, 5, 5, 5)]
+        );
+
+        // Adjacent rectangles share no area.
+        assert!(a.intersect(&region(&[(10, 0, 10, 10)])).is_empty());
+        assert!(a.intersect(&Region::new()).is_empty());
+    }
+
+    #[test]
+    fn subtract() {
+        let a = region(&[(0, 0, 10, 10)]);
+        assert_eq!(
+            a.subtract(&region(&[(5, 5, 10, 10)])).rects(),
+            [rect(0, 0, 10, 5), rect(0, 5, 5, 5)]
+        );
+
+        // A hole splits the band it is in.
+        let hole = a.subtract(&region(&[(4, 4, 2, 2)]));
+        assert_eq!(
+            hole.rects(),
+            [
+                rect(0, 0, 10, 4),
+                rect(0, 4, 4, 2),
+                rect(6, 4, 4, 2),
+                rect(0, 6, 10, 4)
+            ]
+        );
+        assert!(!hole.contains((5, 5)));
+        assert!(hole.contains((3, 5)));
+    }
+
+    #[test]
+    fn subtract_to_empty() {
+        let a = region(&[(0, 0, 10, 10), (20, 0, 10, 10)]);
+        assert!(a.subtract(&a).is_empty());
+        assert!(a.subtract(&region(&[(-5, -5, 50, 50)])).is_empty());
+        assert_eq!(a.subtract(&a).bounding_box(), None);
+
+        // Subtracting nothing leaves the region unchanged.
+        assert_eq!(a.subtract(&Region::new()), a);
+    }
+
+    #[test]
+    fn translate() {
+        let a = region(&[(0, 0, 10, 10), (5, 5, 10, 10)]);
+        assert_eq!(
+            a.translate((3, -2)),
+            region(&[(3, -2, 10, 10), (8, 3, 10, 10)])
+        );
+    }
+
+    #[test]
+    fn scale() {
+        let a = region(&[(1, 1, 2, 2), (3, 1, 1, 4)]);
+        assert_eq!(a.scale(2), region(&[(2, 2, 4, 4), (6, 2, 2, 8)]));
+        assert_eq!(a.scale(1), a);
+    }
+
+    #[test]
+    fn equivalent_regions_are_equal() {
+        // An L shape, built from a wide rectangle on top and from a tall rectangle on the left.
+        let wide = region(&[(0, 0, 10, 5), (0, 5, 5, 5)]);
+        let tall = region(&[(0, 0, 5, 10), (5, 0, 5, 5)]);
+        assert_eq!(wide, tall);
+
+        // The order of rectangles and operations does not matter.
+        let rects = [(0, 0, 10, 10), (5, 5, 10, 10), (20, 0, 5, 5)];
+        let forward = rects
+            .iter()
+            .fold(Region::new(), |union, &r| union.union(&region(&[r])));
+        let backward = rects
+            .iter()
+            .rev()
+            .fold(Region::new(), |union, &r| union.union(&region(&[r])));
+        assert_eq!(forward, backward);
+        assert_eq!(forward, region(&rects));
+
+        let square = region(&[(0, 0, 10, 10)]);
+        assert_eq!(
+            square.subtract(&region(&[(5, 0, 5, 10)])),
+            square.intersect(&region(&[(-5, 0, 10, 10)]))
+        );
+    }
+}
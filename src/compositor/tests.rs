@@ -0,0 +1,771 @@
+use std::sync::Arc;
+
+use hecs::Entity;
+use smithay::utils::{Logical, Point, Rectangle};
+use wayland_server::{
+    protocol::{wl_output::Transform, wl_subsurface, wl_surface},
+    Resource,
+};
+
+use crate::testing::{Arg, Fixture, TestState, TestSurface};
+
+use super::{
+    Buffer, BufferAssignment, Compositor, CompositorEvent, InputRegion, ManualBlocker,
+    OpaqueRegion, Region, Subsurface, SubsurfaceChildren, SurfaceDamage, SurfaceGeometry,
+};
+
+const POOL_SIZE: usize = 64 * 1024;
+
+// wl_surface requests
+const DESTROY: u16 = 0;
+const ATTACH: u16 = 1;
+const COMMIT: u16 = 6;
+const SET_BUFFER_TRANSFORM: u16 = 7;
+const SET_BUFFER_SCALE: u16 = 8;
+const DAMAGE_BUFFER: u16 = 9;
+const SET_OPAQUE_REGION: u16 = 4;
+const SET_INPUT_REGION: u16 = 5;
+const OFFSET: u16 = 10;
+
+// wl_region requests
+const SUBTRACT: u16 = 2;
+
+// wl_subsurface requests
+const SET_POSITION: u16 = 1;
+const PLACE_ABOVE: u16 = 2;
+const PLACE_BELOW: u16 = 3;
+const SET_DESYNC: u16 = 5;
+
+/// The committed [`Buffer`] of a surface, with buffers identified by their protocol id.
+#[derive(Debug, PartialEq)]
+struct Committed {
+    buffer: Option<Option<u32>>,
+    current: Option<u32>,
+    delta: Option<Point<i32, Logical>>,
+    scale: i32,
+    transform: Transform,
+}
+
+fn committed(fixture: &mut Fixture, surface: &TestSurface) -> Committed {
+    let buffer = fixture
+        .world()
+        .query_one_mut::<&Buffer>(surface.entity)
+        .unwrap();
+
+    Committed {
+        buffer: buffer.buffer().map(|assignment| match assignment {
+            BufferAssignment::NewBuffer(buffer) => Some(buffer.id().protocol_id()),
+            BufferAssignment::Removed => None,
+        }),
+        current: buffer
+            .current_buffer()
+            .map(|buffer| buffer.id().protocol_id()),
+        delta: buffer.delta(),
+        scale: buffer.scale(),
+        transform: buffer.transform(),
+    }
+}
+
+fn stacking(fixture: &mut Fixture, surface: &TestSurface) -> (Vec<Entity>, Vec<Entity>) {
+    let children = fixture
+        .world()
+        .query_one_mut::<&SubsurfaceChildren>(surface.entity)
+        .unwrap();
+    (
+        children.below_parent().to_vec(),
+        children.above_parent().to_vec(),
+    )
+}
+
+fn last_damage(
+    fixture: &mut Fixture,
+    surface: &TestSurface,
+) -> Vec<Rectangle<i32, smithay::utils::Buffer>> {
+    fixture
+        .world()
+        .query_one_mut::<&SurfaceDamage>(surface.entity)
+        .unwrap()
+        .last_damage()
+        .expect("Whole buffer was damaged")
+        .to_vec()
+}
+
+#[test]
+fn attach_and_offset_are_consumed_by_commit() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    let mut pool = fixture.create_pool(POOL_SIZE);
+    let buffer = fixture.create_buffer(&mut pool, 4, 4);
+
+    fixture.send(
+        surface.id,
+        ATTACH,
+        &[Arg::Object(buffer), Arg::Int(2), Arg::Int(3)],
+    );
+    fixture.commit(surface.id);
+    assert_eq!(
+        committed(&mut fixture, &surface),
+        Committed {
+            buffer: Some(Some(buffer)),
+            current: Some(buffer),
+            delta: Some((2, 3).into()),
+            scale: 1,
+            transform: Transform::Normal,
+        }
+    );
+
+    // A commit without an attach keeps the buffer, but does not attach it or move it again.
+    fixture.commit(surface.id);
+    assert_eq!(
+        committed(&mut fixture, &surface),
+        Committed {
+            buffer: None,
+            current: Some(buffer),
+            delta: None,
+            scale: 1,
+            transform: Transform::Normal,
+        }
+    );
+
+    fixture.attach(surface.id, 0);
+    fixture.commit(surface.id);
+    assert_eq!(
+        committed(&mut fixture, &surface),
+        Committed {
+            buffer: Some(None),
+            current: None,
+            delta: None,
+            scale: 1,
+            transform: Transform::Normal,
+        }
+    );
+
+    fixture.commit(surface.id);
+    assert_eq!(committed(&mut fixture, &surface).buffer, None);
+}
+
+#[test]
+fn offset_request_is_consumed_by_commit() {
+    let mut fixture = Fixture::with_compositor_version(5);
+    let surface = fixture.create_surface();
+    let mut pool = fixture.create_pool(POOL_SIZE);
+    let buffer = fixture.create_buffer(&mut pool, 4, 4);
+
+    fixture.attach(surface.id, buffer);
+    fixture.send(surface.id, OFFSET, &[Arg::Int(-1), Arg::Int(5)]);
+    fixture.commit(surface.id);
+    assert_eq!(
+        committed(&mut fixture, &surface).delta,
+        Some((-1, 5).into())
+    );
+
+    fixture.commit(surface.id);
+    assert_eq!(committed(&mut fixture, &surface).delta, None);
+
+    // The offset may also be sent without attaching a buffer.
+    fixture.send(surface.id, OFFSET, &[Arg::Int(7), Arg::Int(0)]);
+    fixture.commit(surface.id);
+    let committed = committed(&mut fixture, &surface);
+    assert_eq!(committed.delta, Some((7, 0).into()));
+    assert_eq!(committed.buffer, None);
+    assert_eq!(committed.current, Some(buffer));
+}
+
+#[test]
+fn damage_is_consumed_by_commit() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    let mut pool = fixture.create_pool(POOL_SIZE);
+    let buffer = fixture.create_buffer(&mut pool, 8, 8);
+
+    fixture.attach(surface.id, buffer);
+    fixture.send(
+        surface.id,
+        DAMAGE_BUFFER,
+        &[Arg::Int(0), Arg::Int(0), Arg::Int(2), Arg::Int(2)],
+    );
+    fixture.commit(surface.id);
+    assert_eq!(
+        last_damage(&mut fixture, &surface),
+        vec![Rectangle::from_loc_and_size((0, 0), (2, 2))]
+    );
+
+    fixture.commit(surface.id);
+    assert_eq!(last_damage(&mut fixture, &surface), vec![]);
+
+    fixture.send(
+        surface.id,
+        DAMAGE_BUFFER,
+        &[Arg::Int(4), Arg::Int(4), Arg::Int(2), Arg::Int(2)],
+    );
+    fixture.commit(surface.id);
+    assert_eq!(
+        last_damage(&mut fixture, &surface),
+        vec![Rectangle::from_loc_and_size((4, 4), (2, 2))]
+    );
+}
+
+#[test]
+fn scale_and_transform_persist_across_commits() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    let mut pool = fixture.create_pool(POOL_SIZE);
+    let first = fixture.create_buffer(&mut pool, 4, 4);
+    let second = fixture.create_buffer(&mut pool, 8, 8);
+
+    fixture.send(surface.id, SET_BUFFER_SCALE, &[Arg::Int(2)]);
+    fixture.send(
+        surface.id,
+        SET_BUFFER_TRANSFORM,
+        &[Arg::Int(Transform::_90 as i32)],
+    );
+    fixture.attach(surface.id, first);
+    fixture.commit(surface.id);
+    let committed_state = committed(&mut fixture, &surface);
+    assert_eq!(committed_state.scale, 2);
+    assert_eq!(committed_state.transform, Transform::_90);
+
+    fixture.commit(surface.id);
+    let committed_state = committed(&mut fixture, &surface);
+    assert_eq!(committed_state.scale, 2);
+    assert_eq!(committed_state.transform, Transform::_90);
+
+    fixture.attach(surface.id, second);
+    fixture.commit(surface.id);
+    assert_eq!(
+        committed(&mut fixture, &surface),
+        Committed {
+            buffer: Some(Some(second)),
+            current: Some(second),
+            delta: None,
+            scale: 2,
+            transform: Transform::_90,
+        }
+    );
+
+    fixture.send(surface.id, SET_BUFFER_SCALE, &[Arg::Int(1)]);
+    fixture.commit(surface.id);
+    let committed_state = committed(&mut fixture, &surface);
+    assert_eq!(committed_state.scale, 1);
+    assert_eq!(committed_state.transform, Transform::_90);
+}
+
+#[test]
+fn non_positive_scale_is_error() {
+    for scale in [0, -1] {
+        let mut fixture = Fixture::new();
+        let surface = fixture.create_surface();
+
+        fixture.send(surface.id, SET_BUFFER_SCALE, &[Arg::Int(scale)]);
+        assert_eq!(
+            fixture.protocol_error(),
+            Some(wl_surface::Error::InvalidScale as u32)
+        );
+    }
+}
+
+#[test]
+fn unknown_transform_is_error() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+
+    fixture.send(surface.id, SET_BUFFER_TRANSFORM, &[Arg::Int(8)]);
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(wl_surface::Error::InvalidTransform as u32)
+    );
+}
+
+#[test]
+fn buffer_size_not_divisible_by_scale_is_error() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    let mut pool = fixture.create_pool(POOL_SIZE);
+    let buffer = fixture.create_buffer(&mut pool, 4, 6);
+
+    // 4x6 is divisible by 2, so the commit is accepted.
+    fixture.send(surface.id, SET_BUFFER_SCALE, &[Arg::Int(2)]);
+    fixture.attach(surface.id, buffer);
+    fixture.commit(surface.id);
+
+    // The size is only checked on commit, so the scale may change before a new buffer is attached.
+    fixture.send(surface.id, SET_BUFFER_SCALE, &[Arg::Int(3)]);
+    fixture.assert_no_error();
+
+    fixture.send(surface.id, COMMIT, &[]);
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(wl_surface::Error::InvalidSize as u32)
+    );
+}
+
+fn regions(fixture: &mut Fixture, surface: &TestSurface) -> (OpaqueRegion, InputRegion) {
+    let (opaque_region, input_region) = fixture
+        .world()
+        .query_one_mut::<(&OpaqueRegion, &InputRegion)>(surface.entity)
+        .unwrap();
+    (opaque_region.clone(), input_region.clone())
+}
+
+#[test]
+fn regions_are_applied_on_commit() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+
+    // Without regions, nothing is opaque and the whole surface accepts input.
+    let (opaque_region, input_region) = regions(&mut fixture, &surface);
+    assert!(opaque_region.region().is_empty());
+    assert_eq!(input_region.region(), None);
+    assert!(input_region.contains((1000, 1000)));
+
+    let opaque = fixture.create_region(&[(0, 0, 10, 10)]);
+    fixture.send(
+        opaque,
+        SUBTRACT,
+        &[Arg::Int(5), Arg::Int(0), Arg::Int(5), Arg::Int(10)],
+    );
+    let input = fixture.create_region(&[(0, 0, 5, 5), (5, 0, 5, 5)]);
+    fixture.send(surface.id, SET_OPAQUE_REGION, &[Arg::Object(opaque)]);
+    fixture.send(surface.id, SET_INPUT_REGION, &[Arg::Object(input)]);
+    fixture.dispatch();
+    assert!(regions(&mut fixture, &surface).0.region().is_empty());
+
+    fixture.commit(surface.id);
+    let rect = |x, y, w, h| Region::from_rects([Rectangle::from_loc_and_size((x, y), (w, h))]);
+    let (opaque_region, input_region) = regions(&mut fixture, &surface);
+    assert_eq!(opaque_region.region(), &rect(0, 0, 5, 10));
+    assert_eq!(input_region.region(), Some(&rect(0, 0, 10, 5)));
+    assert!(!input_region.contains((5, 5)));
+
+    // The regions persist until they are unset.
+    fixture.commit(surface.id);
+    assert_eq!(
+        regions(&mut fixture, &surface).0.region(),
+        &rect(0, 0, 5, 10)
+    );
+
+    fixture.send(surface.id, SET_OPAQUE_REGION, &[Arg::Object(0)]);
+    fixture.send(surface.id, SET_INPUT_REGION, &[Arg::Object(0)]);
+    fixture.commit(surface.id);
+    let (opaque_region, input_region) = regions(&mut fixture, &surface);
+    assert!(opaque_region.region().is_empty());
+    assert_eq!(input_region.region(), None);
+}
+
+#[test]
+fn buffer_size_is_validated_against_queued_commits() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    let mut pool = fixture.create_pool(POOL_SIZE);
+    let buffer = fixture.create_buffer(&mut pool, 3, 3);
+
+    // Hold back the commit which attaches the buffer.
+    fixture.add_blocker(&surface);
+    fixture.attach(surface.id, buffer);
+    fixture.commit(surface.id);
+    assert_eq!(committed(&mut fixture, &surface).current, None);
+
+    // The queued 3x3 buffer is not divisible by the new scale, even though no buffer is applied yet.
+    fixture.send(surface.id, SET_BUFFER_SCALE, &[Arg::Int(2)]);
+    fixture.send(surface.id, COMMIT, &[]);
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(wl_surface::Error::InvalidSize as u32)
+    );
+}
+
+#[test]
+fn released_blocker_applies_transaction() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    let mut pool = fixture.create_pool(POOL_SIZE);
+    let buffer = fixture.create_buffer(&mut pool, 4, 4);
+
+    let blocker = fixture.add_blocker(&surface);
+    fixture.attach(surface.id, buffer);
+    fixture.commit(surface.id);
+    assert_eq!(committed(&mut fixture, &surface).current, None);
+
+    fixture.release(&blocker);
+    assert_eq!(committed(&mut fixture, &surface).current, Some(buffer));
+}
+
+/// Records the order in which the commits of a surface are applied.
+#[derive(Debug, Default)]
+struct CommitOrder {
+    committed: u32,
+    applied: Vec<u32>,
+    /// Shared with the state of every commit, so tests can check that the state of dropped commits is dropped.
+    token: Arc<()>,
+}
+
+/// The number of a commit, carried as extension state.
+struct Numbered {
+    number: u32,
+    _token: Arc<()>,
+}
+
+fn number_commit(state: &mut TestState, surface: &wl_surface::WlSurface) {
+    let entity = surface.data::<crate::EntityData>().unwrap().0;
+    let order = state
+        .ecs
+        .world
+        .query_one_mut::<&mut CommitOrder>(entity)
+        .unwrap();
+    order.committed += 1;
+    let number = Numbered {
+        number: order.committed,
+        _token: order.token.clone(),
+    };
+    Compositor::insert_commit_state::<TestState, _>(&mut state.ecs, surface, number);
+}
+
+fn record_commit(state: &mut TestState, surface: &wl_surface::WlSurface) {
+    let number = Compositor::take_commit_state::<TestState, Numbered>(&mut state.ecs, surface);
+    let entity = surface.data::<crate::EntityData>().unwrap().0;
+    let order = state
+        .ecs
+        .world
+        .query_one_mut::<&mut CommitOrder>(entity)
+        .unwrap();
+    order.applied.extend(number.map(|number| number.number));
+}
+
+fn numbered_surface(fixture: &mut Fixture) -> TestSurface {
+    let surface = fixture.create_surface();
+    fixture
+        .world()
+        .insert_one(surface.entity, CommitOrder::default())
+        .unwrap();
+    Compositor::add_pre_commit::<TestState>(
+        &mut fixture.server.state.ecs,
+        &surface.surface,
+        number_commit,
+    );
+    Compositor::add_post_commit::<TestState>(
+        &mut fixture.server.state.ecs,
+        &surface.surface,
+        record_commit,
+    );
+    surface
+}
+
+fn applied(fixture: &mut Fixture, surface: &TestSurface) -> Vec<u32> {
+    fixture
+        .world()
+        .query_one_mut::<&CommitOrder>(surface.entity)
+        .unwrap()
+        .applied
+        .clone()
+}
+
+#[test]
+fn blocked_commits_apply_their_state_in_order() {
+    let mut fixture = Fixture::new();
+    let surface = numbered_surface(&mut fixture);
+    fixture.commit(surface.id);
+    assert_eq!(applied(&mut fixture, &surface), vec![1]);
+
+    // Every commit waiting behind the blocker carries its own state.
+    let blocker = fixture.add_blocker(&surface);
+    fixture.commit(surface.id);
+    fixture.commit(surface.id);
+    assert_eq!(applied(&mut fixture, &surface), vec![1]);
+
+    fixture.release(&blocker);
+    assert_eq!(applied(&mut fixture, &surface), vec![1, 2, 3]);
+}
+
+#[test]
+fn state_of_dropped_commit_is_dropped() {
+    let mut fixture = Fixture::new();
+    let surface = numbered_surface(&mut fixture);
+
+    let token = fixture
+        .world()
+        .query_one_mut::<&CommitOrder>(surface.entity)
+        .unwrap()
+        .token
+        .clone();
+
+    let blocker = fixture.add_blocker(&surface);
+    fixture.commit(surface.id);
+    assert_eq!(Arc::strong_count(&token), 3);
+
+    // The commit is dropped instead of applied since the surface is gone.
+    fixture.send(surface.id, DESTROY, &[]);
+    fixture.release(&blocker);
+    assert_eq!(Arc::strong_count(&token), 2);
+}
+
+/// A blocker which a post-commit system of a surface releases when the surface is applied.
+struct ReleaseOnCommit(ManualBlocker);
+
+fn release_on_commit(state: &mut TestState, surface: &wl_surface::WlSurface) {
+    let entity = surface.data::<crate::EntityData>().unwrap().0;
+    let blocker = state
+        .ecs
+        .world
+        .query_one_mut::<&ReleaseOnCommit>(entity)
+        .unwrap()
+        .0
+        .clone();
+    blocker.release(state);
+}
+
+#[test]
+fn blocker_released_while_applying_is_applied() {
+    let mut fixture = Fixture::new();
+    let blocked = numbered_surface(&mut fixture);
+    let releasing = fixture.create_surface();
+
+    let blocker = fixture.add_blocker(&blocked);
+    fixture.commit(blocked.id);
+    assert_eq!(applied(&mut fixture, &blocked), Vec::<u32>::new());
+
+    // Applying the commit of one surface releases the transaction of another, which was queued earlier.
+    fixture
+        .world()
+        .insert_one(releasing.entity, ReleaseOnCommit(blocker))
+        .unwrap();
+    Compositor::add_post_commit::<TestState>(
+        &mut fixture.server.state.ecs,
+        &releasing.surface,
+        release_on_commit,
+    );
+    fixture.commit(releasing.id);
+    assert_eq!(applied(&mut fixture, &blocked), vec![1]);
+}
+
+fn discard_commit(state: &mut TestState, surface: &wl_surface::WlSurface) {
+    Compositor::discard_commit::<TestState>(&mut state.ecs, surface);
+}
+
+#[test]
+fn discarded_commit_is_not_applied() {
+    let mut fixture = Fixture::new();
+    let surface = numbered_surface(&mut fixture);
+    Compositor::add_pre_commit::<TestState>(
+        &mut fixture.server.state.ecs,
+        &surface.surface,
+        discard_commit,
+    );
+    let mut pool = fixture.create_pool(POOL_SIZE);
+    let buffer = fixture.create_buffer(&mut pool, 4, 4);
+
+    fixture.attach(surface.id, buffer);
+    fixture.commit(surface.id);
+    assert_eq!(applied(&mut fixture, &surface), Vec::<u32>::new());
+    assert_eq!(committed(&mut fixture, &surface).current, None);
+}
+
+#[test]
+fn sync_subsurface_is_applied_with_parent() {
+    let mut fixture = Fixture::new();
+    let parent = fixture.create_surface();
+    let child = fixture.create_surface();
+    fixture.create_subsurface(&child, &parent);
+    let mut pool = fixture.create_pool(POOL_SIZE);
+    let buffer = fixture.create_buffer(&mut pool, 4, 4);
+
+    fixture.attach(child.id, buffer);
+    fixture.commit(child.id);
+    assert_eq!(committed(&mut fixture, &child).current, None);
+
+    fixture.commit(parent.id);
+    assert_eq!(committed(&mut fixture, &child).current, Some(buffer));
+}
+
+#[test]
+fn desync_applies_cached_state() {
+    let mut fixture = Fixture::new();
+    let parent = fixture.create_surface();
+    let child = fixture.create_surface();
+    let grandchild = fixture.create_surface();
+    let subsurface = fixture.create_subsurface(&child, &parent);
+    fixture.create_subsurface(&grandchild, &child);
+    let mut pool = fixture.create_pool(POOL_SIZE);
+    let first = fixture.create_buffer(&mut pool, 4, 4);
+    let second = fixture.create_buffer(&mut pool, 4, 4);
+
+    // The state of the grandchild is cached by the child, which is cached by the parent.
+    fixture.attach(grandchild.id, second);
+    fixture.commit(grandchild.id);
+    fixture.attach(child.id, first);
+    fixture.commit(child.id);
+    assert_eq!(committed(&mut fixture, &child).current, None);
+    assert_eq!(committed(&mut fixture, &grandchild).current, None);
+
+    fixture.send(subsurface, SET_DESYNC, &[]);
+    fixture.assert_no_error();
+    assert_eq!(committed(&mut fixture, &child).current, Some(first));
+    assert_eq!(committed(&mut fixture, &grandchild).current, Some(second));
+
+    // Once desynchronized, commits are applied immediately.
+    fixture.attach(child.id, 0);
+    fixture.commit(child.id);
+    assert_eq!(committed(&mut fixture, &child).current, None);
+}
+
+#[test]
+fn stacking_order_is_applied_with_parent() {
+    let mut fixture = Fixture::new();
+    let parent = fixture.create_surface();
+    let first = fixture.create_surface();
+    let second = fixture.create_surface();
+    let first_subsurface = fixture.create_subsurface(&first, &parent);
+    let second_subsurface = fixture.create_subsurface(&second, &parent);
+    fixture.dispatch();
+    assert_eq!(
+        stacking(&mut fixture, &parent),
+        (vec![], vec![first.entity, second.entity])
+    );
+
+    fixture.send(second_subsurface, PLACE_BELOW, &[Arg::Object(parent.id)]);
+    fixture.send(first_subsurface, PLACE_BELOW, &[Arg::Object(second.id)]);
+    fixture.dispatch();
+    assert_eq!(
+        stacking(&mut fixture, &parent),
+        (vec![], vec![first.entity, second.entity])
+    );
+
+    fixture.commit(parent.id);
+    assert_eq!(
+        stacking(&mut fixture, &parent),
+        (vec![first.entity, second.entity], vec![])
+    );
+
+    fixture.send(first_subsurface, PLACE_ABOVE, &[Arg::Object(parent.id)]);
+    fixture.commit(parent.id);
+    assert_eq!(
+        stacking(&mut fixture, &parent),
+        (vec![second.entity], vec![first.entity])
+    );
+}
+
+#[test]
+fn placing_subsurface_relative_to_itself_is_an_error() {
+    let mut fixture = Fixture::new();
+    let parent = fixture.create_surface();
+    let child = fixture.create_surface();
+    let subsurface = fixture.create_subsurface(&child, &parent);
+
+    fixture.send(subsurface, PLACE_ABOVE, &[Arg::Object(child.id)]);
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(wl_subsurface::Error::BadSurface as u32)
+    );
+}
+
+fn bounding_box(fixture: &mut Fixture, surface: &TestSurface) -> Rectangle<i32, Logical> {
+    fixture
+        .world()
+        .query_one_mut::<&SurfaceGeometry>(surface.entity)
+        .unwrap()
+        .bounding_box()
+}
+
+#[test]
+fn bounding_box_includes_mapped_subsurfaces() {
+    let mut fixture = Fixture::new();
+    let parent = fixture.create_surface();
+    let child = fixture.create_surface();
+    let subsurface = fixture.create_subsurface(&child, &parent);
+    let mut pool = fixture.create_pool(POOL_SIZE);
+    let parent_buffer = fixture.create_buffer(&mut pool, 4, 4);
+    let child_buffer = fixture.create_buffer(&mut pool, 2, 2);
+
+    fixture.send(subsurface, SET_POSITION, &[Arg::Int(-2), Arg::Int(6)]);
+    fixture.attach(child.id, child_buffer);
+    fixture.commit(child.id);
+    fixture.attach(parent.id, parent_buffer);
+    fixture.commit(parent.id);
+    assert_eq!(
+        bounding_box(&mut fixture, &parent),
+        Rectangle::from_loc_and_size((-2, 0), (6, 8))
+    );
+
+    // Unmapping the subsurface removes it from the bounding box of its parent.
+    fixture.attach(child.id, 0);
+    fixture.commit(child.id);
+    fixture.commit(parent.id);
+    assert_eq!(
+        bounding_box(&mut fixture, &parent),
+        Rectangle::from_loc_and_size((0, 0), (4, 4))
+    );
+}
+
+/// The kinds of the drained compositor events with the entity of their surface.
+fn drain_events(fixture: &mut Fixture) -> Vec<(&'static str, Entity)> {
+    fixture
+        .server
+        .state
+        .ecs
+        .drain_events::<CompositorEvent>()
+        .into_iter()
+        .map(|event| match event {
+            CompositorEvent::SurfaceCreated { entity, .. } => ("created", entity),
+            CompositorEvent::SurfaceCommitted { entity, .. } => ("committed", entity),
+            CompositorEvent::RoleAssigned { entity, role, .. } => (role, entity),
+            CompositorEvent::SurfaceDestroyed { entity, .. } => ("destroyed", entity),
+        })
+        .collect()
+}
+
+#[test]
+fn events_are_pushed_in_order() {
+    let mut fixture = Fixture::new();
+    let parent = fixture.create_surface();
+    fixture.server.state.ecs.enable_events::<CompositorEvent>();
+
+    let child = fixture.create_surface();
+    fixture.create_subsurface(&child, &parent);
+    fixture.commit(child.id);
+    fixture.commit(parent.id);
+    fixture.send(child.id, DESTROY, &[]);
+    fixture.assert_no_error();
+
+    // The commit of the synchronized child is applied right after its parent.
+    assert_eq!(
+        drain_events(&mut fixture),
+        vec![
+            ("created", child.entity),
+            (Subsurface::ROLE, child.entity),
+            ("committed", parent.entity),
+            ("committed", child.entity),
+            ("destroyed", child.entity),
+        ]
+    );
+    assert_eq!(drain_events(&mut fixture), vec![]);
+}
+
+#[test]
+fn events_are_dropped_while_disabled() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    fixture.commit(surface.id);
+    assert_eq!(drain_events(&mut fixture), vec![]);
+
+    // Enabling the queue does not bring back earlier events.
+    fixture.server.state.ecs.enable_events::<CompositorEvent>();
+    assert_eq!(drain_events(&mut fixture), vec![]);
+
+    fixture.commit(surface.id);
+    let events = fixture.server.state.ecs.drain_events::<CompositorEvent>();
+    match events.as_slice() {
+        [CompositorEvent::SurfaceCommitted {
+            surface: committed,
+            commit,
+            ..
+        }] => {
+            assert_eq!(committed, &surface.surface);
+            let current = fixture
+                .world()
+                .query_one_mut::<&SurfaceDamage>(surface.entity)
+                .unwrap()
+                .current_commit();
+            assert_eq!(*commit, current);
+        }
+        events => panic!("Unexpected events {events:?}"),
+    }
+}
@@ -0,0 +1,436 @@
+use std::{
+    collections::HashSet,
+    fmt, mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use hecs::Entity;
+use wayland_server::{protocol::wl_surface::WlSurface, Resource};
+
+use crate::{viewporter::Viewport, Ecs, EcsAccess, EntityData};
+
+use super::{
+    geometry, Buffer, BufferAssignment, BufferDimensions, Compositor, CompositorEvent,
+    CompositorHandler, FrameCallbacks, InputRegion, Internal, OpaqueRegion, Pending, Subsurface,
+    SubsurfaceChildren, SurfaceDamage,
+};
+
+/// Something which prevents a [`Transaction`] from being applied.
+///
+/// Blockers are polled whenever transactions are applied. Once a blocker is released, it must stay released.
+pub trait Blocker: Send + Sync + 'static {
+    /// Returns whether the blocker no longer holds back the transaction.
+    fn is_released(&self) -> bool;
+}
+
+/// A [`Blocker`] which is released by calling [`ManualBlocker::release`].
+///
+/// Clones of a blocker share the same state. Adding clones of one blocker to the next commit of several surfaces
+/// makes the commits of all those surfaces appear at the same time.
+#[derive(Debug, Clone, Default)]
+pub struct ManualBlocker(Arc<AtomicBool>);
+
+impl ManualBlocker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Releases the blocker and applies any transactions which are now ready.
+    pub fn release<State>(&self, state: &mut State)
+    where
+        State: CompositorHandler,
+    {
+        self.0.store(true, Ordering::Release);
+        Compositor::apply_transactions(state);
+    }
+}
+
+impl Blocker for ManualBlocker {
+    fn is_released(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// A set of surface commits which are applied together.
+///
+/// Each commit of a surface which is not a synchronized subsurface spawns an entity with a transaction. The
+/// commits of synchronized subsurfaces are added to the transaction of their parent.
+///
+/// The transaction is applied once the blockers of all of its commits are released and every earlier transaction
+/// of the same surfaces has been applied. Until then, the [`Buffer`] and other committed state of the surfaces is
+/// unchanged.
+pub struct Transaction {
+    commits: Vec<SurfaceCommit>,
+}
+
+impl Transaction {
+    /// The surfaces which are committed by this transaction.
+    pub fn surfaces(&self) -> impl Iterator<Item = &WlSurface> {
+        self.commits.iter().map(|commit| &commit.surface)
+    }
+
+    /// Returns whether all blockers of the transaction were released.
+    pub fn is_ready(&self) -> bool {
+        self.commits.iter().all(SurfaceCommit::is_ready)
+    }
+}
+
+impl fmt::Debug for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transaction")
+            .field("surfaces", &self.surfaces().collect::<Vec<_>>())
+            .field(
+                "blockers",
+                &self
+                    .commits
+                    .iter()
+                    .map(|commit| commit.blockers.len())
+                    .sum::<usize>(),
+            )
+            .finish()
+    }
+}
+
+/// The state of a single surface commit.
+pub(super) struct SurfaceCommit {
+    pub(super) entity: Entity,
+    pub(super) surface: WlSurface,
+    pub(super) state: Pending,
+    /// Blockers which were added to this commit.
+    pub(super) blockers: Vec<Box<dyn Blocker>>,
+}
+
+impl SurfaceCommit {
+    fn is_ready(&self) -> bool {
+        self.blockers.iter().all(|blocker| blocker.is_released())
+    }
+}
+
+impl Compositor {
+    /// Adds a blocker to the next commit of a surface.
+    ///
+    /// This may be called from a [`SurfacePreCommit`](super::SurfacePreCommit) system to block the commit
+    /// which is currently being processed. If the surface is a synchronized subsurface, the blocker also holds
+    /// back the commit of the parent which applies it.
+    pub fn add_blocker<State>(ecs: &mut Ecs, surface: &WlSurface, blocker: impl Blocker)
+    where
+        State: EcsAccess,
+    {
+        let data = surface.data::<EntityData>().unwrap();
+        let internal = ecs
+            .world
+            .query_one_mut::<&mut Internal<State>>(data.0)
+            .expect("State type did not match");
+        internal.blockers.push(Box::new(blocker));
+    }
+
+    /// Applies every transaction which is ready, in the order the transactions were created.
+    ///
+    /// This is called after every commit and when a blocker provided by this crate is released. Compositors
+    /// should call this when a blocker of their own may have been released.
+    ///
+    /// Applying a transaction runs post-commit systems and [`CompositorHandler::commit`], which may commit
+    /// surfaces or release blockers themselves. A call made while transactions are being applied only marks
+    /// the queue for another pass by the outer call.
+    pub fn apply_transactions<State>(state: &mut State)
+    where
+        State: CompositorHandler,
+    {
+        let compositor = state.compositor();
+        if compositor.applying {
+            compositor.dirty = true;
+            return;
+        }
+        compositor.applying = true;
+
+        loop {
+            state.compositor().dirty = false;
+            // Surfaces with an earlier transaction that is blocked. Commits of a surface must be applied in order.
+            let mut blocked = HashSet::new();
+            let mut index = 0;
+
+            // Transactions queued while applying are appended, so they are visited in the same pass.
+            while let Some(&entity) = state.compositor().transactions.get(index) {
+                let transaction = state
+                    .ecs()
+                    .world()
+                    .query_one_mut::<&Transaction>(entity)
+                    .expect("Transaction entity was despawned");
+
+                let surfaces = transaction
+                    .commits
+                    .iter()
+                    .map(|commit| commit.entity)
+                    .collect::<Vec<_>>();
+
+                if !transaction.is_ready()
+                    || surfaces.iter().any(|surface| blocked.contains(surface))
+                {
+                    blocked.extend(surfaces);
+                    index += 1;
+                    continue;
+                }
+
+                state.compositor().transactions.remove(index);
+                let transaction = state
+                    .ecs()
+                    .world()
+                    .remove_one::<Transaction>(entity)
+                    .unwrap();
+                let _ = state.ecs().world().despawn(entity);
+
+                for commit in transaction.commits {
+                    apply_commit(state, commit);
+                }
+            }
+
+            // A blocker of a transaction which was already skipped may have been released meanwhile.
+            if !state.compositor().dirty {
+                break;
+            }
+        }
+
+        state.compositor().applying = false;
+    }
+}
+
+/// Takes the pending state of a surface after a commit and queues it in a transaction.
+pub(super) fn commit<State>(state: &mut State, surface: &WlSurface, entity: Entity)
+where
+    State: CompositorHandler,
+{
+    let internal = state
+        .ecs()
+        .world()
+        .query_one_mut::<&mut Internal<State>>(entity)
+        .expect("Surface must be a valid entity if dispatched");
+
+    let pending = internal.pending.take_commit();
+    match &pending.buffer {
+        Some(BufferAssignment::NewBuffer(buffer)) => internal.queued_buffer = Some(buffer.clone()),
+        Some(BufferAssignment::Removed) => internal.queued_buffer = None,
+        None => (),
+    }
+
+    let mut commits = vec![SurfaceCommit {
+        entity,
+        surface: surface.clone(),
+        state: pending,
+        blockers: mem::take(&mut internal.blockers),
+    }];
+    // Commits of synchronized child subsurfaces are applied together with this commit.
+    commits.append(&mut internal.cached);
+
+    if let Some(parent) = sync_parent(state.ecs(), entity) {
+        let parent = state
+            .ecs()
+            .world()
+            .query_one_mut::<&mut Internal<State>>(parent)
+            .expect("Parent surface must be a valid entity");
+        parent.cached.append(&mut commits);
+        return;
+    }
+
+    queue(state, commits);
+}
+
+/// Applies the cached state of a subsurface which became desynchronized.
+///
+/// The commits of the subsurface and its descendants which are cached by its ancestors are moved into a new
+/// transaction. Commits cached by the subsurface itself still wait for its next commit.
+pub(super) fn desync<State>(state: &mut State, entity: Entity)
+where
+    State: CompositorHandler,
+{
+    // A parent higher up the tree may still be synchronized.
+    if sync_parent(state.ecs(), entity).is_some() {
+        return;
+    }
+
+    let mut subtree = HashSet::new();
+    collect_subtree(state.ecs(), entity, &mut subtree);
+
+    let mut commits = Vec::new();
+    let mut current = entity;
+    while let Some(ancestor) = parent(state.ecs(), current) {
+        if let Ok(internal) = state
+            .ecs()
+            .world()
+            .query_one_mut::<&mut Internal<State>>(ancestor)
+        {
+            let (desynced, cached) = mem::take(&mut internal.cached)
+                .into_iter()
+                .partition(|commit| subtree.contains(&commit.entity));
+            internal.cached = cached;
+            commits.push(desynced);
+        }
+
+        current = ancestor;
+    }
+
+    // Commits cached higher up the tree are older.
+    let commits = commits.into_iter().rev().flatten().collect::<Vec<_>>();
+    if !commits.is_empty() {
+        queue(state, commits);
+    }
+}
+
+/// Spawns a transaction for a set of commits and applies every transaction which is ready.
+fn queue<State>(state: &mut State, commits: Vec<SurfaceCommit>)
+where
+    State: CompositorHandler,
+{
+    let transaction = state.ecs().world().spawn((Transaction { commits },));
+    state.compositor().transactions.push(transaction);
+    Compositor::apply_transactions(state);
+}
+
+/// Returns the parent of a subsurface.
+fn parent(ecs: &mut Ecs, entity: Entity) -> Option<Entity> {
+    let subsurface = ecs.world.query_one_mut::<&Subsurface>(entity).ok()?;
+    Some(subsurface.parent.upgrade().ok()?.data::<EntityData>()?.0)
+}
+
+/// Collects a surface and all of its descendant subsurfaces.
+fn collect_subtree(ecs: &mut Ecs, entity: Entity, subtree: &mut HashSet<Entity>) {
+    subtree.insert(entity);
+
+    let children = match ecs.world.query_one_mut::<&SubsurfaceChildren>(entity) {
+        Ok(children) => children.children.clone(),
+        Err(_) => return,
+    };
+    for child in children {
+        collect_subtree(ecs, child, subtree);
+    }
+}
+
+/// Returns the parent of the surface if the surface is a synchronized subsurface.
+///
+/// A subsurface is synchronized if it or any of its ancestors is in synchronized mode.
+fn sync_parent(ecs: &mut Ecs, entity: Entity) -> Option<Entity> {
+    let mut parent = None;
+    let mut current = entity;
+
+    loop {
+        let sync = ecs.world.query_one_mut::<&Subsurface>(current).ok()?.sync;
+        let next = self::parent(ecs, current)?;
+        parent.get_or_insert(next);
+
+        if sync {
+            return parent;
+        }
+
+        current = next;
+    }
+}
+
+/// Applies the state of a single commit to the committed state of a surface.
+fn apply_commit<State>(state: &mut State, commit: SurfaceCommit)
+where
+    State: CompositorHandler,
+{
+    let SurfaceCommit {
+        entity,
+        surface,
+        state: pending,
+        ..
+    } = commit;
+
+    // The surface may have been destroyed while the transaction was blocked.
+    if !surface.is_alive() {
+        return;
+    }
+
+    let (internal, buffer) = state
+        .ecs()
+        .world()
+        .query_one_mut::<(&mut Internal<State>, &mut Buffer)>(entity)
+        .expect("Surface must be a valid entity if alive");
+    let post_commit_systems = internal.post_commit_systems.clone();
+    internal.applying = pending.commit_state;
+
+    buffer.buffer = pending.buffer;
+    match &buffer.buffer {
+        Some(BufferAssignment::NewBuffer(new)) => buffer.current = Some(new.clone()),
+        Some(BufferAssignment::Removed) => buffer.current = None,
+        None => (),
+    }
+    buffer.delta = pending.delta;
+    buffer.scale = pending.scale;
+    buffer.transform = pending.transform;
+
+    let buffer_size = match buffer.current.clone() {
+        Some(buffer) => BufferDimensions::query(state.ecs(), &buffer),
+        None => None,
+    };
+
+    let (surface_damage, frame_callbacks, viewport) = state
+        .ecs()
+        .world()
+        .query_one_mut::<(&mut SurfaceDamage, &mut FrameCallbacks, &mut Viewport)>(entity)
+        .expect("Surface must be a valid entity if alive");
+    frame_callbacks.callbacks.extend(pending.frame_callbacks);
+    *viewport = pending.viewport;
+    surface_damage.commit(
+        pending.damage,
+        pending.scale,
+        pending.transform,
+        viewport,
+        buffer_size,
+    );
+    let counter = surface_damage.current_commit();
+
+    let (opaque_region, input_region) = state
+        .ecs()
+        .world()
+        .query_one_mut::<(&mut OpaqueRegion, &mut InputRegion)>(entity)
+        .expect("Surface must be a valid entity if alive");
+    opaque_region.0 = pending
+        .opaque_region
+        .map(|attributes| attributes.to_region())
+        .unwrap_or_default();
+    input_region.0 = pending
+        .input_region
+        .map(|attributes| attributes.to_region());
+
+    // The positions and stacking order of subsurfaces are applied together with the state of their parent.
+    let children = state
+        .ecs()
+        .world()
+        .query_one_mut::<&mut SubsurfaceChildren>(entity)
+        .expect("Surface must be a valid entity if alive");
+    children.apply_pending();
+    let children = children.children.clone();
+    for child in children {
+        if let Ok(subsurface) = state.ecs().world().query_one_mut::<&mut Subsurface>(child) {
+            if let Some(position) = subsurface.pending_position.take() {
+                subsurface.position = position;
+            }
+        }
+    }
+
+    geometry::update(state.ecs(), &surface, entity, pending.window_geometry);
+
+    for system in post_commit_systems {
+        system(state, &surface)
+    }
+
+    // Drop any extension state which no system took.
+    if let Ok(internal) = state
+        .ecs()
+        .world()
+        .query_one_mut::<&mut Internal<State>>(entity)
+    {
+        internal.applying = Default::default();
+    }
+
+    state.ecs().push_event(CompositorEvent::SurfaceCommitted {
+        surface: surface.clone(),
+        entity,
+        commit: counter,
+    });
+    state.commit(&surface);
+}
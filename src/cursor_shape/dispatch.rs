@@ -0,0 +1,122 @@
+use wayland_server::{
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource, WEnum,
+};
+
+use crate::{
+    protocols::cursor_shape_v1::{
+        wp_cursor_shape_device_v1::{self, WpCursorShapeDeviceV1},
+        wp_cursor_shape_manager_v1::{self, WpCursorShapeManagerV1},
+    },
+    seat::{self, CursorImage},
+    EcsAccess, EntityData,
+};
+
+use super::{CursorShapeDeviceData, CursorShapeManager};
+
+impl<State> GlobalDispatch<WpCursorShapeManagerV1, (), State> for CursorShapeManager
+where
+    State: GlobalDispatch<WpCursorShapeManagerV1, ()>
+        + Dispatch<WpCursorShapeManagerV1, ()>
+        + EcsAccess,
+{
+    fn bind(
+        _state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<WpCursorShapeManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<State> Dispatch<WpCursorShapeManagerV1, (), State> for CursorShapeManager
+where
+    State: Dispatch<WpCursorShapeManagerV1, ()>
+        + Dispatch<WpCursorShapeDeviceV1, CursorShapeDeviceData>
+        + EcsAccess,
+{
+    fn request(
+        _state: &mut State,
+        _client: &Client,
+        _resource: &WpCursorShapeManagerV1,
+        request: wp_cursor_shape_manager_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            wp_cursor_shape_manager_v1::Request::GetPointer {
+                cursor_shape_device,
+                pointer,
+            } => {
+                // The wl_pointer uses the entity data of its seat.
+                let seat = pointer.data::<EntityData>().unwrap().0;
+                data_init.init(
+                    cursor_shape_device,
+                    CursorShapeDeviceData { seat: Some(seat) },
+                );
+            }
+
+            wp_cursor_shape_manager_v1::Request::GetTabletToolV2 {
+                cursor_shape_device,
+                tablet_tool: _,
+            } => {
+                data_init.init(cursor_shape_device, CursorShapeDeviceData { seat: None });
+            }
+
+            wp_cursor_shape_manager_v1::Request::Destroy => {
+                // Cursor shape devices are unaffected by the manager being destroyed.
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<State> Dispatch<WpCursorShapeDeviceV1, CursorShapeDeviceData, State> for CursorShapeManager
+where
+    State: Dispatch<WpCursorShapeDeviceV1, CursorShapeDeviceData> + EcsAccess,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        resource: &WpCursorShapeDeviceV1,
+        request: wp_cursor_shape_device_v1::Request,
+        data: &CursorShapeDeviceData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            wp_cursor_shape_device_v1::Request::SetShape { serial, shape } => {
+                let shape = match shape {
+                    WEnum::Value(shape) => shape,
+                    WEnum::Unknown(_) => {
+                        resource.post_error(
+                            wp_cursor_shape_device_v1::Error::InvalidShape,
+                            "Unknown cursor shape",
+                        );
+                        return;
+                    }
+                };
+
+                if let Some(seat) = data.seat {
+                    seat::set_cursor_image(
+                        state.ecs().world(),
+                        seat,
+                        resource,
+                        serial,
+                        CursorImage::Named(shape),
+                    );
+                }
+            }
+
+            wp_cursor_shape_device_v1::Request::Destroy => {
+                // The cursor shape stays after the device is destroyed.
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! Implementation of the cursor shape protocol.
+//!
+//! Instead of drawing a cursor surface, clients can ask the compositor to draw one of a set of named
+//! [`Shape`]s. The shape is stored in the [`CursorImage`](crate::seat::CursorImage) of the seat, replacing any
+//! cursor surface set using `wl_pointer.set_cursor`. Like cursor surfaces, shapes are only set while the pointer
+//! is over a surface of the client, and only with the serial of the `wl_pointer.enter` event of that surface.
+//!
+//! Tablet tools are not implemented by this crate, so the cursor shape devices of tablet tools ignore their
+//! requests.
+
+mod dispatch;
+#[cfg(test)]
+mod tests;
+
+use hecs::Entity;
+use wayland_server::{DisplayHandle, GlobalDispatch};
+
+use crate::{
+    protocols::cursor_shape_v1::wp_cursor_shape_manager_v1::WpCursorShapeManagerV1, EcsAccess,
+};
+
+pub use crate::protocols::cursor_shape_v1::wp_cursor_shape_device_v1::Shape;
+
+pub struct CursorShapeManager {}
+
+impl CursorShapeManager {
+    pub fn new<State>(display: &mut DisplayHandle) -> Self
+    where
+        State: GlobalDispatch<WpCursorShapeManagerV1, ()> + EcsAccess,
+    {
+        let _global = display.create_global::<State, WpCursorShapeManagerV1, ()>(1, ());
+        Self {}
+    }
+}
+
+/// The data of a `wp_cursor_shape_device_v1`.
+#[derive(Debug, Clone, Copy)]
+pub struct CursorShapeDeviceData {
+    /// The seat entity of the pointer, or [`None`] for tablet tools.
+    seat: Option<Entity>,
+}
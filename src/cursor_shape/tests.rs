@@ -0,0 +1,94 @@
+use crate::{
+    protocols::cursor_shape_v1::wp_cursor_shape_device_v1,
+    seat::CursorImage,
+    testing::{Arg, Fixture},
+};
+
+use super::Shape;
+
+// wl_seat requests
+const GET_POINTER: u16 = 0;
+
+// wl_pointer events
+const ENTER: u16 = 0;
+
+// wp_cursor_shape_manager_v1 requests
+const MANAGER_GET_POINTER: u16 = 1;
+
+// wp_cursor_shape_device_v1 requests
+const SET_SHAPE: u16 = 1;
+
+/// A cursor shape device of a pointer, with the serial of the pointer entering a surface.
+fn entered_device(fixture: &mut Fixture) -> (u32, u32) {
+    let seat = fixture.client.bind("wl_seat", 7);
+    let pointer = fixture.client.new_id();
+    fixture.send(seat, GET_POINTER, &[Arg::NewId(pointer)]);
+
+    let manager = fixture.client.bind("wp_cursor_shape_manager_v1", 1);
+    let device = fixture.client.new_id();
+    fixture.send(
+        manager,
+        MANAGER_GET_POINTER,
+        &[Arg::NewId(device), Arg::Object(pointer)],
+    );
+
+    let surface = fixture.create_surface();
+    fixture.set_pointer_focus(Some(&surface));
+    let events = fixture.events(pointer);
+    assert_eq!(events[0].opcode, ENTER);
+    (device, events[0].uint(0))
+}
+
+fn cursor_image(fixture: &mut Fixture) -> CursorImage {
+    let seat = fixture.seat();
+    *fixture.world().query_one_mut::<&CursorImage>(seat).unwrap()
+}
+
+#[test]
+fn shape_is_set_with_enter_serial() {
+    let mut fixture = Fixture::new();
+    let (device, serial) = entered_device(&mut fixture);
+
+    fixture.send(
+        device,
+        SET_SHAPE,
+        &[Arg::Uint(serial + 1), Arg::Uint(Shape::Text as u32)],
+    );
+    fixture.assert_no_error();
+    assert_eq!(cursor_image(&mut fixture), CursorImage::default());
+
+    fixture.send(
+        device,
+        SET_SHAPE,
+        &[Arg::Uint(serial), Arg::Uint(Shape::Text as u32)],
+    );
+    fixture.assert_no_error();
+    assert_eq!(cursor_image(&mut fixture), CursorImage::Named(Shape::Text));
+}
+
+#[test]
+fn shape_is_ignored_without_pointer_focus() {
+    let mut fixture = Fixture::new();
+    let (device, serial) = entered_device(&mut fixture);
+    fixture.set_pointer_focus(None);
+
+    fixture.send(
+        device,
+        SET_SHAPE,
+        &[Arg::Uint(serial), Arg::Uint(Shape::Pointer as u32)],
+    );
+    fixture.assert_no_error();
+    assert_eq!(cursor_image(&mut fixture), CursorImage::default());
+}
+
+#[test]
+fn unknown_shape_is_error() {
+    let mut fixture = Fixture::new();
+    let (device, serial) = entered_device(&mut fixture);
+
+    fixture.send(device, SET_SHAPE, &[Arg::Uint(serial), Arg::Uint(0)]);
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(wp_cursor_shape_device_v1::Error::InvalidShape as u32)
+    );
+}
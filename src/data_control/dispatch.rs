@@ -0,0 +1,289 @@
+use wayland_backend::server::{ClientId, ObjectId};
+use wayland_protocols::wp::primary_selection::zv1::server::zwp_primary_selection_offer_v1::ZwpPrimarySelectionOfferV1;
+use wayland_protocols_wlr::data_control::v1::server::{
+    zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+    zwlr_data_control_manager_v1::{self, ZwlrDataControlManagerV1},
+    zwlr_data_control_offer_v1::{self, ZwlrDataControlOfferV1},
+    zwlr_data_control_source_v1::{self, ZwlrDataControlSourceV1},
+};
+use wayland_server::{
+    protocol::wl_data_offer::WlDataOffer, Client, DataInit, Dispatch, DisplayHandle,
+    GlobalDispatch, New, Resource,
+};
+
+use crate::{
+    data_device::{
+        offer_selection, replace_selection, source_destroyed, DataDevice, DataDeviceManager,
+        DataSource, SelectionSource, SelectionTarget, SourceObject,
+    },
+    primary_selection::PrimarySelectionDevice,
+    seat::Seat,
+    EntityData,
+};
+
+use super::{
+    selection_changed, DataControlDevice, DataControlHandler, DataControlManager, DataControlOffer,
+    UsedSource,
+};
+
+impl<State> GlobalDispatch<ZwlrDataControlManagerV1, (), State> for DataControlManager
+where
+    State: GlobalDispatch<ZwlrDataControlManagerV1, ()>
+        + Dispatch<ZwlrDataControlManagerV1, ()>
+        + DataControlHandler,
+{
+    fn bind(
+        _state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwlrDataControlManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<State> Dispatch<ZwlrDataControlManagerV1, (), State> for DataControlManager
+where
+    State: Dispatch<ZwlrDataControlManagerV1, ()>
+        + Dispatch<ZwlrDataControlSourceV1, EntityData>
+        + Dispatch<ZwlrDataControlDeviceV1, EntityData>
+        + Dispatch<ZwlrDataControlOfferV1, EntityData>
+        + DataControlHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        _resource: &ZwlrDataControlManagerV1,
+        request: zwlr_data_control_manager_v1::Request,
+        _data: &(),
+        dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwlr_data_control_manager_v1::Request::CreateDataSource { id } => {
+                let entity = state.ecs().world().reserve_entity();
+                let object = data_init.init(id, EntityData(entity));
+                state
+                    .ecs()
+                    .world()
+                    .insert_one(entity, DataSource::new(SourceObject::DataControl(object)))
+                    .expect("Entity was reserved");
+            }
+
+            zwlr_data_control_manager_v1::Request::GetDataDevice { id, seat } => {
+                let seat = Seat::entity_of(&seat);
+                let entity = state.ecs().world().reserve_entity();
+                let device = data_init.init(id, EntityData(entity));
+                let world = state.ecs().world();
+                world
+                    .insert_one(
+                        entity,
+                        DataControlDevice {
+                            object: device.clone(),
+                            seat,
+                        },
+                    )
+                    .expect("Entity was reserved");
+
+                // Unlike other devices, the selections are offered immediately regardless of the keyboard focus.
+                for target in [SelectionTarget::Clipboard, SelectionTarget::Primary] {
+                    offer_selection::<State, DataControlDevice>(
+                        world, dhandle, seat, &device, target,
+                    );
+                }
+
+                DataDeviceManager::add_selection_system::<State>(
+                    state.ecs(),
+                    seat,
+                    selection_changed::<State>,
+                );
+            }
+
+            zwlr_data_control_manager_v1::Request::Destroy => {
+                // Devices and sources are unaffected by the manager being destroyed.
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<State> Dispatch<ZwlrDataControlSourceV1, EntityData, State> for DataControlManager
+where
+    State: Dispatch<ZwlrDataControlSourceV1, EntityData> + DataControlHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        resource: &ZwlrDataControlSourceV1,
+        request: zwlr_data_control_source_v1::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwlr_data_control_source_v1::Request::Offer { mime_type } => {
+                let (source, used) = state
+                    .ecs()
+                    .world()
+                    .query_one_mut::<(&mut DataSource, Option<&UsedSource>)>(data.0)
+                    .expect("Source exists until destroyed");
+
+                if used.is_some() {
+                    resource.post_error(
+                        zwlr_data_control_source_v1::Error::InvalidOffer,
+                        "Source was already used",
+                    );
+                    return;
+                }
+
+                source.add_mime_type(mime_type);
+            }
+
+            zwlr_data_control_source_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        let _ = state.ecs().world().despawn(data.0);
+        source_destroyed::<State, DataDevice>(state, data.0, SelectionTarget::Clipboard);
+        source_destroyed::<State, PrimarySelectionDevice>(state, data.0, SelectionTarget::Primary);
+    }
+}
+
+impl<State> Dispatch<ZwlrDataControlDeviceV1, EntityData, State> for DataControlManager
+where
+    State: Dispatch<ZwlrDataControlDeviceV1, EntityData>
+        + Dispatch<ZwlrDataControlOfferV1, EntityData>
+        + Dispatch<WlDataOffer, EntityData>
+        + Dispatch<ZwpPrimarySelectionOfferV1, EntityData>
+        + DataControlHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        resource: &ZwlrDataControlDeviceV1,
+        request: zwlr_data_control_device_v1::Request,
+        data: &EntityData,
+        dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        let seat = state
+            .ecs()
+            .world()
+            .query_one_mut::<&DataControlDevice>(data.0)
+            .expect("Device exists until destroyed")
+            .seat;
+
+        let (source, target) = match request {
+            zwlr_data_control_device_v1::Request::SetSelection { source } => {
+                (source, SelectionTarget::Clipboard)
+            }
+
+            zwlr_data_control_device_v1::Request::SetPrimarySelection { source } => {
+                (source, SelectionTarget::Primary)
+            }
+
+            zwlr_data_control_device_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+                return;
+            }
+
+            _ => unreachable!(),
+        };
+
+        let source = source.map(|source| source.data::<EntityData>().unwrap().0);
+
+        // A source can only be set as a selection once.
+        if let Some(source) = source {
+            let world = state.ecs().world();
+
+            if world.query_one_mut::<&UsedSource>(source).is_ok() {
+                resource.post_error(
+                    zwlr_data_control_device_v1::Error::UsedSource,
+                    "Source was already used",
+                );
+                return;
+            }
+
+            world
+                .insert_one(source, UsedSource)
+                .expect("Source exists until destroyed");
+        }
+
+        let source = source.map(SelectionSource::Client);
+
+        match target {
+            SelectionTarget::Clipboard => {
+                replace_selection::<State, DataDevice>(state, dhandle, seat, target, source);
+                state.new_selection(seat);
+            }
+
+            SelectionTarget::Primary => {
+                replace_selection::<State, PrimarySelectionDevice>(
+                    state, dhandle, seat, target, source,
+                );
+                state.new_primary_selection(seat);
+            }
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        let _ = state.ecs().world().despawn(data.0);
+    }
+}
+
+impl<State> Dispatch<ZwlrDataControlOfferV1, EntityData, State> for DataControlManager
+where
+    State: Dispatch<ZwlrDataControlOfferV1, EntityData> + DataControlHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        _resource: &ZwlrDataControlOfferV1,
+        request: zwlr_data_control_offer_v1::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwlr_data_control_offer_v1::Request::Receive { mime_type, fd } => {
+                let offer = state
+                    .ecs()
+                    .world()
+                    .query_one_mut::<&DataControlOffer>(data.0)
+                    .expect("Offer exists until destroyed");
+
+                // Mime types which are not offered are ignored, which closes the file descriptor.
+                if !offer.mime_types.contains(&mime_type) {
+                    return;
+                }
+
+                let seat = offer.seat;
+                let source = offer.source.clone();
+                let send_compositor = match offer.target {
+                    SelectionTarget::Clipboard => State::send_selection,
+                    SelectionTarget::Primary => State::send_primary_selection,
+                };
+
+                source.send(state, seat, mime_type, fd, send_compositor);
+            }
+
+            zwlr_data_control_offer_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        let _ = state.ecs().world().despawn(data.0);
+    }
+}
@@ -0,0 +1,153 @@
+//! Implementation of the wlr data control protocol.
+//!
+//! Data control lets privileged clients such as clipboard managers read and replace the clipboard and the
+//! primary selection of a seat without having keyboard focus. It shares the selection model of the
+//! [data device](crate::data_device) and the [primary selection](crate::primary_selection): sources are
+//! entities with a [`DataSource`](crate::data_device::DataSource), and setting a selection through data control
+//! replaces the [`Selection`](crate::data_device::Selection) or
+//! [`PrimarySelection`](crate::primary_selection::PrimarySelection) of the seat.
+//!
+//! Devices and offers are entities with a [`DataControlDevice`] or [`DataControlOffer`]. Every device is told
+//! about every change of the selections of its seat, regardless of the keyboard focus.
+
+mod dispatch;
+
+#[cfg(test)]
+mod tests;
+
+use hecs::Entity;
+use wayland_protocols_wlr::data_control::v1::server::{
+    zwlr_data_control_device_v1::ZwlrDataControlDeviceV1,
+    zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+    zwlr_data_control_offer_v1::ZwlrDataControlOfferV1,
+};
+use wayland_server::{Dispatch, DisplayHandle, GlobalDispatch, Resource};
+
+use crate::{
+    data_device::{
+        offer_selection_to_all, DataDeviceHandler, SelectionDevice, SelectionSource,
+        SelectionTarget,
+    },
+    primary_selection::PrimarySelectionHandler,
+    seat::Seat,
+    EntityData,
+};
+
+pub trait DataControlHandler: DataDeviceHandler + PrimarySelectionHandler {}
+
+pub struct DataControlManager {}
+
+impl DataControlManager {
+    pub fn new<State>(display: &mut DisplayHandle) -> Self
+    where
+        State: GlobalDispatch<ZwlrDataControlManagerV1, ()> + DataControlHandler,
+    {
+        let _global = display.create_global::<State, ZwlrDataControlManagerV1, ()>(2, ());
+        Self {}
+    }
+}
+
+/// A data control device of a client.
+#[derive(Debug)]
+pub struct DataControlDevice {
+    object: ZwlrDataControlDeviceV1,
+    seat: Entity,
+}
+
+impl DataControlDevice {
+    /// The seat entity of the device.
+    pub fn seat(&self) -> Entity {
+        self.seat
+    }
+}
+
+/// A data control offer sent to a client.
+#[derive(Debug)]
+pub struct DataControlOffer {
+    seat: Entity,
+    source: SelectionSource,
+    mime_types: Vec<String>,
+    target: SelectionTarget,
+}
+
+impl DataControlOffer {
+    /// The source of the offered data.
+    pub fn source(&self) -> &SelectionSource {
+        &self.source
+    }
+
+    /// The selection which is offered.
+    pub fn target(&self) -> SelectionTarget {
+        self.target
+    }
+}
+
+/// Marks a data control source which was set as a selection.
+///
+/// A source may only be used once, and its mime types are fixed once it is used.
+struct UsedSource;
+
+impl SelectionDevice for DataControlDevice {
+    type Object = ZwlrDataControlDeviceV1;
+    type Offer = ZwlrDataControlOfferV1;
+    type OfferData = DataControlOffer;
+
+    fn object(&self) -> &ZwlrDataControlDeviceV1 {
+        &self.object
+    }
+
+    fn seat(&self) -> Entity {
+        self.seat
+    }
+
+    fn supports(object: &ZwlrDataControlDeviceV1, target: SelectionTarget) -> bool {
+        // The primary selection was added in version 2.
+        target == SelectionTarget::Clipboard || object.version() >= 2
+    }
+
+    fn offer_data(
+        seat: Entity,
+        source: SelectionSource,
+        mime_types: Vec<String>,
+        target: SelectionTarget,
+    ) -> DataControlOffer {
+        DataControlOffer {
+            seat,
+            source,
+            mime_types,
+            target,
+        }
+    }
+
+    fn send_offer(
+        object: &ZwlrDataControlDeviceV1,
+        offer: &ZwlrDataControlOfferV1,
+        mime_types: &[String],
+    ) {
+        object.data_offer(offer);
+
+        for mime_type in mime_types {
+            offer.offer(mime_type.clone());
+        }
+    }
+
+    fn send_selection(
+        object: &ZwlrDataControlDeviceV1,
+        target: SelectionTarget,
+        offer: Option<&ZwlrDataControlOfferV1>,
+    ) {
+        match target {
+            SelectionTarget::Clipboard => object.selection(offer),
+            SelectionTarget::Primary => object.primary_selection(offer),
+        }
+    }
+}
+
+/// Offers a changed selection to every data control device of the seat.
+fn selection_changed<State>(state: &mut State, seat: Entity, target: SelectionTarget)
+where
+    State: Dispatch<ZwlrDataControlOfferV1, EntityData> + DataControlHandler,
+{
+    let display = Seat::display(state.ecs(), seat);
+    offer_selection_to_all::<State, DataControlDevice>(state.ecs().world(), &display, seat, target);
+}
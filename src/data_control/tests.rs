@@ -0,0 +1,146 @@
+use crate::{
+    data_device::DataDeviceManager,
+    primary_selection::PrimarySelectionManager,
+    testing::{Arg, Event, Fixture, TestSurface},
+};
+
+// zwlr_data_control_manager_v1 and zwp_primary_selection_device_manager_v1 requests
+const CREATE_SOURCE: u16 = 0;
+const GET_DEVICE: u16 = 1;
+
+// zwlr_data_control_source_v1 requests
+const OFFER: u16 = 0;
+const SOURCE_DESTROY: u16 = 1;
+
+// zwlr_data_control_device_v1 requests
+const SET_PRIMARY_SELECTION: u16 = 2;
+
+// zwlr_data_control_device_v1 and zwp_primary_selection_device_v1 events
+const DATA_OFFER: u16 = 0;
+const SELECTION: u16 = 1;
+const PRIMARY_SELECTION: u16 = 3;
+
+/// The objects of a client which has a data control device and a primary selection device.
+struct Client {
+    manager: u32,
+    device: u32,
+    primary_device: u32,
+    surface: TestSurface,
+}
+
+fn setup(fixture: &mut Fixture) -> Client {
+    let seat = fixture.client.bind("wl_seat", 7);
+    let primary_manager = fixture
+        .client
+        .bind("zwp_primary_selection_device_manager_v1", 1);
+    let manager = fixture.client.bind("zwlr_data_control_manager_v1", 2);
+    let surface = fixture.create_surface();
+
+    let primary_device = fixture.client.new_id();
+    fixture.send(
+        primary_manager,
+        GET_DEVICE,
+        &[Arg::NewId(primary_device), Arg::Object(seat)],
+    );
+    let device = fixture.client.new_id();
+    fixture.send(
+        manager,
+        GET_DEVICE,
+        &[Arg::NewId(device), Arg::Object(seat)],
+    );
+    fixture.assert_no_error();
+
+    Client {
+        manager,
+        device,
+        primary_device,
+        surface,
+    }
+}
+
+/// The opcodes of events with their first argument, which is the offer for every event of a device.
+fn opcodes_and_offers(events: &[Event]) -> Vec<(u16, u32)> {
+    events
+        .iter()
+        .map(|event| (event.opcode, event.uint(0)))
+        .collect()
+}
+
+#[test]
+fn selections_are_offered_without_focus() {
+    let mut fixture = Fixture::new();
+    let client = setup(&mut fixture);
+
+    // A new device is told that there is no selection of either kind.
+    let events = fixture.events(client.device);
+    assert_eq!(
+        opcodes_and_offers(&events),
+        vec![(SELECTION, 0), (PRIMARY_SELECTION, 0)]
+    );
+
+    let display = fixture.server.display.handle();
+    let seat = fixture.seat();
+    DataDeviceManager::set_selection(
+        &mut fixture.server.state,
+        &display,
+        seat,
+        vec!["text/plain".into()],
+    );
+    PrimarySelectionManager::set_selection(
+        &mut fixture.server.state,
+        &display,
+        seat,
+        vec!["text/plain".into()],
+    );
+
+    // Every selection event refers to the offer announced before it.
+    let events = fixture.events(client.device);
+    let clipboard = events[0].uint(0);
+    let primary = events[2].uint(0);
+    assert_eq!(
+        opcodes_and_offers(&events),
+        vec![
+            (DATA_OFFER, clipboard),
+            (SELECTION, clipboard),
+            (DATA_OFFER, primary),
+            (PRIMARY_SELECTION, primary)
+        ]
+    );
+}
+
+#[test]
+fn destroyed_source_clears_primary_selection() {
+    let mut fixture = Fixture::new();
+    let client = setup(&mut fixture);
+    let seat = fixture.seat();
+    fixture.set_keyboard_focus(Some(&client.surface));
+    fixture.events(client.device);
+    fixture.events(client.primary_device);
+
+    let source = fixture.client.new_id();
+    fixture.send(client.manager, CREATE_SOURCE, &[Arg::NewId(source)]);
+    fixture.send(source, OFFER, &[Arg::Str("text/plain")]);
+    fixture.send(client.device, SET_PRIMARY_SELECTION, &[Arg::Object(source)]);
+
+    // The primary selection is offered to the focused client and every data control device.
+    let events = fixture.events(client.primary_device);
+    assert_eq!(events.last().unwrap().opcode, SELECTION);
+    assert_ne!(events.last().unwrap().uint(0), 0);
+    let events = fixture.events(client.device);
+    assert_eq!(events.last().unwrap().opcode, PRIMARY_SELECTION);
+    assert_eq!(
+        PrimarySelectionManager::selection_mime_types(&mut fixture.server.state, seat),
+        Some(vec!["text/plain".into()])
+    );
+
+    fixture.send(source, SOURCE_DESTROY, &[]);
+
+    let events = fixture.events(client.primary_device);
+    assert_eq!(opcodes_and_offers(&events), vec![(SELECTION, 0)]);
+    let events = fixture.events(client.device);
+    assert_eq!(opcodes_and_offers(&events), vec![(PRIMARY_SELECTION, 0)]);
+    assert_eq!(
+        PrimarySelectionManager::selection_mime_types(&mut fixture.server.state, seat),
+        None
+    );
+}
@@ -0,0 +1,439 @@
+use wayland_backend::server::{ClientId, ObjectId};
+use wayland_server::{
+    protocol::{
+        wl_data_device::{self, WlDataDevice},
+        wl_data_device_manager::{self, WlDataDeviceManager},
+        wl_data_offer::{self, WlDataOffer},
+        wl_data_source::{self, WlDataSource},
+    },
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource, WEnum,
+};
+
+use crate::{
+    compositor::{AlreadyHasRole, CompositorEvent, Role},
+    seat::{self, KeyboardFocus, Seat},
+    EntityData,
+};
+
+use super::{
+    cancel_drags, cancel_source, offer_selection, offer_selection_to_focus, replace_selection,
+    source_destroyed, DataDevice, DataDeviceHandler, DataDeviceManager, DataOffer, DataSource,
+    Drag, Selection, SelectionSource, SelectionTarget, SourceObject,
+};
+
+impl<State> GlobalDispatch<WlDataDeviceManager, (), State> for DataDeviceManager
+where
+    State: GlobalDispatch<WlDataDeviceManager, ()>
+        + Dispatch<WlDataDeviceManager, ()>
+        + DataDeviceHandler,
+{
+    fn bind(
+        _state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<WlDataDeviceManager>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<State> Dispatch<WlDataDeviceManager, (), State> for DataDeviceManager
+where
+    State: Dispatch<WlDataDeviceManager, ()>
+        + Dispatch<WlDataSource, EntityData>
+        + Dispatch<WlDataDevice, EntityData>
+        + Dispatch<WlDataOffer, EntityData>
+        + DataDeviceHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        _resource: &WlDataDeviceManager,
+        request: wl_data_device_manager::Request,
+        _data: &(),
+        dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            wl_data_device_manager::Request::CreateDataSource { id } => {
+                let entity = state.ecs().world().reserve_entity();
+                let object = data_init.init(id, EntityData(entity));
+                state
+                    .ecs()
+                    .world()
+                    .insert_one(entity, DataSource::new(SourceObject::DataDevice(object)))
+                    .expect("Entity was reserved");
+            }
+
+            wl_data_device_manager::Request::GetDataDevice { id, seat } => {
+                let seat = Seat::entity_of(&seat);
+                let entity = state.ecs().world().reserve_entity();
+                let device = data_init.init(id, EntityData(entity));
+                let world = state.ecs().world();
+                world
+                    .insert_one(
+                        entity,
+                        DataDevice {
+                            object: device.clone(),
+                            seat,
+                        },
+                    )
+                    .expect("Entity was reserved");
+
+                if world.query_one_mut::<&Selection>(seat).is_err() {
+                    world
+                        .insert_one(seat, Selection::default())
+                        .expect("Seat exists while its objects exist");
+                }
+
+                // The selection is offered to the new device immediately if its client is focused.
+                let focused = world
+                    .query_one_mut::<&KeyboardFocus>(seat)
+                    .map(|focus| focus.is_client_focused(&device))
+                    .unwrap_or(false);
+
+                if focused {
+                    offer_selection::<State, DataDevice>(
+                        world,
+                        dhandle,
+                        seat,
+                        &device,
+                        SelectionTarget::Clipboard,
+                    );
+                }
+
+                Seat::add_focus_system::<State>(state.ecs(), seat, focus_changed::<State>);
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Offers the selection to the newly focused client.
+fn focus_changed<State>(state: &mut State, display: &DisplayHandle, seat: hecs::Entity)
+where
+    State: Dispatch<WlDataOffer, EntityData> + DataDeviceHandler,
+{
+    offer_selection_to_focus::<State, DataDevice>(
+        state.ecs().world(),
+        display,
+        seat,
+        SelectionTarget::Clipboard,
+    );
+}
+
+impl<State> Dispatch<WlDataSource, EntityData, State> for DataDeviceManager
+where
+    State: Dispatch<WlDataSource, EntityData> + DataDeviceHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        resource: &WlDataSource,
+        request: wl_data_source::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        let source = state
+            .ecs()
+            .world()
+            .query_one_mut::<&mut DataSource>(data.0)
+            .expect("Data source exists until destroyed");
+
+        match request {
+            wl_data_source::Request::Offer { mime_type } => {
+                source.add_mime_type(mime_type);
+            }
+
+            wl_data_source::Request::SetActions { dnd_actions } => match dnd_actions {
+                WEnum::Value(dnd_actions) => source.dnd_actions = dnd_actions,
+                WEnum::Unknown(dnd_actions) => {
+                    resource.post_error(
+                        wl_data_source::Error::InvalidActionMask,
+                        format!("Invalid actions {dnd_actions:#x}"),
+                    );
+                }
+            },
+
+            wl_data_source::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        let _ = state.ecs().world().despawn(data.0);
+        cancel_drags(state.ecs().world(), data.0);
+        source_destroyed::<State, DataDevice>(state, data.0, SelectionTarget::Clipboard);
+    }
+}
+
+impl<State> Dispatch<WlDataDevice, EntityData, State> for DataDeviceManager
+where
+    State:
+        Dispatch<WlDataDevice, EntityData> + Dispatch<WlDataOffer, EntityData> + DataDeviceHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        resource: &WlDataDevice,
+        request: wl_data_device::Request,
+        data: &EntityData,
+        dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        let seat = state
+            .ecs()
+            .world()
+            .query_one_mut::<&DataDevice>(data.0)
+            .expect("Data device exists until destroyed")
+            .seat;
+
+        match request {
+            wl_data_device::Request::StartDrag {
+                source,
+                origin,
+                icon,
+                serial,
+            } => {
+                let world = state.ecs().world();
+
+                // Only a client holding the implicit grab of the pointer may start a drag.
+                if !seat::is_grab_serial(world, seat, resource, serial) {
+                    cancel_source(
+                        world,
+                        source.map(|source| source.data::<EntityData>().unwrap().0),
+                    );
+                    return;
+                }
+
+                if let Some(icon) = &icon {
+                    let role = world
+                        .query_one_mut::<&mut Role>(icon.data::<EntityData>().unwrap().0)
+                        .expect("Surface must be a valid entity if dispatched");
+
+                    if role.role() != Some(DataDeviceManager::DND_ICON_ROLE) {
+                        if let Err(AlreadyHasRole) = role.set_role(DataDeviceManager::DND_ICON_ROLE)
+                        {
+                            resource.post_error(
+                                wl_data_device::Error::Role,
+                                "Icon surface already has a role",
+                            );
+                            return;
+                        }
+
+                        state.ecs().push_event(CompositorEvent::RoleAssigned {
+                            surface: icon.clone(),
+                            entity: icon.data::<EntityData>().unwrap().0,
+                            role: DataDeviceManager::DND_ICON_ROLE,
+                        });
+                    }
+                }
+
+                // Only one drag can happen on a seat at a time.
+                let world = state.ecs().world();
+                if world.query_one_mut::<&Drag>(seat).is_ok() {
+                    cancel_source(
+                        world,
+                        source.map(|source| source.data::<EntityData>().unwrap().0),
+                    );
+                    return;
+                }
+
+                world
+                    .insert_one(
+                        seat,
+                        Drag {
+                            source: source.map(|source| source.data::<EntityData>().unwrap().0),
+                            origin,
+                            icon,
+                            focus: None,
+                        },
+                    )
+                    .expect("Seat exists while its objects exist");
+
+                state.start_drag(seat);
+            }
+
+            wl_data_device::Request::SetSelection { source, serial } => {
+                // Only the focused client may change the selection, in response to user input.
+                let world = state.ecs().world();
+                let focused = world
+                    .query_one_mut::<&KeyboardFocus>(seat)
+                    .map(|focus| focus.is_client_focused(resource))
+                    .unwrap_or(false);
+
+                if !focused || !seat::is_input_serial(world, seat, resource, serial) {
+                    if let Some(source) = source {
+                        source.cancelled();
+                    }
+                    return;
+                }
+
+                let source = source
+                    .map(|source| SelectionSource::Client(source.data::<EntityData>().unwrap().0));
+                replace_selection::<State, DataDevice>(
+                    state,
+                    dhandle,
+                    seat,
+                    SelectionTarget::Clipboard,
+                    source,
+                );
+                state.new_selection(seat);
+            }
+
+            wl_data_device::Request::Release => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        let _ = state.ecs().world().despawn(data.0);
+    }
+}
+
+impl<State> Dispatch<WlDataOffer, EntityData, State> for DataDeviceManager
+where
+    State: Dispatch<WlDataOffer, EntityData> + DataDeviceHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        resource: &WlDataOffer,
+        request: wl_data_offer::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        let world = state.ecs().world();
+        let offer = world
+            .query_one_mut::<&mut DataOffer>(data.0)
+            .expect("Data offer exists until destroyed");
+
+        match request {
+            wl_data_offer::Request::Accept {
+                serial: _,
+                mime_type,
+            } => {
+                let dnd = match offer.dnd.as_mut() {
+                    Some(dnd) if dnd.active => dnd,
+                    _ => return,
+                };
+
+                dnd.accepted = mime_type.clone();
+                let source = match offer.source {
+                    SelectionSource::Client(source) => source,
+                    SelectionSource::Compositor { .. } => return,
+                };
+
+                if let Ok(DataSource {
+                    object: SourceObject::DataDevice(object),
+                    ..
+                }) = world.query_one_mut::<&DataSource>(source)
+                {
+                    object.target(mime_type);
+                }
+            }
+
+            wl_data_offer::Request::Receive { mime_type, fd } => {
+                // Mime types which are not offered are ignored, which closes the file descriptor.
+                if offer.mime_types.contains(&mime_type) {
+                    let seat = offer.seat;
+                    let source = offer.source.clone();
+                    source.send(state, seat, mime_type, fd, State::send_selection);
+                }
+            }
+
+            wl_data_offer::Request::Finish => {
+                let dropped = offer.dnd.as_ref().map(|dnd| dnd.dropped).unwrap_or(false);
+
+                if !dropped {
+                    resource
+                        .post_error(wl_data_offer::Error::InvalidFinish, "Offer was not dropped");
+                    return;
+                }
+
+                if let SelectionSource::Client(source) = offer.source {
+                    if let Ok(source) = world.query_one_mut::<&DataSource>(source) {
+                        if let Some(object) = source.object.dnd() {
+                            object.dnd_finished();
+                        }
+                    }
+                }
+            }
+
+            wl_data_offer::Request::SetActions {
+                dnd_actions,
+                preferred_action,
+            } => {
+                let (actions, preferred) = match (dnd_actions, preferred_action) {
+                    (WEnum::Value(actions), WEnum::Value(preferred)) => (actions, preferred),
+                    _ => {
+                        resource
+                            .post_error(wl_data_offer::Error::InvalidActionMask, "Invalid actions");
+                        return;
+                    }
+                };
+
+                // The preferred action must be a single action.
+                if preferred.bits().count_ones() > 1 {
+                    resource.post_error(
+                        wl_data_offer::Error::InvalidAction,
+                        format!("Invalid preferred action {preferred:?}"),
+                    );
+                    return;
+                }
+
+                let dnd = match offer.dnd.as_mut() {
+                    Some(dnd) => dnd,
+                    None => {
+                        resource.post_error(
+                            wl_data_offer::Error::InvalidOffer,
+                            "Offer is not for drag and drop",
+                        );
+                        return;
+                    }
+                };
+
+                dnd.actions = actions;
+                dnd.preferred = preferred;
+                let chosen = dnd.choose_action();
+                let active = dnd.active;
+                let source = offer.source.clone();
+
+                if !active {
+                    return;
+                }
+
+                resource.action(chosen);
+
+                if let SelectionSource::Client(source) = source {
+                    if let Ok(source) = world.query_one_mut::<&DataSource>(source) {
+                        if let Some(object) = source.object.dnd() {
+                            object.action(chosen);
+                        }
+                    }
+                }
+            }
+
+            wl_data_offer::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        let _ = state.ecs().world().despawn(data.0);
+    }
+}
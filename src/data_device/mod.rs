@@ -0,0 +1,1044 @@
+//! Implementation of the data device, which provides the clipboard and drag and drop.
+//!
+//! # Entities
+//!
+//! Every `wl_data_source`, `wl_data_device` and `wl_data_offer` is an entity with a [`DataSource`],
+//! [`DataDevice`] or [`DataOffer`] component. Data devices belong to a [seat](crate::seat) entity.
+//!
+//! # Selection
+//!
+//! The clipboard of a seat is stored in the [`Selection`] of the seat entity. The selection is owned either by
+//! the data source of a client or by the compositor, see [`SelectionSource`]. Whenever the selection or the
+//! keyboard focus of the seat changes, the selection is offered to the data devices of the focused client. Only
+//! the focused client may set the selection, using the serial of a recent input event it received.
+//!
+//! The contents of the selection are transferred by the clients themselves through a file descriptor. The
+//! compositor can read the selection using [`DataDeviceManager::request_selection`] and provide its own
+//! selection using [`DataDeviceManager::set_selection`], whose contents are written by
+//! [`DataDeviceHandler::send_selection`].
+//!
+//! Protocols which observe the selection regardless of the keyboard focus add a [`SelectionChanged`] system to
+//! the seat using [`DataDeviceManager::add_selection_system`].
+//!
+//! # Drag and drop
+//!
+//! A drag started by a client is stored in the [`Drag`] of the seat entity. Clients start a drag using the serial
+//! of the implicit grab of the pointer, see [`PointerButtons`](crate::seat::PointerButtons). The icon of the drag
+//! is a surface with the [`DND_ICON_ROLE`](DataDeviceManager::DND_ICON_ROLE). The compositor moves the drag
+//! between surfaces using [`DataDeviceManager::drag_focus`] and ends it with [`DataDeviceManager::drop`] or
+//! [`DataDeviceManager::cancel_drag`].
+
+mod dispatch;
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+    mem,
+    os::unix::io::{AsRawFd, OwnedFd},
+};
+
+use hecs::Entity;
+use smithay::utils::{Logical, Point};
+use wayland_protocols::wp::primary_selection::zv1::server::zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1;
+use wayland_protocols_wlr::data_control::v1::server::zwlr_data_control_source_v1::ZwlrDataControlSourceV1;
+use wayland_server::{
+    protocol::{
+        wl_data_device::WlDataDevice,
+        wl_data_device_manager::{DndAction, WlDataDeviceManager},
+        wl_data_offer::WlDataOffer,
+        wl_data_source::WlDataSource,
+        wl_surface::WlSurface,
+    },
+    Dispatch, DisplayHandle, GlobalDispatch, Resource,
+};
+
+use crate::{
+    next_serial, primary_selection::PrimarySelection, seat::KeyboardFocus, Ecs, EcsAccess,
+    EntityData,
+};
+
+pub trait DataDeviceHandler: EcsAccess {
+    /// Writes the contents of a selection provided by the compositor to a file descriptor.
+    ///
+    /// The file descriptor should be closed once everything was written.
+    fn send_selection(&mut self, seat: Entity, mime_type: String, fd: OwnedFd);
+
+    /// A client started a drag.
+    ///
+    /// The [`Drag`] of the seat entity describes the drag.
+    fn start_drag(&mut self, seat: Entity);
+
+    /// A client changed the selection of a seat.
+    fn new_selection(&mut self, seat: Entity) {
+        let _ = seat;
+    }
+}
+
+pub struct DataDeviceManager {}
+
+impl DataDeviceManager {
+    pub fn new<State>(display: &mut DisplayHandle) -> Self
+    where
+        State: GlobalDispatch<WlDataDeviceManager, ()> + DataDeviceHandler,
+    {
+        let _global = display.create_global::<State, WlDataDeviceManager, ()>(3, ());
+        Self {}
+    }
+
+    pub const DND_ICON_ROLE: &str = "dnd_icon";
+
+    /// Adds a system which is run whenever the clipboard or the primary selection of a seat changes.
+    ///
+    /// This is used by protocols observing the selection regardless of the keyboard focus. Adding the same
+    /// system more than once has no effect.
+    pub fn add_selection_system<State>(ecs: &mut Ecs, seat: Entity, system: SelectionChanged<State>)
+    where
+        State: EcsAccess,
+    {
+        let world = ecs.world();
+
+        if world
+            .query_one_mut::<&SelectionSystems<State>>(seat)
+            .is_err()
+        {
+            world
+                .insert_one(seat, SelectionSystems::<State>(Vec::new()))
+                .expect("Not a seat entity");
+        }
+
+        let systems = world
+            .query_one_mut::<&mut SelectionSystems<State>>(seat)
+            .expect("Selection systems were inserted");
+
+        if !systems.0.contains(&system) {
+            systems.0.push(system);
+        }
+    }
+
+    /// Sets the selection of a seat to contents provided by the compositor.
+    ///
+    /// Clients request the contents using [`DataDeviceHandler::send_selection`].
+    pub fn set_selection<State>(
+        state: &mut State,
+        display: &DisplayHandle,
+        seat: Entity,
+        mime_types: Vec<String>,
+    ) where
+        State: Dispatch<WlDataOffer, EntityData> + DataDeviceHandler,
+    {
+        replace_selection::<State, DataDevice>(
+            state,
+            display,
+            seat,
+            SelectionTarget::Clipboard,
+            Some(SelectionSource::Compositor { mime_types }),
+        );
+    }
+
+    /// Clears the selection of a seat.
+    pub fn clear_selection<State>(state: &mut State, display: &DisplayHandle, seat: Entity)
+    where
+        State: Dispatch<WlDataOffer, EntityData> + DataDeviceHandler,
+    {
+        replace_selection::<State, DataDevice>(
+            state,
+            display,
+            seat,
+            SelectionTarget::Clipboard,
+            None,
+        );
+    }
+
+    /// The mime types of the selection of a seat, or [`None`] if there is no selection.
+    pub fn selection_mime_types<State>(state: &mut State, seat: Entity) -> Option<Vec<String>>
+    where
+        State: DataDeviceHandler,
+    {
+        let world = state.ecs().world();
+        let source = world.query_one_mut::<&Selection>(seat).ok()?.0.clone()?;
+        Some(source.mime_types(world))
+    }
+
+    /// Requests the contents of the selection of a seat to be written to a file descriptor.
+    pub fn request_selection<State>(
+        state: &mut State,
+        seat: Entity,
+        mime_type: String,
+        fd: OwnedFd,
+    ) -> Result<(), SelectionError>
+    where
+        State: DataDeviceHandler,
+    {
+        let world = state.ecs().world();
+        let source = world
+            .query_one_mut::<&Selection>(seat)
+            .ok()
+            .and_then(|selection| selection.0.clone())
+            .ok_or(SelectionError::NoSelection)?;
+
+        if !source.mime_types(world).contains(&mime_type) {
+            return Err(SelectionError::InvalidMimeType);
+        }
+
+        source.send(state, seat, mime_type, fd, State::send_selection);
+        Ok(())
+    }
+
+    /// Moves a drag to a surface, or away from any surface.
+    ///
+    /// `focus` is the surface below the pointer and the location of the pointer relative to the surface.
+    pub fn drag_focus<State>(
+        state: &mut State,
+        display: &DisplayHandle,
+        seat: Entity,
+        focus: Option<(&WlSurface, Point<f64, Logical>)>,
+        time: u32,
+    ) where
+        State: Dispatch<WlDataOffer, EntityData> + DataDeviceHandler,
+    {
+        let world = state.ecs().world();
+        let drag = match world.query_one_mut::<&mut Drag>(seat) {
+            Ok(drag) => drag,
+            Err(_) => return,
+        };
+
+        if let (Some(current), Some((surface, location))) = (&drag.focus, focus) {
+            if current.surface == *surface {
+                for device in &current.devices {
+                    device.motion(time, location.x, location.y);
+                }
+                return;
+            }
+        }
+
+        let source = drag.source;
+        let origin = drag.origin.clone();
+        let previous = drag.focus.take();
+
+        if let Some(previous) = previous {
+            leave(world, previous);
+        }
+
+        let (surface, location) = match focus {
+            Some(focus) => focus,
+            None => return,
+        };
+
+        // Drags without a source are only offered to the client which started the drag.
+        if source.is_none() && !surface.id().same_client_as(&origin.id()) {
+            return;
+        }
+
+        let (mime_types, source_actions) = source
+            .and_then(|source| world.query_one_mut::<&DataSource>(source).ok())
+            .map(|source| (source.mime_types.clone(), source.dnd_actions))
+            .unwrap_or_else(|| (Vec::new(), DndAction::empty()));
+
+        let devices = seat_devices::<DataDevice>(world, seat)
+            .into_iter()
+            .filter(|device| device.id().same_client_as(&surface.id()))
+            .collect::<Vec<_>>();
+
+        let serial = next_serial();
+        let mut offers = Vec::new();
+
+        for device in &devices {
+            let offer = source.and_then(|source| {
+                create_offer::<State>(
+                    world,
+                    display,
+                    device,
+                    seat,
+                    SelectionSource::Client(source),
+                    &mime_types,
+                    Some(source_actions),
+                )
+            });
+
+            if let Some((entity, offer)) = &offer {
+                if offer.version() >= 3 {
+                    offer.source_actions(source_actions);
+                }
+                offers.push(*entity);
+            }
+
+            device.enter(
+                serial,
+                surface,
+                location.x,
+                location.y,
+                offer.as_ref().map(|(_, offer)| offer),
+            );
+        }
+
+        if let Ok(drag) = world.query_one_mut::<&mut Drag>(seat) {
+            drag.focus = Some(DragFocus {
+                surface: surface.clone(),
+                devices,
+                offers,
+            });
+        }
+    }
+
+    /// Drops the drag of a seat on the surface it is over.
+    ///
+    /// The drag is cancelled if the surface did not accept it.
+    pub fn drop<State>(state: &mut State, seat: Entity)
+    where
+        State: DataDeviceHandler,
+    {
+        let world = state.ecs().world();
+        let drag = match world.remove_one::<Drag>(seat) {
+            Ok(drag) => drag,
+            Err(_) => return,
+        };
+
+        let focus = match drag.focus {
+            Some(focus) => focus,
+            None => {
+                cancel_source(world, drag.source);
+                return;
+            }
+        };
+
+        let source = match drag.source {
+            Some(source) => source,
+            // Drags without a source are handled by the client itself.
+            None => {
+                for device in &focus.devices {
+                    device.drop();
+                }
+                return;
+            }
+        };
+
+        let accepted = focus.offers.iter().any(|&offer| {
+            world
+                .query_one_mut::<&DataOffer>(offer)
+                .ok()
+                .and_then(|offer| offer.dnd.as_ref())
+                .map(|dnd| dnd.accepted.is_some() && !dnd.chosen.is_empty())
+                .unwrap_or(false)
+        });
+
+        if !accepted {
+            leave(world, focus);
+            cancel_source(world, Some(source));
+            return;
+        }
+
+        for &offer in &focus.offers {
+            if let Some(dnd) = world
+                .query_one_mut::<&mut DataOffer>(offer)
+                .ok()
+                .and_then(|offer| offer.dnd.as_mut())
+            {
+                dnd.dropped = true;
+            }
+        }
+
+        for device in &focus.devices {
+            device.drop();
+        }
+
+        if let Ok(source) = world.query_one_mut::<&DataSource>(source) {
+            if let Some(object) = source.object.dnd() {
+                object.dnd_drop_performed();
+            }
+        }
+    }
+
+    /// Cancels the drag of a seat.
+    pub fn cancel_drag<State>(state: &mut State, seat: Entity)
+    where
+        State: DataDeviceHandler,
+    {
+        let world = state.ecs().world();
+
+        if let Ok(drag) = world.remove_one::<Drag>(seat) {
+            if let Some(focus) = drag.focus {
+                leave(world, focus);
+            }
+
+            cancel_source(world, drag.source);
+        }
+    }
+}
+
+/// An error when requesting the contents of a selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionError {
+    /// The seat has no selection.
+    NoSelection,
+    /// The selection is not available in the requested mime type.
+    InvalidMimeType,
+}
+
+/// A selection of a seat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SelectionTarget {
+    /// The clipboard, which is the [`Selection`] of the seat.
+    Clipboard,
+    /// The [primary selection](crate::primary_selection).
+    Primary,
+}
+
+/// A system that is run after a selection of a seat changed.
+pub type SelectionChanged<State> = fn(state: &mut State, seat: Entity, target: SelectionTarget);
+
+/// The selection systems of a seat.
+struct SelectionSystems<State: EcsAccess>(Vec<SelectionChanged<State>>);
+
+/// The owner of a selection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelectionSource {
+    /// The data source of a client, which is an entity with a [`DataSource`].
+    Client(Entity),
+
+    /// Contents provided by the compositor, which are written by [`DataDeviceHandler::send_selection`].
+    Compositor { mime_types: Vec<String> },
+}
+
+impl SelectionSource {
+    pub(crate) fn mime_types(&self, world: &mut hecs::World) -> Vec<String> {
+        match self {
+            Self::Client(entity) => world
+                .query_one_mut::<&DataSource>(*entity)
+                .map(|source| source.mime_types.clone())
+                .unwrap_or_default(),
+            Self::Compositor { mime_types } => mime_types.clone(),
+        }
+    }
+
+    /// Asks the owner of the selection to write its contents to a file descriptor.
+    ///
+    /// Contents provided by the compositor are written by `send_compositor`, which is the handler method of the
+    /// selection, such as [`DataDeviceHandler::send_selection`].
+    pub(crate) fn send<State: EcsAccess>(
+        &self,
+        state: &mut State,
+        seat: Entity,
+        mime_type: String,
+        fd: OwnedFd,
+        send_compositor: fn(&mut State, Entity, String, OwnedFd),
+    ) {
+        match self {
+            Self::Client(entity) => send_client_source(state.ecs().world(), *entity, mime_type, fd),
+            Self::Compositor { .. } => send_compositor(state, seat, mime_type, fd),
+        }
+    }
+}
+
+/// The clipboard of a seat.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selection(Option<SelectionSource>);
+
+impl Selection {
+    pub fn source(&self) -> Option<&SelectionSource> {
+        self.0.as_ref()
+    }
+}
+
+/// A data source of a client.
+///
+/// Besides `wl_data_source`, sources of the other selection protocols, the
+/// [primary selection](crate::primary_selection) and [data control](crate::data_control), are data sources.
+#[derive(Debug)]
+pub struct DataSource {
+    object: SourceObject,
+    mime_types: Vec<String>,
+    dnd_actions: DndAction,
+}
+
+impl DataSource {
+    pub(crate) fn new(object: SourceObject) -> Self {
+        Self {
+            object,
+            mime_types: Vec::new(),
+            dnd_actions: DndAction::empty(),
+        }
+    }
+
+    pub(crate) fn add_mime_type(&mut self, mime_type: String) {
+        if !self.mime_types.contains(&mime_type) {
+            self.mime_types.push(mime_type);
+        }
+    }
+
+    pub fn mime_types(&self) -> &[String] {
+        &self.mime_types
+    }
+
+    /// The drag and drop actions the source supports.
+    pub fn dnd_actions(&self) -> DndAction {
+        self.dnd_actions
+    }
+}
+
+/// The protocol object of a data source.
+#[derive(Debug)]
+pub(crate) enum SourceObject {
+    DataDevice(WlDataSource),
+    PrimarySelection(ZwpPrimarySelectionSourceV1),
+    DataControl(ZwlrDataControlSourceV1),
+}
+
+impl SourceObject {
+    fn send(&self, mime_type: String, fd: &OwnedFd) {
+        match self {
+            Self::DataDevice(object) => object.send(mime_type, fd.as_raw_fd()),
+            Self::PrimarySelection(object) => object.send(mime_type, fd.as_raw_fd()),
+            Self::DataControl(object) => object.send(mime_type, fd.as_raw_fd()),
+        }
+    }
+
+    fn cancelled(&self) {
+        match self {
+            Self::DataDevice(object) => object.cancelled(),
+            Self::PrimarySelection(object) => object.cancelled(),
+            Self::DataControl(object) => object.cancelled(),
+        }
+    }
+
+    /// The `wl_data_source` if the source supports drag and drop events, which were added in version 3.
+    fn dnd(&self) -> Option<&WlDataSource> {
+        match self {
+            Self::DataDevice(object) if object.version() >= 3 => Some(object),
+            _ => None,
+        }
+    }
+}
+
+/// A data device of a client.
+#[derive(Debug)]
+pub struct DataDevice {
+    object: WlDataDevice,
+    seat: Entity,
+}
+
+impl DataDevice {
+    /// The seat entity of the data device.
+    pub fn seat(&self) -> Entity {
+        self.seat
+    }
+}
+
+impl SelectionDevice for DataDevice {
+    type Object = WlDataDevice;
+    type Offer = WlDataOffer;
+    type OfferData = DataOffer;
+
+    fn object(&self) -> &WlDataDevice {
+        &self.object
+    }
+
+    fn seat(&self) -> Entity {
+        self.seat
+    }
+
+    fn offer_data(
+        seat: Entity,
+        source: SelectionSource,
+        mime_types: Vec<String>,
+        _target: SelectionTarget,
+    ) -> DataOffer {
+        DataOffer {
+            seat,
+            source,
+            mime_types,
+            dnd: None,
+        }
+    }
+
+    fn send_offer(object: &WlDataDevice, offer: &WlDataOffer, mime_types: &[String]) {
+        object.data_offer(offer);
+
+        for mime_type in mime_types {
+            offer.offer(mime_type.clone());
+        }
+    }
+
+    fn send_selection(
+        object: &WlDataDevice,
+        _target: SelectionTarget,
+        offer: Option<&WlDataOffer>,
+    ) {
+        object.selection(offer);
+    }
+}
+
+/// A data offer sent to a client.
+#[derive(Debug)]
+pub struct DataOffer {
+    seat: Entity,
+    source: SelectionSource,
+    mime_types: Vec<String>,
+    /// The state of drag and drop, if this offer is for a drag.
+    dnd: Option<DndOffer>,
+}
+
+impl DataOffer {
+    /// The source of the offered data.
+    pub fn source(&self) -> &SelectionSource {
+        &self.source
+    }
+}
+
+#[derive(Debug)]
+struct DndOffer {
+    source_actions: DndAction,
+    actions: DndAction,
+    preferred: DndAction,
+    /// The action chosen from the actions of the source and the destination.
+    chosen: DndAction,
+    /// The mime type accepted by the destination.
+    accepted: Option<String>,
+    dropped: bool,
+    /// Whether the drag is still over the surface of the offer.
+    active: bool,
+}
+
+impl DndOffer {
+    /// Chooses the action from the actions supported by both sides, preferring the action of the destination.
+    fn choose_action(&mut self) -> DndAction {
+        let available = self.source_actions & self.actions;
+
+        self.chosen = if available.contains(self.preferred) && !self.preferred.is_empty() {
+            self.preferred
+        } else {
+            [DndAction::Copy, DndAction::Move, DndAction::Ask]
+                .into_iter()
+                .find(|&action| available.contains(action))
+                .unwrap_or(DndAction::empty())
+        };
+
+        self.chosen
+    }
+}
+
+/// A drag started by a client.
+#[derive(Debug)]
+pub struct Drag {
+    /// The data source entity, or [`None`] if the drag is only within the client.
+    source: Option<Entity>,
+    origin: WlSurface,
+    icon: Option<WlSurface>,
+    focus: Option<DragFocus>,
+}
+
+impl Drag {
+    /// The entity of the data source which is dragged.
+    ///
+    /// This is [`None`] for drags which are only within the client which started the drag.
+    pub fn source(&self) -> Option<Entity> {
+        self.source
+    }
+
+    /// The surface the drag was started from.
+    pub fn origin(&self) -> &WlSurface {
+        &self.origin
+    }
+
+    /// The icon surface of the drag, which has the [`DND_ICON_ROLE`](DataDeviceManager::DND_ICON_ROLE).
+    pub fn icon(&self) -> Option<&WlSurface> {
+        self.icon.as_ref()
+    }
+
+    /// The surface the drag is over.
+    pub fn focus(&self) -> Option<&WlSurface> {
+        self.focus.as_ref().map(|focus| &focus.surface)
+    }
+}
+
+#[derive(Debug)]
+struct DragFocus {
+    surface: WlSurface,
+    devices: Vec<WlDataDevice>,
+    /// Offer entities sent to the devices.
+    offers: Vec<Entity>,
+}
+
+/// Creates an offer entity for a drag and announces the offer to a data device.
+fn create_offer<State>(
+    world: &mut hecs::World,
+    display: &DisplayHandle,
+    device: &WlDataDevice,
+    seat: Entity,
+    source: SelectionSource,
+    mime_types: &[String],
+    source_actions: Option<DndAction>,
+) -> Option<(Entity, WlDataOffer)>
+where
+    State: Dispatch<WlDataOffer, EntityData> + 'static,
+{
+    let entity = world.reserve_entity();
+    let offer = match display.get_client(device.id()).and_then(|client| {
+        client.create_resource::<WlDataOffer, EntityData, State>(
+            display,
+            device.version(),
+            EntityData(entity),
+        )
+    }) {
+        Ok(offer) => offer,
+        // The client is disconnecting.
+        Err(_) => {
+            let _ = world.despawn(entity);
+            return None;
+        }
+    };
+
+    DataDevice::send_offer(device, &offer, mime_types);
+
+    world
+        .insert_one(
+            entity,
+            DataOffer {
+                seat,
+                source,
+                mime_types: mime_types.to_vec(),
+                dnd: source_actions.map(|source_actions| DndOffer {
+                    source_actions,
+                    actions: DndAction::empty(),
+                    preferred: DndAction::empty(),
+                    chosen: DndAction::empty(),
+                    accepted: None,
+                    dropped: false,
+                    active: true,
+                }),
+            },
+        )
+        .expect("Entity was reserved");
+
+    Some((entity, offer))
+}
+
+/// A device of a selection protocol, which is offered the selections of its seat.
+///
+/// This is implemented by the device components of the data device, the [primary
+/// selection](crate::primary_selection) and [data control](crate::data_control), which offer selections the
+/// same way.
+pub(crate) trait SelectionDevice: hecs::Component {
+    type Object: Resource + Clone;
+    type Offer: Resource + 'static;
+    /// The component of the offer entities sent to the device.
+    type OfferData: hecs::Component;
+
+    fn object(&self) -> &Self::Object;
+
+    /// The seat entity of the device.
+    fn seat(&self) -> Entity;
+
+    /// Whether a selection can be offered to the device.
+    fn supports(object: &Self::Object, target: SelectionTarget) -> bool {
+        let _ = (object, target);
+        true
+    }
+
+    fn offer_data(
+        seat: Entity,
+        source: SelectionSource,
+        mime_types: Vec<String>,
+        target: SelectionTarget,
+    ) -> Self::OfferData;
+
+    /// Announces a new offer and its mime types to the device.
+    fn send_offer(object: &Self::Object, offer: &Self::Offer, mime_types: &[String]);
+
+    /// Tells the device the selection of a target changed.
+    fn send_selection(object: &Self::Object, target: SelectionTarget, offer: Option<&Self::Offer>);
+}
+
+/// The source of a selection of a seat.
+pub(crate) fn selection_source(
+    world: &mut hecs::World,
+    seat: Entity,
+    target: SelectionTarget,
+) -> Option<SelectionSource> {
+    match target {
+        SelectionTarget::Clipboard => world
+            .query_one_mut::<&Selection>(seat)
+            .ok()
+            .and_then(|selection| selection.0.clone()),
+        SelectionTarget::Primary => world
+            .query_one_mut::<&PrimarySelection>(seat)
+            .ok()
+            .and_then(|selection| selection.0.clone()),
+    }
+}
+
+/// Replaces the source of a selection of a seat and returns the previous source.
+fn set_selection_source(
+    world: &mut hecs::World,
+    seat: Entity,
+    target: SelectionTarget,
+    source: Option<SelectionSource>,
+) -> Option<SelectionSource> {
+    // The selections are only inserted once they are used.
+    match target {
+        SelectionTarget::Clipboard => match world.query_one_mut::<&mut Selection>(seat) {
+            Ok(selection) => mem::replace(&mut selection.0, source),
+            Err(_) => {
+                world
+                    .insert_one(seat, Selection(source))
+                    .expect("Not a seat entity");
+                None
+            }
+        },
+        SelectionTarget::Primary => match world.query_one_mut::<&mut PrimarySelection>(seat) {
+            Ok(selection) => mem::replace(&mut selection.0, source),
+            Err(_) => {
+                world
+                    .insert_one(seat, PrimarySelection(source))
+                    .expect("Not a seat entity");
+                None
+            }
+        },
+    }
+}
+
+/// The devices of a seat.
+fn seat_devices<D: SelectionDevice>(world: &mut hecs::World, seat: Entity) -> Vec<D::Object> {
+    world
+        .query_mut::<&D>()
+        .into_iter()
+        .filter(|(_, device)| device.seat() == seat)
+        .map(|(_, device)| device.object().clone())
+        .collect()
+}
+
+/// Offers a selection of a seat to a device.
+pub(crate) fn offer_selection<State, D>(
+    world: &mut hecs::World,
+    display: &DisplayHandle,
+    seat: Entity,
+    device: &D::Object,
+    target: SelectionTarget,
+) where
+    State: Dispatch<D::Offer, EntityData> + 'static,
+    D: SelectionDevice,
+{
+    if !D::supports(device, target) {
+        return;
+    }
+
+    let offer = selection_source(world, seat, target).and_then(|source| {
+        let mime_types = source.mime_types(world);
+        let entity = world.reserve_entity();
+        let offer = match display.get_client(device.id()).and_then(|client| {
+            client.create_resource::<D::Offer, EntityData, State>(
+                display,
+                device.version(),
+                EntityData(entity),
+            )
+        }) {
+            Ok(offer) => offer,
+            // The client is disconnecting.
+            Err(_) => {
+                let _ = world.despawn(entity);
+                return None;
+            }
+        };
+
+        D::send_offer(device, &offer, &mime_types);
+
+        world
+            .insert_one(entity, D::offer_data(seat, source, mime_types, target))
+            .expect("Entity was reserved");
+
+        Some(offer)
+    });
+
+    D::send_selection(device, target, offer.as_ref());
+}
+
+/// Offers a selection of a seat to the devices of the focused client.
+pub(crate) fn offer_selection_to_focus<State, D>(
+    world: &mut hecs::World,
+    display: &DisplayHandle,
+    seat: Entity,
+    target: SelectionTarget,
+) where
+    State: Dispatch<D::Offer, EntityData> + 'static,
+    D: SelectionDevice,
+{
+    let focus = match world.query_one_mut::<&KeyboardFocus>(seat) {
+        Ok(focus) => focus.clone(),
+        Err(_) => return,
+    };
+
+    for device in seat_devices::<D>(world, seat) {
+        if focus.is_client_focused(&device) {
+            offer_selection::<State, D>(world, display, seat, &device, target);
+        }
+    }
+}
+
+/// Offers a selection of a seat to every device of the seat, regardless of the keyboard focus.
+pub(crate) fn offer_selection_to_all<State, D>(
+    world: &mut hecs::World,
+    display: &DisplayHandle,
+    seat: Entity,
+    target: SelectionTarget,
+) where
+    State: Dispatch<D::Offer, EntityData> + 'static,
+    D: SelectionDevice,
+{
+    for device in seat_devices::<D>(world, seat) {
+        offer_selection::<State, D>(world, display, seat, &device, target);
+    }
+}
+
+/// Replaces a selection of a seat, cancelling the previous data source.
+///
+/// The selection is offered to the devices `D` of the focused client.
+pub(crate) fn replace_selection<State, D>(
+    state: &mut State,
+    display: &DisplayHandle,
+    seat: Entity,
+    target: SelectionTarget,
+    source: Option<SelectionSource>,
+) where
+    State: Dispatch<D::Offer, EntityData> + EcsAccess,
+    D: SelectionDevice,
+{
+    let world = state.ecs().world();
+    let previous = set_selection_source(world, seat, target, source.clone());
+
+    if previous == source {
+        return;
+    }
+
+    if let Some(SelectionSource::Client(previous)) = previous {
+        cancel_source(world, Some(previous));
+    }
+
+    offer_selection_to_focus::<State, D>(world, display, seat, target);
+    selection_changed(state, seat, target);
+}
+
+/// Removes a destroyed data source from a selection of every seat.
+///
+/// The devices `D` of the focused client are told that there is no selection anymore.
+pub(crate) fn source_destroyed<State, D>(state: &mut State, source: Entity, target: SelectionTarget)
+where
+    State: EcsAccess,
+    D: SelectionDevice,
+{
+    let world = state.ecs().world();
+    let source = Some(SelectionSource::Client(source));
+
+    let clear = |seat: Entity, selection: &mut Option<SelectionSource>| {
+        (*selection == source).then(|| {
+            *selection = None;
+            seat
+        })
+    };
+
+    let seats = match target {
+        SelectionTarget::Clipboard => world
+            .query_mut::<&mut Selection>()
+            .into_iter()
+            .filter_map(|(seat, selection)| clear(seat, &mut selection.0))
+            .collect::<Vec<_>>(),
+        SelectionTarget::Primary => world
+            .query_mut::<&mut PrimarySelection>()
+            .into_iter()
+            .filter_map(|(seat, selection)| clear(seat, &mut selection.0))
+            .collect::<Vec<_>>(),
+    };
+
+    for &seat in &seats {
+        let focus = match world.query_one_mut::<&KeyboardFocus>(seat) {
+            Ok(focus) => focus.clone(),
+            Err(_) => continue,
+        };
+
+        for device in seat_devices::<D>(world, seat) {
+            if focus.is_client_focused(&device) {
+                D::send_selection(&device, target, None);
+            }
+        }
+    }
+
+    for seat in seats {
+        selection_changed(state, seat, target);
+    }
+}
+
+/// Ends the drags of a destroyed data source.
+pub(crate) fn cancel_drags(world: &mut hecs::World, source: Entity) {
+    let dragging = world
+        .query_mut::<&Drag>()
+        .into_iter()
+        .filter(|(_, drag)| drag.source == Some(source))
+        .map(|(seat, _)| seat)
+        .collect::<Vec<_>>();
+
+    for seat in dragging {
+        if let Ok(Drag {
+            focus: Some(focus), ..
+        }) = world.remove_one::<Drag>(seat)
+        {
+            leave(world, focus);
+        }
+    }
+}
+
+/// Runs the selection systems of a seat.
+pub(crate) fn selection_changed<State: EcsAccess>(
+    state: &mut State,
+    seat: Entity,
+    target: SelectionTarget,
+) {
+    let systems = match state
+        .ecs()
+        .world()
+        .query_one_mut::<&SelectionSystems<State>>(seat)
+    {
+        Ok(systems) => systems.0.clone(),
+        Err(_) => return,
+    };
+
+    for system in systems {
+        system(state, seat, target);
+    }
+}
+
+/// Tells the devices a drag was over that it left.
+fn leave(world: &mut hecs::World, focus: DragFocus) {
+    for device in &focus.devices {
+        device.leave();
+    }
+
+    for offer in focus.offers {
+        if let Some(dnd) = world
+            .query_one_mut::<&mut DataOffer>(offer)
+            .ok()
+            .and_then(|offer| offer.dnd.as_mut())
+        {
+            dnd.active = false;
+        }
+    }
+}
+
+/// Asks the data source of a client to write its contents to a file descriptor.
+pub(crate) fn send_client_source(
+    world: &mut hecs::World,
+    source: Entity,
+    mime_type: String,
+    fd: OwnedFd,
+) {
+    // The source may have been destroyed, which closes the file descriptor.
+    if let Ok(source) = world.query_one_mut::<&DataSource>(source) {
+        source.object.send(mime_type, &fd);
+    }
+}
+
+/// Tells a data source it is no longer used.
+pub(crate) fn cancel_source(world: &mut hecs::World, source: Option<Entity>) {
+    if let Some(source) = source.and_then(|source| world.query_one_mut::<&DataSource>(source).ok())
+    {
+        source.object.cancelled();
+    }
+}
@@ -0,0 +1,213 @@
+use wayland_server::protocol::{wl_keyboard, wl_pointer};
+
+use crate::testing::{Arg, Fixture, TestSurface};
+
+use super::{DataDeviceManager, Drag};
+
+// wl_seat requests
+const GET_KEYBOARD: u16 = 1;
+
+// wl_data_device_manager requests
+const CREATE_DATA_SOURCE: u16 = 0;
+const GET_DATA_DEVICE: u16 = 1;
+
+// wl_data_source requests
+const OFFER: u16 = 0;
+
+// wl_data_device requests
+const START_DRAG: u16 = 0;
+const SET_SELECTION: u16 = 1;
+
+// wl_data_source events
+const CANCELLED: u16 = 2;
+
+// wl_data_device events
+const DATA_OFFER: u16 = 0;
+const SELECTION: u16 = 5;
+
+// wl_keyboard events
+const ENTER: u16 = 1;
+const LEAVE: u16 = 2;
+
+/// The objects a client needs to take part in data transfers.
+struct Client {
+    manager: u32,
+    keyboard: u32,
+    device: u32,
+    surface: TestSurface,
+}
+
+fn setup(fixture: &mut Fixture) -> Client {
+    let seat = fixture.client.bind("wl_seat", 7);
+    let manager = fixture.client.bind("wl_data_device_manager", 3);
+    let surface = fixture.create_surface();
+
+    let keyboard = fixture.client.new_id();
+    fixture.send(seat, GET_KEYBOARD, &[Arg::NewId(keyboard)]);
+    let device = fixture.client.new_id();
+    fixture.send(
+        manager,
+        GET_DATA_DEVICE,
+        &[Arg::NewId(device), Arg::Object(seat)],
+    );
+    fixture.events(keyboard);
+
+    Client {
+        manager,
+        keyboard,
+        device,
+        surface,
+    }
+}
+
+fn create_source(fixture: &mut Fixture, client: &Client, mime_type: &str) -> u32 {
+    let source = fixture.client.new_id();
+    fixture.send(client.manager, CREATE_DATA_SOURCE, &[Arg::NewId(source)]);
+    fixture.send(source, OFFER, &[Arg::Str(mime_type)]);
+    source
+}
+
+fn set_selection(fixture: &mut Fixture, client: &Client, source: u32, serial: u32) {
+    fixture.send(
+        client.device,
+        SET_SELECTION,
+        &[Arg::Object(source), Arg::Uint(serial)],
+    );
+    fixture.assert_no_error();
+}
+
+fn start_drag(fixture: &mut Fixture, client: &Client, source: u32, serial: u32) {
+    fixture.send(
+        client.device,
+        START_DRAG,
+        &[
+            Arg::Object(source),
+            Arg::Object(client.surface.id),
+            Arg::Object(0),
+            Arg::Uint(serial),
+        ],
+    );
+    fixture.assert_no_error();
+}
+
+fn selection(fixture: &mut Fixture) -> Option<Vec<String>> {
+    let seat = fixture.seat();
+    DataDeviceManager::selection_mime_types(&mut fixture.server.state, seat)
+}
+
+fn is_cancelled(fixture: &mut Fixture, source: u32) -> bool {
+    fixture.opcodes(source).contains(&CANCELLED)
+}
+
+fn press_key(fixture: &mut Fixture) -> u32 {
+    fixture.key(30, wl_keyboard::KeyState::Pressed).unwrap()
+}
+
+#[test]
+fn selection_is_sent_before_keyboard_enter() {
+    let mut fixture = Fixture::new();
+    let client = setup(&mut fixture);
+    let display = fixture.server.display.handle();
+    let seat = fixture.seat();
+    DataDeviceManager::set_selection(
+        &mut fixture.server.state,
+        &display,
+        seat,
+        vec!["text/plain".into()],
+    );
+
+    fixture.set_keyboard_focus(Some(&client.surface));
+
+    let (device, keyboard) = (client.device, client.keyboard);
+    let order = fixture
+        .client
+        .events()
+        .iter()
+        .filter(|event| event.object == device || event.object == keyboard)
+        .map(|event| (event.object, event.opcode))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        order[..3],
+        [(device, DATA_OFFER), (device, SELECTION), (keyboard, ENTER)]
+    );
+
+    fixture.client.take_events(keyboard);
+    fixture.set_keyboard_focus(None);
+    assert_eq!(fixture.opcodes(keyboard), vec![LEAVE]);
+}
+
+#[test]
+fn selection_with_input_serial_is_accepted() {
+    let mut fixture = Fixture::new();
+    let client = setup(&mut fixture);
+    fixture.set_keyboard_focus(Some(&client.surface));
+
+    // The serial of the enter event is valid.
+    let enter = fixture.client.take_events(client.keyboard)[0].uint(0);
+    let source = create_source(&mut fixture, &client, "text/plain");
+    set_selection(&mut fixture, &client, source, enter);
+    assert_eq!(selection(&mut fixture), Some(vec!["text/plain".into()]));
+
+    // So is the serial of the last key.
+    let serial = press_key(&mut fixture);
+    let source = create_source(&mut fixture, &client, "text/html");
+    set_selection(&mut fixture, &client, source, serial);
+    assert_eq!(selection(&mut fixture), Some(vec!["text/html".into()]));
+}
+
+#[test]
+fn selection_with_stale_serial_is_ignored() {
+    let mut fixture = Fixture::new();
+    let client = setup(&mut fixture);
+    fixture.set_keyboard_focus(Some(&client.surface));
+    let serial = press_key(&mut fixture);
+    press_key(&mut fixture);
+
+    let source = create_source(&mut fixture, &client, "text/plain");
+    set_selection(&mut fixture, &client, source, serial);
+
+    assert!(is_cancelled(&mut fixture, source));
+    assert_eq!(selection(&mut fixture), None);
+}
+
+#[test]
+fn selection_without_focus_is_ignored() {
+    let mut fixture = Fixture::new();
+    let client = setup(&mut fixture);
+    fixture.set_keyboard_focus(Some(&client.surface));
+    let serial = press_key(&mut fixture);
+    fixture.set_keyboard_focus(None);
+
+    let source = create_source(&mut fixture, &client, "text/plain");
+    set_selection(&mut fixture, &client, source, serial);
+
+    assert!(is_cancelled(&mut fixture, source));
+    assert_eq!(selection(&mut fixture), None);
+}
+
+#[test]
+fn drag_requires_implicit_grab_serial() {
+    let mut fixture = Fixture::new();
+    let client = setup(&mut fixture);
+    let seat = fixture.seat();
+    fixture.set_pointer_focus(Some(&client.surface));
+
+    let grab = fixture
+        .button(0x110, wl_pointer::ButtonState::Pressed)
+        .unwrap();
+    fixture.button(0x110, wl_pointer::ButtonState::Released);
+
+    // The grab ended with the release.
+    let source = create_source(&mut fixture, &client, "text/plain");
+    start_drag(&mut fixture, &client, source, grab);
+    assert!(is_cancelled(&mut fixture, source));
+    assert!(fixture.world().query_one_mut::<&Drag>(seat).is_err());
+
+    let grab = fixture
+        .button(0x110, wl_pointer::ButtonState::Pressed)
+        .unwrap();
+    let source = create_source(&mut fixture, &client, "text/plain");
+    start_drag(&mut fixture, &client, source, grab);
+    assert!(!is_cancelled(&mut fixture, source));
+    assert!(fixture.world().query_one_mut::<&Drag>(seat).is_ok());
+}
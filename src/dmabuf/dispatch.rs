@@ -0,0 +1,384 @@
+use std::{collections::HashSet, sync::Mutex};
+
+use hecs::Entity;
+use wayland_backend::server::{ClientId, ObjectId};
+use wayland_protocols::wp::linux_dmabuf::zv1::server::{
+    zwp_linux_buffer_params_v1::{self, ZwpLinuxBufferParamsV1},
+    zwp_linux_dmabuf_feedback_v1::{self, ZwpLinuxDmabufFeedbackV1},
+    zwp_linux_dmabuf_v1::{self, ZwpLinuxDmabufV1},
+};
+use wayland_server::{
+    protocol::wl_buffer::WlBuffer, Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New,
+    Resource, WEnum,
+};
+
+use crate::{compositor::BufferDimensions, EntityData};
+
+use super::{
+    validate, Dmabuf, DmabufBuffer, DmabufFeedbackData, DmabufGlobalData, DmabufHandler,
+    DmabufParams, DmabufParamsData, DmabufPlane, DmabufSurfaceFeedback,
+};
+
+impl<State> GlobalDispatch<ZwpLinuxDmabufV1, DmabufGlobalData, State> for Dmabuf
+where
+    State: GlobalDispatch<ZwpLinuxDmabufV1, DmabufGlobalData>
+        + Dispatch<ZwpLinuxDmabufV1, DmabufGlobalData>
+        + DmabufHandler,
+{
+    fn bind(
+        _state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwpLinuxDmabufV1>,
+        global_data: &DmabufGlobalData,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        let dmabuf = data_init.init(resource, global_data.clone());
+
+        // Since version 4 formats are only advertised through feedback.
+        if dmabuf.version() >= 4 {
+            return;
+        }
+
+        let formats = &global_data.default_feedback.inner.formats;
+
+        if dmabuf.version() >= 3 {
+            for format in formats {
+                dmabuf.modifier(
+                    format.format,
+                    (format.modifier >> 32) as u32,
+                    format.modifier as u32,
+                );
+            }
+        } else {
+            let formats = formats
+                .iter()
+                .map(|format| format.format)
+                .collect::<HashSet<_>>();
+
+            for format in formats {
+                dmabuf.format(format);
+            }
+        }
+    }
+}
+
+impl<State> Dispatch<ZwpLinuxDmabufV1, DmabufGlobalData, State> for Dmabuf
+where
+    State: Dispatch<ZwpLinuxDmabufV1, DmabufGlobalData>
+        + Dispatch<ZwpLinuxBufferParamsV1, DmabufParamsData>
+        + Dispatch<ZwpLinuxDmabufFeedbackV1, DmabufFeedbackData>
+        + DmabufHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        _resource: &ZwpLinuxDmabufV1,
+        request: zwp_linux_dmabuf_v1::Request,
+        data: &DmabufGlobalData,
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwp_linux_dmabuf_v1::Request::Destroy => {
+                // Objects created from the global are unaffected by the global being destroyed.
+            }
+
+            zwp_linux_dmabuf_v1::Request::CreateParams { params_id } => {
+                data_init.init(
+                    params_id,
+                    DmabufParamsData {
+                        default_feedback: data.default_feedback.clone(),
+                        inner: Mutex::new(DmabufParams::default()),
+                    },
+                );
+            }
+
+            zwp_linux_dmabuf_v1::Request::GetDefaultFeedback { id } => {
+                let feedback = data_init.init(
+                    id,
+                    DmabufFeedbackData {
+                        default_feedback: data.default_feedback.clone(),
+                        surface: None,
+                    },
+                );
+                data.default_feedback.send(&feedback);
+            }
+
+            zwp_linux_dmabuf_v1::Request::GetSurfaceFeedback { id, surface } => {
+                let entity = surface.data::<EntityData>().unwrap().0;
+                let feedback = data_init.init(
+                    id,
+                    DmabufFeedbackData {
+                        default_feedback: data.default_feedback.clone(),
+                        surface: Some(entity),
+                    },
+                );
+
+                let world = state.ecs().world();
+                if world
+                    .query_one_mut::<&DmabufSurfaceFeedback>(entity)
+                    .is_err()
+                {
+                    world
+                        .insert_one(entity, DmabufSurfaceFeedback::default())
+                        .expect("Surface must be a valid entity if dispatched");
+                }
+
+                let surface_feedback = world
+                    .query_one_mut::<&mut DmabufSurfaceFeedback>(entity)
+                    .unwrap();
+                surface_feedback
+                    .feedback
+                    .as_ref()
+                    .unwrap_or(&data.default_feedback)
+                    .send(&feedback);
+                surface_feedback.objects.push(feedback);
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<State> Dispatch<ZwpLinuxDmabufFeedbackV1, DmabufFeedbackData, State> for Dmabuf
+where
+    State: Dispatch<ZwpLinuxDmabufFeedbackV1, DmabufFeedbackData> + DmabufHandler,
+{
+    fn request(
+        _state: &mut State,
+        _client: &Client,
+        _resource: &ZwpLinuxDmabufFeedbackV1,
+        request: zwp_linux_dmabuf_feedback_v1::Request,
+        _data: &DmabufFeedbackData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwp_linux_dmabuf_feedback_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(
+        state: &mut State,
+        _client: ClientId,
+        resource: ObjectId,
+        data: &DmabufFeedbackData,
+    ) {
+        if let Some(entity) = data.surface {
+            if let Ok(surface_feedback) = state
+                .ecs()
+                .world()
+                .query_one_mut::<&mut DmabufSurfaceFeedback>(entity)
+            {
+                surface_feedback
+                    .objects
+                    .retain(|object| object.id() != resource);
+            }
+        }
+    }
+}
+
+impl<State> Dispatch<ZwpLinuxBufferParamsV1, DmabufParamsData, State> for Dmabuf
+where
+    State: Dispatch<ZwpLinuxBufferParamsV1, DmabufParamsData>
+        + Dispatch<WlBuffer, EntityData>
+        + DmabufHandler,
+{
+    fn request(
+        state: &mut State,
+        client: &Client,
+        params: &ZwpLinuxBufferParamsV1,
+        request: zwp_linux_buffer_params_v1::Request,
+        data: &DmabufParamsData,
+        dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwp_linux_buffer_params_v1::Request::Destroy => {
+                // all is handled by our destructor
+            }
+
+            zwp_linux_buffer_params_v1::Request::Add {
+                fd,
+                plane_idx,
+                offset,
+                stride,
+                modifier_hi,
+                modifier_lo,
+            } => {
+                let mut guard = data.inner.lock().unwrap();
+
+                if guard.used {
+                    params.post_error(
+                        zwp_linux_buffer_params_v1::Error::AlreadyUsed,
+                        "Params were already used",
+                    );
+                    return;
+                }
+
+                if plane_idx >= 4 {
+                    params.post_error(
+                        zwp_linux_buffer_params_v1::Error::PlaneIdx,
+                        format!("Plane index {plane_idx} is out of bounds"),
+                    );
+                    return;
+                }
+
+                if guard
+                    .planes
+                    .iter()
+                    .any(|plane| plane.plane_idx == plane_idx)
+                {
+                    params.post_error(
+                        zwp_linux_buffer_params_v1::Error::PlaneSet,
+                        format!("Plane {plane_idx} was already set"),
+                    );
+                    return;
+                }
+
+                guard.planes.push(DmabufPlane {
+                    fd,
+                    plane_idx,
+                    offset,
+                    stride,
+                    modifier: ((modifier_hi as u64) << 32) | modifier_lo as u64,
+                });
+            }
+
+            zwp_linux_buffer_params_v1::Request::Create {
+                width,
+                height,
+                format,
+                flags,
+            } => {
+                let dmabuf =
+                    match create_dmabuf(state, params, data, width, height, format, flags, false) {
+                        Some(dmabuf) => dmabuf,
+                        None => return,
+                    };
+
+                let entity = state.ecs().world().reserve_entity();
+                let buffer = match client.create_resource::<WlBuffer, EntityData, State>(
+                    dhandle,
+                    1,
+                    EntityData(entity),
+                ) {
+                    Ok(buffer) => buffer,
+                    // The client disconnected.
+                    Err(_) => {
+                        let _ = state.ecs().world().despawn(entity);
+                        return;
+                    }
+                };
+
+                insert_buffer(state, entity, dmabuf);
+                params.created(&buffer);
+            }
+
+            zwp_linux_buffer_params_v1::Request::CreateImmed {
+                buffer_id,
+                width,
+                height,
+                format,
+                flags,
+            } => {
+                let dmabuf =
+                    match create_dmabuf(state, params, data, width, height, format, flags, true) {
+                        Some(dmabuf) => dmabuf,
+                        None => return,
+                    };
+
+                // The entity is only reserved once the dmabuf is valid, since initializing the buffer cannot
+                // fail.
+                let entity = state.ecs().world().reserve_entity();
+                data_init.init(buffer_id, EntityData(entity));
+                insert_buffer(state, entity, dmabuf);
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Validates the params and lets the renderer import the dmabuf.
+///
+/// Returns [`None`] if the dmabuf could not be created, after posting a protocol error or sending the failed
+/// event. Buffers created immediately cannot fail gracefully, so a failed import is a protocol error.
+#[allow(clippy::too_many_arguments)]
+fn create_dmabuf<State>(
+    state: &mut State,
+    params: &ZwpLinuxBufferParamsV1,
+    data: &DmabufParamsData,
+    width: i32,
+    height: i32,
+    format: u32,
+    flags: WEnum<zwp_linux_buffer_params_v1::Flags>,
+    immediate: bool,
+) -> Option<DmabufBuffer>
+where
+    State: DmabufHandler,
+{
+    let flags = match flags {
+        WEnum::Value(flags) => flags,
+        WEnum::Unknown(flags) => zwp_linux_buffer_params_v1::Flags::from_bits_truncate(flags),
+    };
+
+    // Clients may allocate buffers using formats which are only advertised in the feedback of a surface.
+    let surface_feedback = state
+        .ecs()
+        .world()
+        .query_mut::<&DmabufSurfaceFeedback>()
+        .into_iter()
+        .filter_map(|(_, surface_feedback)| surface_feedback.feedback.clone())
+        .collect::<Vec<_>>();
+    let supports = |format| {
+        data.default_feedback.supports(format)
+            || surface_feedback
+                .iter()
+                .any(|feedback| feedback.supports(format))
+    };
+
+    let mut guard = data.inner.lock().unwrap();
+    let dmabuf = match validate(&mut guard, supports, width, height, format, flags) {
+        Ok(dmabuf) => dmabuf,
+        Err((error, message)) => {
+            params.post_error(error, message);
+            return None;
+        }
+    };
+    drop(guard);
+
+    if state.dmabuf_imported(&dmabuf).is_err() {
+        if immediate {
+            params.post_error(
+                zwp_linux_buffer_params_v1::Error::InvalidWlBuffer,
+                "Failed to import the dmabuf",
+            );
+        } else {
+            params.failed();
+        }
+
+        return None;
+    }
+
+    Some(dmabuf)
+}
+
+fn insert_buffer<State: DmabufHandler>(state: &mut State, entity: Entity, dmabuf: DmabufBuffer) {
+    state
+        .ecs()
+        .world()
+        .insert(
+            entity,
+            (
+                BufferDimensions::new((dmabuf.width, dmabuf.height).into()),
+                dmabuf,
+            ),
+        )
+        .expect("Entity was reserved");
+}
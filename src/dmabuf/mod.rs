@@ -0,0 +1,406 @@
+//! Implementation of the linux-dmabuf protocol.
+//!
+//! The `zwp_linux_dmabuf_v1` global allows clients to create
+//! [`WlBuffer`](wayland_server::protocol::wl_buffer::WlBuffer)s from dmabufs. Every buffer is an
+//! entity with a [`DmabufBuffer`] and a [`BufferDimensions`](crate::compositor::BufferDimensions).
+//!
+//! # Validation
+//!
+//! The parameters of a buffer are validated before the buffer is created: all planes must be set, use the same
+//! modifier and lie inside of the dmabuf file, and the format and modifier must be advertised in the default
+//! feedback or the feedback of a surface. Afterwards [`DmabufHandler::dmabuf_imported`] is called, which allows the renderer to veto the
+//! buffer if it cannot import it.
+//!
+//! # Feedback
+//!
+//! Version 4 of the protocol replaces the list of formats with feedback, a table of formats grouped in tranches
+//! of preference. The default feedback is specified when creating the global and the feedback of a surface may
+//! be changed using [`Dmabuf::set_surface_feedback`], for example to prefer formats which can be scanned out
+//! when the surface is fullscreen.
+
+mod dispatch;
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    io,
+    os::unix::io::{AsRawFd, OwnedFd},
+    sync::{Arc, Mutex},
+};
+
+use hecs::Entity;
+use wayland_protocols::wp::linux_dmabuf::zv1::server::{
+    zwp_linux_buffer_params_v1,
+    zwp_linux_dmabuf_feedback_v1::{self, ZwpLinuxDmabufFeedbackV1},
+    zwp_linux_dmabuf_v1::ZwpLinuxDmabufV1,
+};
+use wayland_server::{protocol::wl_surface::WlSurface, DisplayHandle, GlobalDispatch, Resource};
+
+use crate::{create_sealed_file, Ecs, EcsAccess, EntityData};
+
+pub trait DmabufHandler: EcsAccess {
+    /// Called when a client created a dmabuf buffer which passed validation.
+    ///
+    /// Returning [`Err`] rejects the buffer, for example if the renderer is unable to import the dmabuf.
+    fn dmabuf_imported(&mut self, dmabuf: &DmabufBuffer) -> Result<(), ImportError>;
+}
+
+/// The renderer could not import a dmabuf.
+#[derive(Debug)]
+pub struct ImportError;
+
+pub struct Dmabuf {}
+
+impl Dmabuf {
+    pub fn new<State>(display: &mut DisplayHandle, default_feedback: DmabufFeedback) -> Self
+    where
+        State: GlobalDispatch<ZwpLinuxDmabufV1, DmabufGlobalData> + DmabufHandler,
+    {
+        let _global = display
+            .create_global::<State, ZwpLinuxDmabufV1, _>(4, DmabufGlobalData { default_feedback });
+
+        Self {}
+    }
+
+    /// Sets the feedback sent to clients for a surface.
+    ///
+    /// Passing [`None`] resets the feedback of the surface to the default feedback.
+    pub fn set_surface_feedback(
+        ecs: &mut Ecs,
+        surface: &WlSurface,
+        feedback: Option<DmabufFeedback>,
+    ) {
+        let entity = surface.data::<EntityData>().unwrap().0;
+
+        match ecs
+            .world
+            .query_one_mut::<&mut DmabufSurfaceFeedback>(entity)
+        {
+            Ok(surface_feedback) => {
+                surface_feedback.feedback = feedback;
+
+                for object in &surface_feedback.objects {
+                    let data = object.data::<DmabufFeedbackData>().unwrap();
+                    surface_feedback
+                        .feedback
+                        .as_ref()
+                        .unwrap_or(&data.default_feedback)
+                        .send(object);
+                }
+            }
+
+            // No client has requested feedback for the surface yet, but may do so later.
+            Err(_) => {
+                let _ = ecs.world.insert_one(
+                    entity,
+                    DmabufSurfaceFeedback {
+                        feedback,
+                        objects: Vec::new(),
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// A buffer created from a dmabuf.
+///
+/// This can be queried from the entity of a [`WlBuffer`](wayland_server::protocol::wl_buffer::WlBuffer)
+/// created using linux-dmabuf.
+#[derive(Debug)]
+pub struct DmabufBuffer {
+    width: i32,
+    height: i32,
+    format: u32,
+    flags: zwp_linux_buffer_params_v1::Flags,
+    planes: Vec<DmabufPlane>,
+}
+
+impl DmabufBuffer {
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    /// The fourcc code of the format.
+    pub fn format(&self) -> u32 {
+        self.format
+    }
+
+    /// The modifier shared by all planes.
+    pub fn modifier(&self) -> u64 {
+        self.planes[0].modifier
+    }
+
+    pub fn flags(&self) -> zwp_linux_buffer_params_v1::Flags {
+        self.flags
+    }
+
+    /// The planes of the buffer, ordered by plane index.
+    pub fn planes(&self) -> &[DmabufPlane] {
+        &self.planes
+    }
+}
+
+/// A plane of a [`DmabufBuffer`].
+#[derive(Debug)]
+pub struct DmabufPlane {
+    pub fd: OwnedFd,
+    pub plane_idx: u32,
+    pub offset: u32,
+    pub stride: u32,
+    pub modifier: u64,
+}
+
+/// A format and modifier pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DmabufFormat {
+    /// The fourcc code of the format.
+    pub format: u32,
+    pub modifier: u64,
+}
+
+/// A group of formats with the same preference in [`DmabufFeedback`].
+#[derive(Debug, Clone)]
+pub struct DmabufTranche {
+    /// The device the buffers should be allocated on.
+    pub target_device: u64,
+
+    /// Whether buffers using these formats may be scanned out directly.
+    pub scanout: bool,
+
+    pub formats: Vec<DmabufFormat>,
+}
+
+/// Feedback describing which formats a client should allocate buffers with.
+///
+/// Cloning the feedback is cheap, the format table is shared.
+#[derive(Debug, Clone)]
+pub struct DmabufFeedback {
+    inner: Arc<FeedbackInner>,
+}
+
+#[derive(Debug)]
+struct FeedbackInner {
+    main_device: u64,
+    tranches: Vec<DmabufTranche>,
+    /// The format table shared with clients.
+    table: File,
+    table_size: u32,
+    /// Indices into the format table for each tranche.
+    indices: Vec<Vec<u8>>,
+    formats: HashSet<DmabufFormat>,
+}
+
+impl DmabufFeedback {
+    /// Creates feedback from tranches in order of preference, most preferred first.
+    ///
+    /// This creates a file containing the format table which is shared with clients.
+    pub fn new(main_device: u64, tranches: Vec<DmabufTranche>) -> io::Result<Self> {
+        let mut formats = Vec::<DmabufFormat>::new();
+        let mut indices = Vec::with_capacity(tranches.len());
+
+        for tranche in &tranches {
+            let mut tranche_indices = Vec::new();
+
+            for format in &tranche.formats {
+                let index = match formats.iter().position(|f| f == format) {
+                    Some(index) => index,
+                    None => {
+                        formats.push(*format);
+                        formats.len() - 1
+                    }
+                };
+
+                let index = u16::try_from(index)
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Too many formats"))?;
+                tranche_indices.extend_from_slice(&index.to_ne_bytes());
+            }
+
+            indices.push(tranche_indices);
+        }
+
+        // Each entry of the table is a 32-bit format, 32 bits of padding and a 64-bit modifier.
+        let mut table = Vec::with_capacity(formats.len() * 16);
+        for format in &formats {
+            table.extend_from_slice(&format.format.to_ne_bytes());
+            table.extend_from_slice(&[0; 4]);
+            table.extend_from_slice(&format.modifier.to_ne_bytes());
+        }
+
+        let file = create_sealed_file(c"smithay-ecs-dmabuf-format-table", &table)?;
+
+        Ok(Self {
+            inner: Arc::new(FeedbackInner {
+                main_device,
+                tranches,
+                table: file,
+                table_size: table.len() as u32,
+                indices,
+                formats: formats.into_iter().collect(),
+            }),
+        })
+    }
+
+    pub fn main_device(&self) -> u64 {
+        self.inner.main_device
+    }
+
+    pub fn tranches(&self) -> &[DmabufTranche] {
+        &self.inner.tranches
+    }
+
+    /// Returns whether the format and modifier are part of any tranche.
+    pub fn supports(&self, format: DmabufFormat) -> bool {
+        self.inner.formats.contains(&format)
+    }
+
+    fn send(&self, feedback: &ZwpLinuxDmabufFeedbackV1) {
+        let inner = &self.inner;
+
+        feedback.format_table(inner.table.as_raw_fd(), inner.table_size);
+        feedback.main_device(inner.main_device.to_ne_bytes().to_vec());
+
+        for (tranche, indices) in inner.tranches.iter().zip(&inner.indices) {
+            feedback.tranche_target_device(tranche.target_device.to_ne_bytes().to_vec());
+            feedback.tranche_formats(indices.clone());
+            feedback.tranche_flags(if tranche.scanout {
+                zwp_linux_dmabuf_feedback_v1::TrancheFlags::Scanout
+            } else {
+                zwp_linux_dmabuf_feedback_v1::TrancheFlags::empty()
+            });
+            feedback.tranche_done();
+        }
+
+        feedback.done();
+    }
+}
+
+/// Global data of the `zwp_linux_dmabuf_v1` global.
+#[derive(Debug, Clone)]
+pub struct DmabufGlobalData {
+    default_feedback: DmabufFeedback,
+}
+
+/// User data of a `zwp_linux_buffer_params_v1`.
+#[derive(Debug)]
+pub struct DmabufParamsData {
+    default_feedback: DmabufFeedback,
+    inner: Mutex<DmabufParams>,
+}
+
+#[derive(Debug, Default)]
+struct DmabufParams {
+    /// Whether a buffer was already created from the params.
+    used: bool,
+    planes: Vec<DmabufPlane>,
+}
+
+/// User data of a `zwp_linux_dmabuf_feedback_v1`.
+#[derive(Debug)]
+pub struct DmabufFeedbackData {
+    default_feedback: DmabufFeedback,
+    /// The surface entity if this is surface feedback.
+    surface: Option<Entity>,
+}
+
+/// Feedback of a surface and the feedback objects created for the surface.
+#[derive(Debug, Default)]
+struct DmabufSurfaceFeedback {
+    /// The feedback set by the compositor, or [`None`] to use the default feedback.
+    feedback: Option<DmabufFeedback>,
+    objects: Vec<ZwpLinuxDmabufFeedbackV1>,
+}
+
+/// Validates the planes of a buffer and creates the buffer.
+///
+/// `supports` returns whether a format is advertised in any feedback. Returns the error to post on the params
+/// object if validation failed.
+fn validate(
+    params: &mut DmabufParams,
+    supports: impl Fn(DmabufFormat) -> bool,
+    width: i32,
+    height: i32,
+    format: u32,
+    flags: zwp_linux_buffer_params_v1::Flags,
+) -> Result<DmabufBuffer, (zwp_linux_buffer_params_v1::Error, String)> {
+    use zwp_linux_buffer_params_v1::Error;
+
+    if params.used {
+        return Err((Error::AlreadyUsed, "Params were already used".into()));
+    }
+    params.used = true;
+
+    let mut planes = std::mem::take(&mut params.planes);
+    planes.sort_by_key(|plane| plane.plane_idx);
+
+    if planes.is_empty()
+        || planes
+            .iter()
+            .enumerate()
+            .any(|(index, plane)| plane.plane_idx != index as u32)
+    {
+        return Err((Error::Incomplete, "Missing planes".into()));
+    }
+
+    let modifier = planes[0].modifier;
+    if planes.iter().any(|plane| plane.modifier != modifier) {
+        return Err((
+            Error::InvalidFormat,
+            "All planes must use the same modifier".into(),
+        ));
+    }
+
+    if !supports(DmabufFormat { format, modifier }) {
+        return Err((
+            Error::InvalidFormat,
+            format!("Format {format:#x} with modifier {modifier:#x} is not supported"),
+        ));
+    }
+
+    if width < 1 || height < 1 {
+        return Err((
+            Error::InvalidDimensions,
+            format!("Invalid buffer size {width}x{height}"),
+        ));
+    }
+
+    for plane in &planes {
+        let offset = plane.offset as u64;
+        let stride = plane.stride as u64;
+
+        // The size of the file is not known if the fd is not seekable.
+        let size = match unsafe { libc::lseek(plane.fd.as_raw_fd(), 0, libc::SEEK_END) } {
+            -1 => continue,
+            size => size as u64,
+        };
+
+        // Only the first plane is known to have as many rows as the buffer. Other planes may be subsampled.
+        let end = if plane.plane_idx == 0 {
+            offset + stride * height as u64
+        } else {
+            offset + stride
+        };
+
+        if offset >= size || end > size {
+            return Err((
+                Error::OutOfBounds,
+                format!("Plane {} is outside of the dmabuf", plane.plane_idx),
+            ));
+        }
+    }
+
+    Ok(DmabufBuffer {
+        width,
+        height,
+        format,
+        flags,
+        planes,
+    })
+}
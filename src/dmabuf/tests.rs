@@ -0,0 +1,158 @@
+use std::os::unix::io::{AsRawFd, OwnedFd};
+
+use wayland_protocols::wp::linux_dmabuf::zv1::server::zwp_linux_buffer_params_v1;
+
+use crate::testing::{dmabuf_feedback, memfd, Arg, Fixture, ARGB8888, XRGB8888};
+
+use super::{Dmabuf, DmabufBuffer};
+
+// zwp_linux_dmabuf_v1 requests
+const CREATE_PARAMS: u16 = 1;
+
+// zwp_linux_buffer_params_v1 requests
+const ADD: u16 = 1;
+const CREATE: u16 = 2;
+const CREATE_IMMED: u16 = 3;
+
+// zwp_linux_buffer_params_v1 events
+const CREATED: u16 = 0;
+const FAILED: u16 = 1;
+
+/// Creates params with a single linear plane backed by a memfd standing in for a dmabuf.
+fn params(fixture: &mut Fixture, fd: &OwnedFd, offset: u32, stride: u32) -> u32 {
+    let dmabuf = fixture.client.bind("zwp_linux_dmabuf_v1", 3);
+    let params = fixture.client.new_id();
+    fixture.send(dmabuf, CREATE_PARAMS, &[Arg::NewId(params)]);
+    fixture.send(
+        params,
+        ADD,
+        &[
+            Arg::Fd(fd.as_raw_fd()),
+            Arg::Uint(0),
+            Arg::Uint(offset),
+            Arg::Uint(stride),
+            Arg::Uint(0),
+            Arg::Uint(0),
+        ],
+    );
+    params
+}
+
+fn create(fixture: &mut Fixture, params: u32, width: i32, height: i32, format: u32) {
+    fixture.send(
+        params,
+        CREATE,
+        &[
+            Arg::Int(width),
+            Arg::Int(height),
+            Arg::Uint(format),
+            Arg::Uint(0),
+        ],
+    );
+}
+
+fn buffers(fixture: &mut Fixture) -> Vec<(i32, i32, u32)> {
+    fixture
+        .world()
+        .query_mut::<&DmabufBuffer>()
+        .into_iter()
+        .map(|(_, buffer)| (buffer.width(), buffer.height(), buffer.format()))
+        .collect()
+}
+
+#[test]
+fn create_buffer() {
+    let mut fixture = Fixture::new();
+    let fd = memfd(4096);
+
+    let params = params(&mut fixture, &fd, 0, 64);
+    create(&mut fixture, params, 16, 16, ARGB8888);
+
+    assert_eq!(fixture.opcodes(params), vec![CREATED]);
+    assert_eq!(buffers(&mut fixture), vec![(16, 16, ARGB8888)]);
+}
+
+#[test]
+fn create_buffer_immediately() {
+    let mut fixture = Fixture::new();
+    let fd = memfd(4096);
+
+    let params = params(&mut fixture, &fd, 0, 64);
+    let buffer = fixture.client.new_id();
+    fixture.send(
+        params,
+        CREATE_IMMED,
+        &[
+            Arg::NewId(buffer),
+            Arg::Int(16),
+            Arg::Int(16),
+            Arg::Uint(ARGB8888),
+            Arg::Uint(0),
+        ],
+    );
+    fixture.assert_no_error();
+
+    assert_eq!(buffers(&mut fixture), vec![(16, 16, ARGB8888)]);
+}
+
+#[test]
+fn plane_outside_of_file_is_an_error() {
+    let mut fixture = Fixture::new();
+    let fd = memfd(1024);
+
+    // 32 rows of 64 bytes do not fit into the file.
+    let params = params(&mut fixture, &fd, 0, 64);
+    create(&mut fixture, params, 16, 32, ARGB8888);
+
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(zwp_linux_buffer_params_v1::Error::OutOfBounds as u32)
+    );
+    assert_eq!(buffers(&mut fixture), vec![]);
+}
+
+#[test]
+fn unsupported_format_is_an_error() {
+    let mut fixture = Fixture::new();
+    let fd = memfd(4096);
+
+    let params = params(&mut fixture, &fd, 0, 64);
+    create(&mut fixture, params, 16, 16, XRGB8888);
+
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(zwp_linux_buffer_params_v1::Error::InvalidFormat as u32)
+    );
+}
+
+#[test]
+fn format_of_surface_feedback_is_supported() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    Dmabuf::set_surface_feedback(
+        &mut fixture.server.state.ecs,
+        &surface.surface,
+        Some(dmabuf_feedback(XRGB8888)),
+    );
+
+    let fd = memfd(4096);
+    let params = params(&mut fixture, &fd, 0, 64);
+    create(&mut fixture, params, 16, 16, XRGB8888);
+
+    assert_eq!(fixture.opcodes(params), vec![CREATED]);
+    assert_eq!(buffers(&mut fixture), vec![(16, 16, XRGB8888)]);
+}
+
+#[test]
+fn failed_import_does_not_create_buffer() {
+    let mut fixture = Fixture::new();
+    fixture.server.state.reject_dmabufs = true;
+    let entities = fixture.world().len();
+    let fd = memfd(4096);
+
+    let params = params(&mut fixture, &fd, 0, 64);
+    create(&mut fixture, params, 16, 16, ARGB8888);
+
+    assert_eq!(fixture.opcodes(params), vec![FAILED]);
+    assert_eq!(fixture.world().len(), entities);
+}
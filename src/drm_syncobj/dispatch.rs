@@ -0,0 +1,212 @@
+use wayland_backend::server::{ClientId, ObjectId};
+use wayland_server::{
+    protocol::wl_surface::WlSurface, Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch,
+    New, Resource,
+};
+
+use crate::{
+    compositor::Compositor,
+    protocols::linux_drm_syncobj_v1::{
+        wp_linux_drm_syncobj_manager_v1::{self, WpLinuxDrmSyncobjManagerV1},
+        wp_linux_drm_syncobj_surface_v1::{self, WpLinuxDrmSyncobjSurfaceV1},
+        wp_linux_drm_syncobj_timeline_v1::{self, WpLinuxDrmSyncobjTimelineV1},
+    },
+    EntityData,
+};
+
+use super::{
+    destroy, post_commit, pre_commit, DrmSyncobj, DrmSyncobjHandler, SyncPoint, SyncTimelineData,
+    SyncobjState, SyncobjSurface,
+};
+
+impl<State> GlobalDispatch<WpLinuxDrmSyncobjManagerV1, (), State> for DrmSyncobj
+where
+    State: GlobalDispatch<WpLinuxDrmSyncobjManagerV1, ()>
+        + Dispatch<WpLinuxDrmSyncobjManagerV1, ()>
+        + DrmSyncobjHandler,
+{
+    fn bind(
+        _state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<WpLinuxDrmSyncobjManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<State> Dispatch<WpLinuxDrmSyncobjManagerV1, (), State> for DrmSyncobj
+where
+    State: Dispatch<WpLinuxDrmSyncobjManagerV1, ()>
+        + Dispatch<WpLinuxDrmSyncobjSurfaceV1, EntityData>
+        + Dispatch<WpLinuxDrmSyncobjTimelineV1, SyncTimelineData>
+        + DrmSyncobjHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        manager: &WpLinuxDrmSyncobjManagerV1,
+        request: wp_linux_drm_syncobj_manager_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            wp_linux_drm_syncobj_manager_v1::Request::Destroy => {
+                // Objects created from the manager are unaffected by the manager being destroyed.
+            }
+
+            wp_linux_drm_syncobj_manager_v1::Request::GetSurface { id, surface } => {
+                let entity = surface.data::<EntityData>().unwrap().0;
+                let world = state.ecs().world();
+
+                if world.query_one_mut::<&SyncobjSurface>(entity).is_ok() {
+                    manager.post_error(
+                        wp_linux_drm_syncobj_manager_v1::Error::SurfaceExists,
+                        "Surface already has a syncobj surface",
+                    );
+                    return;
+                }
+
+                // The commit systems are only added the first time, since they outlive the syncobj surface.
+                if world.query_one_mut::<&SyncobjState>(entity).is_err() {
+                    world
+                        .insert_one(entity, SyncobjState::default())
+                        .expect("Surface must be a valid entity if dispatched");
+                    Compositor::add_pre_commit::<State>(state.ecs(), &surface, pre_commit::<State>);
+                    Compositor::add_post_commit::<State>(
+                        state.ecs(),
+                        &surface,
+                        post_commit::<State>,
+                    );
+                    Compositor::add_destroy::<State>(state.ecs(), &surface, destroy::<State>);
+                }
+
+                let object = data_init.init(id, EntityData(entity));
+                state
+                    .ecs()
+                    .world()
+                    .insert_one(
+                        entity,
+                        SyncobjSurface {
+                            object,
+                            acquire: None,
+                            release: None,
+                        },
+                    )
+                    .expect("Surface must be a valid entity if dispatched");
+            }
+
+            wp_linux_drm_syncobj_manager_v1::Request::ImportTimeline { id, fd } => {
+                match state.import_timeline(fd) {
+                    Some(timeline) => {
+                        data_init.init(id, SyncTimelineData { timeline });
+                    }
+
+                    None => {
+                        manager.post_error(
+                            wp_linux_drm_syncobj_manager_v1::Error::InvalidTimeline,
+                            "Failed to import the timeline",
+                        );
+                    }
+                }
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<State> Dispatch<WpLinuxDrmSyncobjTimelineV1, SyncTimelineData, State> for DrmSyncobj
+where
+    State: Dispatch<WpLinuxDrmSyncobjTimelineV1, SyncTimelineData> + DrmSyncobjHandler,
+{
+    fn request(
+        _state: &mut State,
+        _client: &Client,
+        _resource: &WpLinuxDrmSyncobjTimelineV1,
+        request: wp_linux_drm_syncobj_timeline_v1::Request,
+        _data: &SyncTimelineData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            wp_linux_drm_syncobj_timeline_v1::Request::Destroy => {
+                // Points which were already set keep the timeline alive.
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<State> Dispatch<WpLinuxDrmSyncobjSurfaceV1, EntityData, State> for DrmSyncobj
+where
+    State: Dispatch<WpLinuxDrmSyncobjSurfaceV1, EntityData> + DrmSyncobjHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        resource: &WpLinuxDrmSyncobjSurfaceV1,
+        request: wp_linux_drm_syncobj_surface_v1::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        let (timeline, point_hi, point_lo, acquire) = match request {
+            wp_linux_drm_syncobj_surface_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+                return;
+            }
+
+            wp_linux_drm_syncobj_surface_v1::Request::SetAcquirePoint {
+                timeline,
+                point_hi,
+                point_lo,
+            } => (timeline, point_hi, point_lo, true),
+
+            wp_linux_drm_syncobj_surface_v1::Request::SetReleasePoint {
+                timeline,
+                point_hi,
+                point_lo,
+            } => (timeline, point_hi, point_lo, false),
+
+            _ => unreachable!(),
+        };
+
+        let (surface, syncobj) = state
+            .ecs()
+            .world()
+            .query_one_mut::<(&WlSurface, &mut SyncobjSurface)>(data.0)
+            .expect("Syncobj surface exists until destroyed");
+
+        if !surface.is_alive() {
+            resource.post_error(
+                wp_linux_drm_syncobj_surface_v1::Error::NoSurface,
+                "The surface was destroyed",
+            );
+            return;
+        }
+
+        let point = SyncPoint {
+            timeline: timeline
+                .data::<SyncTimelineData>()
+                .unwrap()
+                .timeline
+                .clone(),
+            point: ((point_hi as u64) << 32) | point_lo as u64,
+        };
+
+        if acquire {
+            syncobj.acquire = Some(point);
+        } else {
+            syncobj.release = Some(point);
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        let _ = state.ecs().world().remove_one::<SyncobjSurface>(data.0);
+    }
+}
@@ -0,0 +1,272 @@
+//! Implementation of the linux-drm-syncobj protocol for explicit synchronization.
+//!
+//! Clients set an acquire and a release point on DRM synchronization object timelines for every commit which
+//! attaches a buffer. The commit is held back by a [`Blocker`] until the acquire point is signalled, after which
+//! the compositor may read from the buffer. The release point is signalled once the compositor no longer uses
+//! the buffer.
+//!
+//! Timelines are imported through [`DrmSyncobjHandler::import_timeline`], which returns a [`SyncTimeline`].
+//! Compositors wrap a real DRM syncobj, while tests may use a software timeline instead.
+//!
+//! # Releasing buffers
+//!
+//! Each release point is tied to the buffer attached by the same commit. The point is signalled once the buffer
+//! is superseded by a later commit which is applied, when the renderer calls [`DrmSyncobj::release`] for the
+//! surface, or when the surface is destroyed. If the commit is never applied, the point is signalled as the
+//! commit is dropped. Since acquire points are signalled asynchronously, the compositor should call
+//! [`Compositor::apply_transactions`] whenever a timeline it waits on may have been signalled.
+
+mod dispatch;
+
+#[cfg(test)]
+mod tests;
+
+use std::{fmt, sync::Arc};
+
+use wayland_server::{protocol::wl_surface::WlSurface, DisplayHandle, GlobalDispatch, Resource};
+
+use crate::{
+    compositor::{Blocker, Buffer, BufferAssignment, Compositor, CompositorHandler},
+    dmabuf::DmabufBuffer,
+    protocols::linux_drm_syncobj_v1::{
+        wp_linux_drm_syncobj_manager_v1::WpLinuxDrmSyncobjManagerV1,
+        wp_linux_drm_syncobj_surface_v1::{self, WpLinuxDrmSyncobjSurfaceV1},
+    },
+    Ecs, EntityData,
+};
+
+/// A DRM synchronization object timeline.
+pub trait SyncTimeline: Send + Sync + 'static {
+    /// Returns whether the point of the timeline was signalled.
+    fn is_signalled(&self, point: u64) -> bool;
+
+    /// Signals the point of the timeline.
+    fn signal(&self, point: u64);
+}
+
+pub trait DrmSyncobjHandler: CompositorHandler {
+    /// Imports a timeline from a DRM syncobj file descriptor.
+    ///
+    /// Returning [`None`] indicates the timeline is invalid.
+    fn import_timeline(&mut self, fd: std::os::unix::io::OwnedFd) -> Option<Arc<dyn SyncTimeline>>;
+}
+
+/// A point on a [`SyncTimeline`].
+#[derive(Clone)]
+pub struct SyncPoint {
+    timeline: Arc<dyn SyncTimeline>,
+    point: u64,
+}
+
+impl SyncPoint {
+    pub fn point(&self) -> u64 {
+        self.point
+    }
+
+    pub fn is_signalled(&self) -> bool {
+        self.timeline.is_signalled(self.point)
+    }
+
+    pub fn signal(&self) {
+        self.timeline.signal(self.point)
+    }
+}
+
+impl fmt::Debug for SyncPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyncPoint")
+            .field("point", &self.point)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Blocker for SyncPoint {
+    fn is_released(&self) -> bool {
+        self.is_signalled()
+    }
+}
+
+pub struct DrmSyncobj {}
+
+impl DrmSyncobj {
+    pub fn new<State>(display: &mut DisplayHandle) -> Self
+    where
+        State: GlobalDispatch<WpLinuxDrmSyncobjManagerV1, ()> + DrmSyncobjHandler,
+    {
+        let _global = display.create_global::<State, WpLinuxDrmSyncobjManagerV1, ()>(1, ());
+        Self {}
+    }
+
+    /// Signals the release point of the buffer currently attached to the surface.
+    ///
+    /// This should be called once the renderer no longer uses the buffer, for example after copying it into a
+    /// texture. Release points of buffers which were superseded are signalled when the commit replacing the
+    /// buffer is applied.
+    pub fn release(ecs: &mut Ecs, surface: &WlSurface) {
+        let entity = surface.data::<EntityData>().unwrap().0;
+
+        if let Ok(state) = ecs.world.query_one_mut::<&mut SyncobjState>(entity) {
+            state.current = None;
+        }
+    }
+}
+
+/// User data of a `wp_linux_drm_syncobj_timeline_v1`.
+pub struct SyncTimelineData {
+    timeline: Arc<dyn SyncTimeline>,
+}
+
+impl fmt::Debug for SyncTimelineData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyncTimelineData").finish_non_exhaustive()
+    }
+}
+
+/// The pending timeline points of a surface.
+///
+/// This exists while the surface has a `wp_linux_drm_syncobj_surface_v1`.
+#[derive(Debug)]
+struct SyncobjSurface {
+    object: WpLinuxDrmSyncobjSurfaceV1,
+    acquire: Option<SyncPoint>,
+    release: Option<SyncPoint>,
+}
+
+/// Explicit synchronization state of a surface which has used the protocol.
+///
+/// This is inserted together with the commit systems and is never removed.
+#[derive(Debug, Default)]
+struct SyncobjState {
+    /// The release point of the buffer which is currently attached.
+    current: Option<ReleasePoint>,
+}
+
+/// The release point of a buffer, which is signalled once dropped.
+///
+/// This is carried in the state of the commit which attaches the buffer.
+#[derive(Debug)]
+struct ReleasePoint(SyncPoint);
+
+impl Drop for ReleasePoint {
+    fn drop(&mut self) {
+        self.0.signal();
+    }
+}
+
+fn pre_commit<State: DrmSyncobjHandler>(state: &mut State, surface: &WlSurface) {
+    let entity = surface.data::<EntityData>().unwrap().0;
+
+    let points = state
+        .ecs()
+        .world()
+        .query_one_mut::<&mut SyncobjSurface>(entity)
+        .ok()
+        .map(|syncobj| {
+            (
+                syncobj.object.clone(),
+                syncobj.acquire.take(),
+                syncobj.release.take(),
+            )
+        });
+
+    let release = match points {
+        Some((object, acquire, release)) => {
+            let buffer = match Compositor::pending_buffer::<State>(state.ecs(), surface) {
+                Some(BufferAssignment::NewBuffer(buffer)) => Some(buffer),
+                _ => None,
+            };
+
+            match (buffer, acquire, release) {
+                (None, None, None) => None,
+
+                (None, _, _) => {
+                    object.post_error(
+                        wp_linux_drm_syncobj_surface_v1::Error::NoBuffer,
+                        "Timeline points were set without attaching a buffer",
+                    );
+                    Compositor::discard_commit::<State>(state.ecs(), surface);
+                    None
+                }
+
+                (Some(_), None, _) => {
+                    object.post_error(
+                        wp_linux_drm_syncobj_surface_v1::Error::NoAcquirePoint,
+                        "No acquire point was set",
+                    );
+                    Compositor::discard_commit::<State>(state.ecs(), surface);
+                    None
+                }
+
+                (Some(_), _, None) => {
+                    object.post_error(
+                        wp_linux_drm_syncobj_surface_v1::Error::NoReleasePoint,
+                        "No release point was set",
+                    );
+                    Compositor::discard_commit::<State>(state.ecs(), surface);
+                    None
+                }
+
+                (Some(buffer), Some(acquire), Some(release)) => {
+                    let is_dmabuf = buffer
+                        .data::<EntityData>()
+                        .map(|data| {
+                            state
+                                .ecs()
+                                .world()
+                                .query_one_mut::<&DmabufBuffer>(data.0)
+                                .is_ok()
+                        })
+                        .unwrap_or(false);
+
+                    if !is_dmabuf {
+                        object.post_error(
+                            wp_linux_drm_syncobj_surface_v1::Error::UnsupportedBuffer,
+                            "Explicit synchronization requires a dmabuf buffer",
+                        );
+                        Compositor::discard_commit::<State>(state.ecs(), surface);
+                        None
+                    } else if Arc::ptr_eq(&acquire.timeline, &release.timeline)
+                        && acquire.point >= release.point
+                    {
+                        object.post_error(
+                            wp_linux_drm_syncobj_surface_v1::Error::ConflictingPoints,
+                            "The release point must be after the acquire point",
+                        );
+                        Compositor::discard_commit::<State>(state.ecs(), surface);
+                        None
+                    } else {
+                        Compositor::add_blocker::<State>(state.ecs(), surface, acquire);
+                        Some(ReleasePoint(release))
+                    }
+                }
+            }
+        }
+
+        None => None,
+    };
+
+    if let Some(release) = release {
+        Compositor::insert_commit_state::<State, _>(state.ecs(), surface, release);
+    }
+}
+
+fn post_commit<State: DrmSyncobjHandler>(state: &mut State, surface: &WlSurface) {
+    let entity = surface.data::<EntityData>().unwrap().0;
+    let release = Compositor::take_commit_state::<State, ReleasePoint>(state.ecs(), surface);
+
+    let (buffer, syncobj_state) = state
+        .ecs()
+        .world()
+        .query_one_mut::<(&Buffer, &mut SyncobjState)>(entity)
+        .expect("Commit systems are only added with the state");
+
+    // Attaching a buffer supersedes the previous buffer, which signals its release point.
+    if buffer.buffer().is_some() {
+        syncobj_state.current = release;
+    }
+}
+
+fn destroy<State: DrmSyncobjHandler>(state: &mut State, surface: &WlSurface) {
+    // The buffers of a destroyed surface are never used again.
+    DrmSyncobj::release(state.ecs(), surface);
+}
@@ -0,0 +1,206 @@
+use std::{os::unix::io::AsRawFd, sync::Arc};
+
+use wayland_server::Resource;
+
+use crate::{
+    compositor::{Buffer, Compositor},
+    testing::{memfd, Arg, Fixture, SoftwareTimeline, TestSurface, ARGB8888},
+};
+
+use super::{DrmSyncobj, SyncTimeline};
+
+// wl_surface requests
+const DESTROY: u16 = 0;
+
+// zwp_linux_dmabuf_v1 requests
+const CREATE_PARAMS: u16 = 1;
+
+// zwp_linux_buffer_params_v1 requests
+const ADD: u16 = 1;
+const CREATE_IMMED: u16 = 3;
+
+// wp_linux_drm_syncobj_manager_v1 requests
+const GET_SURFACE: u16 = 1;
+const IMPORT_TIMELINE: u16 = 2;
+
+// wp_linux_drm_syncobj_surface_v1 requests
+const SET_ACQUIRE_POINT: u16 = 1;
+const SET_RELEASE_POINT: u16 = 2;
+
+/// A surface with a syncobj surface and an imported timeline.
+struct SyncSurface {
+    surface: TestSurface,
+    syncobj_surface: u32,
+    timeline: u32,
+}
+
+fn create_sync_surface(fixture: &mut Fixture) -> SyncSurface {
+    let manager = fixture.client.bind("wp_linux_drm_syncobj_manager_v1", 1);
+    let surface = fixture.create_surface();
+
+    let syncobj_surface = fixture.client.new_id();
+    fixture.send(
+        manager,
+        GET_SURFACE,
+        &[Arg::NewId(syncobj_surface), Arg::Object(surface.id)],
+    );
+
+    // Any file stands in for the DRM syncobj.
+    let timeline_fd = memfd(0);
+    let timeline = fixture.client.new_id();
+    fixture.send(
+        manager,
+        IMPORT_TIMELINE,
+        &[Arg::NewId(timeline), Arg::Fd(timeline_fd.as_raw_fd())],
+    );
+    fixture.assert_no_error();
+
+    SyncSurface {
+        surface,
+        syncobj_surface,
+        timeline,
+    }
+}
+
+/// Creates a dmabuf buffer backed by a memfd.
+fn create_buffer(fixture: &mut Fixture) -> u32 {
+    let dmabuf = fixture.client.bind("zwp_linux_dmabuf_v1", 3);
+    let fd = memfd(4096);
+    let params = fixture.client.new_id();
+    fixture.send(dmabuf, CREATE_PARAMS, &[Arg::NewId(params)]);
+    fixture.send(
+        params,
+        ADD,
+        &[
+            Arg::Fd(fd.as_raw_fd()),
+            Arg::Uint(0),
+            Arg::Uint(0),
+            Arg::Uint(64),
+            Arg::Uint(0),
+            Arg::Uint(0),
+        ],
+    );
+
+    let buffer = fixture.client.new_id();
+    fixture.send(
+        params,
+        CREATE_IMMED,
+        &[
+            Arg::NewId(buffer),
+            Arg::Int(16),
+            Arg::Int(16),
+            Arg::Uint(ARGB8888),
+            Arg::Uint(0),
+        ],
+    );
+    buffer
+}
+
+/// Attaches a buffer with an acquire and release point and commits.
+fn commit_buffer(
+    fixture: &mut Fixture,
+    surface: &SyncSurface,
+    buffer: u32,
+    acquire: u32,
+    release: u32,
+) {
+    fixture.attach(surface.surface.id, buffer);
+    for (opcode, point) in [(SET_ACQUIRE_POINT, acquire), (SET_RELEASE_POINT, release)] {
+        fixture.send(
+            surface.syncobj_surface,
+            opcode,
+            &[
+                Arg::Object(surface.timeline),
+                Arg::Uint(0),
+                Arg::Uint(point),
+            ],
+        );
+    }
+    fixture.commit(surface.surface.id);
+}
+
+fn timeline(fixture: &Fixture) -> Arc<SoftwareTimeline> {
+    fixture.server.state.timelines[0].clone()
+}
+
+fn signal(fixture: &mut Fixture, point: u64) {
+    timeline(fixture).signal(point);
+    Compositor::apply_transactions(&mut fixture.server.state);
+}
+
+fn is_signalled(fixture: &Fixture, point: u64) -> bool {
+    timeline(fixture).is_signalled(point)
+}
+
+fn current_buffer(fixture: &mut Fixture, surface: &SyncSurface) -> Option<u32> {
+    fixture
+        .world()
+        .query_one_mut::<&Buffer>(surface.surface.entity)
+        .unwrap()
+        .current_buffer()
+        .map(|buffer| buffer.id().protocol_id())
+}
+
+#[test]
+fn acquire_point_blocks_commit() {
+    let mut fixture = Fixture::new();
+    let surface = create_sync_surface(&mut fixture);
+    let buffer = create_buffer(&mut fixture);
+
+    commit_buffer(&mut fixture, &surface, buffer, 1, 2);
+    assert_eq!(current_buffer(&mut fixture, &surface), None);
+
+    signal(&mut fixture, 1);
+    assert_eq!(current_buffer(&mut fixture, &surface), Some(buffer));
+    assert!(!is_signalled(&fixture, 2));
+}
+
+#[test]
+fn release_point_is_signalled_when_buffer_is_superseded() {
+    let mut fixture = Fixture::new();
+    let surface = create_sync_surface(&mut fixture);
+    let first = create_buffer(&mut fixture);
+    let second = create_buffer(&mut fixture);
+
+    commit_buffer(&mut fixture, &surface, first, 1, 2);
+    signal(&mut fixture, 1);
+
+    // The first buffer is still attached until the second commit is applied.
+    commit_buffer(&mut fixture, &surface, second, 3, 4);
+    assert!(!is_signalled(&fixture, 2));
+
+    signal(&mut fixture, 3);
+    assert_eq!(current_buffer(&mut fixture, &surface), Some(second));
+    assert!(is_signalled(&fixture, 2));
+    assert!(!is_signalled(&fixture, 4));
+}
+
+#[test]
+fn release_point_is_signalled_when_buffer_is_released() {
+    let mut fixture = Fixture::new();
+    let surface = create_sync_surface(&mut fixture);
+    let buffer = create_buffer(&mut fixture);
+
+    commit_buffer(&mut fixture, &surface, buffer, 1, 2);
+    signal(&mut fixture, 1);
+    assert!(!is_signalled(&fixture, 2));
+
+    DrmSyncobj::release(&mut fixture.server.state.ecs, &surface.surface.surface);
+    assert!(is_signalled(&fixture, 2));
+}
+
+#[test]
+fn release_point_of_discarded_commit_is_signalled() {
+    let mut fixture = Fixture::new();
+    let surface = create_sync_surface(&mut fixture);
+    let buffer = create_buffer(&mut fixture);
+
+    commit_buffer(&mut fixture, &surface, buffer, 1, 2);
+    fixture.send(surface.surface.id, DESTROY, &[]);
+    fixture.assert_no_error();
+    assert!(!is_signalled(&fixture, 2));
+
+    // The commit is dropped instead of applied since the surface is gone.
+    signal(&mut fixture, 1);
+    assert!(is_signalled(&fixture, 2));
+}
@@ -0,0 +1,262 @@
+use wayland_backend::server::{ClientId, ObjectId};
+use wayland_protocols::xdg::shell::server::xdg_toplevel::XdgToplevel;
+use wayland_protocols_wlr::foreign_toplevel::v1::server::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
+use wayland_server::{
+    protocol::wl_surface::WlSurface, Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch,
+    New, Resource,
+};
+
+use crate::{
+    protocols::ext_foreign_toplevel_list_v1::{
+        ext_foreign_toplevel_handle_v1::{self, ExtForeignToplevelHandleV1},
+        ext_foreign_toplevel_list_v1::{self, ExtForeignToplevelListV1},
+    },
+    EntityData,
+};
+
+use super::{ExtList, ForeignToplevel, ForeignToplevelHandler, ForeignToplevelManager, WlrManager};
+
+impl<State> GlobalDispatch<ZwlrForeignToplevelManagerV1, (), State> for ForeignToplevelManager
+where
+    State: GlobalDispatch<ZwlrForeignToplevelManagerV1, ()>
+        + Dispatch<ZwlrForeignToplevelManagerV1, EntityData>
+        + Dispatch<ZwlrForeignToplevelHandleV1, EntityData>
+        + ForeignToplevelHandler,
+{
+    fn bind(
+        state: &mut State,
+        handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwlrForeignToplevelManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        let world = state.ecs().world();
+        let entity = world.reserve_entity();
+        let manager = data_init.init(resource, EntityData(entity));
+        world
+            .insert_one(entity, WlrManager(manager.clone()))
+            .expect("Entity was reserved");
+
+        // Announce the toplevels which already exist. Toplevels which were destroyed are closed by the next
+        // refresh, so they are not announced.
+        for (entity, (foreign, _)) in world.query_mut::<(&mut ForeignToplevel, &XdgToplevel)>() {
+            foreign.add_wlr_handle::<State>(handle, &manager, entity);
+        }
+    }
+}
+
+impl<State> Dispatch<ZwlrForeignToplevelManagerV1, EntityData, State> for ForeignToplevelManager
+where
+    State: Dispatch<ZwlrForeignToplevelManagerV1, EntityData> + ForeignToplevelHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        resource: &ZwlrForeignToplevelManagerV1,
+        request: zwlr_foreign_toplevel_manager_v1::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwlr_foreign_toplevel_manager_v1::Request::Stop => {
+                // The manager is destroyed by the finished event.
+                let _ = state.ecs().world().despawn(data.0);
+                resource.finished();
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        let _ = state.ecs().world().despawn(data.0);
+    }
+}
+
+impl<State> Dispatch<ZwlrForeignToplevelHandleV1, EntityData, State> for ForeignToplevelManager
+where
+    State: Dispatch<ZwlrForeignToplevelHandleV1, EntityData> + ForeignToplevelHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        resource: &ZwlrForeignToplevelHandleV1,
+        request: zwlr_foreign_toplevel_handle_v1::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        if let zwlr_foreign_toplevel_handle_v1::Request::SetRectangle { width, height, .. } =
+            request
+        {
+            // The rectangle is only a hint for animations, so it is not used.
+            if width < 0 || height < 0 {
+                resource.post_error(
+                    zwlr_foreign_toplevel_handle_v1::Error::InvalidRectangle,
+                    format!("Invalid rectangle size {width}x{height}"),
+                );
+            }
+            return;
+        }
+
+        // Requests for toplevels which were destroyed are ignored, as are requests of handles which were closed
+        // because the surface became a new toplevel.
+        let surface = match state
+            .ecs()
+            .world()
+            .query_one_mut::<(&WlSurface, &XdgToplevel, &ForeignToplevel)>(data.0)
+        {
+            Ok((surface, _, foreign)) if foreign.wlr.contains(resource) => surface.clone(),
+            _ => return,
+        };
+
+        match request {
+            zwlr_foreign_toplevel_handle_v1::Request::SetMaximized => {
+                state.set_maximized(&surface, true);
+            }
+
+            zwlr_foreign_toplevel_handle_v1::Request::UnsetMaximized => {
+                state.set_maximized(&surface, false);
+            }
+
+            zwlr_foreign_toplevel_handle_v1::Request::SetMinimized => {
+                state.set_minimized(&surface, true);
+            }
+
+            zwlr_foreign_toplevel_handle_v1::Request::UnsetMinimized => {
+                state.set_minimized(&surface, false);
+            }
+
+            zwlr_foreign_toplevel_handle_v1::Request::Activate { seat } => {
+                state.activate(&surface, &seat);
+            }
+
+            zwlr_foreign_toplevel_handle_v1::Request::Close => {
+                state.close(&surface);
+            }
+
+            zwlr_foreign_toplevel_handle_v1::Request::SetFullscreen { output } => {
+                state.set_fullscreen(&surface, true, output);
+            }
+
+            zwlr_foreign_toplevel_handle_v1::Request::UnsetFullscreen => {
+                state.set_fullscreen(&surface, false, None);
+            }
+
+            zwlr_foreign_toplevel_handle_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, resource: ObjectId, data: &EntityData) {
+        if let Ok(foreign) = state
+            .ecs()
+            .world()
+            .query_one_mut::<&mut ForeignToplevel>(data.0)
+        {
+            foreign.wlr.retain(|handle| handle.id() != resource);
+        }
+    }
+}
+
+impl<State> GlobalDispatch<ExtForeignToplevelListV1, (), State> for ForeignToplevelManager
+where
+    State: GlobalDispatch<ExtForeignToplevelListV1, ()>
+        + Dispatch<ExtForeignToplevelListV1, EntityData>
+        + Dispatch<ExtForeignToplevelHandleV1, EntityData>
+        + ForeignToplevelHandler,
+{
+    fn bind(
+        state: &mut State,
+        handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ExtForeignToplevelListV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        let world = state.ecs().world();
+        let entity = world.reserve_entity();
+        let list = data_init.init(resource, EntityData(entity));
+        world
+            .insert_one(entity, ExtList(list.clone()))
+            .expect("Entity was reserved");
+
+        for (entity, (foreign, _)) in world.query_mut::<(&mut ForeignToplevel, &XdgToplevel)>() {
+            foreign.add_ext_handle::<State>(handle, &list, entity);
+        }
+    }
+}
+
+impl<State> Dispatch<ExtForeignToplevelListV1, EntityData, State> for ForeignToplevelManager
+where
+    State: Dispatch<ExtForeignToplevelListV1, EntityData> + ForeignToplevelHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        resource: &ExtForeignToplevelListV1,
+        request: ext_foreign_toplevel_list_v1::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            ext_foreign_toplevel_list_v1::Request::Stop => {
+                // No more toplevels are announced, but the client destroys the list itself.
+                let _ = state.ecs().world().despawn(data.0);
+                resource.finished();
+            }
+
+            ext_foreign_toplevel_list_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        let _ = state.ecs().world().despawn(data.0);
+    }
+}
+
+impl<State> Dispatch<ExtForeignToplevelHandleV1, EntityData, State> for ForeignToplevelManager
+where
+    State: Dispatch<ExtForeignToplevelHandleV1, EntityData> + ForeignToplevelHandler,
+{
+    fn request(
+        _state: &mut State,
+        _client: &Client,
+        _resource: &ExtForeignToplevelHandleV1,
+        request: ext_foreign_toplevel_handle_v1::Request,
+        _data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            ext_foreign_toplevel_handle_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, resource: ObjectId, data: &EntityData) {
+        if let Ok(foreign) = state
+            .ecs()
+            .world()
+            .query_one_mut::<&mut ForeignToplevel>(data.0)
+        {
+            foreign.ext.retain(|handle| handle.id() != resource);
+        }
+    }
+}
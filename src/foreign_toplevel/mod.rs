@@ -0,0 +1,405 @@
+//! Implementation of the wlr-foreign-toplevel-management and ext-foreign-toplevel-list protocols.
+//!
+//! These protocols let clients such as taskbars list the toplevels of other clients. wlr-foreign-toplevel-management
+//! additionally lets them control the toplevels, which is routed to the [`ForeignToplevelHandler`].
+//!
+//! # Components
+//!
+//! The handles of a toplevel are driven by the components of its [`WlSurface`]:
+//!
+//! - The [`Title`] and [`AppId`] set by the client.
+//! - The [`ToplevelState`] and [`ToplevelOutputs`], which are inserted and updated by the compositor.
+//!
+//! Changes to these components are broadcast to every handle by [`ForeignToplevelManager::refresh`], which
+//! should be called before clients are flushed. Toplevels which were destroyed are closed by the same call.
+
+mod dispatch;
+#[cfg(test)]
+mod tests;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use hecs::Entity;
+use wayland_backend::server::ObjectId;
+use wayland_protocols::xdg::shell::server::xdg_toplevel::XdgToplevel;
+use wayland_protocols_wlr::foreign_toplevel::v1::server::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1,
+};
+use wayland_server::{
+    protocol::{wl_output::WlOutput, wl_seat::WlSeat, wl_surface::WlSurface},
+    Dispatch, DisplayHandle, GlobalDispatch, Resource,
+};
+
+use crate::{
+    protocols::ext_foreign_toplevel_list_v1::{
+        ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
+        ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1,
+    },
+    xdg_shell::{AppId, Title, XdgShellHandler},
+    EntityData,
+};
+
+pub trait ForeignToplevelHandler: XdgShellHandler {
+    /// A client requested the toplevel to be activated, giving it keyboard focus of the seat.
+    fn activate(&mut self, surface: &WlSurface, seat: &WlSeat);
+
+    /// A client requested the toplevel to be closed.
+    fn close(&mut self, surface: &WlSurface);
+
+    /// A client requested the toplevel to be minimized or unminimized.
+    fn set_minimized(&mut self, surface: &WlSurface, minimized: bool);
+
+    /// A client requested the toplevel to be maximized or unmaximized.
+    fn set_maximized(&mut self, surface: &WlSurface, maximized: bool);
+
+    /// A client requested the toplevel to be made fullscreen or to leave fullscreen.
+    ///
+    /// `output` is the output the client would like the toplevel to be shown on when made fullscreen.
+    fn set_fullscreen(&mut self, surface: &WlSurface, fullscreen: bool, output: Option<WlOutput>);
+}
+
+pub struct ForeignToplevelManager {}
+
+impl ForeignToplevelManager {
+    pub fn new<State>(display: &mut DisplayHandle) -> Self
+    where
+        State: GlobalDispatch<ZwlrForeignToplevelManagerV1, ()>
+            + GlobalDispatch<ExtForeignToplevelListV1, ()>
+            + ForeignToplevelHandler,
+    {
+        let _wlr_global = display.create_global::<State, ZwlrForeignToplevelManagerV1, ()>(3, ());
+        let _ext_global = display.create_global::<State, ExtForeignToplevelListV1, ()>(1, ());
+        Self {}
+    }
+
+    /// Sends the changes of every toplevel to the handles of the toplevel.
+    ///
+    /// New toplevels are announced to every bound manager and destroyed toplevels are closed.
+    pub fn refresh<State>(state: &mut State, display: &DisplayHandle)
+    where
+        State: Dispatch<ZwlrForeignToplevelHandleV1, EntityData>
+            + Dispatch<ExtForeignToplevelHandleV1, EntityData>
+            + ForeignToplevelHandler,
+    {
+        let world = state.ecs().world();
+
+        let wlr_managers = world
+            .query_mut::<&WlrManager>()
+            .into_iter()
+            .map(|(_, manager)| manager.0.clone())
+            .collect::<Vec<_>>();
+        let ext_lists = world
+            .query_mut::<&ExtList>()
+            .into_iter()
+            .map(|(_, list)| list.0.clone())
+            .collect::<Vec<_>>();
+
+        // A surface which was given a new xdg_toplevel is a new toplevel, so its old handles are closed too and
+        // it is announced again with a new identifier.
+        let closed = world
+            .query_mut::<(&ForeignToplevel, Option<&XdgToplevel>)>()
+            .into_iter()
+            .filter(|(_, (foreign, toplevel))| {
+                toplevel.map(|toplevel| toplevel.id()).as_ref() != Some(&foreign.toplevel)
+            })
+            .map(|(entity, _)| entity)
+            .collect::<Vec<_>>();
+
+        for entity in closed {
+            let foreign = world
+                .remove_one::<ForeignToplevel>(entity)
+                .expect("Toplevel was queried");
+
+            for handle in foreign.wlr {
+                handle.closed();
+            }
+
+            for handle in foreign.ext {
+                handle.closed();
+            }
+        }
+
+        let toplevels = world
+            .query_mut::<(
+                &XdgToplevel,
+                Option<&Title>,
+                Option<&AppId>,
+                Option<&ToplevelState>,
+                Option<&ToplevelOutputs>,
+            )>()
+            .into_iter()
+            .map(
+                |(entity, (toplevel, title, app_id, toplevel_state, outputs))| {
+                    let snapshot = Snapshot {
+                        title: title.map(|title| title.get().to_owned()),
+                        app_id: app_id.map(|app_id| app_id.get().to_owned()),
+                        state: toplevel_state.copied().unwrap_or_default(),
+                        outputs: outputs
+                            .map(|outputs| outputs.outputs.clone())
+                            .unwrap_or_default(),
+                    };
+                    (entity, toplevel.id(), snapshot)
+                },
+            )
+            .collect::<Vec<_>>();
+
+        for (entity, toplevel, snapshot) in toplevels {
+            if let Ok(foreign) = world.query_one_mut::<&mut ForeignToplevel>(entity) {
+                foreign.update(snapshot);
+                continue;
+            }
+
+            let mut foreign = ForeignToplevel::new(toplevel, snapshot);
+
+            for manager in &wlr_managers {
+                foreign.add_wlr_handle::<State>(display, manager, entity);
+            }
+
+            for list in &ext_lists {
+                foreign.add_ext_handle::<State>(display, list, entity);
+            }
+
+            world
+                .insert_one(entity, foreign)
+                .expect("Toplevel was queried");
+        }
+    }
+}
+
+/// The state of a toplevel shown to foreign toplevel clients.
+///
+/// This is inserted on the [`WlSurface`] of a toplevel by the compositor. Toplevels without this component
+/// have no state set.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ToplevelState {
+    pub maximized: bool,
+    pub minimized: bool,
+    pub activated: bool,
+    pub fullscreen: bool,
+}
+
+/// The outputs a toplevel is shown on.
+///
+/// Clients bind outputs separately, so this contains the `wl_output` objects of every client for those outputs.
+/// Each handle is only told about the objects of its own client. This is inserted on the [`WlSurface`] of a
+/// toplevel by the compositor.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ToplevelOutputs {
+    pub outputs: Vec<WlOutput>,
+}
+
+/// The foreign toplevel handles of a toplevel.
+///
+/// This is inserted on the [`WlSurface`] of a toplevel once it was announced by
+/// [`ForeignToplevelManager::refresh`].
+#[derive(Debug)]
+pub struct ForeignToplevel {
+    identifier: String,
+    /// The xdg_toplevel the handles were created for.
+    toplevel: ObjectId,
+    wlr: Vec<ZwlrForeignToplevelHandleV1>,
+    ext: Vec<ExtForeignToplevelHandleV1>,
+    /// The state the handles were last told about.
+    sent: Snapshot,
+}
+
+impl ForeignToplevel {
+    /// The identifier of the toplevel, which is unique and never reused.
+    pub fn identifier(&self) -> &str {
+        &self.identifier
+    }
+
+    fn new(toplevel: ObjectId, sent: Snapshot) -> Self {
+        static IDENTIFIER: AtomicU64 = AtomicU64::new(1);
+        let identifier = IDENTIFIER.fetch_add(1, Ordering::Relaxed);
+
+        Self {
+            identifier: format!("{identifier:016x}"),
+            toplevel,
+            wlr: Vec::new(),
+            ext: Vec::new(),
+            sent,
+        }
+    }
+
+    /// Creates a handle for a wlr manager and sends the current state to it.
+    fn add_wlr_handle<State>(
+        &mut self,
+        display: &DisplayHandle,
+        manager: &ZwlrForeignToplevelManagerV1,
+        entity: Entity,
+    ) where
+        State: Dispatch<ZwlrForeignToplevelHandleV1, EntityData> + 'static,
+    {
+        let handle = match display.get_client(manager.id()).and_then(|client| {
+            client.create_resource::<ZwlrForeignToplevelHandleV1, EntityData, State>(
+                display,
+                manager.version(),
+                EntityData(entity),
+            )
+        }) {
+            Ok(handle) => handle,
+            // The client is disconnecting.
+            Err(_) => return,
+        };
+
+        manager.toplevel(&handle);
+        send_wlr(&handle, &Snapshot::default(), &self.sent);
+        handle.done();
+        self.wlr.push(handle);
+    }
+
+    /// Creates a handle for an ext list and sends the current state to it.
+    fn add_ext_handle<State>(
+        &mut self,
+        display: &DisplayHandle,
+        list: &ExtForeignToplevelListV1,
+        entity: Entity,
+    ) where
+        State: Dispatch<ExtForeignToplevelHandleV1, EntityData> + 'static,
+    {
+        let handle = match display.get_client(list.id()).and_then(|client| {
+            client.create_resource::<ExtForeignToplevelHandleV1, EntityData, State>(
+                display,
+                list.version(),
+                EntityData(entity),
+            )
+        }) {
+            Ok(handle) => handle,
+            // The client is disconnecting.
+            Err(_) => return,
+        };
+
+        list.toplevel(&handle);
+        handle.identifier(self.identifier.clone());
+        send_ext(&handle, &Snapshot::default(), &self.sent);
+        handle.done();
+        self.ext.push(handle);
+    }
+
+    /// Sends the changes since the last update to every handle.
+    fn update(&mut self, snapshot: Snapshot) {
+        if snapshot == self.sent {
+            return;
+        }
+
+        for handle in &self.wlr {
+            if send_wlr(handle, &self.sent, &snapshot) {
+                handle.done();
+            }
+        }
+
+        for handle in &self.ext {
+            if send_ext(handle, &self.sent, &snapshot) {
+                handle.done();
+            }
+        }
+
+        self.sent = snapshot;
+    }
+}
+
+/// A bound wlr manager.
+struct WlrManager(ZwlrForeignToplevelManagerV1);
+
+/// A bound ext list.
+struct ExtList(ExtForeignToplevelListV1);
+
+/// The components of a toplevel handles are told about.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Snapshot {
+    title: Option<String>,
+    app_id: Option<String>,
+    state: ToplevelState,
+    outputs: Vec<WlOutput>,
+}
+
+/// Sends the differences between two snapshots to a wlr handle, returning whether anything was sent.
+fn send_wlr(handle: &ZwlrForeignToplevelHandleV1, old: &Snapshot, new: &Snapshot) -> bool {
+    use zwlr_foreign_toplevel_handle_v1::State;
+
+    let mut changed = false;
+
+    if new.title != old.title {
+        if let Some(title) = &new.title {
+            handle.title(title.clone());
+            changed = true;
+        }
+    }
+
+    if new.app_id != old.app_id {
+        if let Some(app_id) = &new.app_id {
+            handle.app_id(app_id.clone());
+            changed = true;
+        }
+    }
+
+    // Only the client's own output objects can be sent.
+    let own = |output: &&WlOutput| output.id().same_client_as(&handle.id());
+
+    for output in old.outputs.iter().filter(own) {
+        if !new.outputs.contains(output) {
+            handle.output_leave(output);
+            changed = true;
+        }
+    }
+
+    for output in new.outputs.iter().filter(own) {
+        if !old.outputs.contains(output) {
+            handle.output_enter(output);
+            changed = true;
+        }
+    }
+
+    if new.state != old.state {
+        let mut states = Vec::new();
+        let toplevel_state = new.state;
+
+        if toplevel_state.maximized {
+            states.push(State::Maximized);
+        }
+
+        if toplevel_state.minimized {
+            states.push(State::Minimized);
+        }
+
+        if toplevel_state.activated {
+            states.push(State::Activated);
+        }
+
+        if toplevel_state.fullscreen && handle.version() >= 2 {
+            states.push(State::Fullscreen);
+        }
+
+        let states = states
+            .into_iter()
+            .flat_map(|state| (state as u32).to_ne_bytes())
+            .collect();
+        handle.state(states);
+        changed = true;
+    }
+
+    changed
+}
+
+/// Sends the differences between two snapshots to an ext handle, returning whether anything was sent.
+fn send_ext(handle: &ExtForeignToplevelHandleV1, old: &Snapshot, new: &Snapshot) -> bool {
+    let mut changed = false;
+
+    if new.title != old.title {
+        if let Some(title) = &new.title {
+            handle.title(title.clone());
+            changed = true;
+        }
+    }
+
+    if new.app_id != old.app_id {
+        if let Some(app_id) = &new.app_id {
+            handle.app_id(app_id.clone());
+            changed = true;
+        }
+    }
+
+    changed
+}
@@ -0,0 +1,261 @@
+use crate::testing::{Arg, Fixture, TestToplevel};
+
+use super::{ForeignToplevel, ForeignToplevelManager, ToplevelState};
+
+// xdg_surface requests
+const GET_TOPLEVEL: u16 = 1;
+
+// xdg_toplevel requests
+const DESTROY_TOPLEVEL: u16 = 0;
+const SET_TITLE: u16 = 2;
+const SET_APP_ID: u16 = 3;
+
+// zwlr_foreign_toplevel_handle_v1 requests
+const SET_MAXIMIZED: u16 = 0;
+const UNSET_MINIMIZED: u16 = 3;
+const ACTIVATE: u16 = 4;
+const CLOSE: u16 = 5;
+const UNSET_FULLSCREEN: u16 = 9;
+
+// zwlr_foreign_toplevel_manager_v1 and ext_foreign_toplevel_list_v1 events
+const TOPLEVEL: u16 = 0;
+
+// zwlr_foreign_toplevel_handle_v1 events
+const WLR_TITLE: u16 = 0;
+const WLR_APP_ID: u16 = 1;
+const WLR_STATE: u16 = 4;
+const WLR_DONE: u16 = 5;
+const WLR_CLOSED: u16 = 6;
+
+// ext_foreign_toplevel_handle_v1 events
+const EXT_CLOSED: u16 = 0;
+const EXT_DONE: u16 = 1;
+const EXT_TITLE: u16 = 2;
+const EXT_IDENTIFIER: u16 = 4;
+
+/// A client which bound the wlr manager and the ext list.
+struct Managers {
+    wlr: u32,
+    ext: u32,
+}
+
+fn bind_managers(fixture: &mut Fixture) -> Managers {
+    let managers = Managers {
+        wlr: fixture.client.bind("zwlr_foreign_toplevel_manager_v1", 3),
+        ext: fixture.client.bind("ext_foreign_toplevel_list_v1", 1),
+    };
+    fixture.assert_no_error();
+    managers
+}
+
+/// Dispatches the requests sent so far and refreshes the handles.
+fn refresh(fixture: &mut Fixture) {
+    fixture.dispatch();
+    let display = fixture.server.display.handle();
+    ForeignToplevelManager::refresh(&mut fixture.server.state, &display);
+    fixture.dispatch();
+}
+
+/// The handles announced to a manager, which the server created on behalf of the client.
+fn announced(fixture: &mut Fixture, manager: u32) -> Vec<u32> {
+    fixture
+        .events(manager)
+        .into_iter()
+        .filter(|event| event.opcode == TOPLEVEL)
+        .map(|event| event.uint(0))
+        .collect()
+}
+
+fn set_title(fixture: &mut Fixture, toplevel: &TestToplevel, title: &str) {
+    fixture.send(toplevel.toplevel, SET_TITLE, &[Arg::Str(title)]);
+}
+
+fn identifier(fixture: &mut Fixture, toplevel: &TestToplevel) -> String {
+    fixture
+        .world()
+        .query_one_mut::<&ForeignToplevel>(toplevel.surface.entity)
+        .unwrap()
+        .identifier()
+        .to_owned()
+}
+
+/// A toplevel announced to a client which bound both managers.
+struct Announced {
+    toplevel: TestToplevel,
+    managers: Managers,
+    /// The handles of the toplevel, whose initial events were taken.
+    wlr: u32,
+    ext: u32,
+}
+
+fn announced_toplevel(fixture: &mut Fixture) -> Announced {
+    let managers = bind_managers(fixture);
+    let toplevel = fixture.create_toplevel();
+    set_title(fixture, &toplevel, "Editor");
+    refresh(fixture);
+
+    let wlr = announced(fixture, managers.wlr)[0];
+    let ext = announced(fixture, managers.ext)[0];
+    fixture.events(wlr);
+    fixture.events(ext);
+    Announced {
+        toplevel,
+        managers,
+        wlr,
+        ext,
+    }
+}
+
+#[test]
+fn toplevel_is_announced_with_its_state() {
+    let mut fixture = Fixture::new();
+    let managers = bind_managers(&mut fixture);
+    let toplevel = fixture.create_toplevel();
+    set_title(&mut fixture, &toplevel, "Editor");
+    refresh(&mut fixture);
+
+    let wlr = announced(&mut fixture, managers.wlr);
+    assert_eq!(wlr.len(), 1);
+    let events = fixture.events(wlr[0]);
+    assert_eq!(events.len(), 2);
+    assert_eq!(
+        (events[0].opcode, events[0].string(0)),
+        (WLR_TITLE, "Editor")
+    );
+    assert_eq!(events[1].opcode, WLR_DONE);
+
+    let ext = announced(&mut fixture, managers.ext);
+    assert_eq!(ext.len(), 1);
+    let events = fixture.events(ext[0]);
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[0].opcode, EXT_IDENTIFIER);
+    assert_eq!(events[0].string(0), identifier(&mut fixture, &toplevel));
+    assert_eq!(events[1].opcode, EXT_TITLE);
+    assert_eq!(events[2].opcode, EXT_DONE);
+}
+
+#[test]
+fn existing_toplevel_is_announced_on_bind() {
+    let mut fixture = Fixture::new();
+    let toplevel = fixture.create_toplevel();
+    set_title(&mut fixture, &toplevel, "Editor");
+    refresh(&mut fixture);
+
+    let managers = bind_managers(&mut fixture);
+    let wlr = announced(&mut fixture, managers.wlr);
+    assert_eq!(wlr.len(), 1);
+    assert_eq!(fixture.opcodes(wlr[0]), vec![WLR_TITLE, WLR_DONE]);
+}
+
+#[test]
+fn changes_are_sent_with_one_done() {
+    let mut fixture = Fixture::new();
+    let Announced {
+        toplevel, wlr, ext, ..
+    } = announced_toplevel(&mut fixture);
+
+    set_title(&mut fixture, &toplevel, "Editor - file");
+    fixture.send(toplevel.toplevel, SET_APP_ID, &[Arg::Str("editor")]);
+    fixture
+        .world()
+        .insert_one(
+            toplevel.surface.entity,
+            ToplevelState {
+                maximized: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    refresh(&mut fixture);
+
+    assert_eq!(
+        fixture.opcodes(wlr),
+        vec![WLR_TITLE, WLR_APP_ID, WLR_STATE, WLR_DONE]
+    );
+    // The ext list does not carry the state of toplevels.
+    let events = fixture.events(ext);
+    assert_eq!(
+        events
+            .iter()
+            .filter(|event| event.opcode == EXT_DONE)
+            .count(),
+        1
+    );
+
+    // Nothing is sent if nothing changed.
+    refresh(&mut fixture);
+    assert!(fixture.events(wlr).is_empty());
+    assert!(fixture.events(ext).is_empty());
+}
+
+#[test]
+fn destroyed_toplevel_is_closed() {
+    let mut fixture = Fixture::new();
+    let Announced {
+        toplevel, wlr, ext, ..
+    } = announced_toplevel(&mut fixture);
+
+    fixture.send(toplevel.toplevel, DESTROY_TOPLEVEL, &[]);
+    refresh(&mut fixture);
+
+    assert_eq!(fixture.opcodes(wlr), vec![WLR_CLOSED]);
+    assert_eq!(fixture.opcodes(ext), vec![EXT_CLOSED]);
+}
+
+#[test]
+fn new_xdg_toplevel_is_announced_as_new_toplevel() {
+    let mut fixture = Fixture::new();
+    let Announced {
+        toplevel,
+        managers,
+        wlr,
+        ext,
+    } = announced_toplevel(&mut fixture);
+    let old_identifier = identifier(&mut fixture, &toplevel);
+
+    // The surface becomes a toplevel again before the next refresh.
+    fixture.send(toplevel.toplevel, DESTROY_TOPLEVEL, &[]);
+    let new_toplevel = fixture.client.new_id();
+    fixture.send(
+        toplevel.xdg_surface,
+        GET_TOPLEVEL,
+        &[Arg::NewId(new_toplevel)],
+    );
+    refresh(&mut fixture);
+
+    assert_eq!(fixture.opcodes(wlr), vec![WLR_CLOSED]);
+    assert_eq!(fixture.opcodes(ext), vec![EXT_CLOSED]);
+    assert_eq!(announced(&mut fixture, managers.wlr).len(), 1);
+    assert_eq!(announced(&mut fixture, managers.ext).len(), 1);
+    assert_ne!(identifier(&mut fixture, &toplevel), old_identifier);
+
+    // Requests of the closed handle no longer reach the toplevel.
+    fixture.send(wlr, CLOSE, &[]);
+    fixture.assert_no_error();
+    assert!(fixture.server.state.toplevel_requests.is_empty());
+}
+
+#[test]
+fn requests_are_routed_to_handler() {
+    let mut fixture = Fixture::new();
+    let wlr = announced_toplevel(&mut fixture).wlr;
+    let seat = fixture.client.bind("wl_seat", 1);
+
+    fixture.send(wlr, SET_MAXIMIZED, &[]);
+    fixture.send(wlr, UNSET_MINIMIZED, &[]);
+    fixture.send(wlr, ACTIVATE, &[Arg::Object(seat)]);
+    fixture.send(wlr, UNSET_FULLSCREEN, &[]);
+    fixture.send(wlr, CLOSE, &[]);
+    fixture.assert_no_error();
+
+    assert_eq!(
+        fixture.server.state.toplevel_requests,
+        vec![
+            "maximize",
+            "unminimize",
+            "activate",
+            "unfullscreen",
+            "close"
+        ]
+    );
+}
@@ -0,0 +1,125 @@
+use wayland_backend::server::{ClientId, ObjectId};
+use wayland_protocols::wp::fractional_scale::v1::server::{
+    wp_fractional_scale_manager_v1::{self, WpFractionalScaleManagerV1},
+    wp_fractional_scale_v1::{self, WpFractionalScaleV1},
+};
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use crate::EntityData;
+
+use super::{FractionalScaleHandler, FractionalScaleManager, PreferredScale};
+
+impl<State> GlobalDispatch<WpFractionalScaleManagerV1, (), State> for FractionalScaleManager
+where
+    State: GlobalDispatch<WpFractionalScaleManagerV1, ()>
+        + Dispatch<WpFractionalScaleManagerV1, ()>
+        + FractionalScaleHandler,
+{
+    fn bind(
+        _state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<WpFractionalScaleManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<State> Dispatch<WpFractionalScaleManagerV1, (), State> for FractionalScaleManager
+where
+    State: Dispatch<WpFractionalScaleManagerV1, ()>
+        + Dispatch<WpFractionalScaleV1, EntityData>
+        + FractionalScaleHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        manager: &WpFractionalScaleManagerV1,
+        request: wp_fractional_scale_manager_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            wp_fractional_scale_manager_v1::Request::Destroy => {
+                // wp_fractional_scale_v1 objects are unaffected by the global being destroyed.
+            }
+
+            wp_fractional_scale_manager_v1::Request::GetFractionalScale { id, surface } => {
+                let entity = surface.data::<EntityData>().unwrap().0;
+                let world = state.ecs().world();
+
+                match world.query_one_mut::<&PreferredScale>(entity) {
+                    Ok(preferred) if preferred.object.is_some() => {
+                        manager.post_error(
+                            wp_fractional_scale_manager_v1::Error::FractionalScaleExists,
+                            "Surface already has a fractional scale object",
+                        );
+                        return;
+                    }
+
+                    Ok(_) => (),
+
+                    Err(_) => {
+                        world
+                            .insert_one(entity, PreferredScale::default())
+                            .expect("Surface must be a valid entity if dispatched");
+                    }
+                }
+
+                let object = data_init.init(id, EntityData(entity));
+                let default = state
+                    .output_scales(&surface)
+                    .into_iter()
+                    .reduce(f64::max)
+                    .unwrap_or(1.0);
+                let preferred = state
+                    .ecs()
+                    .world()
+                    .query_one_mut::<&mut PreferredScale>(entity)
+                    .unwrap();
+                preferred.object = Some(object);
+                // A scale set before the client asked for it takes precedence over the default.
+                preferred.scale.get_or_insert(default);
+                preferred.send();
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<State> Dispatch<WpFractionalScaleV1, EntityData, State> for FractionalScaleManager
+where
+    State: Dispatch<WpFractionalScaleV1, EntityData> + FractionalScaleHandler,
+{
+    fn request(
+        _state: &mut State,
+        _client: &Client,
+        _resource: &WpFractionalScaleV1,
+        request: wp_fractional_scale_v1::Request,
+        _data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            wp_fractional_scale_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        if let Ok(preferred) = state
+            .ecs()
+            .world()
+            .query_one_mut::<&mut PreferredScale>(data.0)
+        {
+            preferred.object = None;
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! Implementation of the fractional-scale protocol.
+//!
+//! The compositor tells clients the scale it would prefer the content of a surface to be rendered at. A
+//! surface carries its preferred scale in a [`PreferredScale`], which is inserted the first time the scale of
+//! the surface is set or a client asks for the preferred scale. Whenever the preferred scale changes, it is sent
+//! to the client.
+//!
+//! The preferred scale is usually derived from the outputs the surface overlaps using
+//! [`FractionalScaleManager::set_preferred_scale_from_outputs`]. When a client asks for the preferred scale of
+//! a surface which has none yet, the default is derived from [`FractionalScaleHandler::output_scales`] and
+//! sent immediately.
+//!
+//! # Surface size
+//!
+//! Clients using fractional scaling attach buffers with a buffer scale of 1 and use a
+//! [`Viewport`](crate::viewporter::Viewport) destination to set the size of the surface. The logical size of
+//! the surface is therefore the one reported by [`Compositor::surface_size`], while
+//! [`Compositor::surface_scale`] reports the scale the buffer was rendered at. [`PreferredScale::buffer_size`]
+//! is the size of the buffer the client is expected to attach.
+//!
+//! [`Compositor::surface_size`]: crate::compositor::Compositor::surface_size
+//! [`Compositor::surface_scale`]: crate::compositor::Compositor::surface_scale
+
+mod dispatch;
+#[cfg(test)]
+mod tests;
+
+use smithay::utils::{Buffer, Logical, Size};
+use wayland_protocols::wp::fractional_scale::v1::server::{
+    wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
+    wp_fractional_scale_v1::WpFractionalScaleV1,
+};
+use wayland_server::{protocol::wl_surface::WlSurface, DisplayHandle, GlobalDispatch, Resource};
+
+use crate::{compositor::CompositorHandler, Ecs, EntityData};
+
+pub trait FractionalScaleHandler: CompositorHandler {
+    /// The scales of the outputs a surface overlaps.
+    ///
+    /// This is used for the default preferred scale of a surface. If the surface does not overlap any output,
+    /// the default is 1.
+    fn output_scales(&mut self, surface: &WlSurface) -> Vec<f64>;
+}
+
+pub struct FractionalScaleManager {}
+
+impl FractionalScaleManager {
+    pub fn new<State>(display: &mut DisplayHandle) -> Self
+    where
+        State: GlobalDispatch<WpFractionalScaleManagerV1, ()> + FractionalScaleHandler,
+    {
+        let _global = display.create_global::<State, WpFractionalScaleManagerV1, ()>(1, ());
+        Self {}
+    }
+
+    /// Sets the preferred scale of a surface.
+    ///
+    /// The scale is only sent to the client if it changed.
+    pub fn set_preferred_scale(ecs: &mut Ecs, surface: &WlSurface, scale: f64) {
+        let entity = surface.data::<EntityData>().unwrap().0;
+
+        if ecs.world.query_one_mut::<&PreferredScale>(entity).is_err()
+            && ecs
+                .world
+                .insert_one(entity, PreferredScale::default())
+                .is_err()
+        {
+            // The surface entity no longer exists.
+            return;
+        }
+
+        let preferred = ecs
+            .world
+            .query_one_mut::<&mut PreferredScale>(entity)
+            .unwrap();
+
+        if preferred.scale == Some(scale) {
+            return;
+        }
+
+        preferred.scale = Some(scale);
+        preferred.send();
+    }
+
+    /// Sets the preferred scale of a surface from the scales of the outputs the surface overlaps.
+    ///
+    /// The largest scale is preferred, so the surface looks sharp on every output it is shown on. If the surface
+    /// does not overlap any output, the preferred scale is left unchanged.
+    pub fn set_preferred_scale_from_outputs(
+        ecs: &mut Ecs,
+        surface: &WlSurface,
+        output_scales: impl IntoIterator<Item = f64>,
+    ) {
+        if let Some(scale) = output_scales.into_iter().reduce(f64::max) {
+            Self::set_preferred_scale(ecs, surface, scale);
+        }
+    }
+}
+
+/// The scale the compositor would prefer the content of a [`WlSurface`] to be rendered at.
+#[derive(Debug, Default)]
+pub struct PreferredScale {
+    scale: Option<f64>,
+    object: Option<WpFractionalScaleV1>,
+}
+
+impl PreferredScale {
+    /// The preferred scale.
+    ///
+    /// Returns [`None`] if no scale was set for the surface yet.
+    pub fn scale(&self) -> Option<f64> {
+        self.scale
+    }
+
+    /// Returns whether the client asked for the preferred scale of the surface.
+    pub fn is_used(&self) -> bool {
+        self.object.is_some()
+    }
+
+    /// The size of the buffer a client rendering at the preferred scale attaches for a surface of the specified
+    /// size.
+    ///
+    /// Quoting `wp_fractional_scale_v1`:
+    /// > The buffer size is calculated by multiplying the surface size by the intended scale, rounding half away
+    /// > from zero.
+    pub fn buffer_size(&self, size: Size<i32, Logical>) -> Size<i32, Buffer> {
+        let scale = self.scale.unwrap_or(1.0);
+        (
+            (size.w as f64 * scale).round() as i32,
+            (size.h as f64 * scale).round() as i32,
+        )
+            .into()
+    }
+
+    fn send(&self) {
+        if let (Some(scale), Some(object)) = (self.scale, self.object.as_ref()) {
+            // The scale is sent as a numerator of a fraction with a denominator of 120.
+            object.preferred_scale((scale * 120.0).round() as u32);
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use smithay::utils::Scale;
+
+use crate::{
+    compositor::{Compositor, SurfaceGeometry},
+    testing::{Arg, Fixture, TestSurface},
+};
+
+use super::FractionalScaleManager;
+
+// wp_fractional_scale_manager_v1 requests
+const GET_FRACTIONAL_SCALE: u16 = 1;
+
+// wp_viewporter requests
+const GET_VIEWPORT: u16 = 1;
+
+// wp_viewport requests
+const SET_SOURCE: u16 = 1;
+const SET_DESTINATION: u16 = 2;
+
+// wp_fractional_scale_v1 events
+const PREFERRED_SCALE: u16 = 0;
+
+/// Creates a fixture whose surfaces overlap outputs with the given scales.
+fn fixture(output_scales: &[f64]) -> Fixture {
+    let mut fixture = Fixture::new();
+    fixture.server.state.output_scales = output_scales.to_vec();
+    fixture
+}
+
+fn get_fractional_scale(fixture: &mut Fixture, surface: &TestSurface) -> u32 {
+    let manager = fixture.client.bind("wp_fractional_scale_manager_v1", 1);
+    let id = fixture.client.new_id();
+    fixture.send(
+        manager,
+        GET_FRACTIONAL_SCALE,
+        &[Arg::NewId(id), Arg::Object(surface.id)],
+    );
+    fixture.assert_no_error();
+    id
+}
+
+/// The preferred scales sent to the client, as numerators of a fraction with a denominator of 120.
+fn preferred_scales(fixture: &mut Fixture, fractional_scale: u32) -> Vec<u32> {
+    fixture
+        .events(fractional_scale)
+        .into_iter()
+        .filter(|event| event.opcode == PREFERRED_SCALE)
+        .map(|event| event.uint(0))
+        .collect()
+}
+
+fn get_viewport(fixture: &mut Fixture, surface: &TestSurface) -> u32 {
+    let viewporter = fixture.client.bind("wp_viewporter", 1);
+    let id = fixture.client.new_id();
+    fixture.send(
+        viewporter,
+        GET_VIEWPORT,
+        &[Arg::NewId(id), Arg::Object(surface.id)],
+    );
+    id
+}
+
+fn surface_geometry(fixture: &mut Fixture, surface: &TestSurface) -> SurfaceGeometry {
+    *fixture
+        .world()
+        .query_one_mut::<&SurfaceGeometry>(surface.entity)
+        .unwrap()
+}
+
+#[test]
+fn default_scale_is_sent_on_creation() {
+    let mut fixture = fixture(&[1.25, 1.5]);
+    let surface = fixture.create_surface();
+    let fractional_scale = get_fractional_scale(&mut fixture, &surface);
+
+    // The largest scale of the overlapped outputs is preferred.
+    assert_eq!(preferred_scales(&mut fixture, fractional_scale), vec![180]);
+}
+
+#[test]
+fn default_scale_without_outputs_is_one() {
+    let mut fixture = fixture(&[]);
+    let surface = fixture.create_surface();
+    let fractional_scale = get_fractional_scale(&mut fixture, &surface);
+
+    assert_eq!(preferred_scales(&mut fixture, fractional_scale), vec![120]);
+}
+
+#[test]
+fn scale_set_before_creation_takes_precedence() {
+    let mut fixture = fixture(&[1.5]);
+    let surface = fixture.create_surface();
+    FractionalScaleManager::set_preferred_scale(
+        &mut fixture.server.state.ecs,
+        &surface.surface,
+        2.0,
+    );
+
+    let fractional_scale = get_fractional_scale(&mut fixture, &surface);
+    assert_eq!(preferred_scales(&mut fixture, fractional_scale), vec![240]);
+
+    // Setting the same scale again sends nothing.
+    FractionalScaleManager::set_preferred_scale(
+        &mut fixture.server.state.ecs,
+        &surface.surface,
+        2.0,
+    );
+    FractionalScaleManager::set_preferred_scale_from_outputs(
+        &mut fixture.server.state.ecs,
+        &surface.surface,
+        [1.0, 1.75],
+    );
+    assert_eq!(preferred_scales(&mut fixture, fractional_scale), vec![210]);
+}
+
+#[test]
+fn surface_size_uses_viewport_and_fractional_scale() {
+    let mut fixture = fixture(&[1.5]);
+    let surface = fixture.create_surface();
+    get_fractional_scale(&mut fixture, &surface);
+
+    // A client rendering at a scale of 1.5 attaches a buffer of 150x90 for a surface of 100x60.
+    let viewport = get_viewport(&mut fixture, &surface);
+    fixture.send(viewport, SET_DESTINATION, &[Arg::Int(100), Arg::Int(60)]);
+    let mut pool = fixture.create_pool(150 * 90 * 4);
+    let buffer = fixture.create_buffer(&mut pool, 150, 90);
+    fixture.attach(surface.id, buffer);
+    fixture.commit(surface.id);
+
+    let geometry = surface_geometry(&mut fixture, &surface);
+    assert_eq!(geometry.buffer_size(), Some((150, 90).into()));
+    assert_eq!(geometry.size(), Some((100, 60).into()));
+    assert_eq!(geometry.scale(), Some(Scale { x: 1.5, y: 1.5 }));
+    assert_eq!(
+        Compositor::surface_size(&mut fixture.server.state.ecs, &surface.surface),
+        Some((100, 60).into())
+    );
+
+    // Cropping the buffer to a source rectangle shows fewer pixels on the same surface.
+    fixture.send(
+        viewport,
+        SET_SOURCE,
+        // wl_fixed values
+        &[
+            Arg::Int(0),
+            Arg::Int(0),
+            Arg::Int(75 * 256),
+            Arg::Int(45 * 256),
+        ],
+    );
+    fixture.commit(surface.id);
+
+    let geometry = surface_geometry(&mut fixture, &surface);
+    assert_eq!(geometry.size(), Some((100, 60).into()));
+    assert_eq!(geometry.scale(), Some(Scale { x: 0.75, y: 0.75 }));
+}
@@ -0,0 +1,106 @@
+use wayland_backend::server::{ClientId, ObjectId};
+use wayland_protocols::wp::idle_inhibit::zv1::server::{
+    zwp_idle_inhibit_manager_v1::{self, ZwpIdleInhibitManagerV1},
+    zwp_idle_inhibitor_v1::{self, ZwpIdleInhibitorV1},
+};
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use crate::{EcsAccess, EntityData};
+
+use super::{IdleInhibitManager, IdleInhibitors};
+
+impl<State> GlobalDispatch<ZwpIdleInhibitManagerV1, (), State> for IdleInhibitManager
+where
+    State: GlobalDispatch<ZwpIdleInhibitManagerV1, ()>
+        + Dispatch<ZwpIdleInhibitManagerV1, ()>
+        + EcsAccess,
+{
+    fn bind(
+        _state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwpIdleInhibitManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<State> Dispatch<ZwpIdleInhibitManagerV1, (), State> for IdleInhibitManager
+where
+    State: Dispatch<ZwpIdleInhibitManagerV1, ()>
+        + Dispatch<ZwpIdleInhibitorV1, EntityData>
+        + EcsAccess,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        _resource: &ZwpIdleInhibitManagerV1,
+        request: zwp_idle_inhibit_manager_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwp_idle_inhibit_manager_v1::Request::CreateInhibitor { id, surface } => {
+                let entity = surface.data::<EntityData>().unwrap().0;
+                let inhibitor = data_init.init(id, EntityData(entity));
+                let world = state.ecs().world();
+
+                match world.query_one_mut::<&mut IdleInhibitors>(entity) {
+                    Ok(inhibitors) => inhibitors.0.push(inhibitor),
+                    Err(_) => world
+                        .insert_one(entity, IdleInhibitors(vec![inhibitor]))
+                        .expect("Surface must be a valid entity if dispatched"),
+                }
+            }
+
+            zwp_idle_inhibit_manager_v1::Request::Destroy => {
+                // Inhibitors are unaffected by the manager being destroyed.
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<State> Dispatch<ZwpIdleInhibitorV1, EntityData, State> for IdleInhibitManager
+where
+    State: Dispatch<ZwpIdleInhibitorV1, EntityData> + EcsAccess,
+{
+    fn request(
+        _state: &mut State,
+        _client: &Client,
+        _resource: &ZwpIdleInhibitorV1,
+        request: zwp_idle_inhibitor_v1::Request,
+        _data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwp_idle_inhibitor_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, resource: ObjectId, data: &EntityData) {
+        let world = state.ecs().world();
+
+        // The surface may have been destroyed before the inhibitor.
+        let empty = match world.query_one_mut::<&mut IdleInhibitors>(data.0) {
+            Ok(inhibitors) => {
+                inhibitors.0.retain(|inhibitor| inhibitor.id() != resource);
+                inhibitors.0.is_empty()
+            }
+            Err(_) => false,
+        };
+
+        if empty {
+            let _ = world.remove_one::<IdleInhibitors>(data.0);
+        }
+    }
+}
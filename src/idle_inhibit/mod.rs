@@ -0,0 +1,57 @@
+//! Implementation of the idle inhibit protocol.
+//!
+//! Clients such as video players keep the system from going idle while one of their surfaces is visible. The
+//! inhibitors of a surface are stored in the [`IdleInhibitors`] of the surface entity, which is removed once the
+//! last inhibitor is destroyed.
+//!
+//! Whether a surface is visible depends on the compositor, so [`IdleInhibitManager::is_inhibited`] asks the
+//! compositor about every mapped surface with inhibitors.
+
+mod dispatch;
+#[cfg(test)]
+mod tests;
+
+use hecs::Entity;
+use wayland_protocols::wp::idle_inhibit::zv1::server::{
+    zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1, zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1,
+};
+use wayland_server::{protocol::wl_surface::WlSurface, DisplayHandle, GlobalDispatch};
+
+use crate::{compositor::SurfaceGeometry, Ecs, EcsAccess};
+
+pub struct IdleInhibitManager {}
+
+impl IdleInhibitManager {
+    pub fn new<State>(display: &mut DisplayHandle) -> Self
+    where
+        State: GlobalDispatch<ZwpIdleInhibitManagerV1, ()> + EcsAccess,
+    {
+        let _global = display.create_global::<State, ZwpIdleInhibitManagerV1, ()>(1, ());
+        Self {}
+    }
+
+    /// Returns whether a visible surface inhibits idle.
+    ///
+    /// `is_visible` is called with every mapped surface which has inhibitors, and decides whether the surface
+    /// is visible to the user, for example whether it is shown on an output and not occluded.
+    pub fn is_inhibited(
+        ecs: &mut Ecs,
+        mut is_visible: impl FnMut(Entity, &WlSurface) -> bool,
+    ) -> bool {
+        ecs.world
+            .query_mut::<(&IdleInhibitors, &SurfaceGeometry, &WlSurface)>()
+            .into_iter()
+            .filter(|(_, (_, geometry, _))| geometry.is_mapped())
+            .any(|(entity, (_, _, surface))| is_visible(entity, surface))
+    }
+}
+
+/// The idle inhibitors of a [`WlSurface`].
+#[derive(Debug, Default)]
+pub struct IdleInhibitors(Vec<ZwpIdleInhibitorV1>);
+
+impl IdleInhibitors {
+    pub fn iter(&self) -> impl Iterator<Item = &ZwpIdleInhibitorV1> {
+        self.0.iter()
+    }
+}
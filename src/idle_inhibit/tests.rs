@@ -0,0 +1,83 @@
+use crate::testing::{Arg, Fixture, TestSurface};
+
+use super::{IdleInhibitManager, IdleInhibitors};
+
+// zwp_idle_inhibit_manager_v1 requests
+const CREATE_INHIBITOR: u16 = 1;
+
+// zwp_idle_inhibitor_v1 requests
+const DESTROY: u16 = 0;
+
+fn create_inhibitor(fixture: &mut Fixture, surface: &TestSurface) -> u32 {
+    let manager = fixture.client.bind("zwp_idle_inhibit_manager_v1", 1);
+    let inhibitor = fixture.client.new_id();
+    fixture.send(
+        manager,
+        CREATE_INHIBITOR,
+        &[Arg::NewId(inhibitor), Arg::Object(surface.id)],
+    );
+    fixture.assert_no_error();
+    inhibitor
+}
+
+fn map(fixture: &mut Fixture, surface: &TestSurface) {
+    let mut pool = fixture.create_pool(4 * 4 * 4);
+    let buffer = fixture.create_buffer(&mut pool, 4, 4);
+    fixture.attach(surface.id, buffer);
+    fixture.commit(surface.id);
+}
+
+/// Returns whether idle is inhibited and the surfaces the visibility callback was asked about.
+fn is_inhibited(fixture: &mut Fixture, visible: bool) -> (bool, Vec<u32>) {
+    let mut asked = Vec::new();
+    let inhibited =
+        IdleInhibitManager::is_inhibited(&mut fixture.server.state.ecs, |_, surface| {
+            asked.push(wayland_server::Resource::id(surface).protocol_id());
+            visible
+        });
+    (inhibited, asked)
+}
+
+#[test]
+fn unmapped_surface_does_not_inhibit() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    create_inhibitor(&mut fixture, &surface);
+
+    assert_eq!(is_inhibited(&mut fixture, true), (false, vec![]));
+}
+
+#[test]
+fn visibility_decides_whether_surface_inhibits() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    let other = fixture.create_surface();
+    map(&mut fixture, &other);
+    create_inhibitor(&mut fixture, &surface);
+    map(&mut fixture, &surface);
+
+    // Only surfaces with inhibitors are asked about.
+    assert_eq!(is_inhibited(&mut fixture, true), (true, vec![surface.id]));
+    assert_eq!(is_inhibited(&mut fixture, false), (false, vec![surface.id]));
+}
+
+#[test]
+fn destroying_last_inhibitor_removes_inhibitors() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    let first = create_inhibitor(&mut fixture, &surface);
+    let second = create_inhibitor(&mut fixture, &surface);
+    map(&mut fixture, &surface);
+
+    fixture.send(first, DESTROY, &[]);
+    fixture.assert_no_error();
+    assert!(is_inhibited(&mut fixture, true).0);
+
+    fixture.send(second, DESTROY, &[]);
+    fixture.assert_no_error();
+    assert!(fixture
+        .world()
+        .query_one_mut::<&IdleInhibitors>(surface.entity)
+        .is_err());
+    assert_eq!(is_inhibited(&mut fixture, true), (false, vec![]));
+}
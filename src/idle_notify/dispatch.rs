@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use wayland_backend::server::{ClientId, ObjectId};
+use wayland_protocols::ext::idle_notify::v1::server::{
+    ext_idle_notification_v1::{self, ExtIdleNotificationV1},
+    ext_idle_notifier_v1::{self, ExtIdleNotifierV1},
+};
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New};
+
+use crate::{seat::Seat, EcsAccess, EntityData};
+
+use super::{IdleNotification, IdleNotifier};
+
+impl<State> GlobalDispatch<ExtIdleNotifierV1, (), State> for IdleNotifier
+where
+    State: GlobalDispatch<ExtIdleNotifierV1, ()> + Dispatch<ExtIdleNotifierV1, ()> + EcsAccess,
+{
+    fn bind(
+        _state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ExtIdleNotifierV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<State> Dispatch<ExtIdleNotifierV1, (), State> for IdleNotifier
+where
+    State:
+        Dispatch<ExtIdleNotifierV1, ()> + Dispatch<ExtIdleNotificationV1, EntityData> + EcsAccess,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        _resource: &ExtIdleNotifierV1,
+        request: ext_idle_notifier_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            ext_idle_notifier_v1::Request::GetIdleNotification { id, timeout, seat } => {
+                let seat = Seat::entity_of(&seat);
+                let entity = state.ecs().world().reserve_entity();
+                let object = data_init.init(id, EntityData(entity));
+
+                state
+                    .ecs()
+                    .world()
+                    .insert_one(
+                        entity,
+                        IdleNotification {
+                            object,
+                            seat,
+                            timeout: Duration::from_millis(timeout as u64),
+                            start: None,
+                            idle: false,
+                        },
+                    )
+                    .expect("Entity was reserved");
+            }
+
+            ext_idle_notifier_v1::Request::Destroy => {
+                // Notifications are unaffected by the notifier being destroyed.
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<State> Dispatch<ExtIdleNotificationV1, EntityData, State> for IdleNotifier
+where
+    State: Dispatch<ExtIdleNotificationV1, EntityData> + EcsAccess,
+{
+    fn request(
+        _state: &mut State,
+        _client: &Client,
+        _resource: &ExtIdleNotificationV1,
+        request: ext_idle_notification_v1::Request,
+        _data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            ext_idle_notification_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        let _ = state.ecs().world().despawn(data.0);
+    }
+}
@@ -0,0 +1,192 @@
+//! Implementation of the idle notify protocol.
+//!
+//! Clients such as screen lockers and power managers ask to be notified once the user of a seat has been idle
+//! for a timeout. The compositor reports user activity using [`IdleNotifier::notify_activity`], which is stored
+//! in the [`IdleTracker`] of the seat entity. Keys and buttons forwarded to the [seat](crate::seat) are
+//! recorded as activity at the time of the event. Every `ext_idle_notification_v1` is an entity with an
+//! [`IdleNotification`] belonging to a seat.
+//!
+//! # Time
+//!
+//! The idle tracker does not read a clock itself. Every time is a [`Duration`] since an epoch chosen by the
+//! compositor, for example the start of a monotonic clock, which also allows driving the tracker with a virtual
+//! clock. The time of input events passed to the seat must use the same clock. Idle notifications are sent by
+//! [`IdleNotifier::update`], which returns when it needs to be called next.
+//!
+//! The timeout of a new notification starts with the first update after it was created, or with the last
+//! activity of the seat if that is later. A seat without any recorded activity is not treated as idle since
+//! the epoch. The compositor should update the tracker after dispatching clients.
+//!
+//! # Inhibitors
+//!
+//! While a visible surface has [idle inhibitors](crate::idle_inhibit), the user is treated as active on every
+//! seat. The idle timeouts start once the surface is hidden or the inhibitors are destroyed.
+
+mod dispatch;
+
+#[cfg(test)]
+mod tests;
+
+use std::time::Duration;
+
+use hecs::Entity;
+use wayland_protocols::ext::idle_notify::v1::server::{
+    ext_idle_notification_v1::ExtIdleNotificationV1, ext_idle_notifier_v1::ExtIdleNotifierV1,
+};
+use wayland_server::{protocol::wl_surface::WlSurface, DisplayHandle, GlobalDispatch};
+
+use crate::{idle_inhibit::IdleInhibitManager, Ecs, EcsAccess};
+
+pub struct IdleNotifier {}
+
+impl IdleNotifier {
+    pub fn new<State>(display: &mut DisplayHandle) -> Self
+    where
+        State: GlobalDispatch<ExtIdleNotifierV1, ()> + EcsAccess,
+    {
+        let _global = display.create_global::<State, ExtIdleNotifierV1, ()>(1, ());
+        Self {}
+    }
+
+    /// Records activity of the user of a seat, such as a key press or pointer motion.
+    ///
+    /// Idle notifications of the seat are resumed.
+    pub fn notify_activity(ecs: &mut Ecs, seat: Entity, now: Duration) {
+        let world = &mut ecs.world;
+
+        match world.query_one_mut::<&mut IdleTracker>(seat) {
+            Ok(tracker) => tracker.last_activity = tracker.last_activity.max(now),
+            // The tracker is only inserted once the seat is used.
+            Err(_) => world
+                .insert_one(seat, IdleTracker { last_activity: now })
+                .expect("Not a seat entity"),
+        }
+
+        for (_, notification) in world
+            .query_mut::<&mut IdleNotification>()
+            .into_iter()
+            .filter(|(_, notification)| notification.seat == seat && notification.idle)
+        {
+            notification.idle = false;
+            notification.object.resumed();
+        }
+    }
+
+    /// Sends the idled event to every notification whose timeout passed.
+    ///
+    /// `is_visible` decides whether a surface with idle inhibitors is visible, see
+    /// [`IdleInhibitManager::is_inhibited`]. Returns the time at which the next notification becomes idle, or
+    /// [`None`] if no notification is waiting.
+    pub fn update(
+        ecs: &mut Ecs,
+        now: Duration,
+        is_visible: impl FnMut(Entity, &WlSurface) -> bool,
+    ) -> Option<Duration> {
+        if IdleInhibitManager::is_inhibited(ecs, is_visible) {
+            let mut seats = ecs
+                .world
+                .query_mut::<&IdleNotification>()
+                .into_iter()
+                .map(|(_, notification)| notification.seat)
+                .collect::<Vec<_>>();
+            seats.extend(
+                ecs.world
+                    .query_mut::<&IdleTracker>()
+                    .into_iter()
+                    .map(|(seat, _)| seat),
+            );
+            seats.sort();
+            seats.dedup();
+
+            for seat in seats {
+                Self::notify_activity(ecs, seat, now);
+            }
+
+            return None;
+        }
+
+        let trackers = ecs
+            .world
+            .query_mut::<&IdleTracker>()
+            .into_iter()
+            .map(|(seat, tracker)| (seat, tracker.last_activity))
+            .collect::<Vec<_>>();
+
+        let mut next = None;
+
+        for (_, notification) in ecs
+            .world
+            .query_mut::<&mut IdleNotification>()
+            .into_iter()
+            .filter(|(_, notification)| !notification.idle)
+        {
+            // The timeout of a new notification starts now, not at the epoch.
+            let start = *notification.start.get_or_insert(now);
+            let last_activity = trackers
+                .iter()
+                .find(|(seat, _)| *seat == notification.seat)
+                .map_or(start, |(_, last_activity)| start.max(*last_activity));
+            let deadline = last_activity + notification.timeout;
+
+            if deadline <= now {
+                notification.idle = true;
+                notification.object.idled();
+            } else {
+                next = Some(next.map_or(deadline, |next: Duration| next.min(deadline)));
+            }
+        }
+
+        next
+    }
+
+    /// Returns how long the user of a seat has been idle, or [`None`] if no activity was recorded on the seat.
+    pub fn idle_time(ecs: &mut Ecs, seat: Entity, now: Duration) -> Option<Duration> {
+        ecs.world
+            .query_one_mut::<&IdleTracker>(seat)
+            .map(|tracker| now.saturating_sub(tracker.last_activity))
+            .ok()
+    }
+}
+
+/// The activity of the user of a seat.
+///
+/// This is inserted the first time activity is recorded on the seat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdleTracker {
+    last_activity: Duration,
+}
+
+impl IdleTracker {
+    /// The time of the most recent activity.
+    pub fn last_activity(&self) -> Duration {
+        self.last_activity
+    }
+}
+
+/// An idle notification of a client.
+#[derive(Debug)]
+pub struct IdleNotification {
+    object: ExtIdleNotificationV1,
+    seat: Entity,
+    timeout: Duration,
+    /// The time of the first update after the notification was created.
+    start: Option<Duration>,
+    idle: bool,
+}
+
+impl IdleNotification {
+    /// The seat entity of the notification.
+    pub fn seat(&self) -> Entity {
+        self.seat
+    }
+
+    /// How long the user must be idle before the client is notified.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Returns whether the idled event was sent without the user becoming active since.
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+}
@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use wayland_server::protocol::{wl_keyboard, wl_pointer};
+
+use crate::{
+    seat::Seat,
+    testing::{Arg, Fixture},
+};
+
+use super::IdleNotifier;
+
+// ext_idle_notifier_v1 requests
+const GET_IDLE_NOTIFICATION: u16 = 1;
+
+// ext_idle_notification_v1 events
+const IDLED: u16 = 0;
+const RESUMED: u16 = 1;
+
+fn get_notification(fixture: &mut Fixture, timeout: Duration) -> u32 {
+    let seat = fixture.client.bind("wl_seat", 7);
+    let notifier = fixture.client.bind("ext_idle_notifier_v1", 1);
+    let notification = fixture.client.new_id();
+    fixture.send(
+        notifier,
+        GET_IDLE_NOTIFICATION,
+        &[
+            Arg::NewId(notification),
+            Arg::Uint(timeout.as_millis() as u32),
+            Arg::Object(seat),
+        ],
+    );
+    fixture.assert_no_error();
+    notification
+}
+
+fn update(fixture: &mut Fixture, now: Duration) -> Option<Duration> {
+    IdleNotifier::update(&mut fixture.server.state.ecs, now, |_, _| true)
+}
+
+fn secs(secs: u64) -> Duration {
+    Duration::from_secs(secs)
+}
+
+#[test]
+fn timeout_starts_at_first_update() {
+    let mut fixture = Fixture::new();
+    let notification = get_notification(&mut fixture, secs(5));
+
+    // Without any activity on the seat, the notification is not idle since the epoch.
+    assert_eq!(update(&mut fixture, secs(1000)), Some(secs(1005)));
+    assert_eq!(update(&mut fixture, secs(1004)), Some(secs(1005)));
+    assert_eq!(fixture.opcodes(notification), Vec::<u16>::new());
+
+    assert_eq!(update(&mut fixture, secs(1005)), None);
+    assert_eq!(fixture.opcodes(notification), vec![IDLED]);
+
+    let seat = fixture.seat();
+    assert_eq!(
+        IdleNotifier::idle_time(&mut fixture.server.state.ecs, seat, secs(1005)),
+        None
+    );
+}
+
+#[test]
+fn seat_input_is_activity() {
+    let mut fixture = Fixture::new();
+    let notification = get_notification(&mut fixture, secs(5));
+    let seat = fixture.seat();
+
+    update(&mut fixture, secs(10));
+    update(&mut fixture, secs(15));
+    assert_eq!(fixture.opcodes(notification), vec![IDLED]);
+
+    // A key resumes the notification and restarts the timeout.
+    Seat::keyboard_key(
+        &mut fixture.server.state.ecs,
+        seat,
+        secs(20),
+        30,
+        wl_keyboard::KeyState::Pressed,
+    );
+    assert_eq!(fixture.opcodes(notification), vec![RESUMED]);
+    assert_eq!(update(&mut fixture, secs(22)), Some(secs(25)));
+
+    Seat::pointer_button(
+        &mut fixture.server.state.ecs,
+        seat,
+        secs(24),
+        0x110,
+        wl_pointer::ButtonState::Pressed,
+    );
+    assert_eq!(update(&mut fixture, secs(25)), Some(secs(29)));
+    assert_eq!(
+        IdleNotifier::idle_time(&mut fixture.server.state.ecs, seat, secs(25)),
+        Some(secs(1))
+    );
+
+    assert_eq!(update(&mut fixture, secs(29)), None);
+    assert_eq!(fixture.opcodes(notification), vec![IDLED]);
+}
+
+#[test]
+fn activity_before_notification_counts() {
+    let mut fixture = Fixture::new();
+    let seat = fixture.seat();
+    IdleNotifier::notify_activity(&mut fixture.server.state.ecs, seat, secs(8));
+
+    let notification = get_notification(&mut fixture, secs(5));
+
+    // The timeout starts with the first update, which is later than the last activity.
+    assert_eq!(update(&mut fixture, secs(10)), Some(secs(15)));
+    assert_eq!(update(&mut fixture, secs(15)), None);
+    assert_eq!(fixture.opcodes(notification), vec![IDLED]);
+}
@@ -0,0 +1,330 @@
+use std::mem;
+
+use wayland_backend::server::{ClientId, ObjectId};
+use wayland_protocols_misc::zwp_input_method_v2::server::{
+    zwp_input_method_keyboard_grab_v2::{self, ZwpInputMethodKeyboardGrabV2},
+    zwp_input_method_manager_v2::{self, ZwpInputMethodManagerV2},
+    zwp_input_method_v2::{self, ZwpInputMethodV2},
+    zwp_input_popup_surface_v2::{self, ZwpInputPopupSurfaceV2},
+};
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use crate::{
+    compositor::{AlreadyHasRole, CompositorEvent, CompositorHandler, Role},
+    seat::Seat,
+    text_input::{self, TextInput, TextInputState},
+    EntityData,
+};
+
+use super::{
+    activate, input_method_of, text_input_focus, update_popups, InputMethod, InputMethodManager,
+    InputPopup, PendingInput,
+};
+
+impl<State> GlobalDispatch<ZwpInputMethodManagerV2, (), State> for InputMethodManager
+where
+    State: GlobalDispatch<ZwpInputMethodManagerV2, ()>
+        + Dispatch<ZwpInputMethodManagerV2, ()>
+        + CompositorHandler,
+{
+    fn bind(
+        _state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwpInputMethodManagerV2>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<State> Dispatch<ZwpInputMethodManagerV2, (), State> for InputMethodManager
+where
+    State: Dispatch<ZwpInputMethodManagerV2, ()>
+        + Dispatch<ZwpInputMethodV2, EntityData>
+        + CompositorHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        _resource: &ZwpInputMethodManagerV2,
+        request: zwp_input_method_manager_v2::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwp_input_method_manager_v2::Request::GetInputMethod { seat, input_method } => {
+                let seat = Seat::entity_of(&seat);
+                let entity = state.ecs().world().reserve_entity();
+                let object = data_init.init(input_method, EntityData(entity));
+                let world = state.ecs().world();
+
+                // A seat only has a single input method, the entity of any further one stays empty.
+                if input_method_of(world, seat).is_some() {
+                    object.unavailable();
+                    return;
+                }
+
+                world
+                    .insert_one(
+                        entity,
+                        InputMethod {
+                            object,
+                            seat,
+                            active: false,
+                            keyboard_grab: None,
+                            pending: PendingInput::default(),
+                        },
+                    )
+                    .expect("Entity was reserved");
+
+                // A text input may already be waiting for an input method.
+                let text_input_state = text_input_focus(world, seat).and_then(|(surface, _)| {
+                    world
+                        .query_one_mut::<&TextInputState>(surface.data::<EntityData>().unwrap().0)
+                        .ok()
+                        .cloned()
+                });
+
+                if let Some(text_input_state) = text_input_state {
+                    activate(world, seat, &text_input_state);
+                }
+            }
+
+            zwp_input_method_manager_v2::Request::Destroy => {
+                // Input methods are unaffected by the manager being destroyed.
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<State> Dispatch<ZwpInputMethodV2, EntityData, State> for InputMethodManager
+where
+    State: Dispatch<ZwpInputMethodV2, EntityData>
+        + Dispatch<ZwpInputPopupSurfaceV2, EntityData>
+        + Dispatch<ZwpInputMethodKeyboardGrabV2, EntityData>
+        + CompositorHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        resource: &ZwpInputMethodV2,
+        request: zwp_input_method_v2::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        let world = state.ecs().world();
+        let input_method = match world.query_one_mut::<&mut InputMethod>(data.0) {
+            Ok(input_method) => input_method,
+            Err(_) => {
+                // Requests of unavailable input methods are ignored, but the objects they create must still
+                // be initialized.
+                match request {
+                    zwp_input_method_v2::Request::GetInputPopupSurface { id, .. } => {
+                        data_init.init(id, *data);
+                    }
+                    zwp_input_method_v2::Request::GrabKeyboard { keyboard } => {
+                        data_init.init(keyboard, *data);
+                    }
+                    _ => (),
+                }
+                return;
+            }
+        };
+
+        match request {
+            zwp_input_method_v2::Request::CommitString { text } => {
+                input_method.pending.commit_string = Some(text);
+            }
+
+            zwp_input_method_v2::Request::SetPreeditString {
+                text,
+                cursor_begin,
+                cursor_end,
+            } => {
+                input_method.pending.preedit_string = Some((text, cursor_begin, cursor_end));
+            }
+
+            zwp_input_method_v2::Request::DeleteSurroundingText {
+                before_length,
+                after_length,
+            } => {
+                input_method.pending.delete_surrounding_text = Some((before_length, after_length));
+            }
+
+            zwp_input_method_v2::Request::Commit { serial: _ } => {
+                let pending = mem::take(&mut input_method.pending);
+                let seat = input_method.seat;
+
+                if !input_method.active {
+                    return;
+                }
+
+                let text_input = match text_input::active_text_input(world, seat)
+                    .and_then(|entity| world.query_one_mut::<&TextInput>(entity).ok())
+                {
+                    Some(text_input) => text_input,
+                    None => return,
+                };
+
+                if let Some((text, cursor_begin, cursor_end)) = pending.preedit_string {
+                    text_input
+                        .object
+                        .preedit_string(Some(text), cursor_begin, cursor_end);
+                }
+
+                if let Some(text) = pending.commit_string {
+                    text_input.object.commit_string(Some(text));
+                }
+
+                if let Some((before_length, after_length)) = pending.delete_surrounding_text {
+                    text_input
+                        .object
+                        .delete_surrounding_text(before_length, after_length);
+                }
+
+                text_input.object.done(text_input.commits);
+            }
+
+            zwp_input_method_v2::Request::GetInputPopupSurface { id, surface } => {
+                let entity = surface.data::<EntityData>().unwrap().0;
+                let seat = input_method.seat;
+                let role = world
+                    .query_one_mut::<&mut Role>(entity)
+                    .expect("Surface must be a valid entity if dispatched");
+
+                if role.role() != Some(InputMethodManager::POPUP_ROLE) {
+                    if let Err(AlreadyHasRole) = role.set_role(InputMethodManager::POPUP_ROLE) {
+                        // The protocol does not define an error enum, so the role error uses the first code.
+                        resource.post_error(0u32, "Surface already has a role");
+                        return;
+                    }
+
+                    state.ecs().push_event(CompositorEvent::RoleAssigned {
+                        surface: surface.clone(),
+                        entity,
+                        role: InputMethodManager::POPUP_ROLE,
+                    });
+                }
+
+                let object = data_init.init(id, EntityData(entity));
+                let world = state.ecs().world();
+                world
+                    .insert_one(
+                        entity,
+                        InputPopup {
+                            object,
+                            input_method: data.0,
+                        },
+                    )
+                    .expect("Surface must be a valid entity if dispatched");
+
+                let active = world
+                    .query_one_mut::<&InputMethod>(data.0)
+                    .map(|input_method| input_method.active)
+                    .unwrap_or(false);
+
+                if active {
+                    let cursor_rectangle = text_input_focus(world, seat).and_then(|(_, rect)| rect);
+                    update_popups(world, data.0, cursor_rectangle);
+                }
+            }
+
+            zwp_input_method_v2::Request::GrabKeyboard { keyboard } => {
+                input_method.keyboard_grab = Some(data_init.init(keyboard, *data));
+            }
+
+            zwp_input_method_v2::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        // Popups stay around, but are no longer shown since their input method is gone.
+        let _ = state.ecs().world().despawn(data.0);
+    }
+}
+
+impl<State> Dispatch<ZwpInputPopupSurfaceV2, EntityData, State> for InputMethodManager
+where
+    State: Dispatch<ZwpInputPopupSurfaceV2, EntityData> + CompositorHandler,
+{
+    fn request(
+        _state: &mut State,
+        _client: &Client,
+        _resource: &ZwpInputPopupSurfaceV2,
+        request: zwp_input_popup_surface_v2::Request,
+        _data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwp_input_popup_surface_v2::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, resource: ObjectId, data: &EntityData) {
+        // The surface keeps its role, but is no longer shown as a popup.
+        let world = state.ecs().world();
+        let is_popup = world
+            .query_one_mut::<&InputPopup>(data.0)
+            .map(|popup| popup.object.id() == resource)
+            .unwrap_or(false);
+
+        if is_popup {
+            let _ = world.remove_one::<InputPopup>(data.0);
+        }
+    }
+}
+
+impl<State> Dispatch<ZwpInputMethodKeyboardGrabV2, EntityData, State> for InputMethodManager
+where
+    State: Dispatch<ZwpInputMethodKeyboardGrabV2, EntityData> + CompositorHandler,
+{
+    fn request(
+        _state: &mut State,
+        _client: &Client,
+        _resource: &ZwpInputMethodKeyboardGrabV2,
+        request: zwp_input_method_keyboard_grab_v2::Request,
+        _data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwp_input_method_keyboard_grab_v2::Request::Release => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, resource: ObjectId, data: &EntityData) {
+        if let Ok(input_method) = state
+            .ecs()
+            .world()
+            .query_one_mut::<&mut InputMethod>(data.0)
+        {
+            if input_method
+                .keyboard_grab
+                .as_ref()
+                .map(|grab| grab.id() == resource)
+                .unwrap_or(false)
+            {
+                input_method.keyboard_grab = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,261 @@
+//! Implementation of the input method protocol.
+//!
+//! An input method is a client which composes text for the [text inputs](crate::text_input) of other clients,
+//! for example to enter CJK text. Every seat can have a single input method, which is an entity with an
+//! [`InputMethod`]. Further input methods of the same seat are told they are unavailable.
+//!
+//! The input method is activated while the surface with keyboard focus has an enabled text input, and receives
+//! the committed [`TextInputState`] of that text input. Text committed by the input method is sent to the text
+//! input.
+//!
+//! # Keyboard grab
+//!
+//! Input methods composing text from key events grab the keyboard of their seat. While
+//! [`InputMethodManager::keyboard_grab`] returns a grab, the compositor forwards keyboard events to it instead
+//! of the focused surface.
+//!
+//! # Popups
+//!
+//! Input methods show candidates in popup surfaces with the [`POPUP_ROLE`](InputMethodManager::POPUP_ROLE),
+//! which can be queried as an [`InputPopup`] from the [`WlSurface`]. Popups are only shown while the input method
+//! is active, and are placed below the cursor rectangle of the text input using
+//! [`InputMethodManager::popup_location`].
+
+mod dispatch;
+#[cfg(test)]
+mod tests;
+
+use std::mem;
+
+use hecs::Entity;
+use smithay::utils::{Logical, Point, Rectangle};
+use wayland_protocols_misc::zwp_input_method_v2::server::{
+    zwp_input_method_keyboard_grab_v2::ZwpInputMethodKeyboardGrabV2,
+    zwp_input_method_manager_v2::ZwpInputMethodManagerV2, zwp_input_method_v2::ZwpInputMethodV2,
+    zwp_input_popup_surface_v2::ZwpInputPopupSurfaceV2,
+};
+use wayland_server::{protocol::wl_surface::WlSurface, DisplayHandle, GlobalDispatch, Resource};
+
+use crate::{
+    compositor::CompositorHandler,
+    text_input::{self, TextInput, TextInputState},
+    Ecs, EntityData,
+};
+
+pub struct InputMethodManager {}
+
+impl InputMethodManager {
+    pub fn new<State>(display: &mut DisplayHandle) -> Self
+    where
+        State: GlobalDispatch<ZwpInputMethodManagerV2, ()> + CompositorHandler,
+    {
+        let _global = display.create_global::<State, ZwpInputMethodManagerV2, ()>(1, ());
+        Self {}
+    }
+
+    pub const POPUP_ROLE: &str = "input_popup";
+
+    /// Returns the keyboard grab of the input method of a seat.
+    ///
+    /// The compositor sends the keymap and repeat info to the grab when it is created, and forwards keyboard
+    /// events to it while it exists.
+    pub fn keyboard_grab(ecs: &mut Ecs, seat: Entity) -> Option<ZwpInputMethodKeyboardGrabV2> {
+        ecs.world
+            .query_mut::<&InputMethod>()
+            .into_iter()
+            .find(|(_, input_method)| input_method.seat == seat)
+            .and_then(|(_, input_method)| input_method.keyboard_grab.clone())
+    }
+
+    /// The location of an input method popup relative to the surface of the text input it belongs to.
+    ///
+    /// The popup is placed below the cursor rectangle of the text input, or at the top left corner of the
+    /// surface if the client did not set a cursor rectangle. Returns [`None`] if the popup is not shown, which
+    /// is the case while its input method is not active.
+    pub fn popup_location(
+        ecs: &mut Ecs,
+        popup: &WlSurface,
+    ) -> Option<(WlSurface, Point<i32, Logical>)> {
+        let entity = popup.data::<EntityData>()?.0;
+        let world = &mut ecs.world;
+        let input_method = world
+            .query_one_mut::<&InputPopup>(entity)
+            .ok()?
+            .input_method;
+        let seat = world
+            .query_one_mut::<&InputMethod>(input_method)
+            .ok()
+            .filter(|input_method| input_method.active)?
+            .seat;
+
+        let (surface, cursor_rectangle) = text_input_focus(world, seat)?;
+        let location = cursor_rectangle
+            .map(|rect| Point::from((rect.loc.x, rect.loc.y + rect.size.h)))
+            .unwrap_or_default();
+
+        Some((surface, location))
+    }
+}
+
+/// The input method of a seat.
+#[derive(Debug)]
+pub struct InputMethod {
+    object: ZwpInputMethodV2,
+    seat: Entity,
+    active: bool,
+    keyboard_grab: Option<ZwpInputMethodKeyboardGrabV2>,
+    pending: PendingInput,
+}
+
+impl InputMethod {
+    /// The seat entity of the input method.
+    pub fn seat(&self) -> Entity {
+        self.seat
+    }
+
+    /// Returns whether a text input activated the input method.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+}
+
+/// Text changes requested by an input method, which are applied by `zwp_input_method_v2.commit`.
+#[derive(Debug, Default)]
+struct PendingInput {
+    preedit_string: Option<(String, i32, i32)>,
+    commit_string: Option<String>,
+    delete_surrounding_text: Option<(u32, u32)>,
+}
+
+/// A popup surface of an input method.
+#[derive(Debug)]
+pub struct InputPopup {
+    object: ZwpInputPopupSurfaceV2,
+    input_method: Entity,
+}
+
+impl InputPopup {
+    /// The entity of the input method the popup belongs to.
+    pub fn input_method(&self) -> Entity {
+        self.input_method
+    }
+}
+
+/// Returns the input method entity of a seat.
+fn input_method_of(world: &mut hecs::World, seat: Entity) -> Option<Entity> {
+    world
+        .query_mut::<&InputMethod>()
+        .into_iter()
+        .find(|(_, input_method)| input_method.seat == seat)
+        .map(|(entity, _)| entity)
+}
+
+/// Returns the surface with the enabled text input of a seat and the cursor rectangle of the text input.
+fn text_input_focus(
+    world: &mut hecs::World,
+    seat: Entity,
+) -> Option<(WlSurface, Option<Rectangle<i32, Logical>>)> {
+    let text_input = text_input::active_text_input(world, seat)?;
+    let surface = world
+        .query_one_mut::<&TextInput>(text_input)
+        .ok()?
+        .focus
+        .clone()?;
+    let cursor_rectangle = world
+        .query_one_mut::<&TextInputState>(surface.data::<EntityData>().unwrap().0)
+        .ok()?
+        .cursor_rectangle;
+
+    Some((surface, cursor_rectangle))
+}
+
+/// Activates the input method of a seat for a newly enabled text input.
+pub(crate) fn activate(world: &mut hecs::World, seat: Entity, state: &TextInputState) {
+    let entity = match input_method_of(world, seat) {
+        Some(entity) => entity,
+        None => return,
+    };
+
+    let input_method = world
+        .query_one_mut::<&mut InputMethod>(entity)
+        .expect("Input method was queried");
+
+    // Activating resets the state of the input method.
+    input_method.active = true;
+    input_method.pending = PendingInput::default();
+    input_method.object.activate();
+    send_text_input_state(&input_method.object, state);
+
+    update_popups(world, entity, state.cursor_rectangle);
+}
+
+/// Sends the changed state of the enabled text input to the input method of a seat.
+pub(crate) fn send_state(world: &mut hecs::World, seat: Entity, state: &TextInputState) {
+    let entity = match input_method_of(world, seat) {
+        Some(entity) => entity,
+        None => return,
+    };
+
+    let input_method = world
+        .query_one_mut::<&InputMethod>(entity)
+        .expect("Input method was queried");
+
+    if !input_method.active {
+        return;
+    }
+
+    send_text_input_state(&input_method.object, state);
+    update_popups(world, entity, state.cursor_rectangle);
+}
+
+/// Deactivates the input method of a seat after its text input was disabled.
+pub(crate) fn deactivate(world: &mut hecs::World, seat: Entity) {
+    let input_method = match input_method_of(world, seat)
+        .and_then(|entity| world.query_one_mut::<&mut InputMethod>(entity).ok())
+    {
+        Some(input_method) => input_method,
+        None => return,
+    };
+
+    if mem::take(&mut input_method.active) {
+        input_method.object.deactivate();
+        input_method.object.done();
+    }
+}
+
+fn send_text_input_state(object: &ZwpInputMethodV2, state: &TextInputState) {
+    if let Some(surrounding_text) = &state.surrounding_text {
+        object.surrounding_text(
+            surrounding_text.text.clone(),
+            surrounding_text.cursor.max(0) as u32,
+            surrounding_text.anchor.max(0) as u32,
+        );
+    }
+
+    object.text_change_cause(state.change_cause);
+    object.content_type(state.content_hint, state.content_purpose);
+    object.done();
+}
+
+/// Tells the popups of an input method where the text input is.
+fn update_popups(
+    world: &mut hecs::World,
+    input_method: Entity,
+    cursor_rectangle: Option<Rectangle<i32, Logical>>,
+) {
+    let rect = match cursor_rectangle {
+        Some(rect) => rect,
+        None => return,
+    };
+
+    for (_, popup) in world
+        .query_mut::<&InputPopup>()
+        .into_iter()
+        .filter(|(_, popup)| popup.input_method == input_method)
+    {
+        // Popups are placed below the cursor rectangle, see `InputMethodManager::popup_location`.
+        popup
+            .object
+            .text_input_rectangle(0, -rect.size.h, rect.size.w, rect.size.h);
+    }
+}
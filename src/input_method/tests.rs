@@ -0,0 +1,167 @@
+use smithay::utils::Point;
+
+use crate::testing::{Arg, Fixture, TestSurface};
+
+use super::InputMethodManager;
+
+// zwp_text_input_manager_v3 requests
+const GET_TEXT_INPUT: u16 = 1;
+
+// zwp_text_input_v3 requests
+const ENABLE: u16 = 1;
+const SET_SURROUNDING_TEXT: u16 = 3;
+const SET_CURSOR_RECTANGLE: u16 = 6;
+const COMMIT: u16 = 7;
+
+// zwp_input_method_manager_v2 requests
+const GET_INPUT_METHOD: u16 = 0;
+
+// zwp_input_method_v2 requests
+const GET_INPUT_POPUP_SURFACE: u16 = 4;
+
+// zwp_input_method_v2 events
+const ACTIVATE: u16 = 0;
+const DEACTIVATE: u16 = 1;
+const SURROUNDING_TEXT: u16 = 2;
+const TEXT_CHANGE_CAUSE: u16 = 3;
+const CONTENT_TYPE: u16 = 4;
+const DONE: u16 = 5;
+const UNAVAILABLE: u16 = 6;
+
+// zwp_input_popup_surface_v2 events
+const TEXT_INPUT_RECTANGLE: u16 = 0;
+
+fn get_input_method(fixture: &mut Fixture) -> u32 {
+    let manager = fixture.client.bind("zwp_input_method_manager_v2", 1);
+    let seat = fixture.client.bind("wl_seat", 7);
+    let input_method = fixture.client.new_id();
+    fixture.send(
+        manager,
+        GET_INPUT_METHOD,
+        &[Arg::Object(seat), Arg::NewId(input_method)],
+    );
+    fixture.assert_no_error();
+    input_method
+}
+
+/// Creates a text input which entered a focused surface.
+fn focused_text_input(fixture: &mut Fixture) -> (TestSurface, u32) {
+    let surface = fixture.create_surface();
+    fixture.set_keyboard_focus(Some(&surface));
+
+    let manager = fixture.client.bind("zwp_text_input_manager_v3", 1);
+    let seat = fixture.client.bind("wl_seat", 7);
+    let text_input = fixture.client.new_id();
+    fixture.send(
+        manager,
+        GET_TEXT_INPUT,
+        &[Arg::NewId(text_input), Arg::Object(seat)],
+    );
+    fixture.assert_no_error();
+    (surface, text_input)
+}
+
+#[test]
+fn second_input_method_is_unavailable() {
+    let mut fixture = Fixture::new();
+    let first = get_input_method(&mut fixture);
+    let second = get_input_method(&mut fixture);
+
+    assert!(fixture.opcodes(first).is_empty());
+    assert_eq!(fixture.opcodes(second), vec![UNAVAILABLE]);
+}
+
+#[test]
+fn text_input_state_is_forwarded() {
+    let mut fixture = Fixture::new();
+    let input_method = get_input_method(&mut fixture);
+    let (_, text_input) = focused_text_input(&mut fixture);
+
+    fixture.send(text_input, ENABLE, &[]);
+    fixture.send(
+        text_input,
+        SET_SURROUNDING_TEXT,
+        &[Arg::Str("hello"), Arg::Int(2), Arg::Int(4)],
+    );
+    fixture.send(text_input, COMMIT, &[]);
+
+    let events = fixture.events(input_method);
+    assert_eq!(
+        events.iter().map(|event| event.opcode).collect::<Vec<_>>(),
+        vec![
+            ACTIVATE,
+            SURROUNDING_TEXT,
+            TEXT_CHANGE_CAUSE,
+            CONTENT_TYPE,
+            DONE
+        ]
+    );
+    assert_eq!(events[1].string(0), "hello");
+    // The string "hello" with its nul byte takes two words after the length.
+    assert_eq!((events[1].uint(3), events[1].uint(4)), (2, 4));
+
+    // Later commits only send the state.
+    fixture.send(text_input, COMMIT, &[]);
+    assert_eq!(
+        fixture.opcodes(input_method),
+        vec![SURROUNDING_TEXT, TEXT_CHANGE_CAUSE, CONTENT_TYPE, DONE]
+    );
+}
+
+#[test]
+fn input_method_is_deactivated_when_focus_leaves() {
+    let mut fixture = Fixture::new();
+    let input_method = get_input_method(&mut fixture);
+    let (_, text_input) = focused_text_input(&mut fixture);
+    fixture.send(text_input, ENABLE, &[]);
+    fixture.send(text_input, COMMIT, &[]);
+    fixture.events(input_method);
+
+    fixture.set_keyboard_focus(None);
+    assert_eq!(fixture.opcodes(input_method), vec![DEACTIVATE, DONE]);
+}
+
+#[test]
+fn popup_is_placed_below_cursor_rectangle() {
+    let mut fixture = Fixture::new();
+    let input_method = get_input_method(&mut fixture);
+    let (surface, text_input) = focused_text_input(&mut fixture);
+    let popup_surface = fixture.create_surface();
+    let popup = fixture.client.new_id();
+    fixture.send(
+        input_method,
+        GET_INPUT_POPUP_SURFACE,
+        &[Arg::NewId(popup), Arg::Object(popup_surface.id)],
+    );
+
+    // The popup is not shown while the input method is inactive.
+    assert!(fixture.opcodes(popup).is_empty());
+    assert!(InputMethodManager::popup_location(
+        &mut fixture.server.state.ecs,
+        &popup_surface.surface
+    )
+    .is_none());
+
+    fixture.send(text_input, ENABLE, &[]);
+    fixture.send(
+        text_input,
+        SET_CURSOR_RECTANGLE,
+        &[Arg::Int(10), Arg::Int(20), Arg::Int(4), Arg::Int(8)],
+    );
+    fixture.send(text_input, COMMIT, &[]);
+
+    // The cursor rectangle is relative to the popup, which is placed below it.
+    let events = fixture.events(popup);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].opcode, TEXT_INPUT_RECTANGLE);
+    assert_eq!(
+        (0..4)
+            .map(|word| events[0].uint(word) as i32)
+            .collect::<Vec<_>>(),
+        vec![0, -8, 4, 8]
+    );
+    assert_eq!(
+        InputMethodManager::popup_location(&mut fixture.server.state.ecs, &popup_surface.surface),
+        Some((surface.surface.clone(), Point::from((10, 28))))
+    );
+}
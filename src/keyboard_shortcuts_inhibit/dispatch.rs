@@ -0,0 +1,150 @@
+use wayland_backend::server::{ClientId, ObjectId};
+use wayland_protocols::wp::keyboard_shortcuts_inhibit::zv1::server::{
+    zwp_keyboard_shortcuts_inhibit_manager_v1::{self, ZwpKeyboardShortcutsInhibitManagerV1},
+    zwp_keyboard_shortcuts_inhibitor_v1::{self, ZwpKeyboardShortcutsInhibitorV1},
+};
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use crate::{
+    seat::{KeyboardFocus, Seat},
+    EcsAccess, EntityData,
+};
+
+use super::{
+    focus_changed, KeyboardShortcutsInhibitManager, KeyboardShortcutsInhibitor,
+    KeyboardShortcutsInhibitors,
+};
+
+impl<State> GlobalDispatch<ZwpKeyboardShortcutsInhibitManagerV1, (), State>
+    for KeyboardShortcutsInhibitManager
+where
+    State: GlobalDispatch<ZwpKeyboardShortcutsInhibitManagerV1, ()>
+        + Dispatch<ZwpKeyboardShortcutsInhibitManagerV1, ()>
+        + EcsAccess,
+{
+    fn bind(
+        _state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwpKeyboardShortcutsInhibitManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<State> Dispatch<ZwpKeyboardShortcutsInhibitManagerV1, (), State>
+    for KeyboardShortcutsInhibitManager
+where
+    State: Dispatch<ZwpKeyboardShortcutsInhibitManagerV1, ()>
+        + Dispatch<ZwpKeyboardShortcutsInhibitorV1, EntityData>
+        + EcsAccess,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        resource: &ZwpKeyboardShortcutsInhibitManagerV1,
+        request: zwp_keyboard_shortcuts_inhibit_manager_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwp_keyboard_shortcuts_inhibit_manager_v1::Request::InhibitShortcuts {
+                id,
+                surface,
+                seat,
+            } => {
+                let seat = Seat::entity_of(&seat);
+                let entity = surface.data::<EntityData>().unwrap().0;
+                let world = state.ecs().world();
+
+                let inhibited = world
+                    .query_one_mut::<&KeyboardShortcutsInhibitors>(entity)
+                    .map(|inhibitors| inhibitors.get(seat).is_some())
+                    .unwrap_or(false);
+
+                if inhibited {
+                    resource.post_error(
+                        zwp_keyboard_shortcuts_inhibit_manager_v1::Error::AlreadyInhibited,
+                        "Shortcuts are already inhibited for the surface and seat",
+                    );
+                    return;
+                }
+
+                let object = data_init.init(id, EntityData(entity));
+                let mut inhibitor = KeyboardShortcutsInhibitor {
+                    object,
+                    seat,
+                    active: false,
+                };
+
+                // The inhibitor is active immediately if the surface already has keyboard focus.
+                let focused = world
+                    .query_one_mut::<&KeyboardFocus>(seat)
+                    .map(|focus| focus.surface() == Some(&surface))
+                    .unwrap_or(false);
+                inhibitor.set_active(focused);
+
+                match world.query_one_mut::<&mut KeyboardShortcutsInhibitors>(entity) {
+                    Ok(inhibitors) => inhibitors.0.push(inhibitor),
+                    Err(_) => world
+                        .insert_one(entity, KeyboardShortcutsInhibitors(vec![inhibitor]))
+                        .expect("Surface must be a valid entity if dispatched"),
+                }
+
+                Seat::add_focus_system::<State>(state.ecs(), seat, focus_changed::<State>);
+            }
+
+            zwp_keyboard_shortcuts_inhibit_manager_v1::Request::Destroy => {
+                // Inhibitors are unaffected by the manager being destroyed.
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<State> Dispatch<ZwpKeyboardShortcutsInhibitorV1, EntityData, State>
+    for KeyboardShortcutsInhibitManager
+where
+    State: Dispatch<ZwpKeyboardShortcutsInhibitorV1, EntityData> + EcsAccess,
+{
+    fn request(
+        _state: &mut State,
+        _client: &Client,
+        _resource: &ZwpKeyboardShortcutsInhibitorV1,
+        request: zwp_keyboard_shortcuts_inhibitor_v1::Request,
+        _data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwp_keyboard_shortcuts_inhibitor_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, resource: ObjectId, data: &EntityData) {
+        let world = state.ecs().world();
+
+        // The surface may have been destroyed before the inhibitor.
+        let empty = match world.query_one_mut::<&mut KeyboardShortcutsInhibitors>(data.0) {
+            Ok(inhibitors) => {
+                inhibitors
+                    .0
+                    .retain(|inhibitor| inhibitor.object.id() != resource);
+                inhibitors.0.is_empty()
+            }
+            Err(_) => false,
+        };
+
+        if empty {
+            let _ = world.remove_one::<KeyboardShortcutsInhibitors>(data.0);
+        }
+    }
+}
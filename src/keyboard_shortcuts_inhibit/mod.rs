@@ -0,0 +1,143 @@
+//! Implementation of the keyboard shortcuts inhibit protocol.
+//!
+//! Clients such as remote desktop viewers and virtual machines ask the compositor to forward key combinations
+//! it would otherwise handle as shortcuts. The inhibitors of a surface are stored in the
+//! [`KeyboardShortcutsInhibitors`] of the surface entity, with at most one [`KeyboardShortcutsInhibitor`] per
+//! seat.
+//!
+//! An inhibitor is active while its surface has the keyboard focus of the seat. The compositor checks
+//! [`KeyboardShortcutsInhibitManager::is_inhibited`] before handling a shortcut, and may restore its shortcuts
+//! using [`KeyboardShortcutsInhibitManager::deactivate`], for example when the user presses a key combination
+//! reserved for this purpose.
+
+mod dispatch;
+#[cfg(test)]
+mod tests;
+
+use hecs::Entity;
+use wayland_protocols::wp::keyboard_shortcuts_inhibit::zv1::server::{
+    zwp_keyboard_shortcuts_inhibit_manager_v1::ZwpKeyboardShortcutsInhibitManagerV1,
+    zwp_keyboard_shortcuts_inhibitor_v1::ZwpKeyboardShortcutsInhibitorV1,
+};
+use wayland_server::{DisplayHandle, GlobalDispatch, Resource};
+
+use crate::{seat::KeyboardFocus, Ecs, EcsAccess, EntityData};
+
+pub struct KeyboardShortcutsInhibitManager {}
+
+impl KeyboardShortcutsInhibitManager {
+    pub fn new<State>(display: &mut DisplayHandle) -> Self
+    where
+        State: GlobalDispatch<ZwpKeyboardShortcutsInhibitManagerV1, ()> + EcsAccess,
+    {
+        let _global =
+            display.create_global::<State, ZwpKeyboardShortcutsInhibitManagerV1, ()>(1, ());
+        Self {}
+    }
+
+    /// Returns whether the surface with the keyboard focus of a seat inhibits shortcuts.
+    pub fn is_inhibited(ecs: &mut Ecs, seat: Entity) -> bool {
+        focused_inhibitor(&mut ecs.world, seat)
+            .map(|inhibitor| inhibitor.active)
+            .unwrap_or(false)
+    }
+
+    /// Restores the shortcuts of the compositor until the focused surface of a seat loses keyboard focus.
+    ///
+    /// The inhibitor is activated again the next time the surface gains keyboard focus.
+    pub fn deactivate(ecs: &mut Ecs, seat: Entity) {
+        if let Some(inhibitor) = focused_inhibitor(&mut ecs.world, seat) {
+            inhibitor.set_active(false);
+        }
+    }
+}
+
+/// The keyboard shortcuts inhibitors of a [`WlSurface`](wayland_server::protocol::wl_surface::WlSurface).
+///
+/// This is removed once the last inhibitor of the surface is destroyed.
+#[derive(Debug, Default)]
+pub struct KeyboardShortcutsInhibitors(Vec<KeyboardShortcutsInhibitor>);
+
+impl KeyboardShortcutsInhibitors {
+    /// The inhibitor of a seat.
+    pub fn get(&self, seat: Entity) -> Option<&KeyboardShortcutsInhibitor> {
+        self.0.iter().find(|inhibitor| inhibitor.seat == seat)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &KeyboardShortcutsInhibitor> {
+        self.0.iter()
+    }
+
+    fn get_mut(&mut self, seat: Entity) -> Option<&mut KeyboardShortcutsInhibitor> {
+        self.0.iter_mut().find(|inhibitor| inhibitor.seat == seat)
+    }
+}
+
+/// A keyboard shortcuts inhibitor of a surface.
+#[derive(Debug)]
+pub struct KeyboardShortcutsInhibitor {
+    object: ZwpKeyboardShortcutsInhibitorV1,
+    seat: Entity,
+    active: bool,
+}
+
+impl KeyboardShortcutsInhibitor {
+    /// The seat entity whose shortcuts are inhibited.
+    pub fn seat(&self) -> Entity {
+        self.seat
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Sends the active or inactive event if the state changed.
+    fn set_active(&mut self, active: bool) {
+        if self.active == active {
+            return;
+        }
+
+        self.active = active;
+
+        if active {
+            self.object.active();
+        } else {
+            self.object.inactive();
+        }
+    }
+}
+
+/// Returns the inhibitor of the surface which has the keyboard focus of a seat.
+fn focused_inhibitor(
+    world: &mut hecs::World,
+    seat: Entity,
+) -> Option<&mut KeyboardShortcutsInhibitor> {
+    let surface = world
+        .query_one_mut::<&KeyboardFocus>(seat)
+        .ok()?
+        .surface()?
+        .data::<EntityData>()
+        .unwrap()
+        .0;
+
+    world
+        .query_one_mut::<&mut KeyboardShortcutsInhibitors>(surface)
+        .ok()?
+        .get_mut(seat)
+}
+
+/// Activates the inhibitor of the surface with keyboard focus and deactivates all other inhibitors of a seat.
+fn focus_changed<State: EcsAccess>(state: &mut State, _display: &DisplayHandle, seat: Entity) {
+    let world = state.ecs().world();
+    let focus = world
+        .query_one_mut::<&KeyboardFocus>(seat)
+        .ok()
+        .and_then(|focus| focus.surface())
+        .map(|surface| surface.data::<EntityData>().unwrap().0);
+
+    for (entity, inhibitors) in world.query_mut::<&mut KeyboardShortcutsInhibitors>() {
+        if let Some(inhibitor) = inhibitors.get_mut(seat) {
+            inhibitor.set_active(Some(entity) == focus);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use wayland_protocols::wp::keyboard_shortcuts_inhibit::zv1::server::zwp_keyboard_shortcuts_inhibit_manager_v1;
+
+use crate::testing::{Arg, Fixture, TestSurface};
+
+use super::KeyboardShortcutsInhibitManager;
+
+// zwp_keyboard_shortcuts_inhibit_manager_v1 requests
+const INHIBIT_SHORTCUTS: u16 = 1;
+
+// zwp_keyboard_shortcuts_inhibitor_v1 events
+const ACTIVE: u16 = 0;
+const INACTIVE: u16 = 1;
+
+fn inhibit_shortcuts(fixture: &mut Fixture, surface: &TestSurface) -> u32 {
+    let manager = fixture
+        .client
+        .bind("zwp_keyboard_shortcuts_inhibit_manager_v1", 1);
+    let seat = fixture.client.bind("wl_seat", 7);
+    let inhibitor = fixture.client.new_id();
+    fixture.send(
+        manager,
+        INHIBIT_SHORTCUTS,
+        &[
+            Arg::NewId(inhibitor),
+            Arg::Object(surface.id),
+            Arg::Object(seat),
+        ],
+    );
+    inhibitor
+}
+
+fn is_inhibited(fixture: &mut Fixture) -> bool {
+    let seat = fixture.seat();
+    KeyboardShortcutsInhibitManager::is_inhibited(&mut fixture.server.state.ecs, seat)
+}
+
+#[test]
+fn second_inhibitor_for_surface_and_seat_is_error() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    inhibit_shortcuts(&mut fixture, &surface);
+    fixture.assert_no_error();
+
+    inhibit_shortcuts(&mut fixture, &surface);
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(zwp_keyboard_shortcuts_inhibit_manager_v1::Error::AlreadyInhibited as u32)
+    );
+}
+
+#[test]
+fn inhibitor_follows_keyboard_focus() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    let other = fixture.create_surface();
+    let inhibitor = inhibit_shortcuts(&mut fixture, &surface);
+    assert!(fixture.opcodes(inhibitor).is_empty());
+    assert!(!is_inhibited(&mut fixture));
+
+    fixture.set_keyboard_focus(Some(&surface));
+    assert_eq!(fixture.opcodes(inhibitor), vec![ACTIVE]);
+    assert!(is_inhibited(&mut fixture));
+
+    fixture.set_keyboard_focus(Some(&other));
+    assert_eq!(fixture.opcodes(inhibitor), vec![INACTIVE]);
+    assert!(!is_inhibited(&mut fixture));
+}
+
+#[test]
+fn inhibitor_of_focused_surface_is_active_immediately() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    fixture.set_keyboard_focus(Some(&surface));
+
+    let inhibitor = inhibit_shortcuts(&mut fixture, &surface);
+    assert_eq!(fixture.opcodes(inhibitor), vec![ACTIVE]);
+    assert!(is_inhibited(&mut fixture));
+}
+
+#[test]
+fn deactivated_inhibitor_is_activated_by_next_focus() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    let inhibitor = inhibit_shortcuts(&mut fixture, &surface);
+    fixture.set_keyboard_focus(Some(&surface));
+    fixture.events(inhibitor);
+
+    let seat = fixture.seat();
+    KeyboardShortcutsInhibitManager::deactivate(&mut fixture.server.state.ecs, seat);
+    assert_eq!(fixture.opcodes(inhibitor), vec![INACTIVE]);
+    assert!(!is_inhibited(&mut fixture));
+
+    fixture.set_keyboard_focus(None);
+    fixture.set_keyboard_focus(Some(&surface));
+    assert_eq!(fixture.opcodes(inhibitor), vec![ACTIVE]);
+    assert!(is_inhibited(&mut fixture));
+}
@@ -0,0 +1,271 @@
+use wayland_backend::server::{ClientId, ObjectId};
+use wayland_protocols_wlr::layer_shell::v1::server::{
+    zwlr_layer_shell_v1::{self, ZwlrLayerShellV1},
+    zwlr_layer_surface_v1::{self, ZwlrLayerSurfaceV1},
+};
+use wayland_server::{
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource, WEnum,
+};
+
+use crate::{
+    compositor::{AlreadyHasRole, Compositor, CompositorEvent, Role},
+    EntityData,
+};
+
+use super::{
+    post_commit, pre_commit, Anchor, DesiredSize, ExclusiveZone, KeyboardInteractivity, Layer,
+    LayerShell, LayerShellHandler, LayerState, LayerSurface, LayerSurfaceInternal, Margin,
+};
+
+impl<State> GlobalDispatch<ZwlrLayerShellV1, (), State> for LayerShell
+where
+    State:
+        GlobalDispatch<ZwlrLayerShellV1, ()> + Dispatch<ZwlrLayerShellV1, ()> + LayerShellHandler,
+{
+    fn bind(
+        _state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwlrLayerShellV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<State> Dispatch<ZwlrLayerShellV1, (), State> for LayerShell
+where
+    State: Dispatch<ZwlrLayerShellV1, ()>
+        + Dispatch<ZwlrLayerSurfaceV1, EntityData>
+        + LayerShellHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        shell: &ZwlrLayerShellV1,
+        request: zwlr_layer_shell_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwlr_layer_shell_v1::Request::Destroy => {
+                // Layer surfaces are unaffected by the global being destroyed.
+            }
+
+            zwlr_layer_shell_v1::Request::GetLayerSurface {
+                id,
+                surface,
+                output,
+                layer,
+                namespace,
+            } => {
+                let layer = match layer {
+                    WEnum::Value(layer) => layer,
+                    WEnum::Unknown(layer) => {
+                        shell.post_error(
+                            zwlr_layer_shell_v1::Error::InvalidLayer,
+                            format!("Unknown layer {layer}"),
+                        );
+                        return;
+                    }
+                };
+
+                let entity = surface.data::<EntityData>().unwrap().0;
+
+                if Compositor::attached_buffer::<State>(state.ecs(), &surface).is_some() {
+                    shell.post_error(
+                        zwlr_layer_shell_v1::Error::AlreadyConstructed,
+                        "Surface already has a buffer attached",
+                    );
+                    return;
+                }
+
+                let role = state
+                    .ecs()
+                    .world()
+                    .query_one_mut::<&mut Role>(entity)
+                    .expect("Surface must be a valid entity if dispatched");
+
+                if let Err(AlreadyHasRole) = role.set_role(LayerShell::ROLE) {
+                    shell.post_error(
+                        zwlr_layer_shell_v1::Error::Role,
+                        "Surface already has a role",
+                    );
+                    return;
+                }
+
+                state.ecs().push_event(CompositorEvent::RoleAssigned {
+                    surface: surface.clone(),
+                    entity,
+                    role: LayerShell::ROLE,
+                });
+
+                let object = data_init.init(id, EntityData(entity));
+                let initial = LayerState::new(layer);
+                let world = state.ecs().world();
+                initial.insert(world, entity);
+                world
+                    .insert(
+                        entity,
+                        (
+                            LayerSurface {
+                                object,
+                                output: output.clone(),
+                                namespace: namespace.clone(),
+                                pending_configures: Vec::new(),
+                                last_acked: None,
+                            },
+                            LayerSurfaceInternal { pending: initial },
+                        ),
+                    )
+                    .expect("Surface must be a valid entity if dispatched");
+
+                Compositor::add_pre_commit::<State>(state.ecs(), &surface, pre_commit::<State>);
+                Compositor::add_post_commit::<State>(state.ecs(), &surface, post_commit::<State>);
+
+                state.new_layer_surface(&surface, output, namespace);
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<State> Dispatch<ZwlrLayerSurfaceV1, EntityData, State> for LayerShell
+where
+    State: Dispatch<ZwlrLayerSurfaceV1, EntityData> + LayerShellHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        layer_surface: &ZwlrLayerSurfaceV1,
+        request: zwlr_layer_surface_v1::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        let (role_object, internal) = state
+            .ecs()
+            .world()
+            .query_one_mut::<(&mut LayerSurface, &mut LayerSurfaceInternal)>(data.0)
+            .expect("Layer surface exists until destroyed");
+        let pending = &mut internal.pending;
+
+        match request {
+            zwlr_layer_surface_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            zwlr_layer_surface_v1::Request::SetSize { width, height } => {
+                if width > i32::MAX as u32 || height > i32::MAX as u32 {
+                    layer_surface.post_error(
+                        zwlr_layer_surface_v1::Error::InvalidSize,
+                        format!("Invalid size {width}x{height}"),
+                    );
+                    return;
+                }
+
+                pending.size = (width as i32, height as i32).into();
+            }
+
+            zwlr_layer_surface_v1::Request::SetAnchor { anchor } => match anchor {
+                WEnum::Value(anchor) => pending.anchor = anchor,
+                WEnum::Unknown(anchor) => {
+                    layer_surface.post_error(
+                        zwlr_layer_surface_v1::Error::InvalidAnchor,
+                        format!("Invalid anchor {anchor}"),
+                    );
+                }
+            },
+
+            zwlr_layer_surface_v1::Request::SetExclusiveZone { zone } => {
+                pending.exclusive_zone = zone;
+            }
+
+            zwlr_layer_surface_v1::Request::SetMargin {
+                top,
+                right,
+                bottom,
+                left,
+            } => {
+                pending.margin = Margin {
+                    top,
+                    right,
+                    bottom,
+                    left,
+                };
+            }
+
+            zwlr_layer_surface_v1::Request::SetKeyboardInteractivity {
+                keyboard_interactivity,
+            } => match keyboard_interactivity {
+                WEnum::Value(keyboard_interactivity) => {
+                    pending.keyboard_interactivity = keyboard_interactivity;
+                }
+                WEnum::Unknown(keyboard_interactivity) => {
+                    layer_surface.post_error(
+                        zwlr_layer_surface_v1::Error::InvalidKeyboardInteractivity,
+                        format!("Invalid keyboard interactivity {keyboard_interactivity}"),
+                    );
+                }
+            },
+
+            zwlr_layer_surface_v1::Request::GetPopup { .. } => {
+                // xdg_popup is not implemented, so the client cannot have a valid popup to parent.
+                layer_surface.post_error(
+                    zwlr_layer_surface_v1::Error::InvalidSurfaceState,
+                    "Popups of layer surfaces are not supported",
+                );
+            }
+
+            zwlr_layer_surface_v1::Request::AckConfigure { serial } => {
+                match role_object
+                    .pending_configures
+                    .iter()
+                    .position(|&s| s == serial)
+                {
+                    // Acknowledging a configure also acknowledges every configure sent before it.
+                    Some(index) => {
+                        role_object.pending_configures.drain(..=index);
+                        role_object.last_acked = Some(serial);
+                    }
+
+                    None => {
+                        layer_surface.post_error(
+                            zwlr_layer_surface_v1::Error::InvalidSurfaceState,
+                            format!("Serial {serial} does not belong to a configure"),
+                        );
+                    }
+                }
+            }
+
+            zwlr_layer_surface_v1::Request::SetLayer { layer } => match layer {
+                WEnum::Value(layer) => pending.layer = layer,
+                WEnum::Unknown(layer) => {
+                    layer_surface.post_error(
+                        zwlr_layer_surface_v1::Error::InvalidSurfaceState,
+                        format!("Unknown layer {layer}"),
+                    );
+                }
+            },
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        // The surface keeps its role, but is no longer shown as a layer surface.
+        let _ = state.ecs().world().remove::<(
+            LayerSurface,
+            LayerSurfaceInternal,
+            Layer,
+            Anchor,
+            ExclusiveZone,
+            Margin,
+            KeyboardInteractivity,
+            DesiredSize,
+        )>(data.0);
+    }
+}
@@ -0,0 +1,380 @@
+//! Implementation of the wlr-layer-shell protocol.
+//!
+//! Layer surfaces are used for panels, docks, backgrounds and similar surfaces which are placed relative to the
+//! edges of an output instead of being managed as windows.
+//!
+//! # Components
+//!
+//! A layer surface is a surface entity with the [`LayerSurface`] role object. The committed state of the
+//! layer surface is stored in the [`Layer`], [`Anchor`], [`ExclusiveZone`], [`Margin`],
+//! [`KeyboardInteractivity`] and [`DesiredSize`] components, which can be queried from the [`WlSurface`] of a
+//! layer surface. Like other surface state, these only change when a commit of the surface is applied.
+//!
+//! # Configuring
+//!
+//! After the initial commit of a layer surface, the compositor decides on a size and sends it using
+//! [`LayerShell::send_configure`]. The client must acknowledge a configure before attaching a buffer.
+//!
+//! # Exclusive zones
+//!
+//! Panels reserve space at an edge of an output using an exclusive zone. [`non_exclusive_zone`] computes the
+//! area of an output which is left for windows.
+
+mod dispatch;
+#[cfg(test)]
+mod tests;
+
+use smithay::utils::{Logical, Rectangle, Size};
+use wayland_protocols_wlr::layer_shell::v1::server::{
+    zwlr_layer_shell_v1::{self, ZwlrLayerShellV1},
+    zwlr_layer_surface_v1::{self, ZwlrLayerSurfaceV1},
+};
+use wayland_server::{
+    protocol::{wl_output::WlOutput, wl_surface::WlSurface},
+    DisplayHandle, GlobalDispatch, Resource,
+};
+
+use crate::{
+    compositor::{BufferAssignment, Compositor, CompositorHandler},
+    next_serial, Ecs, EntityData,
+};
+
+pub trait LayerShellHandler: CompositorHandler {
+    /// A client created a layer surface.
+    ///
+    /// `output` is the output the client wants the surface to be shown on. If it is [`None`], the compositor
+    /// chooses the output.
+    fn new_layer_surface(
+        &mut self,
+        surface: &WlSurface,
+        output: Option<WlOutput>,
+        namespace: String,
+    );
+}
+
+pub struct LayerShell {}
+
+impl LayerShell {
+    pub fn new<State>(display: &mut DisplayHandle) -> Self
+    where
+        State: GlobalDispatch<ZwlrLayerShellV1, ()> + LayerShellHandler,
+    {
+        let _global = display.create_global::<State, ZwlrLayerShellV1, ()>(4, ());
+        Self {}
+    }
+
+    pub const ROLE: &str = "zwlr_layer_surface_v1";
+
+    /// Sends a configure event with the size of the layer surface.
+    ///
+    /// A width or height of zero lets the client choose that dimension. Returns the serial of the configure, or
+    /// [`None`] if the surface is not a layer surface.
+    pub fn send_configure(
+        ecs: &mut Ecs,
+        surface: &WlSurface,
+        size: Size<i32, Logical>,
+    ) -> Option<u32> {
+        let entity = surface.data::<EntityData>()?.0;
+        let layer_surface = ecs.world.query_one_mut::<&mut LayerSurface>(entity).ok()?;
+
+        let serial = next_serial();
+        layer_surface.pending_configures.push(serial);
+        layer_surface
+            .object
+            .configure(serial, size.w as u32, size.h as u32);
+        Some(serial)
+    }
+
+    /// Tells the client the layer surface will not be shown anymore, for example because its output was
+    /// removed.
+    pub fn close(ecs: &mut Ecs, surface: &WlSurface) {
+        let entity = surface.data::<EntityData>().unwrap().0;
+
+        if let Ok(layer_surface) = ecs.world.query_one_mut::<&LayerSurface>(entity) {
+            layer_surface.object.closed();
+        }
+    }
+}
+
+/// The role object of a layer surface.
+#[derive(Debug)]
+pub struct LayerSurface {
+    object: ZwlrLayerSurfaceV1,
+    output: Option<WlOutput>,
+    namespace: String,
+    /// Serials of configure events which were not acknowledged yet, oldest first.
+    pending_configures: Vec<u32>,
+    last_acked: Option<u32>,
+}
+
+impl LayerSurface {
+    /// The output the client wants the surface to be shown on.
+    pub fn output(&self) -> Option<&WlOutput> {
+        self.output.as_ref()
+    }
+
+    /// The namespace of the layer surface, which describes its purpose such as "panel" or "wallpaper".
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// The serial of the most recently acknowledged configure.
+    pub fn last_acked(&self) -> Option<u32> {
+        self.last_acked
+    }
+}
+
+/// The layer a layer surface is shown on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layer(zwlr_layer_shell_v1::Layer);
+
+impl Layer {
+    pub fn get(&self) -> zwlr_layer_shell_v1::Layer {
+        self.0
+    }
+}
+
+/// The edges of the output a layer surface is anchored to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Anchor(zwlr_layer_surface_v1::Anchor);
+
+impl Anchor {
+    pub fn get(&self) -> zwlr_layer_surface_v1::Anchor {
+        self.0
+    }
+
+    /// The single edge the exclusive zone of the surface applies to.
+    ///
+    /// The exclusive zone only applies if the surface is anchored to one edge, or to one edge and both
+    /// perpendicular edges.
+    fn exclusive_edge(&self) -> Option<zwlr_layer_surface_v1::Anchor> {
+        use zwlr_layer_surface_v1::Anchor as A;
+
+        let anchor = self.0;
+        [A::Top, A::Bottom, A::Left, A::Right]
+            .into_iter()
+            .find(|&edge| {
+                let perpendicular = if edge == A::Top || edge == A::Bottom {
+                    A::Left | A::Right
+                } else {
+                    A::Top | A::Bottom
+                };
+
+                anchor == edge || anchor == edge | perpendicular
+            })
+    }
+}
+
+/// The exclusive zone of a layer surface.
+///
+/// A positive zone is the distance from the anchored edge which other surfaces should not cover. A zone of 0
+/// means the surface should be moved to avoid the exclusive zones of other surfaces, while -1 means the surface
+/// should be placed at its anchor regardless of other surfaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExclusiveZone(i32);
+
+impl ExclusiveZone {
+    pub fn get(&self) -> i32 {
+        self.0
+    }
+}
+
+/// The distance of a layer surface from the edges it is anchored to.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Margin {
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+    pub left: i32,
+}
+
+/// How a layer surface receives keyboard focus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyboardInteractivity(zwlr_layer_surface_v1::KeyboardInteractivity);
+
+impl KeyboardInteractivity {
+    pub fn get(&self) -> zwlr_layer_surface_v1::KeyboardInteractivity {
+        self.0
+    }
+}
+
+/// The size the client requested for a layer surface.
+///
+/// A width or height of zero means the client lets the compositor choose that dimension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DesiredSize(Size<i32, Logical>);
+
+impl DesiredSize {
+    pub fn get(&self) -> Size<i32, Logical> {
+        self.0
+    }
+}
+
+/// Computes the area of an output which is not covered by the exclusive zones of layer surfaces.
+///
+/// `output` is the geometry of the output and `surfaces` are all layer surfaces shown on the output. Exclusive
+/// zones are applied in the order of the surfaces.
+pub fn non_exclusive_zone<'a>(
+    ecs: &mut Ecs,
+    output: Rectangle<i32, Logical>,
+    surfaces: impl IntoIterator<Item = &'a WlSurface>,
+) -> Rectangle<i32, Logical> {
+    use zwlr_layer_surface_v1::Anchor as A;
+
+    let mut zone = output;
+
+    for surface in surfaces {
+        let entity = match surface.data::<EntityData>() {
+            Some(data) => data.0,
+            None => continue,
+        };
+
+        let (anchor, exclusive_zone, margin) = match ecs
+            .world
+            .query_one_mut::<(&Anchor, &ExclusiveZone, &Margin)>(entity)
+        {
+            Ok((anchor, exclusive_zone, margin)) => (*anchor, exclusive_zone.0, *margin),
+            Err(_) => continue,
+        };
+
+        if exclusive_zone <= 0 {
+            continue;
+        }
+
+        match anchor.exclusive_edge() {
+            Some(A::Top) => {
+                let amount = (exclusive_zone + margin.top).min(zone.size.h);
+                zone.loc.y += amount;
+                zone.size.h -= amount;
+            }
+
+            Some(A::Bottom) => {
+                let amount = (exclusive_zone + margin.bottom).min(zone.size.h);
+                zone.size.h -= amount;
+            }
+
+            Some(A::Left) => {
+                let amount = (exclusive_zone + margin.left).min(zone.size.w);
+                zone.loc.x += amount;
+                zone.size.w -= amount;
+            }
+
+            Some(A::Right) => {
+                let amount = (exclusive_zone + margin.right).min(zone.size.w);
+                zone.size.w -= amount;
+            }
+
+            _ => (),
+        }
+    }
+
+    zone
+}
+
+/// The double-buffered state of a layer surface.
+#[derive(Debug, Clone, Copy)]
+struct LayerState {
+    layer: zwlr_layer_shell_v1::Layer,
+    anchor: zwlr_layer_surface_v1::Anchor,
+    exclusive_zone: i32,
+    margin: Margin,
+    keyboard_interactivity: zwlr_layer_surface_v1::KeyboardInteractivity,
+    size: Size<i32, Logical>,
+}
+
+impl LayerState {
+    fn new(layer: zwlr_layer_shell_v1::Layer) -> Self {
+        Self {
+            layer,
+            anchor: zwlr_layer_surface_v1::Anchor::empty(),
+            exclusive_zone: 0,
+            margin: Margin::default(),
+            keyboard_interactivity: zwlr_layer_surface_v1::KeyboardInteractivity::None,
+            size: (0, 0).into(),
+        }
+    }
+
+    /// Inserts the state as the committed components of a surface.
+    fn insert(&self, world: &mut hecs::World, entity: hecs::Entity) {
+        world
+            .insert(
+                entity,
+                (
+                    Layer(self.layer),
+                    Anchor(self.anchor),
+                    ExclusiveZone(self.exclusive_zone),
+                    self.margin,
+                    KeyboardInteractivity(self.keyboard_interactivity),
+                    DesiredSize(self.size),
+                ),
+            )
+            .expect("Surface must be a valid entity if alive");
+    }
+}
+
+/// Internal component with the pending state of a layer surface.
+struct LayerSurfaceInternal {
+    pending: LayerState,
+}
+
+/// Validates the pending state and stores it in the commit.
+fn pre_commit<State: LayerShellHandler>(state: &mut State, surface: &WlSurface) {
+    use zwlr_layer_surface_v1::Anchor as A;
+
+    let entity = surface.data::<EntityData>().unwrap().0;
+    let attaches_buffer = matches!(
+        Compositor::pending_buffer::<State>(state.ecs(), surface),
+        Some(BufferAssignment::NewBuffer(_))
+    );
+
+    let (layer_surface, internal) = match state
+        .ecs()
+        .world()
+        .query_one_mut::<(&LayerSurface, &mut LayerSurfaceInternal)>(entity)
+    {
+        Ok(components) => components,
+        // The layer surface was destroyed.
+        Err(_) => return,
+    };
+
+    if attaches_buffer && layer_surface.last_acked.is_none() {
+        layer_surface.object.post_error(
+            zwlr_layer_surface_v1::Error::InvalidSurfaceState,
+            "A buffer was attached before the first configure was acknowledged",
+        );
+        Compositor::discard_commit::<State>(state.ecs(), surface);
+        return;
+    }
+
+    let pending = internal.pending;
+    let anchor = pending.anchor;
+
+    if (pending.size.w == 0 && !anchor.contains(A::Left | A::Right))
+        || (pending.size.h == 0 && !anchor.contains(A::Top | A::Bottom))
+    {
+        layer_surface.object.post_error(
+            zwlr_layer_surface_v1::Error::InvalidSize,
+            "A dimension of zero requires the surface to be anchored to both opposite edges",
+        );
+        Compositor::discard_commit::<State>(state.ecs(), surface);
+        return;
+    }
+
+    Compositor::insert_commit_state::<State, _>(state.ecs(), surface, pending);
+}
+
+/// Applies the state stored in the commit to the components of the surface.
+fn post_commit<State: LayerShellHandler>(state: &mut State, surface: &WlSurface) {
+    let entity = surface.data::<EntityData>().unwrap().0;
+
+    let committed = match Compositor::take_commit_state::<State, LayerState>(state.ecs(), surface) {
+        Some(committed) => committed,
+        None => return,
+    };
+
+    // The layer surface may have been destroyed while the commit was waiting in a transaction.
+    let world = state.ecs().world();
+    if world.query_one_mut::<&LayerSurface>(entity).is_ok() {
+        committed.insert(world, entity);
+    }
+}
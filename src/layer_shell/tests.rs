@@ -0,0 +1,109 @@
+use wayland_protocols_wlr::layer_shell::v1::server::{zwlr_layer_shell_v1, zwlr_layer_surface_v1};
+
+use crate::testing::{Arg, Fixture, TestSurface};
+
+use super::ExclusiveZone;
+
+// zwlr_layer_shell_v1 requests
+const GET_LAYER_SURFACE: u16 = 0;
+
+// zwlr_layer_surface_v1 requests
+const SET_SIZE: u16 = 0;
+const SET_ANCHOR: u16 = 1;
+const SET_EXCLUSIVE_ZONE: u16 = 2;
+const SET_LAYER: u16 = 8;
+
+/// Sends `get_layer_surface` for a surface on the top layer, without an output, and returns the
+/// `zwlr_layer_surface_v1`.
+fn get_layer_surface(fixture: &mut Fixture, surface: &TestSurface) -> u32 {
+    let shell = fixture.client.bind("zwlr_layer_shell_v1", 4);
+    let layer_surface = fixture.client.new_id();
+    fixture.send(
+        shell,
+        GET_LAYER_SURFACE,
+        &[
+            Arg::NewId(layer_surface),
+            Arg::Object(surface.id),
+            Arg::Object(0),
+            Arg::Uint(zwlr_layer_shell_v1::Layer::Top as u32),
+            Arg::Str("panel"),
+        ],
+    );
+    layer_surface
+}
+
+/// Creates a layer surface on the top layer, without an output, and returns the `zwlr_layer_surface_v1`.
+fn create_layer_surface(fixture: &mut Fixture) -> (TestSurface, u32) {
+    let surface = fixture.create_surface();
+    let layer_surface = get_layer_surface(fixture, &surface);
+    // Anchoring to every edge lets the compositor choose the size.
+    fixture.send(layer_surface, SET_ANCHOR, &[Arg::Uint(0xf)]);
+    fixture.assert_no_error();
+
+    (surface, layer_surface)
+}
+
+fn exclusive_zone(fixture: &mut Fixture, surface: &TestSurface) -> i32 {
+    fixture
+        .world()
+        .query_one_mut::<&ExclusiveZone>(surface.entity)
+        .unwrap()
+        .get()
+}
+
+#[test]
+fn exclusive_zone_is_applied_on_commit() {
+    let mut fixture = Fixture::new();
+    let (surface, layer_surface) = create_layer_surface(&mut fixture);
+
+    fixture.send(layer_surface, SET_EXCLUSIVE_ZONE, &[Arg::Int(10)]);
+    fixture.dispatch();
+    assert_eq!(exclusive_zone(&mut fixture, &surface), 0);
+
+    fixture.commit(surface.id);
+    assert_eq!(exclusive_zone(&mut fixture, &surface), 10);
+}
+
+#[test]
+fn unknown_layer_is_error() {
+    let mut fixture = Fixture::new();
+    let (_, layer_surface) = create_layer_surface(&mut fixture);
+
+    fixture.send(layer_surface, SET_LAYER, &[Arg::Uint(7)]);
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(zwlr_layer_surface_v1::Error::InvalidSurfaceState as u32)
+    );
+}
+
+#[test]
+fn size_above_i32_max_is_error() {
+    let mut fixture = Fixture::new();
+    let (_, layer_surface) = create_layer_surface(&mut fixture);
+
+    fixture.send(
+        layer_surface,
+        SET_SIZE,
+        &[Arg::Uint(i32::MAX as u32 + 1), Arg::Uint(10)],
+    );
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(zwlr_layer_surface_v1::Error::InvalidSize as u32)
+    );
+}
+
+#[test]
+fn surface_with_pending_buffer_is_already_constructed() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    let mut pool = fixture.create_pool(4 * 4 * 4);
+    let buffer = fixture.create_buffer(&mut pool, 4, 4);
+
+    // The buffer is only attached, not committed.
+    fixture.attach(surface.id, buffer);
+    get_layer_surface(&mut fixture, &surface);
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(zwlr_layer_shell_v1::Error::AlreadyConstructed as u32)
+    );
+}
@@ -9,10 +9,45 @@
 //!
 
 pub mod compositor;
+pub mod cursor_shape;
+pub mod data_control;
+pub mod data_device;
+pub mod dmabuf;
+pub mod drm_syncobj;
+pub mod foreign_toplevel;
+pub mod fractional_scale;
+pub mod idle_inhibit;
+pub mod idle_notify;
+pub mod input_method;
+pub mod keyboard_shortcuts_inhibit;
+pub mod layer_shell;
+pub mod pointer_constraints;
+pub mod presentation;
+pub mod primary_selection;
+pub mod protocols;
+pub mod relative_pointer;
+pub mod screencopy;
+pub mod seat;
+pub mod session_lock;
 pub mod shm;
+pub mod text_input;
+pub mod viewporter;
+pub mod xdg_decoration;
 pub mod xdg_shell;
 
-use std::fmt::Debug;
+#[cfg(test)]
+mod testing;
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    ffi::CStr,
+    fmt::Debug,
+    fs::File,
+    io::{self, Write},
+    os::unix::io::{AsRawFd, FromRawFd},
+    sync::atomic::{AtomicU32, Ordering},
+};
 
 pub use hecs;
 use hecs::{Entity, Query, QueryItem, QueryOneError};
@@ -22,12 +57,15 @@ use wayland_server::Resource;
 
 pub struct Ecs {
     world: hecs::World,
+    /// Event queues, keyed by the type of event.
+    events: HashMap<TypeId, Box<dyn Any>>,
 }
 
 impl Ecs {
     pub fn new() -> Self {
         Self {
             world: hecs::World::new(),
+            events: HashMap::new(),
         }
     }
 
@@ -46,6 +84,45 @@ impl Ecs {
             .0;
         self.world().query_one_mut::<Q>(entity)
     }
+
+    /// Enables the event queue for some type of event.
+    ///
+    /// Events are only recorded if the queue for the event type is enabled. This avoids accumulating events
+    /// which nothing will ever drain.
+    pub fn enable_events<E: 'static>(&mut self) {
+        self.events
+            .entry(TypeId::of::<E>())
+            .or_insert_with(|| Box::new(Vec::<E>::new()));
+    }
+
+    /// Pushes an event into the queue for the event type.
+    ///
+    /// The event is dropped if the queue is not enabled.
+    pub fn push_event<E: 'static>(&mut self, event: E) {
+        if let Some(queue) = self.events.get_mut(&TypeId::of::<E>()) {
+            queue
+                .downcast_mut::<Vec<E>>()
+                .expect("Event queue has wrong type")
+                .push(event);
+        }
+    }
+
+    /// Takes all events of some type which were pushed since the queue was last drained.
+    ///
+    /// Events are returned in the order they were pushed. This is intended to be called by systems once per
+    /// frame.
+    pub fn drain_events<E: 'static>(&mut self) -> Vec<E> {
+        self.events
+            .get_mut(&TypeId::of::<E>())
+            .map(|queue| {
+                std::mem::take(
+                    queue
+                        .downcast_mut::<Vec<E>>()
+                        .expect("Event queue has wrong type"),
+                )
+            })
+            .unwrap_or_default()
+    }
 }
 
 impl Debug for Ecs {
@@ -58,6 +135,33 @@ pub trait EcsAccess: 'static {
     fn ecs(&mut self) -> &mut Ecs;
 }
 
+/// Returns a new serial for events which clients acknowledge, such as configure events.
+pub(crate) fn next_serial() -> u32 {
+    static SERIAL: AtomicU32 = AtomicU32::new(1);
+    SERIAL.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Creates a sealed memfd containing the data, which is shared read-only with clients.
+pub(crate) fn create_sealed_file(name: &CStr, data: &[u8]) -> io::Result<File> {
+    let fd =
+        unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING) };
+
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: memfd_create returned a new file descriptor which nothing else owns.
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    file.write_all(data)?;
+
+    let seals = libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE | libc::F_SEAL_SEAL;
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_ADD_SEALS, seals) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(file)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct EntityData(Entity);
 
@@ -0,0 +1,327 @@
+use wayland_backend::server::{ClientId, ObjectId};
+use wayland_protocols::wp::pointer_constraints::zv1::server::{
+    zwp_confined_pointer_v1::{self, ZwpConfinedPointerV1},
+    zwp_locked_pointer_v1::{self, ZwpLockedPointerV1},
+    zwp_pointer_constraints_v1::{self, ZwpPointerConstraintsV1},
+};
+use wayland_server::{
+    protocol::{wl_pointer::WlPointer, wl_region::WlRegion, wl_surface::WlSurface},
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource, WEnum,
+};
+
+use crate::{
+    compositor::{Compositor, Region, RegionData},
+    seat::Seat,
+    EntityData,
+};
+
+use super::{
+    focus_changed, post_commit, pre_commit, update, ConstraintKind, ConstraintObject, Lifetime,
+    PendingConstraint, PointerConstraint, PointerConstraints, PointerConstraintsHandler,
+    PointerConstraintsManager,
+};
+
+impl<State> GlobalDispatch<ZwpPointerConstraintsV1, (), State> for PointerConstraintsManager
+where
+    State: GlobalDispatch<ZwpPointerConstraintsV1, ()>
+        + Dispatch<ZwpPointerConstraintsV1, ()>
+        + PointerConstraintsHandler,
+{
+    fn bind(
+        _state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwpPointerConstraintsV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<State> Dispatch<ZwpPointerConstraintsV1, (), State> for PointerConstraintsManager
+where
+    State: Dispatch<ZwpPointerConstraintsV1, ()>
+        + Dispatch<ZwpLockedPointerV1, EntityData>
+        + Dispatch<ZwpConfinedPointerV1, EntityData>
+        + PointerConstraintsHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        resource: &ZwpPointerConstraintsV1,
+        request: zwp_pointer_constraints_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwp_pointer_constraints_v1::Request::LockPointer {
+                id,
+                surface,
+                pointer,
+                region,
+                lifetime,
+            } => {
+                if !check_constrained(state, resource, &surface, &pointer) {
+                    return;
+                }
+
+                let entity = surface.data::<EntityData>().unwrap().0;
+                let object = data_init.init(id, EntityData(entity));
+                add_constraint(
+                    state,
+                    &surface,
+                    &pointer,
+                    ConstraintObject::Locked(object),
+                    region,
+                    lifetime,
+                );
+            }
+
+            zwp_pointer_constraints_v1::Request::ConfinePointer {
+                id,
+                surface,
+                pointer,
+                region,
+                lifetime,
+            } => {
+                if !check_constrained(state, resource, &surface, &pointer) {
+                    return;
+                }
+
+                let entity = surface.data::<EntityData>().unwrap().0;
+                let object = data_init.init(id, EntityData(entity));
+                add_constraint(
+                    state,
+                    &surface,
+                    &pointer,
+                    ConstraintObject::Confined(object),
+                    region,
+                    lifetime,
+                );
+            }
+
+            zwp_pointer_constraints_v1::Request::Destroy => {
+                // Constraints are unaffected by the manager being destroyed.
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<State> Dispatch<ZwpLockedPointerV1, EntityData, State> for PointerConstraintsManager
+where
+    State: Dispatch<ZwpLockedPointerV1, EntityData> + PointerConstraintsHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        resource: &ZwpLockedPointerV1,
+        request: zwp_locked_pointer_v1::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        // Defunct constraints ignore further requests.
+        let constraint = match state
+            .ecs()
+            .world()
+            .query_one_mut::<&mut PointerConstraints>(data.0)
+            .ok()
+            .and_then(|constraints| constraints.find_object(&resource.id()))
+        {
+            Some(constraint) if !constraint.defunct => constraint,
+            _ => return,
+        };
+
+        match request {
+            zwp_locked_pointer_v1::Request::SetCursorPositionHint {
+                surface_x,
+                surface_y,
+            } => {
+                constraint.pending.cursor_position_hint = Some((surface_x, surface_y).into());
+            }
+
+            zwp_locked_pointer_v1::Request::SetRegion { region } => {
+                constraint.pending.region = Some(region.as_ref().map(to_region));
+            }
+
+            zwp_locked_pointer_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, resource: ObjectId, data: &EntityData) {
+        destroyed(state, resource, data);
+    }
+}
+
+impl<State> Dispatch<ZwpConfinedPointerV1, EntityData, State> for PointerConstraintsManager
+where
+    State: Dispatch<ZwpConfinedPointerV1, EntityData> + PointerConstraintsHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        resource: &ZwpConfinedPointerV1,
+        request: zwp_confined_pointer_v1::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        // Defunct constraints ignore further requests.
+        let constraint = match state
+            .ecs()
+            .world()
+            .query_one_mut::<&mut PointerConstraints>(data.0)
+            .ok()
+            .and_then(|constraints| constraints.find_object(&resource.id()))
+        {
+            Some(constraint) if !constraint.defunct => constraint,
+            _ => return,
+        };
+
+        match request {
+            zwp_confined_pointer_v1::Request::SetRegion { region } => {
+                constraint.pending.region = Some(region.as_ref().map(to_region));
+            }
+
+            zwp_confined_pointer_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, resource: ObjectId, data: &EntityData) {
+        destroyed(state, resource, data);
+    }
+}
+
+fn to_region(region: &WlRegion) -> Region {
+    region
+        .data::<RegionData>()
+        .unwrap()
+        .attributes()
+        .to_region()
+}
+
+/// Returns whether the pointer of a seat can be constrained to a surface, posting an error otherwise.
+fn check_constrained<State: PointerConstraintsHandler>(
+    state: &mut State,
+    resource: &ZwpPointerConstraintsV1,
+    surface: &WlSurface,
+    pointer: &WlPointer,
+) -> bool {
+    // The wl_pointer uses the entity data of its seat.
+    let seat = pointer.data::<EntityData>().unwrap().0;
+    let entity = surface.data::<EntityData>().unwrap().0;
+
+    let constrained = state
+        .ecs()
+        .world()
+        .query_one_mut::<&PointerConstraints>(entity)
+        .map(|constraints| constraints.get(seat).is_some())
+        .unwrap_or(false);
+
+    if constrained {
+        resource.post_error(
+            zwp_pointer_constraints_v1::Error::AlreadyConstrained,
+            "The pointer is already constrained to the surface",
+        );
+    }
+
+    !constrained
+}
+
+fn add_constraint<State: PointerConstraintsHandler>(
+    state: &mut State,
+    surface: &WlSurface,
+    pointer: &WlPointer,
+    object: ConstraintObject,
+    region: Option<WlRegion>,
+    lifetime: WEnum<Lifetime>,
+) {
+    let seat = pointer.data::<EntityData>().unwrap().0;
+    let entity = surface.data::<EntityData>().unwrap().0;
+    let world = state.ecs().world();
+
+    // The commit systems are only added the first time, since the constraints are never removed.
+    if world.query_one_mut::<&PointerConstraints>(entity).is_err() {
+        world
+            .insert_one(entity, PointerConstraints::default())
+            .expect("Surface must be a valid entity if dispatched");
+        Compositor::add_pre_commit::<State>(state.ecs(), surface, pre_commit::<State>);
+        Compositor::add_post_commit::<State>(state.ecs(), surface, post_commit::<State>);
+    }
+
+    let lifetime = match lifetime {
+        WEnum::Value(lifetime) => lifetime,
+        // Unknown lifetimes are treated like the shortest one.
+        WEnum::Unknown(_) => Lifetime::Oneshot,
+    };
+
+    state
+        .ecs()
+        .world()
+        .query_one_mut::<&mut PointerConstraints>(entity)
+        .expect("Constraints were inserted")
+        .constraints
+        .push(PointerConstraint {
+            object,
+            seat,
+            lifetime,
+            // The initial region is applied immediately instead of with the next commit.
+            region: region.as_ref().map(to_region),
+            cursor_position_hint: None,
+            active: false,
+            defunct: false,
+            pending: PendingConstraint::default(),
+        });
+
+    Seat::add_pointer_focus_system::<State>(state.ecs(), seat, focus_changed::<State>);
+
+    // The surface may already have pointer focus.
+    update(state, seat);
+}
+
+fn destroyed<State: PointerConstraintsHandler>(
+    state: &mut State,
+    resource: ObjectId,
+    data: &EntityData,
+) {
+    let world = state.ecs().world();
+    let constraints = match world.query_one_mut::<&mut PointerConstraints>(data.0) {
+        Ok(constraints) => constraints,
+        Err(_) => return,
+    };
+
+    let index = match constraints
+        .constraints
+        .iter()
+        .position(|constraint| constraint.object.id() == resource)
+    {
+        Some(index) => index,
+        None => return,
+    };
+
+    let constraint = constraints.constraints.remove(index);
+
+    // Destroying an active lock unlocks the pointer, which is where the hint applies.
+    let hint = match (constraint.active, constraint.kind()) {
+        (true, ConstraintKind::Lock) => constraint.cursor_position_hint,
+        _ => None,
+    };
+
+    let surface = world.query_one_mut::<&WlSurface>(data.0).ok().cloned();
+
+    if let (Some(hint), Some(surface)) = (hint, surface) {
+        state.cursor_position_hint(constraint.seat, &surface, hint);
+    }
+}
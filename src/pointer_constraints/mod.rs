@@ -0,0 +1,387 @@
+//! Implementation of the pointer constraints protocol.
+//!
+//! Clients lock the pointer to a position or confine it to a region of a surface. The constraints of a surface
+//! are stored in the [`PointerConstraints`] of the surface entity, with at most one [`PointerConstraint`] per
+//! seat. The region and cursor position hint of a constraint are double-buffered state of the surface.
+//!
+//! # Activation
+//!
+//! A constraint is activated once its surface has the [pointer focus](crate::seat::PointerFocus) of the seat and
+//! the pointer is inside the region of the constraint. It is deactivated when the surface loses pointer focus or
+//! the compositor calls [`PointerConstraintsManager::deactivate`]. A [`Lifetime::Oneshot`] constraint becomes
+//! [defunct](PointerConstraint::is_defunct) once deactivated, while a [`Lifetime::Persistent`] one is activated
+//! again the next time the conditions are met.
+//!
+//! While a lock is active, the compositor does not move the pointer but still sends
+//! [relative motion](crate::relative_pointer). When a lock with a cursor position hint is deactivated,
+//! [`PointerConstraintsHandler::cursor_position_hint`] is called so the compositor can warp the pointer.
+
+mod dispatch;
+
+#[cfg(test)]
+mod tests;
+
+use hecs::Entity;
+use smithay::utils::{Logical, Point};
+use wayland_backend::server::ObjectId;
+use wayland_protocols::wp::pointer_constraints::zv1::server::{
+    zwp_confined_pointer_v1::ZwpConfinedPointerV1, zwp_locked_pointer_v1::ZwpLockedPointerV1,
+    zwp_pointer_constraints_v1::ZwpPointerConstraintsV1,
+};
+use wayland_server::{protocol::wl_surface::WlSurface, DisplayHandle, GlobalDispatch, Resource};
+
+use crate::{
+    compositor::{Compositor, CompositorHandler, Region},
+    seat::PointerFocus,
+    Ecs, EntityData,
+};
+
+pub use wayland_protocols::wp::pointer_constraints::zv1::server::zwp_pointer_constraints_v1::Lifetime;
+
+pub trait PointerConstraintsHandler: CompositorHandler {
+    /// A lock with a cursor position hint was deactivated.
+    ///
+    /// The compositor should warp the pointer to the hint, which is relative to the surface, so the pointer
+    /// continues where the client drew its cursor.
+    fn cursor_position_hint(
+        &mut self,
+        seat: Entity,
+        surface: &WlSurface,
+        location: Point<f64, Logical>,
+    );
+}
+
+pub struct PointerConstraintsManager {}
+
+impl PointerConstraintsManager {
+    pub fn new<State>(display: &mut DisplayHandle) -> Self
+    where
+        State: GlobalDispatch<ZwpPointerConstraintsV1, ()> + PointerConstraintsHandler,
+    {
+        let _global = display.create_global::<State, ZwpPointerConstraintsV1, ()>(1, ());
+        Self {}
+    }
+
+    /// Returns the surface entity with the active constraint of a seat and the constraint.
+    pub fn active_constraint(ecs: &mut Ecs, seat: Entity) -> Option<(Entity, &PointerConstraint)> {
+        ecs.world
+            .query_mut::<&PointerConstraints>()
+            .into_iter()
+            .find_map(|(entity, constraints)| {
+                constraints
+                    .get(seat)
+                    .filter(|constraint| constraint.active)
+                    .map(|constraint| (entity, constraint))
+            })
+    }
+
+    /// Deactivates the active constraint of a seat, for example when the user presses a key to release the
+    /// pointer.
+    pub fn deactivate<State>(state: &mut State, seat: Entity)
+    where
+        State: PointerConstraintsHandler,
+    {
+        let surface = Self::active_constraint(state.ecs(), seat).map(|(surface, _)| surface);
+
+        if let Some(surface) = surface {
+            deactivate(state, surface, seat);
+        }
+    }
+}
+
+/// The kind of a pointer constraint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConstraintKind {
+    /// The pointer does not move while the constraint is active.
+    Lock,
+    /// The pointer can only move within the region of the constraint.
+    Confine,
+}
+
+/// The pointer constraints of a [`WlSurface`].
+///
+/// This is inserted the first time a client constrains the pointer to the surface and is never removed.
+#[derive(Debug, Default)]
+pub struct PointerConstraints {
+    constraints: Vec<PointerConstraint>,
+}
+
+impl PointerConstraints {
+    /// The constraint of a seat.
+    pub fn get(&self, seat: Entity) -> Option<&PointerConstraint> {
+        self.constraints
+            .iter()
+            .find(|constraint| constraint.seat == seat)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PointerConstraint> {
+        self.constraints.iter()
+    }
+
+    fn get_mut(&mut self, seat: Entity) -> Option<&mut PointerConstraint> {
+        self.constraints
+            .iter_mut()
+            .find(|constraint| constraint.seat == seat)
+    }
+
+    fn find_object(&mut self, object: &ObjectId) -> Option<&mut PointerConstraint> {
+        self.constraints
+            .iter_mut()
+            .find(|constraint| constraint.object.id() == *object)
+    }
+}
+
+/// A pointer constraint of a surface.
+#[derive(Debug)]
+pub struct PointerConstraint {
+    object: ConstraintObject,
+    seat: Entity,
+    lifetime: Lifetime,
+    region: Option<Region>,
+    cursor_position_hint: Option<Point<f64, Logical>>,
+    active: bool,
+    defunct: bool,
+    pending: PendingConstraint,
+}
+
+impl PointerConstraint {
+    pub fn kind(&self) -> ConstraintKind {
+        match self.object {
+            ConstraintObject::Locked(_) => ConstraintKind::Lock,
+            ConstraintObject::Confined(_) => ConstraintKind::Confine,
+        }
+    }
+
+    /// The seat entity whose pointer is constrained.
+    pub fn seat(&self) -> Entity {
+        self.seat
+    }
+
+    pub fn lifetime(&self) -> Lifetime {
+        self.lifetime
+    }
+
+    /// The region of the surface the pointer is constrained to, or [`None`] for the whole surface.
+    pub fn region(&self) -> Option<&Region> {
+        self.region.as_ref()
+    }
+
+    /// Where the client draws its cursor while the pointer is locked, relative to the surface.
+    pub fn cursor_position_hint(&self) -> Option<Point<f64, Logical>> {
+        self.cursor_position_hint
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Whether the constraint is a [`Lifetime::Oneshot`] constraint which was deactivated.
+    ///
+    /// A defunct constraint is never activated again, but still prevents the client from constraining the
+    /// pointer of the seat to the surface until the client destroys it.
+    pub fn is_defunct(&self) -> bool {
+        self.defunct
+    }
+}
+
+#[derive(Debug)]
+enum ConstraintObject {
+    Locked(ZwpLockedPointerV1),
+    Confined(ZwpConfinedPointerV1),
+}
+
+impl ConstraintObject {
+    fn id(&self) -> ObjectId {
+        match self {
+            Self::Locked(object) => object.id(),
+            Self::Confined(object) => object.id(),
+        }
+    }
+
+    fn activated(&self) {
+        match self {
+            Self::Locked(object) => object.locked(),
+            Self::Confined(object) => object.confined(),
+        }
+    }
+
+    fn deactivated(&self) {
+        match self {
+            Self::Locked(object) => object.unlocked(),
+            Self::Confined(object) => object.unconfined(),
+        }
+    }
+}
+
+/// Double-buffered state of a constraint, which is applied with the next commit of the surface.
+#[derive(Debug, Default)]
+struct PendingConstraint {
+    region: Option<Option<Region>>,
+    cursor_position_hint: Option<Point<f64, Logical>>,
+}
+
+/// The pending state of every constraint of a surface, carried by a commit until it is applied.
+#[derive(Debug)]
+struct CommittedConstraints(Vec<(ObjectId, PendingConstraint)>);
+
+/// Activates and deactivates the constraints of a seat after its pointer focus changed.
+fn update<State: PointerConstraintsHandler>(state: &mut State, seat: Entity) {
+    let world = state.ecs().world();
+    let focus = world
+        .query_one_mut::<&PointerFocus>(seat)
+        .map(|focus| focus.clone())
+        .unwrap_or_default();
+    let focused = focus
+        .surface()
+        .map(|surface| surface.data::<EntityData>().unwrap().0);
+
+    // Constraints are deactivated once their surface loses pointer focus.
+    let unfocused = world
+        .query_mut::<&PointerConstraints>()
+        .into_iter()
+        .filter(|(entity, constraints)| {
+            Some(*entity) != focused
+                && constraints
+                    .get(seat)
+                    .map(|constraint| constraint.active)
+                    .unwrap_or(false)
+        })
+        .map(|(entity, _)| entity)
+        .collect::<Vec<_>>();
+
+    for surface in unfocused {
+        deactivate(state, surface, seat);
+    }
+
+    let constraint = focused.and_then(|surface| {
+        state
+            .ecs()
+            .world()
+            .query_one_mut::<&mut PointerConstraints>(surface)
+            .ok()?
+            .get_mut(seat)
+    });
+
+    if let Some(constraint) = constraint {
+        let inside = constraint
+            .region
+            .as_ref()
+            .map(|region| region.contains(focus.location().to_i32_floor()))
+            .unwrap_or(true);
+
+        if !constraint.active && !constraint.defunct && inside {
+            constraint.active = true;
+            constraint.object.activated();
+        }
+    }
+}
+
+/// Deactivates the constraint of a seat on a surface.
+fn deactivate<State: PointerConstraintsHandler>(state: &mut State, surface: Entity, seat: Entity) {
+    let world = state.ecs().world();
+    let constraints = match world.query_one_mut::<&mut PointerConstraints>(surface) {
+        Ok(constraints) => constraints,
+        Err(_) => return,
+    };
+
+    let index = match constraints
+        .constraints
+        .iter()
+        .position(|constraint| constraint.seat == seat && constraint.active)
+    {
+        Some(index) => index,
+        None => return,
+    };
+
+    let constraint = &mut constraints.constraints[index];
+    constraint.active = false;
+    constraint.object.deactivated();
+
+    // Oneshot constraints become defunct once deactivated, but stay until the client destroys them.
+    constraint.defunct = constraint.lifetime == Lifetime::Oneshot;
+
+    let hint = match constraint.kind() {
+        ConstraintKind::Lock => constraint.cursor_position_hint,
+        ConstraintKind::Confine => None,
+    };
+
+    if let Some(hint) = hint {
+        let surface = world
+            .query_one_mut::<&WlSurface>(surface)
+            .expect("Surface entity has constraints")
+            .clone();
+        state.cursor_position_hint(seat, &surface, hint);
+    }
+}
+
+/// Moves the pending state of the constraints into the commit.
+fn pre_commit<State: PointerConstraintsHandler>(state: &mut State, surface: &WlSurface) {
+    let entity = surface.data::<EntityData>().unwrap().0;
+    let constraints = state
+        .ecs()
+        .world()
+        .query_one_mut::<&mut PointerConstraints>(entity)
+        .expect("Commit systems are only added with the constraints");
+
+    let pending = constraints
+        .constraints
+        .iter_mut()
+        .map(|constraint| {
+            (
+                constraint.object.id(),
+                std::mem::take(&mut constraint.pending),
+            )
+        })
+        .collect();
+    Compositor::insert_commit_state::<State, _>(
+        state.ecs(),
+        surface,
+        CommittedConstraints(pending),
+    );
+}
+
+/// Applies the committed state of the constraints.
+fn post_commit<State: PointerConstraintsHandler>(state: &mut State, surface: &WlSurface) {
+    let committed =
+        match Compositor::take_commit_state::<State, CommittedConstraints>(state.ecs(), surface) {
+            Some(committed) => committed.0,
+            None => return,
+        };
+
+    let entity = surface.data::<EntityData>().unwrap().0;
+    let constraints = state
+        .ecs()
+        .world()
+        .query_one_mut::<&mut PointerConstraints>(entity)
+        .expect("Commit systems are only added with the constraints");
+
+    let mut seats = Vec::new();
+
+    for (object, pending) in committed {
+        // The constraint may have been destroyed since the commit.
+        if let Some(constraint) = constraints.find_object(&object) {
+            if let Some(region) = pending.region {
+                constraint.region = region;
+                seats.push(constraint.seat);
+            }
+
+            if let Some(hint) = pending.cursor_position_hint {
+                constraint.cursor_position_hint = Some(hint);
+            }
+        }
+    }
+
+    // A new region may allow a constraint to activate.
+    for seat in seats {
+        update(state, seat);
+    }
+}
+
+/// Updates the constraints of a seat after its pointer focus changed.
+fn focus_changed<State: PointerConstraintsHandler>(
+    state: &mut State,
+    _display: &DisplayHandle,
+    seat: Entity,
+) {
+    update(state, seat);
+}
@@ -0,0 +1,83 @@
+use wayland_protocols::wp::pointer_constraints::zv1::server::zwp_pointer_constraints_v1;
+
+use crate::testing::{Arg, Fixture, TestSurface};
+
+use super::{Lifetime, PointerConstraints, PointerConstraintsManager};
+
+// wl_seat requests
+const GET_POINTER: u16 = 0;
+
+// zwp_pointer_constraints_v1 requests
+const LOCK_POINTER: u16 = 1;
+
+// zwp_locked_pointer_v1 and zwp_confined_pointer_v1 events
+const ACTIVATED: u16 = 0;
+const DEACTIVATED: u16 = 1;
+
+/// Binds the pointer constraints global and returns it with a `wl_pointer`.
+fn bind(fixture: &mut Fixture) -> (u32, u32) {
+    let seat = fixture.client.bind("wl_seat", 7);
+    let constraints = fixture.client.bind("zwp_pointer_constraints_v1", 1);
+    let pointer = fixture.client.new_id();
+    fixture.send(seat, GET_POINTER, &[Arg::NewId(pointer)]);
+    fixture.assert_no_error();
+    (constraints, pointer)
+}
+
+fn lock_pointer(
+    fixture: &mut Fixture,
+    (constraints, pointer): (u32, u32),
+    surface: &TestSurface,
+    lifetime: Lifetime,
+) -> u32 {
+    let id = fixture.client.new_id();
+    fixture.send(
+        constraints,
+        LOCK_POINTER,
+        &[
+            Arg::NewId(id),
+            Arg::Object(surface.id),
+            Arg::Object(pointer),
+            Arg::Object(0),
+            Arg::Uint(lifetime as u32),
+        ],
+    );
+    id
+}
+
+#[test]
+fn deactivated_oneshot_constraint_is_defunct() {
+    let mut fixture = Fixture::new();
+    let globals = bind(&mut fixture);
+    let surface = fixture.create_surface();
+    let locked = lock_pointer(&mut fixture, globals, &surface, Lifetime::Oneshot);
+    fixture.assert_no_error();
+
+    fixture.set_pointer_focus(Some(&surface));
+    assert_eq!(fixture.opcodes(locked), vec![ACTIVATED]);
+
+    let seat = fixture.seat();
+    PointerConstraintsManager::deactivate(&mut fixture.server.state, seat);
+    assert_eq!(fixture.opcodes(locked), vec![DEACTIVATED]);
+
+    let constraint = fixture
+        .world()
+        .query_one_mut::<&PointerConstraints>(surface.entity)
+        .unwrap()
+        .get(seat)
+        .unwrap();
+    assert!(!constraint.is_active());
+    assert!(constraint.is_defunct());
+
+    // A defunct constraint is never activated again.
+    fixture.set_pointer_focus(None);
+    fixture.set_pointer_focus(Some(&surface));
+    assert_eq!(fixture.opcodes(locked), Vec::<u16>::new());
+
+    // It still counts as constraining the pointer until the client destroys it.
+    lock_pointer(&mut fixture, globals, &surface, Lifetime::Oneshot);
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(zwp_pointer_constraints_v1::Error::AlreadyConstrained as u32)
+    );
+}
@@ -0,0 +1,112 @@
+use wayland_protocols::wp::presentation_time::server::{
+    wp_presentation::{self, WpPresentation},
+    wp_presentation_feedback::WpPresentationFeedback,
+};
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use crate::{
+    compositor::{Compositor, CompositorHandler},
+    EntityData,
+};
+
+use super::{
+    destroy, post_commit, pre_commit, Presentation, PresentationFeedback, PresentationGlobalData,
+    PresentationState,
+};
+
+impl<State> GlobalDispatch<WpPresentation, PresentationGlobalData, State> for Presentation
+where
+    State: GlobalDispatch<WpPresentation, PresentationGlobalData>
+        + Dispatch<WpPresentation, ()>
+        + CompositorHandler,
+{
+    fn bind(
+        _state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<WpPresentation>,
+        global_data: &PresentationGlobalData,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        let presentation = data_init.init(resource, ());
+        presentation.clock_id(global_data.clock_id);
+    }
+}
+
+impl<State> Dispatch<WpPresentation, (), State> for Presentation
+where
+    State: Dispatch<WpPresentation, ()>
+        + Dispatch<WpPresentationFeedback, EntityData>
+        + CompositorHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        _resource: &WpPresentation,
+        request: wp_presentation::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            wp_presentation::Request::Feedback { surface, callback } => {
+                let entity = surface.data::<EntityData>().unwrap().0;
+                let feedback = data_init.init(callback, EntityData(entity));
+                let world = state.ecs().world();
+
+                match world.query_one_mut::<&mut PresentationState>(entity) {
+                    Ok(presentation_state) => presentation_state.pending.push(feedback),
+
+                    // The commit systems are only added the first time, since the state is never removed.
+                    Err(_) => {
+                        world
+                            .insert(
+                                entity,
+                                (
+                                    PresentationState {
+                                        pending: vec![feedback],
+                                    },
+                                    PresentationFeedback::default(),
+                                ),
+                            )
+                            .expect("Surface must be a valid entity if dispatched");
+                        Compositor::add_pre_commit::<State>(
+                            state.ecs(),
+                            &surface,
+                            pre_commit::<State>,
+                        );
+                        Compositor::add_post_commit::<State>(
+                            state.ecs(),
+                            &surface,
+                            post_commit::<State>,
+                        );
+                        Compositor::add_destroy::<State>(state.ecs(), &surface, destroy::<State>);
+                    }
+                }
+            }
+
+            wp_presentation::Request::Destroy => {
+                // Feedback is unaffected by the presentation object being destroyed.
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<State> Dispatch<WpPresentationFeedback, EntityData, State> for Presentation
+where
+    State: Dispatch<WpPresentationFeedback, EntityData> + CompositorHandler,
+{
+    fn request(
+        _state: &mut State,
+        _client: &Client,
+        _resource: &WpPresentationFeedback,
+        _request: <WpPresentationFeedback as Resource>::Request,
+        _data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        unreachable!("no requests")
+    }
+}
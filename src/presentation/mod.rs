@@ -0,0 +1,198 @@
+//! Implementation of the presentation time protocol.
+//!
+//! Clients request feedback about when the content of a commit was shown. Feedback objects are attached to the
+//! pending state of a surface, carried by the commit, and moved into the [`PresentationFeedback`] of the surface
+//! once the commit is applied. Feedback which is still waiting when a later commit is applied is discarded,
+//! since that content was superseded before it was shown. So is the feedback of a commit which is never applied.
+//!
+//! # Presenting
+//!
+//! Renderers take the feedback of a surface using [`PresentationFeedback::take`] when drawing the surface.
+//! Once the frame is shown on an output, for example after a page flip, the renderer marks the taken feedback
+//! [presented](PresentationFeedback::presented). If the frame is never shown, the feedback is
+//! [discarded](PresentationFeedback::discarded) instead.
+//!
+//! Timestamps are read from the clock whose id is passed to [`Presentation::new`], which is advertised to
+//! clients. This is typically `CLOCK_MONOTONIC`.
+
+mod dispatch;
+
+#[cfg(test)]
+mod tests;
+
+use std::time::Duration;
+
+use wayland_protocols::wp::presentation_time::server::{
+    wp_presentation::WpPresentation, wp_presentation_feedback::WpPresentationFeedback,
+};
+use wayland_server::{
+    protocol::{wl_output::WlOutput, wl_surface::WlSurface},
+    DisplayHandle, GlobalDispatch, Resource,
+};
+
+use crate::{
+    compositor::{Compositor, CompositorHandler},
+    EntityData,
+};
+
+pub use wayland_protocols::wp::presentation_time::server::wp_presentation_feedback::Kind;
+
+pub struct Presentation {}
+
+impl Presentation {
+    /// Creates the global. `clock_id` is the id of the clock used for presentation timestamps.
+    pub fn new<State>(display: &mut DisplayHandle, clock_id: u32) -> Self
+    where
+        State: GlobalDispatch<WpPresentation, PresentationGlobalData> + CompositorHandler,
+    {
+        let _global = display
+            .create_global::<State, WpPresentation, _>(1, PresentationGlobalData { clock_id });
+        Self {}
+    }
+}
+
+/// User data of the `wp_presentation` global.
+#[derive(Debug, Clone, Copy)]
+pub struct PresentationGlobalData {
+    clock_id: u32,
+}
+
+/// Presentation feedback of a surface which is waiting to be presented.
+///
+/// This holds the feedback of the currently applied state of the surface. It is inserted the first time a
+/// client requests feedback for the surface.
+#[derive(Debug, Default)]
+pub struct PresentationFeedback {
+    feedback: Vec<WpPresentationFeedback>,
+}
+
+impl PresentationFeedback {
+    /// Takes the feedback, leaving this empty.
+    ///
+    /// Renderers hold the returned feedback until the frame which draws the surface is shown.
+    pub fn take(&mut self) -> Self {
+        Self {
+            feedback: std::mem::take(&mut self.feedback),
+        }
+    }
+
+    /// Tells the clients that the content was shown.
+    ///
+    /// `outputs` are the `wl_output` objects of the output the content was shown on. `time` is the time the
+    /// content turned into light, read from the clock passed to [`Presentation::new`], and `refresh` is the
+    /// duration of a refresh cycle of the output, or zero if it is unknown. `seq` is the refresh counter of the
+    /// output.
+    pub fn presented(
+        &mut self,
+        outputs: &[WlOutput],
+        time: Duration,
+        refresh: Duration,
+        seq: u64,
+        flags: Kind,
+    ) {
+        let secs = time.as_secs();
+        let refresh = u32::try_from(refresh.as_nanos()).unwrap_or(u32::MAX);
+
+        for feedback in self.feedback.drain(..) {
+            // Clients only know about their own objects of the output.
+            for output in outputs
+                .iter()
+                .filter(|output| output.client() == feedback.client())
+            {
+                feedback.sync_output(output);
+            }
+
+            feedback.presented(
+                (secs >> 32) as u32,
+                secs as u32,
+                time.subsec_nanos(),
+                refresh,
+                (seq >> 32) as u32,
+                seq as u32,
+                flags,
+            );
+        }
+    }
+
+    /// Tells the clients that the content was never shown.
+    pub fn discarded(&mut self) {
+        for feedback in self.feedback.drain(..) {
+            feedback.discarded();
+        }
+    }
+
+    /// Returns whether there is no feedback waiting.
+    pub fn is_empty(&self) -> bool {
+        self.feedback.is_empty()
+    }
+}
+
+/// Feedback of a surface which was not committed yet.
+///
+/// This is inserted together with the commit systems and is never removed.
+#[derive(Debug)]
+struct PresentationState {
+    pending: Vec<WpPresentationFeedback>,
+}
+
+/// Feedback carried by a commit until it is applied.
+///
+/// Feedback which is still held when this is dropped, because the commit was never applied, is discarded.
+#[derive(Debug)]
+struct CommittedFeedback(Vec<WpPresentationFeedback>);
+
+impl Drop for CommittedFeedback {
+    fn drop(&mut self) {
+        for feedback in self.0.drain(..) {
+            feedback.discarded();
+        }
+    }
+}
+
+fn pre_commit<State: CompositorHandler>(state: &mut State, surface: &WlSurface) {
+    let entity = surface.data::<EntityData>().unwrap().0;
+    let presentation_state = state
+        .ecs()
+        .world()
+        .query_one_mut::<&mut PresentationState>(entity)
+        .expect("Commit systems are only added with the state");
+
+    let pending = std::mem::take(&mut presentation_state.pending);
+    Compositor::insert_commit_state::<State, _>(state.ecs(), surface, CommittedFeedback(pending));
+}
+
+fn post_commit<State: CompositorHandler>(state: &mut State, surface: &WlSurface) {
+    let committed = Compositor::take_commit_state::<State, CommittedFeedback>(state.ecs(), surface);
+
+    let entity = surface.data::<EntityData>().unwrap().0;
+    let feedback = state
+        .ecs()
+        .world()
+        .query_one_mut::<&mut PresentationFeedback>(entity)
+        .expect("Commit systems are only added with the state");
+
+    // The content which was waiting to be presented is replaced by this commit.
+    feedback.discarded();
+
+    if let Some(mut committed) = committed {
+        feedback.feedback = std::mem::take(&mut committed.0);
+    }
+}
+
+fn destroy<State: CompositorHandler>(state: &mut State, surface: &WlSurface) {
+    let entity = surface.data::<EntityData>().unwrap().0;
+
+    // The content of a destroyed surface is never shown. Feedback of commits which were not applied yet is
+    // discarded when the compositor drops those commits.
+    if let Ok((presentation_state, feedback)) = state
+        .ecs()
+        .world()
+        .query_one_mut::<(&mut PresentationState, &mut PresentationFeedback)>(entity)
+    {
+        feedback.discarded();
+
+        for feedback in presentation_state.pending.drain(..) {
+            feedback.discarded();
+        }
+    }
+}
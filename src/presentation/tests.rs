@@ -0,0 +1,68 @@
+use crate::testing::{Arg, Fixture, TestSurface};
+
+use super::PresentationFeedback;
+
+// wl_surface requests
+const DESTROY: u16 = 0;
+
+// wp_presentation requests
+const FEEDBACK: u16 = 1;
+
+// wp_presentation_feedback events
+const DISCARDED: u16 = 2;
+
+/// Requests feedback for the next commit of `surface` and commits it.
+fn commit_with_feedback(fixture: &mut Fixture, presentation: u32, surface: &TestSurface) -> u32 {
+    let feedback = fixture.client.new_id();
+    fixture.send(
+        presentation,
+        FEEDBACK,
+        &[Arg::Object(surface.id), Arg::NewId(feedback)],
+    );
+    fixture.commit(surface.id);
+    feedback
+}
+
+fn is_discarded(fixture: &mut Fixture, feedback: u32) -> bool {
+    fixture.opcodes(feedback).contains(&DISCARDED)
+}
+
+fn waiting_feedback(fixture: &mut Fixture, surface: &TestSurface) -> usize {
+    fixture
+        .world()
+        .query_one_mut::<&PresentationFeedback>(surface.entity)
+        .unwrap()
+        .feedback
+        .len()
+}
+
+#[test]
+fn superseded_feedback_is_discarded() {
+    let mut fixture = Fixture::new();
+    let presentation = fixture.client.bind("wp_presentation", 1);
+    let surface = fixture.create_surface();
+
+    let first = commit_with_feedback(&mut fixture, presentation, &surface);
+    let second = commit_with_feedback(&mut fixture, presentation, &surface);
+
+    // The second commit replaced the content before the first was presented.
+    assert!(is_discarded(&mut fixture, first));
+    assert!(!is_discarded(&mut fixture, second));
+    assert_eq!(waiting_feedback(&mut fixture, &surface), 1);
+}
+
+#[test]
+fn feedback_of_dropped_commit_is_discarded() {
+    let mut fixture = Fixture::new();
+    let presentation = fixture.client.bind("wp_presentation", 1);
+    let surface = fixture.create_surface();
+    let blocker = fixture.add_blocker(&surface);
+
+    let feedback = commit_with_feedback(&mut fixture, presentation, &surface);
+    fixture.send(surface.id, DESTROY, &[]);
+    fixture.dispatch();
+
+    // The commit is dropped instead of applied since the surface is gone.
+    fixture.release(&blocker);
+    assert!(is_discarded(&mut fixture, feedback));
+}
@@ -0,0 +1,255 @@
+use wayland_backend::server::{ClientId, ObjectId};
+use wayland_protocols::wp::primary_selection::zv1::server::{
+    zwp_primary_selection_device_manager_v1::{self, ZwpPrimarySelectionDeviceManagerV1},
+    zwp_primary_selection_device_v1::{self, ZwpPrimarySelectionDeviceV1},
+    zwp_primary_selection_offer_v1::{self, ZwpPrimarySelectionOfferV1},
+    zwp_primary_selection_source_v1::{self, ZwpPrimarySelectionSourceV1},
+};
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use crate::{
+    data_device::{
+        offer_selection, replace_selection, source_destroyed, DataSource, SelectionSource,
+        SelectionTarget, SourceObject,
+    },
+    seat::{KeyboardFocus, Seat},
+    EntityData,
+};
+
+use super::{
+    focus_changed, PrimarySelectionDevice, PrimarySelectionHandler, PrimarySelectionManager,
+    PrimarySelectionOffer,
+};
+
+impl<State> GlobalDispatch<ZwpPrimarySelectionDeviceManagerV1, (), State>
+    for PrimarySelectionManager
+where
+    State: GlobalDispatch<ZwpPrimarySelectionDeviceManagerV1, ()>
+        + Dispatch<ZwpPrimarySelectionDeviceManagerV1, ()>
+        + PrimarySelectionHandler,
+{
+    fn bind(
+        _state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwpPrimarySelectionDeviceManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<State> Dispatch<ZwpPrimarySelectionDeviceManagerV1, (), State> for PrimarySelectionManager
+where
+    State: Dispatch<ZwpPrimarySelectionDeviceManagerV1, ()>
+        + Dispatch<ZwpPrimarySelectionSourceV1, EntityData>
+        + Dispatch<ZwpPrimarySelectionDeviceV1, EntityData>
+        + Dispatch<ZwpPrimarySelectionOfferV1, EntityData>
+        + PrimarySelectionHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        _resource: &ZwpPrimarySelectionDeviceManagerV1,
+        request: zwp_primary_selection_device_manager_v1::Request,
+        _data: &(),
+        dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwp_primary_selection_device_manager_v1::Request::CreateSource { id } => {
+                let entity = state.ecs().world().reserve_entity();
+                let object = data_init.init(id, EntityData(entity));
+                state
+                    .ecs()
+                    .world()
+                    .insert_one(
+                        entity,
+                        DataSource::new(SourceObject::PrimarySelection(object)),
+                    )
+                    .expect("Entity was reserved");
+            }
+
+            zwp_primary_selection_device_manager_v1::Request::GetDevice { id, seat } => {
+                let seat = Seat::entity_of(&seat);
+                let entity = state.ecs().world().reserve_entity();
+                let device = data_init.init(id, EntityData(entity));
+                let world = state.ecs().world();
+                world
+                    .insert_one(
+                        entity,
+                        PrimarySelectionDevice {
+                            object: device.clone(),
+                            seat,
+                        },
+                    )
+                    .expect("Entity was reserved");
+
+                // The primary selection is offered to the new device immediately if its client is focused.
+                let focused = world
+                    .query_one_mut::<&KeyboardFocus>(seat)
+                    .map(|focus| focus.is_client_focused(&device))
+                    .unwrap_or(false);
+
+                if focused {
+                    offer_selection::<State, PrimarySelectionDevice>(
+                        world,
+                        dhandle,
+                        seat,
+                        &device,
+                        SelectionTarget::Primary,
+                    );
+                }
+
+                Seat::add_focus_system::<State>(state.ecs(), seat, focus_changed::<State>);
+            }
+
+            zwp_primary_selection_device_manager_v1::Request::Destroy => {
+                // Devices and sources are unaffected by the manager being destroyed.
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<State> Dispatch<ZwpPrimarySelectionSourceV1, EntityData, State> for PrimarySelectionManager
+where
+    State: Dispatch<ZwpPrimarySelectionSourceV1, EntityData> + PrimarySelectionHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        _resource: &ZwpPrimarySelectionSourceV1,
+        request: zwp_primary_selection_source_v1::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwp_primary_selection_source_v1::Request::Offer { mime_type } => {
+                state
+                    .ecs()
+                    .world()
+                    .query_one_mut::<&mut DataSource>(data.0)
+                    .expect("Source exists until destroyed")
+                    .add_mime_type(mime_type);
+            }
+
+            zwp_primary_selection_source_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        let _ = state.ecs().world().despawn(data.0);
+        source_destroyed::<State, PrimarySelectionDevice>(state, data.0, SelectionTarget::Primary);
+    }
+}
+
+impl<State> Dispatch<ZwpPrimarySelectionDeviceV1, EntityData, State> for PrimarySelectionManager
+where
+    State: Dispatch<ZwpPrimarySelectionDeviceV1, EntityData>
+        + Dispatch<ZwpPrimarySelectionOfferV1, EntityData>
+        + PrimarySelectionHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        resource: &ZwpPrimarySelectionDeviceV1,
+        request: zwp_primary_selection_device_v1::Request,
+        data: &EntityData,
+        dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwp_primary_selection_device_v1::Request::SetSelection { source, serial: _ } => {
+                let world = state.ecs().world();
+                let seat = world
+                    .query_one_mut::<&PrimarySelectionDevice>(data.0)
+                    .expect("Device exists until destroyed")
+                    .seat;
+
+                // Only the focused client may change the primary selection.
+                let focused = world
+                    .query_one_mut::<&KeyboardFocus>(seat)
+                    .map(|focus| focus.is_client_focused(resource))
+                    .unwrap_or(false);
+
+                if !focused {
+                    if let Some(source) = source {
+                        source.cancelled();
+                    }
+                    return;
+                }
+
+                let source = source
+                    .map(|source| SelectionSource::Client(source.data::<EntityData>().unwrap().0));
+                replace_selection::<State, PrimarySelectionDevice>(
+                    state,
+                    dhandle,
+                    seat,
+                    SelectionTarget::Primary,
+                    source,
+                );
+                state.new_primary_selection(seat);
+            }
+
+            zwp_primary_selection_device_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        let _ = state.ecs().world().despawn(data.0);
+    }
+}
+
+impl<State> Dispatch<ZwpPrimarySelectionOfferV1, EntityData, State> for PrimarySelectionManager
+where
+    State: Dispatch<ZwpPrimarySelectionOfferV1, EntityData> + PrimarySelectionHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        _resource: &ZwpPrimarySelectionOfferV1,
+        request: zwp_primary_selection_offer_v1::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwp_primary_selection_offer_v1::Request::Receive { mime_type, fd } => {
+                let offer = state
+                    .ecs()
+                    .world()
+                    .query_one_mut::<&PrimarySelectionOffer>(data.0)
+                    .expect("Offer exists until destroyed");
+
+                // Mime types which are not offered are ignored, which closes the file descriptor.
+                if offer.mime_types.contains(&mime_type) {
+                    let seat = offer.seat;
+                    let source = offer.source.clone();
+                    source.send(state, seat, mime_type, fd, State::send_primary_selection);
+                }
+            }
+
+            zwp_primary_selection_offer_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        let _ = state.ecs().world().despawn(data.0);
+    }
+}
@@ -0,0 +1,224 @@
+//! Implementation of the primary selection protocol.
+//!
+//! The primary selection is a second clipboard which holds the most recently selected text, usually pasted by a
+//! middle click. It works like the selection of the [data device](crate::data_device): sources are entities with
+//! a [`DataSource`](crate::data_device::DataSource), and the primary selection of a seat is stored in the
+//! [`PrimarySelection`] of the seat entity. Devices and offers are entities with a [`PrimarySelectionDevice`] or
+//! [`PrimarySelectionOffer`].
+//!
+//! The compositor can query and change the primary selection using [`PrimarySelectionManager`], for example to
+//! mirror it into Xwayland or to clear it when the keyboard focus changes.
+
+mod dispatch;
+
+use std::os::unix::io::OwnedFd;
+
+use hecs::Entity;
+use wayland_protocols::wp::primary_selection::zv1::server::{
+    zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1,
+    zwp_primary_selection_device_v1::ZwpPrimarySelectionDeviceV1,
+    zwp_primary_selection_offer_v1::ZwpPrimarySelectionOfferV1,
+};
+use wayland_server::{Dispatch, DisplayHandle, GlobalDispatch};
+
+use crate::{
+    data_device::{
+        offer_selection_to_focus, replace_selection, selection_source, SelectionDevice,
+        SelectionError, SelectionSource, SelectionTarget,
+    },
+    EcsAccess, EntityData,
+};
+
+pub trait PrimarySelectionHandler: EcsAccess {
+    /// Writes the contents of a primary selection provided by the compositor to a file descriptor.
+    ///
+    /// The file descriptor should be closed once everything was written.
+    fn send_primary_selection(&mut self, seat: Entity, mime_type: String, fd: OwnedFd);
+
+    /// A client changed the primary selection of a seat.
+    fn new_primary_selection(&mut self, seat: Entity) {
+        let _ = seat;
+    }
+}
+
+pub struct PrimarySelectionManager {}
+
+impl PrimarySelectionManager {
+    pub fn new<State>(display: &mut DisplayHandle) -> Self
+    where
+        State: GlobalDispatch<ZwpPrimarySelectionDeviceManagerV1, ()> + PrimarySelectionHandler,
+    {
+        let _global = display.create_global::<State, ZwpPrimarySelectionDeviceManagerV1, ()>(1, ());
+        Self {}
+    }
+
+    /// Returns the owner of the primary selection of a seat.
+    pub fn selection<State>(state: &mut State, seat: Entity) -> Option<SelectionSource>
+    where
+        State: PrimarySelectionHandler,
+    {
+        selection_source(state.ecs().world(), seat, SelectionTarget::Primary)
+    }
+
+    /// The mime types of the primary selection of a seat, or [`None`] if there is no primary selection.
+    pub fn selection_mime_types<State>(state: &mut State, seat: Entity) -> Option<Vec<String>>
+    where
+        State: PrimarySelectionHandler,
+    {
+        let source = Self::selection(state, seat)?;
+        Some(source.mime_types(state.ecs().world()))
+    }
+
+    /// Sets the primary selection of a seat to contents provided by the compositor.
+    ///
+    /// Clients request the contents using [`PrimarySelectionHandler::send_primary_selection`].
+    pub fn set_selection<State>(
+        state: &mut State,
+        display: &DisplayHandle,
+        seat: Entity,
+        mime_types: Vec<String>,
+    ) where
+        State: Dispatch<ZwpPrimarySelectionOfferV1, EntityData> + PrimarySelectionHandler,
+    {
+        replace_selection::<State, PrimarySelectionDevice>(
+            state,
+            display,
+            seat,
+            SelectionTarget::Primary,
+            Some(SelectionSource::Compositor { mime_types }),
+        );
+    }
+
+    /// Clears the primary selection of a seat.
+    pub fn clear_selection<State>(state: &mut State, display: &DisplayHandle, seat: Entity)
+    where
+        State: Dispatch<ZwpPrimarySelectionOfferV1, EntityData> + PrimarySelectionHandler,
+    {
+        replace_selection::<State, PrimarySelectionDevice>(
+            state,
+            display,
+            seat,
+            SelectionTarget::Primary,
+            None,
+        );
+    }
+
+    /// Requests the contents of the primary selection of a seat to be written to a file descriptor.
+    pub fn request_selection<State>(
+        state: &mut State,
+        seat: Entity,
+        mime_type: String,
+        fd: OwnedFd,
+    ) -> Result<(), SelectionError>
+    where
+        State: PrimarySelectionHandler,
+    {
+        let source = Self::selection(state, seat).ok_or(SelectionError::NoSelection)?;
+
+        if !source.mime_types(state.ecs().world()).contains(&mime_type) {
+            return Err(SelectionError::InvalidMimeType);
+        }
+
+        source.send(state, seat, mime_type, fd, State::send_primary_selection);
+        Ok(())
+    }
+}
+
+/// The primary selection of a seat.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrimarySelection(pub(crate) Option<SelectionSource>);
+
+impl PrimarySelection {
+    pub fn source(&self) -> Option<&SelectionSource> {
+        self.0.as_ref()
+    }
+}
+
+/// A primary selection device of a client.
+#[derive(Debug)]
+pub struct PrimarySelectionDevice {
+    object: ZwpPrimarySelectionDeviceV1,
+    seat: Entity,
+}
+
+impl PrimarySelectionDevice {
+    /// The seat entity of the device.
+    pub fn seat(&self) -> Entity {
+        self.seat
+    }
+}
+
+/// A primary selection offer sent to a client.
+#[derive(Debug)]
+pub struct PrimarySelectionOffer {
+    seat: Entity,
+    source: SelectionSource,
+    mime_types: Vec<String>,
+}
+
+impl PrimarySelectionOffer {
+    /// The source of the offered data.
+    pub fn source(&self) -> &SelectionSource {
+        &self.source
+    }
+}
+
+impl SelectionDevice for PrimarySelectionDevice {
+    type Object = ZwpPrimarySelectionDeviceV1;
+    type Offer = ZwpPrimarySelectionOfferV1;
+    type OfferData = PrimarySelectionOffer;
+
+    fn object(&self) -> &ZwpPrimarySelectionDeviceV1 {
+        &self.object
+    }
+
+    fn seat(&self) -> Entity {
+        self.seat
+    }
+
+    fn offer_data(
+        seat: Entity,
+        source: SelectionSource,
+        mime_types: Vec<String>,
+        _target: SelectionTarget,
+    ) -> PrimarySelectionOffer {
+        PrimarySelectionOffer {
+            seat,
+            source,
+            mime_types,
+        }
+    }
+
+    fn send_offer(
+        object: &ZwpPrimarySelectionDeviceV1,
+        offer: &ZwpPrimarySelectionOfferV1,
+        mime_types: &[String],
+    ) {
+        object.data_offer(offer);
+
+        for mime_type in mime_types {
+            offer.offer(mime_type.clone());
+        }
+    }
+
+    fn send_selection(
+        object: &ZwpPrimarySelectionDeviceV1,
+        _target: SelectionTarget,
+        offer: Option<&ZwpPrimarySelectionOfferV1>,
+    ) {
+        object.selection(offer);
+    }
+}
+
+/// Offers the primary selection to the newly focused client.
+fn focus_changed<State>(state: &mut State, display: &DisplayHandle, seat: Entity)
+where
+    State: Dispatch<ZwpPrimarySelectionOfferV1, EntityData> + PrimarySelectionHandler,
+{
+    offer_selection_to_focus::<State, PrimarySelectionDevice>(
+        state.ecs().world(),
+        display,
+        seat,
+        SelectionTarget::Primary,
+    );
+}
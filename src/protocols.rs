@@ -0,0 +1,54 @@
+//! Protocols which are not available in the version of `wayland-protocols` used by this crate.
+//!
+//! The protocol definitions are stored in the `protocols` directory of the crate.
+
+#![allow(
+    non_upper_case_globals,
+    non_camel_case_types,
+    unused_imports,
+    clippy::all
+)]
+
+/// linux-drm-syncobj-v1, explicit synchronization using DRM synchronization object timelines.
+pub mod linux_drm_syncobj_v1 {
+    use wayland_server;
+    use wayland_server::protocol::*;
+
+    pub mod __interfaces {
+        use wayland_server::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!("protocols/linux-drm-syncobj-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_server_code!("protocols/linux-drm-syncobj-v1.xml");
+}
+
+/// ext-foreign-toplevel-list-v1, listing the toplevels of other clients.
+pub mod ext_foreign_toplevel_list_v1 {
+    use wayland_server;
+    use wayland_server::protocol::*;
+
+    pub mod __interfaces {
+        use wayland_server::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!("protocols/ext-foreign-toplevel-list-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_server_code!("protocols/ext-foreign-toplevel-list-v1.xml");
+}
+
+/// cursor-shape-v1, setting the cursor image from a set of named shapes.
+pub mod cursor_shape_v1 {
+    use wayland_protocols::wp::tablet::zv2::server::*;
+    use wayland_server;
+    use wayland_server::protocol::*;
+
+    pub mod __interfaces {
+        use wayland_protocols::wp::tablet::zv2::server::__interfaces::*;
+        use wayland_server::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!("protocols/cursor-shape-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_server_code!("protocols/cursor-shape-v1.xml");
+}
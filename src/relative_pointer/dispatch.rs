@@ -0,0 +1,106 @@
+use wayland_backend::server::{ClientId, ObjectId};
+use wayland_protocols::wp::relative_pointer::zv1::server::{
+    zwp_relative_pointer_manager_v1::{self, ZwpRelativePointerManagerV1},
+    zwp_relative_pointer_v1::{self, ZwpRelativePointerV1},
+};
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use crate::{EcsAccess, EntityData};
+
+use super::{RelativePointerManager, RelativePointers};
+
+impl<State> GlobalDispatch<ZwpRelativePointerManagerV1, (), State> for RelativePointerManager
+where
+    State: GlobalDispatch<ZwpRelativePointerManagerV1, ()>
+        + Dispatch<ZwpRelativePointerManagerV1, ()>
+        + EcsAccess,
+{
+    fn bind(
+        _state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwpRelativePointerManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<State> Dispatch<ZwpRelativePointerManagerV1, (), State> for RelativePointerManager
+where
+    State: Dispatch<ZwpRelativePointerManagerV1, ()>
+        + Dispatch<ZwpRelativePointerV1, EntityData>
+        + EcsAccess,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        _resource: &ZwpRelativePointerManagerV1,
+        request: zwp_relative_pointer_manager_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwp_relative_pointer_manager_v1::Request::GetRelativePointer { id, pointer } => {
+                // The wl_pointer uses the entity data of its seat.
+                let seat = *pointer.data::<EntityData>().unwrap();
+                let relative_pointer = data_init.init(id, seat);
+                let world = state.ecs().world();
+
+                // The relative pointers are only inserted once they are used.
+                if world.query_one_mut::<&RelativePointers>(seat.0).is_err() {
+                    world
+                        .insert_one(seat.0, RelativePointers::default())
+                        .expect("Seat exists while its objects exist");
+                }
+
+                world
+                    .query_one_mut::<&mut RelativePointers>(seat.0)
+                    .expect("Relative pointers were inserted")
+                    .0
+                    .push(relative_pointer);
+            }
+
+            zwp_relative_pointer_manager_v1::Request::Destroy => {
+                // Relative pointers are unaffected by the manager being destroyed.
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<State> Dispatch<ZwpRelativePointerV1, EntityData, State> for RelativePointerManager
+where
+    State: Dispatch<ZwpRelativePointerV1, EntityData> + EcsAccess,
+{
+    fn request(
+        _state: &mut State,
+        _client: &Client,
+        _resource: &ZwpRelativePointerV1,
+        request: zwp_relative_pointer_v1::Request,
+        _data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwp_relative_pointer_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, resource: ObjectId, data: &EntityData) {
+        if let Ok(pointers) = state
+            .ecs()
+            .world()
+            .query_one_mut::<&mut RelativePointers>(data.0)
+        {
+            pointers.0.retain(|pointer| pointer.id() != resource);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! Implementation of the relative pointer protocol.
+//!
+//! Relative pointers receive the unclamped motion of the pointer, which is used by games and 3D tools together
+//! with [pointer constraints](crate::pointer_constraints). The relative pointers clients created for a seat are
+//! stored in the [`RelativePointers`] of the seat entity, and the compositor sends motion using
+//! [`RelativePointerManager::send_relative_motion`].
+
+mod dispatch;
+
+use hecs::Entity;
+use smithay::utils::{Logical, Point};
+use wayland_protocols::wp::relative_pointer::zv1::server::{
+    zwp_relative_pointer_manager_v1::ZwpRelativePointerManagerV1,
+    zwp_relative_pointer_v1::ZwpRelativePointerV1,
+};
+use wayland_server::{DisplayHandle, GlobalDispatch};
+
+use crate::{seat::PointerFocus, Ecs, EcsAccess};
+
+pub struct RelativePointerManager {}
+
+impl RelativePointerManager {
+    pub fn new<State>(display: &mut DisplayHandle) -> Self
+    where
+        State: GlobalDispatch<ZwpRelativePointerManagerV1, ()> + EcsAccess,
+    {
+        let _global = display.create_global::<State, ZwpRelativePointerManagerV1, ()>(1, ());
+        Self {}
+    }
+
+    /// Sends relative motion to the relative pointers of the client with pointer focus.
+    ///
+    /// `time` is a timestamp in microseconds. The motion is sent even while the pointer is locked.
+    pub fn send_relative_motion(
+        ecs: &mut Ecs,
+        seat: Entity,
+        time: u64,
+        delta: Point<f64, Logical>,
+        delta_unaccel: Point<f64, Logical>,
+    ) {
+        let (focus, pointers) = match ecs
+            .world
+            .query_one_mut::<(&PointerFocus, &RelativePointers)>(seat)
+        {
+            Ok(components) => components,
+            // No client created a relative pointer for the seat yet.
+            Err(_) => return,
+        };
+
+        for pointer in pointers
+            .0
+            .iter()
+            .filter(|pointer| focus.is_client_focused(*pointer))
+        {
+            pointer.relative_motion(
+                (time >> 32) as u32,
+                time as u32,
+                delta.x,
+                delta.y,
+                delta_unaccel.x,
+                delta_unaccel.y,
+            );
+        }
+    }
+}
+
+/// The relative pointers clients created for a seat.
+#[derive(Debug, Default)]
+pub struct RelativePointers(Vec<ZwpRelativePointerV1>);
+
+impl RelativePointers {
+    pub fn iter(&self) -> impl Iterator<Item = &ZwpRelativePointerV1> {
+        self.0.iter()
+    }
+}
@@ -0,0 +1,214 @@
+use smithay::utils::Rectangle;
+use wayland_backend::server::{ClientId, ObjectId};
+use wayland_protocols_wlr::screencopy::v1::server::{
+    zwlr_screencopy_frame_v1::{self, ZwlrScreencopyFrameV1},
+    zwlr_screencopy_manager_v1::{self, ZwlrScreencopyManagerV1},
+};
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use crate::{shm::ShmBuffer, EntityData};
+
+use super::{
+    copy_frame, Capture, FrameState, Screencopy, ScreencopyFrame, ScreencopyHandler, FORMAT,
+};
+
+impl<State> GlobalDispatch<ZwlrScreencopyManagerV1, (), State> for Screencopy
+where
+    State: GlobalDispatch<ZwlrScreencopyManagerV1, ()>
+        + Dispatch<ZwlrScreencopyManagerV1, ()>
+        + ScreencopyHandler,
+{
+    fn bind(
+        _state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwlrScreencopyManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<State> Dispatch<ZwlrScreencopyManagerV1, (), State> for Screencopy
+where
+    State: Dispatch<ZwlrScreencopyManagerV1, ()>
+        + Dispatch<ZwlrScreencopyFrameV1, EntityData>
+        + ScreencopyHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        _resource: &ZwlrScreencopyManagerV1,
+        request: zwlr_screencopy_manager_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        let (frame, overlay_cursor, output, region) = match request {
+            zwlr_screencopy_manager_v1::Request::CaptureOutput {
+                frame,
+                overlay_cursor,
+                output,
+            } => (frame, overlay_cursor, output, None),
+
+            zwlr_screencopy_manager_v1::Request::CaptureOutputRegion {
+                frame,
+                overlay_cursor,
+                output,
+                x,
+                y,
+                width,
+                height,
+            } => (
+                frame,
+                overlay_cursor,
+                output,
+                Some(Rectangle::from_loc_and_size((x, y), (width, height))),
+            ),
+
+            zwlr_screencopy_manager_v1::Request::Destroy => {
+                // Frames are unaffected by the global being destroyed.
+                return;
+            }
+
+            _ => unreachable!(),
+        };
+
+        let entity = state.ecs().world().reserve_entity();
+        let object = data_init.init(frame, EntityData(entity));
+
+        let region = match region {
+            // An empty region cannot be captured.
+            Some(region) if region.size.w <= 0 || region.size.h <= 0 => None,
+            region => state.capture_region(&output, region),
+        }
+        .filter(|region| region.size.w > 0 && region.size.h > 0);
+
+        let capture = Capture {
+            output,
+            region: region.unwrap_or_else(|| Rectangle::from_loc_and_size((0, 0), (0, 0))),
+            overlay_cursor: overlay_cursor != 0,
+        };
+
+        let frame_state = if region.is_some() {
+            object.buffer(
+                FORMAT,
+                capture.region.size.w as u32,
+                capture.region.size.h as u32,
+                capture.stride() as u32,
+            );
+
+            if object.version() >= 3 {
+                object.buffer_done();
+            }
+
+            FrameState::Idle
+        } else {
+            object.failed();
+            FrameState::Used
+        };
+
+        state
+            .ecs()
+            .world()
+            .insert_one(
+                entity,
+                ScreencopyFrame {
+                    object,
+                    capture,
+                    state: frame_state,
+                },
+            )
+            .expect("Entity was reserved");
+    }
+}
+
+impl<State> Dispatch<ZwlrScreencopyFrameV1, EntityData, State> for Screencopy
+where
+    State: Dispatch<ZwlrScreencopyFrameV1, EntityData> + ScreencopyHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        resource: &ZwlrScreencopyFrameV1,
+        request: zwlr_screencopy_frame_v1::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        let (buffer, with_damage) = match request {
+            zwlr_screencopy_frame_v1::Request::Copy { buffer } => (buffer, false),
+            zwlr_screencopy_frame_v1::Request::CopyWithDamage { buffer } => (buffer, true),
+
+            zwlr_screencopy_frame_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+                return;
+            }
+
+            _ => unreachable!(),
+        };
+
+        let shm_buffer = buffer.data::<EntityData>().and_then(|buffer| {
+            state
+                .ecs()
+                .world()
+                .query_one_mut::<&ShmBuffer>(buffer.0)
+                .ok()
+                .cloned()
+        });
+
+        let frame = state
+            .ecs()
+            .world()
+            .query_one_mut::<&mut ScreencopyFrame>(data.0)
+            .expect("Frame exists until destroyed");
+
+        if !matches!(frame.state, FrameState::Idle) {
+            resource.post_error(
+                zwlr_screencopy_frame_v1::Error::AlreadyUsed,
+                "Frame was already copied",
+            );
+            return;
+        }
+
+        let shm_buffer = match shm_buffer {
+            Some(shm_buffer) => shm_buffer,
+            None => {
+                // Only shm buffers are advertised.
+                frame.state = FrameState::Used;
+                resource.failed();
+                return;
+            }
+        };
+
+        let size = frame.capture.region.size;
+        if shm_buffer.format() != FORMAT
+            || shm_buffer.width() != size.w
+            || shm_buffer.height() != size.h
+            || shm_buffer.stride() != frame.capture.stride()
+        {
+            resource.post_error(
+                zwlr_screencopy_frame_v1::Error::InvalidBuffer,
+                format!(
+                    "Expected a {:?} buffer of {}x{} with stride {}",
+                    FORMAT,
+                    size.w,
+                    size.h,
+                    frame.capture.stride()
+                ),
+            );
+            return;
+        }
+
+        frame.state = FrameState::Waiting(shm_buffer);
+
+        if !with_damage {
+            copy_frame(state, data.0);
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        let _ = state.ecs().world().despawn(data.0);
+    }
+}
@@ -0,0 +1,239 @@
+//! Implementation of the wlr-screencopy protocol.
+//!
+//! Clients capture the contents of an output, or a region of it, into a shm buffer. The compositor provides the
+//! pixels through [`ScreencopyHandler::copy_pixels`].
+//!
+//! # Frames
+//!
+//! Every capture is a frame entity with a [`ScreencopyFrame`] component. When a frame is created, the
+//! compositor chooses the region of the output to capture and the client is told the size of the buffer it
+//! must provide. Frames are copied at most once.
+//!
+//! Frames copied using `copy_with_damage` wait until the captured region is damaged. The compositor reports
+//! damage of an output using [`Screencopy::damage_output`], usually after rendering the output.
+
+mod dispatch;
+
+use std::mem;
+
+use smithay::utils::{Buffer, Logical, Point, Rectangle};
+use wayland_protocols_wlr::screencopy::v1::server::{
+    zwlr_screencopy_frame_v1::{self, ZwlrScreencopyFrameV1},
+    zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+};
+use wayland_server::{
+    protocol::{wl_output::WlOutput, wl_shm},
+    DisplayHandle, GlobalDispatch,
+};
+
+use crate::{shm::ShmBuffer, EcsAccess};
+
+/// The format of buffers frames are copied into.
+const FORMAT: wl_shm::Format = wl_shm::Format::Xrgb8888;
+
+pub trait ScreencopyHandler: EcsAccess {
+    /// Chooses the area of an output a client captures.
+    ///
+    /// `region` is the area of the output in logical coordinates the client asked for, or [`None`] if the
+    /// whole output should be captured. Returns the area in the coordinates of the rendered output, or [`None`]
+    /// if the output cannot be captured.
+    fn capture_region(
+        &mut self,
+        output: &WlOutput,
+        region: Option<Rectangle<i32, Logical>>,
+    ) -> Option<Rectangle<i32, Buffer>>;
+
+    /// Copies the contents of the captured area into the buffer of a frame.
+    ///
+    /// `data` is an xrgb8888 image with the size of the captured area and rows of `4 * width` bytes. Returns
+    /// `false` if the contents are not available, which fails the frame.
+    fn copy_pixels(&mut self, capture: &Capture, data: &mut [u8]) -> bool;
+}
+
+pub struct Screencopy {}
+
+impl Screencopy {
+    pub fn new<State>(display: &mut DisplayHandle) -> Self
+    where
+        State: GlobalDispatch<ZwlrScreencopyManagerV1, ()> + ScreencopyHandler,
+    {
+        let _global = display.create_global::<State, ZwlrScreencopyManagerV1, ()>(3, ());
+        Self {}
+    }
+
+    /// Reports damage of an output, completing the frames which wait for damage of the output.
+    ///
+    /// `damage` is in the coordinates of the rendered output, like the regions returned by
+    /// [`ScreencopyHandler::capture_region`].
+    pub fn damage_output<State: ScreencopyHandler>(
+        state: &mut State,
+        output: &WlOutput,
+        damage: &[Rectangle<i32, Buffer>],
+    ) {
+        let mut damaged = Vec::new();
+
+        for (entity, frame) in state.ecs().world().query_mut::<&mut ScreencopyFrame>() {
+            if frame.capture.output != *output || !matches!(frame.state, FrameState::Waiting(_)) {
+                continue;
+            }
+
+            let region = frame.capture.region;
+            let frame_damage = damage
+                .iter()
+                .filter_map(|rect| intersection(*rect, region))
+                .map(|rect| Rectangle::from_loc_and_size(rect.loc - region.loc, rect.size))
+                .collect::<Vec<_>>();
+
+            if frame_damage.is_empty() {
+                continue;
+            }
+
+            for rect in &frame_damage {
+                frame.object.damage(
+                    rect.loc.x as u32,
+                    rect.loc.y as u32,
+                    rect.size.w as u32,
+                    rect.size.h as u32,
+                );
+            }
+
+            damaged.push(entity);
+        }
+
+        for entity in damaged {
+            copy_frame(state, entity);
+        }
+    }
+}
+
+/// What a frame captures.
+#[derive(Debug, Clone)]
+pub struct Capture {
+    output: WlOutput,
+    region: Rectangle<i32, Buffer>,
+    overlay_cursor: bool,
+}
+
+impl Capture {
+    pub fn output(&self) -> &WlOutput {
+        &self.output
+    }
+
+    /// The captured area in the coordinates of the rendered output.
+    pub fn region(&self) -> Rectangle<i32, Buffer> {
+        self.region
+    }
+
+    /// Whether the cursor should be included in the captured contents.
+    pub fn overlay_cursor(&self) -> bool {
+        self.overlay_cursor
+    }
+
+    /// The number of bytes in a row of the buffer.
+    fn stride(&self) -> i32 {
+        self.region.size.w * 4
+    }
+}
+
+/// A screencopy frame.
+#[derive(Debug)]
+pub struct ScreencopyFrame {
+    object: ZwlrScreencopyFrameV1,
+    capture: Capture,
+    state: FrameState,
+}
+
+impl ScreencopyFrame {
+    pub fn capture(&self) -> &Capture {
+        &self.capture
+    }
+
+    /// Whether the frame waits for damage of the captured area before it is copied.
+    pub fn is_waiting(&self) -> bool {
+        matches!(self.state, FrameState::Waiting(_))
+    }
+}
+
+#[derive(Debug)]
+enum FrameState {
+    /// The client has not provided a buffer yet.
+    Idle,
+    /// The frame is copied into the buffer once the captured area is damaged.
+    Waiting(ShmBuffer),
+    /// The frame was copied or failed.
+    Used,
+}
+
+/// Copies a frame into the buffer the client provided.
+fn copy_frame<State: ScreencopyHandler>(state: &mut State, entity: hecs::Entity) {
+    let frame = match state
+        .ecs()
+        .world()
+        .query_one_mut::<&mut ScreencopyFrame>(entity)
+    {
+        Ok(frame) => frame,
+        // The frame was destroyed.
+        Err(_) => return,
+    };
+
+    let buffer = match mem::replace(&mut frame.state, FrameState::Used) {
+        FrameState::Waiting(buffer) => buffer,
+        _ => return,
+    };
+    let object = frame.object.clone();
+    let capture = frame.capture.clone();
+
+    // The pixels are copied into the buffer afterwards, so the pool is not locked while the compositor renders,
+    // which may read other buffers of the same pool.
+    let mut pixels = vec![0; buffer.stride() as usize * buffer.height() as usize];
+    if !state.copy_pixels(&capture, &mut pixels) {
+        object.failed();
+        return;
+    }
+
+    if buffer
+        .with_data_mut(|data| data.copy_from_slice(&pixels))
+        .is_err()
+    {
+        object.failed();
+        return;
+    }
+
+    let (tv_sec, tv_nsec) = monotonic_time();
+    object.flags(zwlr_screencopy_frame_v1::Flags::empty());
+    object.ready((tv_sec >> 32) as u32, tv_sec as u32, tv_nsec);
+}
+
+/// The current time of `CLOCK_MONOTONIC` in seconds and nanoseconds.
+fn monotonic_time() -> (u64, u32) {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: The timespec is valid for writes.
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time);
+    }
+
+    (time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+fn intersection(
+    a: Rectangle<i32, Buffer>,
+    b: Rectangle<i32, Buffer>,
+) -> Option<Rectangle<i32, Buffer>> {
+    let loc = Point::from((a.loc.x.max(b.loc.x), a.loc.y.max(b.loc.y)));
+    let end = Point::<i32, Buffer>::from((
+        (a.loc.x + a.size.w).min(b.loc.x + b.size.w),
+        (a.loc.y + a.size.h).min(b.loc.y + b.size.h),
+    ));
+
+    if end.x <= loc.x || end.y <= loc.y {
+        return None;
+    }
+
+    Some(Rectangle::from_loc_and_size(
+        loc,
+        (end.x - loc.x, end.y - loc.y),
+    ))
+}
@@ -0,0 +1,170 @@
+use hecs::Entity;
+use smithay::utils::{Logical, Point};
+use wayland_server::{
+    protocol::{
+        wl_pointer::{self, WlPointer},
+        wl_surface::WlSurface,
+    },
+    Resource,
+};
+
+use crate::{
+    compositor::{AlreadyHasRole, Buffer, Compositor, CompositorEvent, Role},
+    protocols::cursor_shape_v1::wp_cursor_shape_device_v1::Shape,
+    EcsAccess, EntityData,
+};
+
+use super::{PointerFocus, Seat};
+
+/// The cursor image of a seat.
+///
+/// The image is reset to the default shape whenever the pointer enters another surface, until the client of
+/// that surface sets a cursor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorImage {
+    /// A surface with the [`CURSOR_ROLE`](Seat::CURSOR_ROLE) drawn by the client. The hotspot of the cursor is
+    /// stored in the [`CursorSurface`] of the surface entity.
+    Surface(Entity),
+    /// A cursor shape drawn by the compositor.
+    Named(Shape),
+    /// The client hid the cursor.
+    Hidden,
+}
+
+impl Default for CursorImage {
+    fn default() -> Self {
+        Self::Named(Shape::Default)
+    }
+}
+
+/// A [`WlSurface`] with the [`CURSOR_ROLE`](Seat::CURSOR_ROLE).
+///
+/// This is inserted when the surface is first used as a cursor and is never removed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CursorSurface {
+    hotspot: Point<i32, Logical>,
+}
+
+impl CursorSurface {
+    /// The location of the pointer relative to the top left corner of the surface.
+    ///
+    /// This is set by `wl_pointer.set_cursor` and moved by the offset of every commit of the surface.
+    pub fn hotspot(&self) -> Point<i32, Logical> {
+        self.hotspot
+    }
+}
+
+/// Sets the cursor image of a seat if the pointer is over a surface of the client which owns the object.
+///
+/// The image is only set if the serial is the serial of the pointer enter event sent to that client.
+pub(crate) fn set_cursor_image(
+    world: &mut hecs::World,
+    seat: Entity,
+    object: &impl Resource,
+    serial: u32,
+    image: CursorImage,
+) {
+    let (focus, current) = world
+        .query_one_mut::<(&PointerFocus, &mut CursorImage)>(seat)
+        .expect("Not a seat entity");
+
+    if focus.is_enter_serial(object, serial) {
+        *current = image;
+    }
+}
+
+/// Handles `wl_pointer.set_cursor`.
+pub(super) fn set_cursor<State: EcsAccess>(
+    state: &mut State,
+    pointer: &WlPointer,
+    seat: Entity,
+    serial: u32,
+    surface: Option<WlSurface>,
+    hotspot: Point<i32, Logical>,
+) {
+    let world = state.ecs().world();
+
+    // The request is ignored unless the pointer is over a surface of the client which entered it with the serial.
+    let focused = world
+        .query_one_mut::<&PointerFocus>(seat)
+        .map(|focus| focus.is_enter_serial(pointer, serial))
+        .unwrap_or(false);
+
+    if !focused {
+        return;
+    }
+
+    let surface = match surface {
+        Some(surface) => surface,
+        None => {
+            set_cursor_image(world, seat, pointer, serial, CursorImage::Hidden);
+            return;
+        }
+    };
+
+    let entity = surface.data::<EntityData>().unwrap().0;
+    let role = world
+        .query_one_mut::<&mut Role>(entity)
+        .expect("Surface must be a valid entity if dispatched");
+
+    if role.role() != Some(Seat::CURSOR_ROLE) {
+        if let Err(AlreadyHasRole) = role.set_role(Seat::CURSOR_ROLE) {
+            pointer.post_error(wl_pointer::Error::Role, "Cursor surface already has a role");
+            return;
+        }
+
+        state.ecs().push_event(CompositorEvent::RoleAssigned {
+            surface: surface.clone(),
+            entity,
+            role: Seat::CURSOR_ROLE,
+        });
+    }
+
+    let world = state.ecs().world();
+    match world.query_one_mut::<&mut CursorSurface>(entity) {
+        // Setting the current cursor surface again only moves the hotspot.
+        Ok(cursor) => cursor.hotspot = hotspot,
+
+        // The commit systems are only added the first time, since the cursor surface is never removed.
+        Err(_) => {
+            world
+                .insert_one(entity, CursorSurface { hotspot })
+                .expect("Surface must be a valid entity if dispatched");
+            Compositor::add_post_commit::<State>(state.ecs(), &surface, post_commit::<State>);
+            Compositor::add_destroy::<State>(state.ecs(), &surface, destroy::<State>);
+        }
+    }
+
+    set_cursor_image(
+        state.ecs().world(),
+        seat,
+        pointer,
+        serial,
+        CursorImage::Surface(entity),
+    );
+}
+
+/// Moves the hotspot by the offset of the buffer, so the cursor image stays in place.
+fn post_commit<State: EcsAccess>(state: &mut State, surface: &WlSurface) {
+    let entity = surface.data::<EntityData>().unwrap().0;
+    let (buffer, cursor) = state
+        .ecs()
+        .world()
+        .query_one_mut::<(&Buffer, &mut CursorSurface)>(entity)
+        .expect("Commit systems are only added with the cursor surface");
+
+    if let Some(delta) = buffer.delta() {
+        cursor.hotspot -= delta;
+    }
+}
+
+/// Hides the cursor of seats which showed the destroyed surface.
+fn destroy<State: EcsAccess>(state: &mut State, surface: &WlSurface) {
+    let entity = surface.data::<EntityData>().unwrap().0;
+
+    for (_, image) in state.ecs().world().query_mut::<&mut CursorImage>() {
+        if *image == CursorImage::Surface(entity) {
+            *image = CursorImage::Hidden;
+        }
+    }
+}
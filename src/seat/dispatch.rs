@@ -0,0 +1,221 @@
+use wayland_backend::server::{ClientId, ObjectId};
+use wayland_server::{
+    protocol::{
+        wl_keyboard::{self, WlKeyboard},
+        wl_pointer::{self, WlPointer},
+        wl_seat::{self, WlSeat},
+        wl_touch::{self, WlTouch},
+    },
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+};
+
+use crate::{EcsAccess, EntityData};
+
+use super::{cursor, input, Capabilities, KeyboardState, Seat, SeatName, SeatObjects};
+
+impl<State> GlobalDispatch<WlSeat, EntityData, State> for Seat
+where
+    State: GlobalDispatch<WlSeat, EntityData> + Dispatch<WlSeat, EntityData> + EcsAccess,
+{
+    fn bind(
+        state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<WlSeat>,
+        global_data: &EntityData,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        let seat = data_init.init(resource, *global_data);
+        let (name, capabilities, objects) = state
+            .ecs()
+            .world()
+            .query_one_mut::<(&SeatName, &Capabilities, &mut SeatObjects)>(global_data.0)
+            .expect("Seat exists while its global exists");
+
+        seat.capabilities(capabilities.0);
+
+        if seat.version() >= 2 {
+            seat.name(name.0.clone());
+        }
+
+        objects.seats.push(seat);
+    }
+}
+
+impl<State> Dispatch<WlSeat, EntityData, State> for Seat
+where
+    State: Dispatch<WlSeat, EntityData>
+        + Dispatch<WlPointer, EntityData>
+        + Dispatch<WlKeyboard, EntityData>
+        + Dispatch<WlTouch, EntityData>
+        + EcsAccess,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        _resource: &WlSeat,
+        request: wl_seat::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        let world = state.ecs().world();
+        let (objects, keyboard) = world
+            .query_one_mut::<(&mut SeatObjects, &KeyboardState)>(data.0)
+            .expect("Seat exists while it has objects");
+
+        match request {
+            wl_seat::Request::GetPointer { id } => {
+                let object = data_init.init(id, *data);
+                objects.pointers.push(object.clone());
+
+                input::enter_new_pointer(world, data.0, &object);
+            }
+
+            wl_seat::Request::GetKeyboard { id } => {
+                let object = data_init.init(id, *data);
+                input::send_keymap(&object, keyboard.keymap());
+                objects.keyboards.push(object.clone());
+
+                input::enter_new_keyboard(world, data.0, &object);
+            }
+
+            wl_seat::Request::GetTouch { id } => {
+                objects.touches.push(data_init.init(id, *data));
+            }
+
+            wl_seat::Request::Release => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, resource: ObjectId, data: &EntityData) {
+        if let Ok(objects) = state
+            .ecs()
+            .world()
+            .query_one_mut::<&mut SeatObjects>(data.0)
+        {
+            objects.seats.retain(|seat| seat.id() != resource);
+        }
+    }
+}
+
+impl<State> Dispatch<WlPointer, EntityData, State> for Seat
+where
+    State: Dispatch<WlPointer, EntityData> + EcsAccess,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        resource: &WlPointer,
+        request: wl_pointer::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            wl_pointer::Request::SetCursor {
+                serial,
+                surface,
+                hotspot_x,
+                hotspot_y,
+            } => {
+                cursor::set_cursor(
+                    state,
+                    resource,
+                    data.0,
+                    serial,
+                    surface,
+                    (hotspot_x, hotspot_y).into(),
+                );
+            }
+
+            wl_pointer::Request::Release => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, resource: ObjectId, data: &EntityData) {
+        if let Ok(objects) = state
+            .ecs()
+            .world()
+            .query_one_mut::<&mut SeatObjects>(data.0)
+        {
+            objects.pointers.retain(|pointer| pointer.id() != resource);
+        }
+    }
+}
+
+impl<State> Dispatch<WlKeyboard, EntityData, State> for Seat
+where
+    State: Dispatch<WlKeyboard, EntityData> + EcsAccess,
+{
+    fn request(
+        _state: &mut State,
+        _client: &Client,
+        _resource: &WlKeyboard,
+        request: wl_keyboard::Request,
+        _data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            wl_keyboard::Request::Release => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, resource: ObjectId, data: &EntityData) {
+        if let Ok(objects) = state
+            .ecs()
+            .world()
+            .query_one_mut::<&mut SeatObjects>(data.0)
+        {
+            objects
+                .keyboards
+                .retain(|keyboard| keyboard.id() != resource);
+        }
+    }
+}
+
+impl<State> Dispatch<WlTouch, EntityData, State> for Seat
+where
+    State: Dispatch<WlTouch, EntityData> + EcsAccess,
+{
+    fn request(
+        _state: &mut State,
+        _client: &Client,
+        _resource: &WlTouch,
+        request: wl_touch::Request,
+        _data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            wl_touch::Request::Release => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, resource: ObjectId, data: &EntityData) {
+        if let Ok(objects) = state
+            .ecs()
+            .world()
+            .query_one_mut::<&mut SeatObjects>(data.0)
+        {
+            objects.touches.retain(|touch| touch.id() != resource);
+        }
+    }
+}
@@ -0,0 +1,434 @@
+use std::{fs::File, io, os::unix::io::AsRawFd, sync::Arc, time::Duration};
+
+use hecs::Entity;
+use smithay::utils::{Logical, Point};
+use wayland_server::{
+    protocol::{
+        wl_keyboard::{self, WlKeyboard},
+        wl_pointer::{ButtonState, WlPointer},
+        wl_surface::WlSurface,
+    },
+    Resource,
+};
+
+use crate::{create_sealed_file, next_serial};
+
+use super::{KeyboardFocus, PointerFocus, SeatObjects};
+
+/// A keymap in the xkb v1 text format, shared with the clients of a seat.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    file: Arc<File>,
+    size: u32,
+}
+
+impl Keymap {
+    /// Creates a keymap from its text, for example as written by `xkb_keymap_get_as_string`.
+    pub fn new(keymap: &str) -> io::Result<Self> {
+        // Clients read the keymap as a NUL terminated string.
+        let mut data = keymap.as_bytes().to_vec();
+        data.push(0);
+
+        let size = u32::try_from(data.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Keymap is too large"))?;
+        let file = create_sealed_file(c"smithay-ecs-keymap", &data)?;
+
+        Ok(Self {
+            file: Arc::new(file),
+            size,
+        })
+    }
+}
+
+/// The state of the modifiers of a keyboard, as serialized by xkbcommon.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub depressed: u32,
+    pub latched: u32,
+    pub locked: u32,
+    pub group: u32,
+}
+
+/// The keyboard of a seat.
+#[derive(Debug, Clone, Default)]
+pub struct KeyboardState {
+    keymap: Option<Keymap>,
+    modifiers: Modifiers,
+    pressed: Vec<u32>,
+    /// The serial of the enter event sent to the focused client.
+    enter_serial: Option<u32>,
+    /// The serial of the last key event sent to the focused client.
+    key_serial: Option<u32>,
+}
+
+impl KeyboardState {
+    pub fn keymap(&self) -> Option<&Keymap> {
+        self.keymap.as_ref()
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    /// The keys which are currently pressed, as evdev key codes.
+    pub fn pressed_keys(&self) -> &[u32] {
+        &self.pressed
+    }
+}
+
+/// The buttons of the pointer of a seat.
+#[derive(Debug, Clone, Default)]
+pub struct PointerButtons {
+    pressed: Vec<u32>,
+    /// The serial of the press which started the implicit grab, while any button is pressed.
+    grab_serial: Option<u32>,
+    /// The serial of the last button event sent to the client below the pointer.
+    serial: Option<u32>,
+}
+
+impl PointerButtons {
+    /// The buttons which are currently pressed, as evdev button codes.
+    pub fn pressed(&self) -> &[u32] {
+        &self.pressed
+    }
+
+    /// The serial of the press which started the implicit grab of the pointer, while any button is pressed.
+    pub fn grab_serial(&self) -> Option<u32> {
+        self.grab_serial
+    }
+}
+
+/// Returns whether a serial belongs to an input event sent to the client of an object.
+///
+/// This accepts the serials of the keyboard enter, the last key and the last button, which clients pass to
+/// requests triggered by user input such as setting the selection.
+pub(crate) fn is_input_serial(
+    world: &mut hecs::World,
+    seat: Entity,
+    object: &impl Resource,
+    serial: u32,
+) -> bool {
+    let (keyboard_focus, keyboard, pointer_focus, buttons) = match world.query_one_mut::<(
+        &KeyboardFocus,
+        &KeyboardState,
+        &PointerFocus,
+        &PointerButtons,
+    )>(seat)
+    {
+        Ok(components) => components,
+        Err(_) => return false,
+    };
+
+    let keyboard_serial = keyboard_focus.is_client_focused(object)
+        && (keyboard.enter_serial == Some(serial) || keyboard.key_serial == Some(serial));
+    let button_serial = pointer_focus.is_client_focused(object) && buttons.serial == Some(serial);
+
+    keyboard_serial || button_serial
+}
+
+/// Returns whether a serial is the serial of the implicit pointer grab of the client of an object.
+pub(crate) fn is_grab_serial(
+    world: &mut hecs::World,
+    seat: Entity,
+    object: &impl Resource,
+    serial: u32,
+) -> bool {
+    match world.query_one_mut::<(&PointerFocus, &PointerButtons)>(seat) {
+        Ok((focus, buttons)) => {
+            focus.is_client_focused(object) && buttons.grab_serial == Some(serial)
+        }
+        Err(_) => false,
+    }
+}
+
+/// The keyboards of the client of a surface.
+fn client_keyboards<'a>(
+    objects: &'a SeatObjects,
+    surface: &'a WlSurface,
+) -> impl Iterator<Item = &'a WlKeyboard> {
+    objects
+        .keyboards()
+        .filter(|keyboard| keyboard.id().same_client_as(&surface.id()))
+}
+
+/// Sends the keymap to a keyboard.
+///
+/// Without a keymap the client is told that there is none.
+pub(super) fn send_keymap(keyboard: &WlKeyboard, keymap: Option<&Keymap>) {
+    match keymap {
+        Some(keymap) => keyboard.keymap(
+            wl_keyboard::KeymapFormat::XkbV1,
+            keymap.file.as_raw_fd(),
+            keymap.size,
+        ),
+
+        // The event requires a file descriptor even if there is no keymap.
+        None => {
+            if let Ok(file) = File::open("/dev/null") {
+                keyboard.keymap(wl_keyboard::KeymapFormat::NoKeymap, file.as_raw_fd(), 0);
+            }
+        }
+    }
+}
+
+/// Sends the keymap to every keyboard of a seat.
+pub(super) fn set_keymap(world: &mut hecs::World, seat: Entity, keymap: Keymap) {
+    let (keyboard, objects) = world
+        .query_one_mut::<(&mut KeyboardState, &SeatObjects)>(seat)
+        .expect("Not a seat entity");
+
+    for object in objects.keyboards() {
+        send_keymap(object, Some(&keymap));
+    }
+
+    keyboard.keymap = Some(keymap);
+}
+
+/// Sends enter and the modifiers to keyboards of the focused client.
+fn send_enter<'a>(
+    keyboards: impl Iterator<Item = &'a WlKeyboard>,
+    surface: &WlSurface,
+    keyboard: &KeyboardState,
+    serial: u32,
+) {
+    let keys = keyboard
+        .pressed
+        .iter()
+        .flat_map(|key| key.to_ne_bytes())
+        .collect::<Vec<_>>();
+    let modifiers = keyboard.modifiers;
+
+    for object in keyboards {
+        object.enter(serial, surface, keys.clone());
+        object.modifiers(
+            serial,
+            modifiers.depressed,
+            modifiers.latched,
+            modifiers.locked,
+            modifiers.group,
+        );
+    }
+}
+
+/// Sends enter to the keyboards of the client which gained keyboard focus.
+pub(super) fn enter(world: &mut hecs::World, seat: Entity) {
+    let (focus, keyboard, objects) = world
+        .query_one_mut::<(&KeyboardFocus, &mut KeyboardState, &SeatObjects)>(seat)
+        .expect("Not a seat entity");
+
+    keyboard.key_serial = None;
+    keyboard.enter_serial = None;
+
+    let surface = match focus.surface() {
+        Some(surface) => surface,
+        None => return,
+    };
+
+    let serial = next_serial();
+    send_enter(
+        client_keyboards(objects, surface),
+        surface,
+        keyboard,
+        serial,
+    );
+    keyboard.enter_serial = Some(serial);
+}
+
+/// Sends enter to a new keyboard if its client has keyboard focus.
+pub(super) fn enter_new_keyboard(world: &mut hecs::World, seat: Entity, object: &WlKeyboard) {
+    let (focus, keyboard) = world
+        .query_one_mut::<(&KeyboardFocus, &mut KeyboardState)>(seat)
+        .expect("Not a seat entity");
+
+    if let Some(surface) = focus.surface().filter(|_| focus.is_client_focused(object)) {
+        // The enter event shares its serial with the other keyboards of the client.
+        let serial = *keyboard.enter_serial.get_or_insert_with(next_serial);
+        send_enter([object].into_iter(), surface, keyboard, serial);
+    }
+}
+
+/// Sends leave to the keyboards of the client which lost keyboard focus.
+pub(super) fn leave(world: &mut hecs::World, seat: Entity, surface: Option<&WlSurface>) {
+    let surface = match surface.filter(|surface| surface.is_alive()) {
+        Some(surface) => surface,
+        None => return,
+    };
+
+    let objects = world
+        .query_one_mut::<&SeatObjects>(seat)
+        .expect("Not a seat entity");
+
+    let serial = next_serial();
+    for object in client_keyboards(objects, surface) {
+        object.leave(serial, surface);
+    }
+}
+
+/// Updates the pressed keys and sends the key to the focused client.
+pub(super) fn key(
+    world: &mut hecs::World,
+    seat: Entity,
+    time: Duration,
+    key: u32,
+    state: wl_keyboard::KeyState,
+) -> Option<u32> {
+    let (focus, keyboard, objects) = world
+        .query_one_mut::<(&KeyboardFocus, &mut KeyboardState, &SeatObjects)>(seat)
+        .expect("Not a seat entity");
+
+    match state {
+        wl_keyboard::KeyState::Pressed if !keyboard.pressed.contains(&key) => {
+            keyboard.pressed.push(key)
+        }
+        wl_keyboard::KeyState::Released => keyboard.pressed.retain(|pressed| *pressed != key),
+        _ => (),
+    }
+
+    let surface = focus.surface()?;
+    let serial = next_serial();
+
+    for object in client_keyboards(objects, surface) {
+        object.key(serial, time.as_millis() as u32, key, state);
+    }
+
+    keyboard.key_serial = Some(serial);
+    Some(serial)
+}
+
+/// Updates the modifiers and sends them to the focused client.
+pub(super) fn modifiers(world: &mut hecs::World, seat: Entity, modifiers: Modifiers) {
+    let (focus, keyboard, objects) = world
+        .query_one_mut::<(&KeyboardFocus, &mut KeyboardState, &SeatObjects)>(seat)
+        .expect("Not a seat entity");
+
+    if keyboard.modifiers == modifiers {
+        return;
+    }
+
+    keyboard.modifiers = modifiers;
+
+    let surface = match focus.surface() {
+        Some(surface) => surface,
+        None => return,
+    };
+
+    let serial = next_serial();
+    for object in client_keyboards(objects, surface) {
+        object.modifiers(
+            serial,
+            modifiers.depressed,
+            modifiers.latched,
+            modifiers.locked,
+            modifiers.group,
+        );
+    }
+}
+
+/// The pointers of the client of a surface.
+fn client_pointers<'a>(
+    objects: &'a SeatObjects,
+    surface: &'a WlSurface,
+) -> impl Iterator<Item = &'a WlPointer> {
+    objects
+        .pointers()
+        .filter(|pointer| pointer.id().same_client_as(&surface.id()))
+}
+
+/// Sends enter to pointers at a location on the surface below the pointer.
+fn send_pointer_enter<'a>(
+    pointers: impl Iterator<Item = &'a WlPointer>,
+    surface: &WlSurface,
+    location: Point<f64, Logical>,
+    serial: u32,
+) {
+    for object in pointers {
+        object.enter(serial, surface, location.x, location.y);
+        if object.version() >= 5 {
+            object.frame();
+        }
+    }
+}
+
+/// Sends leave to the pointers of the client which lost pointer focus and enter to the pointers of the client
+/// which gained it.
+///
+/// Returns the serial of the enter event, or [`None`] if no surface is below the pointer.
+pub(super) fn pointer_enter(
+    objects: &SeatObjects,
+    previous: Option<&WlSurface>,
+    surface: Option<&WlSurface>,
+    location: Point<f64, Logical>,
+) -> Option<u32> {
+    if let Some(previous) = previous.filter(|surface| surface.is_alive()) {
+        let serial = next_serial();
+        for object in client_pointers(objects, previous) {
+            object.leave(serial, previous);
+            if object.version() >= 5 {
+                object.frame();
+            }
+        }
+    }
+
+    let surface = surface?;
+    let serial = next_serial();
+    send_pointer_enter(client_pointers(objects, surface), surface, location, serial);
+    Some(serial)
+}
+
+/// Sends enter to a new pointer if its client has the surface below the pointer.
+pub(super) fn enter_new_pointer(world: &mut hecs::World, seat: Entity, object: &WlPointer) {
+    let focus = world
+        .query_one_mut::<&PointerFocus>(seat)
+        .expect("Not a seat entity");
+
+    if let (Some(surface), Some(serial)) = (focus.surface(), focus.serial()) {
+        if focus.is_client_focused(object) {
+            send_pointer_enter([object].into_iter(), surface, focus.location(), serial);
+        }
+    }
+}
+
+/// Updates the pressed buttons and sends the button to the client below the pointer.
+pub(super) fn button(
+    world: &mut hecs::World,
+    seat: Entity,
+    time: Duration,
+    button: u32,
+    state: ButtonState,
+) -> Option<u32> {
+    let (focus, buttons, objects) = world
+        .query_one_mut::<(&PointerFocus, &mut PointerButtons, &SeatObjects)>(seat)
+        .expect("Not a seat entity");
+
+    let serial = focus.surface().map(|_| next_serial());
+
+    match state {
+        ButtonState::Pressed if !buttons.pressed.contains(&button) => {
+            // The first press starts an implicit grab, which lasts until every button is released.
+            if buttons.pressed.is_empty() {
+                buttons.grab_serial = serial;
+            }
+            buttons.pressed.push(button);
+        }
+        ButtonState::Released => {
+            buttons.pressed.retain(|pressed| *pressed != button);
+            if buttons.pressed.is_empty() {
+                buttons.grab_serial = None;
+            }
+        }
+        _ => (),
+    }
+
+    let surface = focus.surface()?;
+    let serial = serial?;
+
+    for object in client_pointers(objects, surface) {
+        object.button(serial, time.as_millis() as u32, button, state);
+        if object.version() >= 5 {
+            object.frame();
+        }
+    }
+
+    buttons.serial = Some(serial);
+    Some(serial)
+}
@@ -0,0 +1,439 @@
+//! Implementation of seats.
+//!
+//! A seat is a group of input devices used by a single user. Every seat is an entity created by [`Seat::new`],
+//! which also creates the `wl_seat` global of the seat.
+//!
+//! # Seat entities
+//!
+//! Objects created from a `wl_seat` use the [`EntityData`] of the seat entity. Protocols which extend a seat,
+//! such as the [data device](crate::data_device), find the seat entity from the `wl_seat` a client passes using
+//! [`Seat::entity_of`].
+//!
+//! The objects clients created from the seat are listed in the [`SeatObjects`] of the seat entity. Input
+//! events are sent by the compositor using those objects.
+//!
+//! # Keyboard focus
+//!
+//! The compositor sets the keyboard focus using [`Seat::set_keyboard_focus`], which is stored in the
+//! [`KeyboardFocus`] of the seat entity. Protocol extensions which depend on the keyboard focus add a
+//! [`SeatFocusChanged`] system to the seat using [`Seat::add_focus_system`].
+//!
+//! The focused client receives the keymap, the pressed keys and the modifiers of the [`KeyboardState`] of the seat.
+//! The compositor forwards keyboard input using [`Seat::keyboard_key`] and [`Seat::keyboard_modifiers`].
+//!
+//! # Pointer focus
+//!
+//! The surface below the pointer and the location of the pointer on that surface are set using
+//! [`Seat::set_pointer_focus`] and stored in the [`PointerFocus`] of the seat entity. Protocol extensions which
+//! depend on the pointer, such as [pointer constraints](crate::pointer_constraints), add a [`SeatFocusChanged`]
+//! system using [`Seat::add_pointer_focus_system`].
+//!
+//! Buttons are forwarded to the client below the pointer using [`Seat::pointer_button`]. The first press while
+//! no button is held starts an implicit grab, whose serial in the [`PointerButtons`] of the seat allows the
+//! client to start a drag.
+//!
+//! # Cursor
+//!
+//! The cursor image of a seat is stored in its [`CursorImage`]. Clients set the image while the pointer is over
+//! one of their surfaces, either by giving a surface the [`CURSOR_ROLE`](Seat::CURSOR_ROLE) using
+//! `wl_pointer.set_cursor`, or by choosing a [named shape](crate::cursor_shape). Either request must carry the
+//! serial of the `wl_pointer.enter` event, which is stored in the [`PointerFocus`]. The hotspot of a cursor
+//! surface is tracked in its [`CursorSurface`].
+
+mod cursor;
+mod dispatch;
+mod input;
+
+#[cfg(test)]
+mod tests;
+
+use std::{mem, time::Duration};
+
+use hecs::Entity;
+use smithay::utils::{Logical, Point};
+use wayland_server::{
+    protocol::{
+        wl_keyboard::{self, WlKeyboard},
+        wl_pointer::{self, WlPointer},
+        wl_seat::{self, WlSeat},
+        wl_surface::WlSurface,
+        wl_touch::WlTouch,
+    },
+    DisplayHandle, GlobalDispatch, Resource,
+};
+
+use crate::{idle_notify::IdleNotifier, session_lock, Ecs, EcsAccess, EntityData};
+
+pub use self::{
+    cursor::{CursorImage, CursorSurface},
+    input::{KeyboardState, Keymap, Modifiers, PointerButtons},
+};
+
+pub(crate) use self::{
+    cursor::set_cursor_image,
+    input::{is_grab_serial, is_input_serial},
+};
+
+/// A system that is run after the keyboard or pointer focus of a seat changed.
+pub type SeatFocusChanged<State> = fn(state: &mut State, display: &DisplayHandle, seat: Entity);
+
+pub struct Seat {
+    entity: Entity,
+}
+
+impl Seat {
+    /// Spawns a seat entity and creates the global of the seat.
+    pub fn new<State>(display: &mut DisplayHandle, ecs: &mut Ecs, name: impl Into<String>) -> Self
+    where
+        State: GlobalDispatch<WlSeat, EntityData> + EcsAccess,
+    {
+        let entity = ecs.world.spawn((
+            SeatName(name.into()),
+            Capabilities(wl_seat::Capability::empty()),
+            SeatObjects::default(),
+            KeyboardFocus(None),
+            KeyboardState::default(),
+            PointerFocus::default(),
+            PointerButtons::default(),
+            CursorImage::default(),
+            SeatDisplay(display.clone()),
+            Internal::<State> {
+                focus_systems: Vec::new(),
+                pointer_focus_systems: Vec::new(),
+            },
+        ));
+        let _global = display.create_global::<State, WlSeat, _>(7, EntityData(entity));
+
+        Self { entity }
+    }
+
+    pub const CURSOR_ROLE: &str = "cursor";
+
+    /// The seat entity.
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+
+    /// Returns the seat entity of a `wl_seat`.
+    pub fn entity_of(seat: &WlSeat) -> Entity {
+        seat.data::<EntityData>()
+            .expect("wl_seat must be created by a Seat")
+            .0
+    }
+
+    /// The display of the seat's global.
+    ///
+    /// This is used by systems which are run without a [`DisplayHandle`], for example from `Dispatch::destroyed`.
+    pub(crate) fn display(ecs: &mut Ecs, seat: Entity) -> DisplayHandle {
+        ecs.world
+            .query_one_mut::<&SeatDisplay>(seat)
+            .expect("Not a seat entity")
+            .0
+            .clone()
+    }
+
+    /// Sets the input devices of a seat, which is announced to every client.
+    pub fn set_capabilities(ecs: &mut Ecs, seat: Entity, capabilities: wl_seat::Capability) {
+        let (current, objects) = ecs
+            .world
+            .query_one_mut::<(&mut Capabilities, &SeatObjects)>(seat)
+            .expect("Not a seat entity");
+
+        if current.0 == capabilities {
+            return;
+        }
+
+        current.0 = capabilities;
+
+        for object in &objects.seats {
+            object.capabilities(capabilities);
+        }
+    }
+
+    /// Sets the surface which has keyboard focus.
+    ///
+    /// The client which lost focus receives leave and the client which gained focus receives enter. The focus
+    /// systems of the seat are run in between, so protocols such as the [data device](crate::data_device) send
+    /// their state before the client receives enter. While the [session is
+    /// locked](crate::session_lock), surfaces other than lock surfaces do not get focus.
+    pub fn set_keyboard_focus<State>(
+        state: &mut State,
+        display: &DisplayHandle,
+        seat: Entity,
+        surface: Option<WlSurface>,
+    ) where
+        State: EcsAccess,
+    {
+        // Only lock surfaces receive input while the session is locked.
+        let surface =
+            surface.filter(|surface| session_lock::accepts_input(&mut state.ecs().world, surface));
+
+        let (focus, internal) = state
+            .ecs()
+            .world
+            .query_one_mut::<(&mut KeyboardFocus, &Internal<State>)>(seat)
+            .expect("Not a seat entity of this State");
+
+        if focus.0 == surface {
+            return;
+        }
+
+        let previous = mem::replace(&mut focus.0, surface);
+        let focus_systems = internal.focus_systems.clone();
+
+        input::leave(&mut state.ecs().world, seat, previous.as_ref());
+
+        for system in focus_systems {
+            system(state, display, seat);
+        }
+
+        input::enter(&mut state.ecs().world, seat);
+    }
+
+    /// Sets the keymap of the keyboard of a seat, which is sent to every client.
+    pub fn set_keymap(ecs: &mut Ecs, seat: Entity, keymap: Keymap) {
+        input::set_keymap(&mut ecs.world, seat, keymap);
+    }
+
+    /// Presses or releases a key, which is sent to the client with keyboard focus.
+    ///
+    /// `time` is the time of the event with millisecond granularity, which is also recorded as
+    /// [activity](IdleNotifier::notify_activity) of the seat. Returns the serial of the event, or [`None`] if no
+    /// surface has keyboard focus.
+    pub fn keyboard_key(
+        ecs: &mut Ecs,
+        seat: Entity,
+        time: Duration,
+        key: u32,
+        state: wl_keyboard::KeyState,
+    ) -> Option<u32> {
+        IdleNotifier::notify_activity(ecs, seat, time);
+        input::key(&mut ecs.world, seat, time, key, state)
+    }
+
+    /// Sets the modifiers of the keyboard, which are sent to the client with keyboard focus if they changed.
+    pub fn keyboard_modifiers(ecs: &mut Ecs, seat: Entity, modifiers: Modifiers) {
+        input::modifiers(&mut ecs.world, seat, modifiers);
+    }
+
+    /// Presses or releases a pointer button, which is sent to the client below the pointer.
+    ///
+    /// `time` is the time of the event with millisecond granularity, which is also recorded as
+    /// [activity](IdleNotifier::notify_activity) of the seat. Returns the serial of the event, or [`None`] if no
+    /// surface is below the pointer.
+    pub fn pointer_button(
+        ecs: &mut Ecs,
+        seat: Entity,
+        time: Duration,
+        button: u32,
+        state: wl_pointer::ButtonState,
+    ) -> Option<u32> {
+        IdleNotifier::notify_activity(ecs, seat, time);
+        input::button(&mut ecs.world, seat, time, button, state)
+    }
+
+    /// Adds a system which is run whenever the keyboard focus of the seat changes.
+    ///
+    /// Adding the same system more than once has no effect.
+    pub fn add_focus_system<State>(ecs: &mut Ecs, seat: Entity, system: SeatFocusChanged<State>)
+    where
+        State: EcsAccess,
+    {
+        let internal = ecs
+            .world
+            .query_one_mut::<&mut Internal<State>>(seat)
+            .expect("Not a seat entity of this State");
+
+        if !internal.focus_systems.contains(&system) {
+            internal.focus_systems.push(system);
+        }
+    }
+
+    /// Sets the surface below the pointer and the location of the pointer relative to that surface.
+    ///
+    /// The pointer focus systems of the seat are run if the focus or the location changed. Entering another
+    /// surface sends `wl_pointer.enter` to its client and resets the [`CursorImage`] of the seat. While the
+    /// [session is locked](crate::session_lock), surfaces other than lock surfaces do not get focus.
+    pub fn set_pointer_focus<State>(
+        state: &mut State,
+        display: &DisplayHandle,
+        seat: Entity,
+        focus: Option<(WlSurface, Point<f64, Logical>)>,
+    ) where
+        State: EcsAccess,
+    {
+        // Only lock surfaces receive input while the session is locked.
+        let focus = focus
+            .filter(|(surface, _)| session_lock::accepts_input(&mut state.ecs().world, surface));
+
+        let (current, cursor_image, objects, internal) = state
+            .ecs()
+            .world
+            .query_one_mut::<(
+                &mut PointerFocus,
+                &mut CursorImage,
+                &SeatObjects,
+                &Internal<State>,
+            )>(seat)
+            .expect("Not a seat entity of this State");
+
+        let (surface, location) = match focus {
+            Some((surface, location)) => (Some(surface), location),
+            None => (None, Point::default()),
+        };
+
+        if current.surface == surface && current.location == location {
+            return;
+        }
+
+        // The client of the entered surface sets its own cursor.
+        if current.surface != surface {
+            *cursor_image = CursorImage::default();
+            current.serial = input::pointer_enter(
+                objects,
+                current.surface.as_ref(),
+                surface.as_ref(),
+                location,
+            );
+        }
+
+        current.surface = surface;
+        current.location = location;
+        let pointer_focus_systems = internal.pointer_focus_systems.clone();
+
+        for system in pointer_focus_systems {
+            system(state, display, seat);
+        }
+    }
+
+    /// Adds a system which is run whenever the pointer focus of the seat changes or the pointer moves.
+    ///
+    /// Adding the same system more than once has no effect.
+    pub fn add_pointer_focus_system<State>(
+        ecs: &mut Ecs,
+        seat: Entity,
+        system: SeatFocusChanged<State>,
+    ) where
+        State: EcsAccess,
+    {
+        let internal = ecs
+            .world
+            .query_one_mut::<&mut Internal<State>>(seat)
+            .expect("Not a seat entity of this State");
+
+        if !internal.pointer_focus_systems.contains(&system) {
+            internal.pointer_focus_systems.push(system);
+        }
+    }
+}
+
+/// The name of a seat, such as "seat0".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeatName(String);
+
+impl SeatName {
+    pub fn get(&self) -> &str {
+        &self.0
+    }
+}
+
+/// The input devices of a seat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities(wl_seat::Capability);
+
+impl Capabilities {
+    pub fn get(&self) -> wl_seat::Capability {
+        self.0
+    }
+}
+
+/// The objects clients created from a seat.
+#[derive(Debug, Default)]
+pub struct SeatObjects {
+    seats: Vec<WlSeat>,
+    pointers: Vec<WlPointer>,
+    keyboards: Vec<WlKeyboard>,
+    touches: Vec<WlTouch>,
+}
+
+impl SeatObjects {
+    pub fn pointers(&self) -> impl Iterator<Item = &WlPointer> {
+        self.pointers.iter()
+    }
+
+    pub fn keyboards(&self) -> impl Iterator<Item = &WlKeyboard> {
+        self.keyboards.iter()
+    }
+
+    pub fn touches(&self) -> impl Iterator<Item = &WlTouch> {
+        self.touches.iter()
+    }
+}
+
+/// The surface which has keyboard focus on a seat.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyboardFocus(Option<WlSurface>);
+
+impl KeyboardFocus {
+    pub fn surface(&self) -> Option<&WlSurface> {
+        self.0.as_ref()
+    }
+
+    /// Returns whether the focused surface belongs to the client of an object.
+    pub fn is_client_focused(&self, object: &impl Resource) -> bool {
+        self.0
+            .as_ref()
+            .map(|surface| surface.id().same_client_as(&object.id()))
+            .unwrap_or(false)
+    }
+}
+
+/// The surface below the pointer of a seat.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PointerFocus {
+    surface: Option<WlSurface>,
+    location: Point<f64, Logical>,
+    /// The serial of the enter event sent to the client of the focused surface.
+    serial: Option<u32>,
+}
+
+impl PointerFocus {
+    pub fn surface(&self) -> Option<&WlSurface> {
+        self.surface.as_ref()
+    }
+
+    /// The location of the pointer relative to the focused surface.
+    pub fn location(&self) -> Point<f64, Logical> {
+        self.location
+    }
+
+    /// The serial of the `wl_pointer.enter` event sent for the focused surface.
+    ///
+    /// Clients pass this serial when setting the cursor image.
+    pub fn serial(&self) -> Option<u32> {
+        self.serial
+    }
+
+    /// Returns whether the surface below the pointer belongs to the client of an object which passed the serial
+    /// of the enter event.
+    pub(crate) fn is_enter_serial(&self, object: &impl Resource, serial: u32) -> bool {
+        self.is_client_focused(object) && self.serial == Some(serial)
+    }
+
+    /// Returns whether the surface below the pointer belongs to the client of an object.
+    pub fn is_client_focused(&self, object: &impl Resource) -> bool {
+        self.surface
+            .as_ref()
+            .map(|surface| surface.id().same_client_as(&object.id()))
+            .unwrap_or(false)
+    }
+}
+
+/// The display a seat was created on.
+struct SeatDisplay(DisplayHandle);
+
+/// Internal component of a seat entity.
+struct Internal<State: EcsAccess> {
+    focus_systems: Vec<SeatFocusChanged<State>>,
+    pointer_focus_systems: Vec<SeatFocusChanged<State>>,
+}
@@ -0,0 +1,359 @@
+use std::time::Duration;
+
+use wayland_server::protocol::{wl_keyboard, wl_pointer};
+
+use crate::{
+    compositor::Role,
+    testing::{Arg, Fixture, TestSurface},
+};
+
+use super::{CursorImage, CursorSurface, Keymap, Modifiers, PointerFocus, Seat};
+
+// wl_surface requests
+const DESTROY_SURFACE: u16 = 0;
+const OFFSET: u16 = 10;
+
+// wl_seat requests
+const GET_POINTER: u16 = 0;
+const GET_KEYBOARD: u16 = 1;
+
+// wl_pointer requests
+const SET_CURSOR: u16 = 0;
+
+// wl_pointer events
+const POINTER_ENTER: u16 = 0;
+const POINTER_LEAVE: u16 = 1;
+const FRAME: u16 = 5;
+
+// wl_keyboard events
+const KEYMAP: u16 = 0;
+const ENTER: u16 = 1;
+const LEAVE: u16 = 2;
+const KEY: u16 = 3;
+const MODIFIERS: u16 = 4;
+
+fn get_keyboard(fixture: &mut Fixture, seat: u32) -> u32 {
+    let keyboard = fixture.client.new_id();
+    fixture.send(seat, GET_KEYBOARD, &[Arg::NewId(keyboard)]);
+    fixture.dispatch();
+    keyboard
+}
+
+fn get_pointer(fixture: &mut Fixture, seat: u32) -> u32 {
+    let pointer = fixture.client.new_id();
+    fixture.send(seat, GET_POINTER, &[Arg::NewId(pointer)]);
+    fixture.assert_no_error();
+    pointer
+}
+
+/// Moves the pointer over a surface and returns the serial of the enter event.
+fn enter(fixture: &mut Fixture, pointer: u32, surface: &TestSurface) -> u32 {
+    fixture.set_pointer_focus(Some(surface));
+    let events = fixture.events(pointer);
+    let enter = events
+        .iter()
+        .find(|event| event.opcode == POINTER_ENTER)
+        .unwrap();
+    enter.uint(0)
+}
+
+fn set_cursor(fixture: &mut Fixture, pointer: u32, serial: u32, surface: u32, hotspot: (i32, i32)) {
+    fixture.send(
+        pointer,
+        SET_CURSOR,
+        &[
+            Arg::Uint(serial),
+            Arg::Object(surface),
+            Arg::Int(hotspot.0),
+            Arg::Int(hotspot.1),
+        ],
+    );
+}
+
+fn cursor_image(fixture: &mut Fixture) -> CursorImage {
+    let seat = fixture.seat();
+    *fixture.world().query_one_mut::<&CursorImage>(seat).unwrap()
+}
+
+fn hotspot(fixture: &mut Fixture, surface: &TestSurface) -> (i32, i32) {
+    let hotspot = fixture
+        .world()
+        .query_one_mut::<&CursorSurface>(surface.entity)
+        .unwrap()
+        .hotspot();
+    (hotspot.x, hotspot.y)
+}
+
+#[test]
+fn keymap_is_sent_to_new_and_existing_keyboards() {
+    let mut fixture = Fixture::new();
+    let wl_seat = fixture.client.bind("wl_seat", 7);
+    let keyboard = get_keyboard(&mut fixture, wl_seat);
+
+    // Without a keymap the client is told that there is none.
+    let events = fixture.client.take_events(keyboard);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].opcode, KEYMAP);
+    assert_eq!(
+        events[0].uint(0),
+        wl_keyboard::KeymapFormat::NoKeymap as u32
+    );
+    assert_eq!(events[0].uint(1), 0);
+
+    let keymap = Keymap::new("xkb_keymap {};").unwrap();
+    let seat = fixture.seat();
+    Seat::set_keymap(&mut fixture.server.state.ecs, seat, keymap);
+    fixture.dispatch();
+
+    let events = fixture.client.take_events(keyboard);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].opcode, KEYMAP);
+    assert_eq!(events[0].uint(0), wl_keyboard::KeymapFormat::XkbV1 as u32);
+    // The keymap is NUL terminated.
+    assert_eq!(events[0].uint(1), "xkb_keymap {};".len() as u32 + 1);
+
+    let keyboard = get_keyboard(&mut fixture, wl_seat);
+    let events = fixture.client.take_events(keyboard);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].uint(0), wl_keyboard::KeymapFormat::XkbV1 as u32);
+}
+
+#[test]
+fn focus_sends_enter_with_pressed_keys_and_leave() {
+    let mut fixture = Fixture::new();
+    let wl_seat = fixture.client.bind("wl_seat", 7);
+    let surface = fixture.create_surface();
+    let keyboard = get_keyboard(&mut fixture, wl_seat);
+    fixture.client.take_events(keyboard);
+
+    let seat = fixture.seat();
+    let modifiers = Modifiers {
+        depressed: 1,
+        ..Modifiers::default()
+    };
+    Seat::keyboard_modifiers(&mut fixture.server.state.ecs, seat, modifiers);
+
+    // Keys pressed without focus are not sent, but are part of the next enter.
+    let serial = Seat::keyboard_key(
+        &mut fixture.server.state.ecs,
+        seat,
+        Duration::from_millis(5),
+        30,
+        wl_keyboard::KeyState::Pressed,
+    );
+    assert_eq!(serial, None);
+
+    fixture.set_keyboard_focus(Some(&surface));
+    let events = fixture.client.take_events(keyboard);
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].opcode, ENTER);
+    assert_eq!(events[0].uint(1), surface.id);
+    // The keys array is its length in bytes followed by the keys.
+    assert_eq!(events[0].uint(2), 4);
+    assert_eq!(events[0].uint(3), 30);
+    assert_eq!(events[1].opcode, MODIFIERS);
+    assert_eq!(events[1].uint(0), events[0].uint(0));
+    assert_eq!(events[1].uint(1), 1);
+
+    let serial = Seat::keyboard_key(
+        &mut fixture.server.state.ecs,
+        seat,
+        Duration::from_millis(10),
+        30,
+        wl_keyboard::KeyState::Released,
+    )
+    .unwrap();
+
+    let events = fixture.events(keyboard);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].opcode, KEY);
+    assert_eq!(events[0].uint(0), serial);
+    assert_eq!(events[0].uint(1), 10);
+    assert_eq!(events[0].uint(2), 30);
+    assert_eq!(events[0].uint(3), wl_keyboard::KeyState::Released as u32);
+
+    fixture.set_keyboard_focus(None);
+    let events = fixture.client.take_events(keyboard);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].opcode, LEAVE);
+    assert_eq!(events[0].uint(1), surface.id);
+}
+
+#[test]
+fn new_keyboard_of_focused_client_receives_enter() {
+    let mut fixture = Fixture::new();
+    let wl_seat = fixture.client.bind("wl_seat", 7);
+    let surface = fixture.create_surface();
+    fixture.set_keyboard_focus(Some(&surface));
+
+    let keyboard = get_keyboard(&mut fixture, wl_seat);
+    let opcodes = fixture.opcodes(keyboard);
+    assert_eq!(opcodes, vec![KEYMAP, ENTER, MODIFIERS]);
+}
+
+#[test]
+fn pointer_focus_sends_enter_and_leave() {
+    let mut fixture = Fixture::new();
+    let wl_seat = fixture.client.bind("wl_seat", 7);
+    let pointer = get_pointer(&mut fixture, wl_seat);
+    let first = fixture.create_surface();
+    let second = fixture.create_surface();
+
+    fixture.set_pointer_focus(Some(&first));
+    let events = fixture.events(pointer);
+    assert_eq!(events.len(), 2);
+    assert_eq!(
+        (events[0].opcode, events[0].uint(1)),
+        (POINTER_ENTER, first.id)
+    );
+    assert_eq!(events[1].opcode, FRAME);
+
+    // The serial of the enter is stored in the pointer focus.
+    let seat = fixture.seat();
+    let serial = fixture
+        .world()
+        .query_one_mut::<&PointerFocus>(seat)
+        .unwrap()
+        .serial();
+    assert_eq!(serial, Some(events[0].uint(0)));
+
+    fixture.set_pointer_focus(Some(&second));
+    let events = fixture.events(pointer);
+    assert_eq!(
+        events.iter().map(|event| event.opcode).collect::<Vec<_>>(),
+        vec![POINTER_LEAVE, FRAME, POINTER_ENTER, FRAME]
+    );
+    assert_eq!(events[0].uint(1), first.id);
+    assert_eq!(events[2].uint(1), second.id);
+
+    // A new pointer of the client enters with the same serial.
+    let serial = events[2].uint(0);
+    let new_pointer = get_pointer(&mut fixture, wl_seat);
+    let events = fixture.events(new_pointer);
+    assert_eq!(events[0].opcode, POINTER_ENTER);
+    assert_eq!(events[0].uint(0), serial);
+
+    fixture.set_pointer_focus(None);
+    assert_eq!(fixture.opcodes(pointer), vec![POINTER_LEAVE, FRAME]);
+}
+
+#[test]
+fn set_cursor_requires_enter_serial() {
+    let mut fixture = Fixture::new();
+    let wl_seat = fixture.client.bind("wl_seat", 7);
+    let pointer = get_pointer(&mut fixture, wl_seat);
+    let surface = fixture.create_surface();
+    let serial = enter(&mut fixture, pointer, &surface);
+
+    set_cursor(&mut fixture, pointer, serial + 1, 0, (0, 0));
+    fixture.assert_no_error();
+    assert_eq!(cursor_image(&mut fixture), CursorImage::default());
+
+    set_cursor(&mut fixture, pointer, serial, 0, (0, 0));
+    fixture.assert_no_error();
+    assert_eq!(cursor_image(&mut fixture), CursorImage::Hidden);
+
+    // The serial of a previous enter is no longer accepted.
+    fixture.set_pointer_focus(None);
+    enter(&mut fixture, pointer, &surface);
+    set_cursor(&mut fixture, pointer, serial, 0, (0, 0));
+    fixture.assert_no_error();
+    assert_eq!(cursor_image(&mut fixture), CursorImage::default());
+}
+
+#[test]
+fn cursor_surface_gets_role_and_hotspot() {
+    let mut fixture = Fixture::new();
+    let wl_seat = fixture.client.bind("wl_seat", 7);
+    let pointer = get_pointer(&mut fixture, wl_seat);
+    let surface = fixture.create_surface();
+    let cursor = fixture.create_surface();
+    let serial = enter(&mut fixture, pointer, &surface);
+
+    set_cursor(&mut fixture, pointer, serial, cursor.id, (3, 4));
+    fixture.assert_no_error();
+    let role = fixture
+        .world()
+        .query_one_mut::<&Role>(cursor.entity)
+        .unwrap()
+        .role();
+    assert_eq!(role, Some(Seat::CURSOR_ROLE));
+    assert_eq!(
+        cursor_image(&mut fixture),
+        CursorImage::Surface(cursor.entity)
+    );
+    assert_eq!(hotspot(&mut fixture, &cursor), (3, 4));
+
+    // Setting the same surface again only moves the hotspot.
+    set_cursor(&mut fixture, pointer, serial, cursor.id, (1, 2));
+    fixture.assert_no_error();
+    assert_eq!(hotspot(&mut fixture, &cursor), (1, 2));
+}
+
+#[test]
+fn surface_with_other_role_is_error() {
+    let mut fixture = Fixture::new();
+    let wl_seat = fixture.client.bind("wl_seat", 7);
+    let pointer = get_pointer(&mut fixture, wl_seat);
+    let surface = fixture.create_surface();
+    let parent = fixture.create_surface();
+    let cursor = fixture.create_surface();
+    fixture.create_subsurface(&cursor, &parent);
+    let serial = enter(&mut fixture, pointer, &surface);
+
+    set_cursor(&mut fixture, pointer, serial, cursor.id, (0, 0));
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(wl_pointer::Error::Role as u32)
+    );
+}
+
+#[test]
+fn hotspot_follows_surface_offset() {
+    let mut fixture = Fixture::with_compositor_version(5);
+    let wl_seat = fixture.client.bind("wl_seat", 7);
+    let pointer = get_pointer(&mut fixture, wl_seat);
+    let surface = fixture.create_surface();
+    let cursor = fixture.create_surface();
+    let serial = enter(&mut fixture, pointer, &surface);
+    set_cursor(&mut fixture, pointer, serial, cursor.id, (3, 4));
+
+    let mut pool = fixture.create_pool(8 * 8 * 4);
+    let buffer = fixture.create_buffer(&mut pool, 8, 8);
+    fixture.attach(cursor.id, buffer);
+    fixture.send(cursor.id, OFFSET, &[Arg::Int(2), Arg::Int(-1)]);
+    fixture.commit(cursor.id);
+    assert_eq!(hotspot(&mut fixture, &cursor), (1, 5));
+
+    // Commits without an offset keep the hotspot.
+    fixture.commit(cursor.id);
+    assert_eq!(hotspot(&mut fixture, &cursor), (1, 5));
+}
+
+#[test]
+fn cursor_image_is_reset_on_enter_and_hidden_on_destroy() {
+    let mut fixture = Fixture::new();
+    let wl_seat = fixture.client.bind("wl_seat", 7);
+    let pointer = get_pointer(&mut fixture, wl_seat);
+    let first = fixture.create_surface();
+    let second = fixture.create_surface();
+    let cursor = fixture.create_surface();
+
+    let serial = enter(&mut fixture, pointer, &first);
+    set_cursor(&mut fixture, pointer, serial, cursor.id, (0, 0));
+    fixture.assert_no_error();
+    assert_eq!(
+        cursor_image(&mut fixture),
+        CursorImage::Surface(cursor.entity)
+    );
+
+    // Entering another surface resets the image until its client sets one.
+    let serial = enter(&mut fixture, pointer, &second);
+    assert_eq!(cursor_image(&mut fixture), CursorImage::default());
+
+    set_cursor(&mut fixture, pointer, serial, cursor.id, (0, 0));
+    fixture.assert_no_error();
+    fixture.send(cursor.id, DESTROY_SURFACE, &[]);
+    fixture.assert_no_error();
+    assert_eq!(cursor_image(&mut fixture), CursorImage::Hidden);
+}
@@ -0,0 +1,313 @@
+use wayland_backend::server::{ClientId, ObjectId};
+use wayland_protocols::ext::session_lock::v1::server::{
+    ext_session_lock_manager_v1::{self, ExtSessionLockManagerV1},
+    ext_session_lock_surface_v1::{self, ExtSessionLockSurfaceV1},
+    ext_session_lock_v1::{self, ExtSessionLockV1},
+};
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use crate::{
+    compositor::{AlreadyHasRole, Compositor, CompositorEvent, Role},
+    EntityData,
+};
+
+use super::{
+    clear_focus, end_lock, pre_commit, LockSurface, SessionLock, SessionLockHandler,
+    SessionLockManager,
+};
+
+impl<State> GlobalDispatch<ExtSessionLockManagerV1, (), State> for SessionLockManager
+where
+    State: GlobalDispatch<ExtSessionLockManagerV1, ()>
+        + Dispatch<ExtSessionLockManagerV1, ()>
+        + SessionLockHandler,
+{
+    fn bind(
+        _state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ExtSessionLockManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<State> Dispatch<ExtSessionLockManagerV1, (), State> for SessionLockManager
+where
+    State: Dispatch<ExtSessionLockManagerV1, ()>
+        + Dispatch<ExtSessionLockV1, EntityData>
+        + SessionLockHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        _resource: &ExtSessionLockManagerV1,
+        request: ext_session_lock_manager_v1::Request,
+        _data: &(),
+        dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            ext_session_lock_manager_v1::Request::Lock { id } => {
+                let entity = state.ecs().world().reserve_entity();
+                let object = data_init.init(id, EntityData(entity));
+                let world = state.ecs().world();
+
+                let locks = world
+                    .query_mut::<&SessionLock>()
+                    .into_iter()
+                    .map(|(lock, session_lock)| (lock, session_lock.abandoned))
+                    .collect::<Vec<_>>();
+
+                // Only one client can hold the lock, the entity of any further lock stays empty.
+                if locks.iter().any(|(_, abandoned)| !abandoned) {
+                    object.finished();
+                    return;
+                }
+
+                // A lock whose client disconnected is replaced by the new lock.
+                for (lock, _) in locks {
+                    end_lock(world, lock);
+                }
+
+                world
+                    .insert_one(
+                        entity,
+                        SessionLock {
+                            object,
+                            locked: false,
+                            abandoned: false,
+                        },
+                    )
+                    .expect("Entity was reserved");
+
+                clear_focus(state, dhandle);
+                state.lock(entity);
+            }
+
+            ext_session_lock_manager_v1::Request::Destroy => {
+                // Locks are unaffected by the manager being destroyed.
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<State> Dispatch<ExtSessionLockV1, EntityData, State> for SessionLockManager
+where
+    State: Dispatch<ExtSessionLockV1, EntityData>
+        + Dispatch<ExtSessionLockSurfaceV1, EntityData>
+        + SessionLockHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        resource: &ExtSessionLockV1,
+        request: ext_session_lock_v1::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        let locked = match state.ecs().world().query_one_mut::<&SessionLock>(data.0) {
+            Ok(session_lock) => session_lock.locked,
+            Err(_) => {
+                // Requests of finished locks are ignored, but the objects they create must still be
+                // initialized.
+                if let ext_session_lock_v1::Request::GetLockSurface { id, surface, .. } = request {
+                    data_init.init(id, EntityData(surface.data::<EntityData>().unwrap().0));
+                }
+                return;
+            }
+        };
+
+        match request {
+            ext_session_lock_v1::Request::GetLockSurface {
+                id,
+                surface,
+                output,
+            } => {
+                let entity = surface.data::<EntityData>().unwrap().0;
+                let world = state.ecs().world();
+
+                let duplicate =
+                    world
+                        .query_mut::<&LockSurface>()
+                        .into_iter()
+                        .any(|(_, lock_surface)| {
+                            lock_surface.lock == data.0 && lock_surface.output == output
+                        });
+
+                if duplicate {
+                    resource.post_error(
+                        ext_session_lock_v1::Error::DuplicateOutput,
+                        "The output already has a lock surface",
+                    );
+                    return;
+                }
+
+                if Compositor::attached_buffer::<State>(state.ecs(), &surface).is_some() {
+                    resource.post_error(
+                        ext_session_lock_v1::Error::AlreadyConstructed,
+                        "Surface already has a buffer attached",
+                    );
+                    return;
+                }
+
+                let role = state
+                    .ecs()
+                    .world()
+                    .query_one_mut::<&mut Role>(entity)
+                    .expect("Surface must be a valid entity if dispatched");
+
+                // The role is kept after a lock ends, so the surface may be reused by a later lock.
+                if role.role() != Some(SessionLockManager::ROLE) {
+                    if let Err(AlreadyHasRole) = role.set_role(SessionLockManager::ROLE) {
+                        resource.post_error(
+                            ext_session_lock_v1::Error::Role,
+                            "Surface already has a role",
+                        );
+                        return;
+                    }
+
+                    state.ecs().push_event(CompositorEvent::RoleAssigned {
+                        surface: surface.clone(),
+                        entity,
+                        role: SessionLockManager::ROLE,
+                    });
+
+                    Compositor::add_pre_commit::<State>(state.ecs(), &surface, pre_commit::<State>);
+                }
+
+                let object = data_init.init(id, EntityData(entity));
+                state
+                    .ecs()
+                    .world()
+                    .insert_one(
+                        entity,
+                        LockSurface {
+                            object,
+                            lock: data.0,
+                            output: output.clone(),
+                            pending_configures: Vec::new(),
+                            acked_size: None,
+                        },
+                    )
+                    .expect("Surface must be a valid entity if dispatched");
+
+                state.new_lock_surface(&surface, &output);
+            }
+
+            ext_session_lock_v1::Request::UnlockAndDestroy => {
+                if !locked {
+                    resource.post_error(
+                        ext_session_lock_v1::Error::InvalidUnlock,
+                        "The session was never locked",
+                    );
+                    return;
+                }
+
+                end_lock(state.ecs().world(), data.0);
+                state.unlock();
+            }
+
+            ext_session_lock_v1::Request::Destroy => {
+                if locked {
+                    resource.post_error(
+                        ext_session_lock_v1::Error::InvalidDestroy,
+                        "A locked session must be unlocked using unlock_and_destroy",
+                    );
+                    return;
+                }
+
+                // The client gave up on locking before the lock was confirmed.
+                end_lock(state.ecs().world(), data.0);
+                state.unlock();
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        let world = state.ecs().world();
+
+        match world.query_one_mut::<&mut SessionLock>(data.0) {
+            // The client disconnected without unlocking, so the session stays locked.
+            Ok(session_lock) => session_lock.abandoned = true,
+            Err(_) => {
+                let _ = world.despawn(data.0);
+            }
+        }
+    }
+}
+
+impl<State> Dispatch<ExtSessionLockSurfaceV1, EntityData, State> for SessionLockManager
+where
+    State: Dispatch<ExtSessionLockSurfaceV1, EntityData> + SessionLockHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        resource: &ExtSessionLockSurfaceV1,
+        request: ext_session_lock_surface_v1::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        let lock_surface = match state
+            .ecs()
+            .world()
+            .query_one_mut::<&mut LockSurface>(data.0)
+        {
+            Ok(lock_surface) if lock_surface.object == *resource => lock_surface,
+            // The lock of the surface ended.
+            _ => return,
+        };
+
+        match request {
+            ext_session_lock_surface_v1::Request::AckConfigure { serial } => {
+                match lock_surface
+                    .pending_configures
+                    .iter()
+                    .position(|&(s, _)| s == serial)
+                {
+                    // Acknowledging a configure also acknowledges every configure sent before it.
+                    Some(index) => {
+                        let (_, size) = lock_surface.pending_configures[index];
+                        lock_surface.pending_configures.drain(..=index);
+                        lock_surface.acked_size = Some(size);
+                    }
+
+                    None => {
+                        resource.post_error(
+                            ext_session_lock_surface_v1::Error::InvalidSerial,
+                            format!("Serial {serial} does not belong to a configure"),
+                        );
+                    }
+                }
+            }
+
+            ext_session_lock_surface_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, resource: ObjectId, data: &EntityData) {
+        // The surface keeps its role, but is no longer shown as a lock surface.
+        let world = state.ecs().world();
+        let is_lock_surface = world
+            .query_one_mut::<&LockSurface>(data.0)
+            .map(|lock_surface| lock_surface.object.id() == resource)
+            .unwrap_or(false);
+
+        if is_lock_surface {
+            let _ = world.remove_one::<LockSurface>(data.0);
+        }
+    }
+}
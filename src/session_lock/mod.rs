@@ -0,0 +1,279 @@
+//! Implementation of the session lock protocol.
+//!
+//! A lock screen client locks the session and shows a lock surface on every output until the user
+//! authenticates. Every `ext_session_lock_v1` is an entity with a [`SessionLock`], and lock surfaces are surface
+//! entities with the [`LockSurface`] role object. Only one lock can exist at a time, further locks are told they
+//! are finished immediately.
+//!
+//! # Locking
+//!
+//! When a client locks the session, [`SessionLockHandler::lock`] is called and the compositor must stop showing
+//! any surface but lock surfaces. Once it did, the compositor confirms the lock using
+//! [`SessionLockManager::confirm_lock`]. The session is unlocked when the client requests it, which calls
+//! [`SessionLockHandler::unlock`].
+//!
+//! While the session is locked, only lock surfaces and their subsurfaces receive input: the keyboard and pointer
+//! focus of every seat is cleared when the session is locked, and [`Seat::set_keyboard_focus`] and
+//! [`Seat::set_pointer_focus`] ignore any other surface.
+//!
+//! If the lock client disconnects without unlocking, for example because it crashed, the session stays locked.
+//! Another lock client may then lock the session, which replaces the abandoned lock.
+
+mod dispatch;
+#[cfg(test)]
+mod tests;
+
+use hecs::Entity;
+use smithay::utils::{Logical, Size};
+use wayland_protocols::ext::session_lock::v1::server::{
+    ext_session_lock_manager_v1::ExtSessionLockManagerV1,
+    ext_session_lock_surface_v1::{self, ExtSessionLockSurfaceV1},
+    ext_session_lock_v1::ExtSessionLockV1,
+};
+use wayland_server::{
+    protocol::{wl_output::WlOutput, wl_surface::WlSurface},
+    DisplayHandle, GlobalDispatch, Resource,
+};
+
+use crate::{
+    compositor::{BufferAssignment, Compositor, CompositorHandler, Subsurface},
+    next_serial,
+    seat::{KeyboardFocus, Seat},
+    Ecs, EntityData,
+};
+
+pub trait SessionLockHandler: CompositorHandler {
+    /// A client locked the session.
+    ///
+    /// The compositor must stop showing any surface but lock surfaces and then confirm the lock using
+    /// [`SessionLockManager::confirm_lock`].
+    fn lock(&mut self, lock: Entity);
+
+    /// The lock client unlocked the session.
+    fn unlock(&mut self);
+
+    /// A client created a lock surface for an output.
+    ///
+    /// The compositor sends the size of the output using [`SessionLockManager::send_configure`].
+    fn new_lock_surface(&mut self, surface: &WlSurface, output: &WlOutput);
+}
+
+pub struct SessionLockManager {}
+
+impl SessionLockManager {
+    pub fn new<State>(display: &mut DisplayHandle) -> Self
+    where
+        State: GlobalDispatch<ExtSessionLockManagerV1, ()> + SessionLockHandler,
+    {
+        let _global = display.create_global::<State, ExtSessionLockManagerV1, ()>(1, ());
+        Self {}
+    }
+
+    pub const ROLE: &str = "ext_session_lock_surface_v1";
+
+    /// Returns whether the session is locked.
+    ///
+    /// The session is locked from the moment a client requests it, including before the lock is confirmed and
+    /// after the lock client disconnected without unlocking.
+    pub fn is_locked(ecs: &mut Ecs) -> bool {
+        is_locked(&mut ecs.world)
+    }
+
+    /// Tells the lock client that the session is locked.
+    ///
+    /// This must only be called once no surface but lock surfaces is shown on any output.
+    pub fn confirm_lock(ecs: &mut Ecs, lock: Entity) {
+        if let Ok(session_lock) = ecs.world.query_one_mut::<&mut SessionLock>(lock) {
+            if !session_lock.locked && !session_lock.abandoned {
+                session_lock.locked = true;
+                session_lock.object.locked();
+            }
+        }
+    }
+
+    /// Sends a configure event with the size of the output of a lock surface.
+    ///
+    /// Returns the serial of the configure, or [`None`] if the surface is not a lock surface.
+    pub fn send_configure(
+        ecs: &mut Ecs,
+        surface: &WlSurface,
+        size: Size<i32, Logical>,
+    ) -> Option<u32> {
+        let entity = surface.data::<EntityData>()?.0;
+        let lock_surface = ecs.world.query_one_mut::<&mut LockSurface>(entity).ok()?;
+
+        let serial = next_serial();
+        lock_surface.pending_configures.push((serial, size));
+        lock_surface
+            .object
+            .configure(serial, size.w as u32, size.h as u32);
+        Some(serial)
+    }
+
+    /// Returns whether a surface may receive input.
+    ///
+    /// While the session is locked, this is only the case for lock surfaces and their subsurfaces.
+    pub fn accepts_input(ecs: &mut Ecs, surface: &WlSurface) -> bool {
+        accepts_input(&mut ecs.world, surface)
+    }
+}
+
+/// A lock of the session.
+#[derive(Debug)]
+pub struct SessionLock {
+    object: ExtSessionLockV1,
+    locked: bool,
+    abandoned: bool,
+}
+
+impl SessionLock {
+    /// Returns whether the lock was confirmed using [`SessionLockManager::confirm_lock`].
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Returns whether the lock client disconnected without unlocking the session.
+    pub fn is_abandoned(&self) -> bool {
+        self.abandoned
+    }
+}
+
+/// The role object of a lock surface.
+#[derive(Debug)]
+pub struct LockSurface {
+    object: ExtSessionLockSurfaceV1,
+    lock: Entity,
+    output: WlOutput,
+    /// Configure events which were not acknowledged yet, oldest first.
+    pending_configures: Vec<(u32, Size<i32, Logical>)>,
+    /// The size of the most recently acknowledged configure.
+    acked_size: Option<Size<i32, Logical>>,
+}
+
+impl LockSurface {
+    /// The entity of the lock the surface belongs to.
+    pub fn lock(&self) -> Entity {
+        self.lock
+    }
+
+    /// The output the lock surface is shown on.
+    pub fn output(&self) -> &WlOutput {
+        &self.output
+    }
+}
+
+pub(crate) fn is_locked(world: &mut hecs::World) -> bool {
+    world
+        .query_mut::<&SessionLock>()
+        .into_iter()
+        .next()
+        .is_some()
+}
+
+/// Returns whether a surface may receive input, see [`SessionLockManager::accepts_input`].
+pub(crate) fn accepts_input(world: &mut hecs::World, surface: &WlSurface) -> bool {
+    if !is_locked(world) {
+        return true;
+    }
+
+    // Subsurfaces receive input if the surface at the root of their tree does.
+    let mut surface = surface.clone();
+
+    loop {
+        let parent = world
+            .query_one_mut::<&Subsurface>(surface.data::<EntityData>().unwrap().0)
+            .ok()
+            .and_then(|subsurface| subsurface.parent());
+
+        match parent {
+            Some(parent) => surface = parent,
+            None => break,
+        }
+    }
+
+    surface
+        .data::<EntityData>()
+        .map(|data| world.query_one_mut::<&LockSurface>(data.0).is_ok())
+        .unwrap_or(false)
+}
+
+/// Clears the keyboard and pointer focus of every seat.
+fn clear_focus<State: SessionLockHandler>(state: &mut State, display: &DisplayHandle) {
+    let seats = state
+        .ecs()
+        .world()
+        .query_mut::<&KeyboardFocus>()
+        .into_iter()
+        .map(|(seat, _)| seat)
+        .collect::<Vec<_>>();
+
+    for seat in seats {
+        Seat::set_keyboard_focus(state, display, seat, None);
+        Seat::set_pointer_focus(state, display, seat, None);
+    }
+}
+
+/// Validates the buffer of a lock surface against the acknowledged configure.
+fn pre_commit<State: SessionLockHandler>(state: &mut State, surface: &WlSurface) {
+    use ext_session_lock_surface_v1::Error;
+
+    let entity = surface.data::<EntityData>().unwrap().0;
+    let pending_buffer = Compositor::pending_buffer::<State>(state.ecs(), surface);
+    let buffer_size = Compositor::pending_buffer_size::<State>(state.ecs(), surface);
+
+    let lock_surface = match state.ecs().world().query_one_mut::<&LockSurface>(entity) {
+        Ok(lock_surface) => lock_surface,
+        // The lock surface was destroyed or its lock ended.
+        Err(_) => return,
+    };
+
+    let acked_size = match lock_surface.acked_size {
+        Some(size) => size,
+        None => {
+            lock_surface.object.post_error(
+                Error::CommitBeforeFirstAck,
+                "The lock surface was committed before the first configure was acknowledged",
+            );
+            Compositor::discard_commit::<State>(state.ecs(), surface);
+            return;
+        }
+    };
+
+    if matches!(pending_buffer, Some(BufferAssignment::Removed)) {
+        lock_surface
+            .object
+            .post_error(Error::NullBuffer, "Lock surfaces must always have a buffer");
+        Compositor::discard_commit::<State>(state.ecs(), surface);
+        return;
+    }
+
+    if let Some(size) = buffer_size {
+        if size != acked_size {
+            lock_surface.object.post_error(
+                Error::DimensionsMismatch,
+                format!(
+                    "Buffer size {}x{} does not match the configured size {}x{}",
+                    size.w, size.h, acked_size.w, acked_size.h
+                ),
+            );
+            Compositor::discard_commit::<State>(state.ecs(), surface);
+        }
+    }
+}
+
+/// Ends a lock and removes the lock surfaces which belong to it.
+fn end_lock(world: &mut hecs::World, lock: Entity) {
+    let surfaces = world
+        .query_mut::<&LockSurface>()
+        .into_iter()
+        .filter(|(_, lock_surface)| lock_surface.lock == lock)
+        .map(|(surface, _)| surface)
+        .collect::<Vec<_>>();
+
+    // The surfaces keep their role, but are no longer shown as lock surfaces.
+    for surface in surfaces {
+        let _ = world.remove_one::<LockSurface>(surface);
+    }
+
+    let _ = world.despawn(lock);
+}
@@ -0,0 +1,154 @@
+use wayland_protocols::ext::session_lock::v1::server::ext_session_lock_v1;
+use wayland_server::Resource;
+
+use crate::{
+    seat::{KeyboardFocus, PointerFocus},
+    testing::{Arg, Fixture, TestClient, TestSurface},
+};
+
+use super::{SessionLock, SessionLockManager};
+
+// ext_session_lock_manager_v1 requests
+const LOCK: u16 = 1;
+
+// ext_session_lock_v1 requests
+const GET_LOCK_SURFACE: u16 = 1;
+
+// ext_session_lock_v1 events
+const LOCKED: u16 = 0;
+const FINISHED: u16 = 1;
+
+fn lock(client: &mut TestClient) -> u32 {
+    let manager = client.bind("ext_session_lock_manager_v1", 1);
+    let lock = client.new_id();
+    client.send(manager, LOCK, &[Arg::NewId(lock)]);
+    lock
+}
+
+fn get_lock_surface(fixture: &mut Fixture, lock: u32, surface: &TestSurface) -> u32 {
+    let output = fixture.client.bind("wl_output", 4);
+    let lock_surface = fixture.client.new_id();
+    fixture.send(
+        lock,
+        GET_LOCK_SURFACE,
+        &[
+            Arg::NewId(lock_surface),
+            Arg::Object(surface.id),
+            Arg::Object(output),
+        ],
+    );
+    lock_surface
+}
+
+/// The surfaces with the keyboard and pointer focus of the seat.
+fn focus(fixture: &mut Fixture) -> (Option<u32>, Option<u32>) {
+    let seat = fixture.seat();
+    let (keyboard, pointer) = fixture
+        .world()
+        .query_one_mut::<(&KeyboardFocus, &PointerFocus)>(seat)
+        .unwrap();
+    (
+        keyboard.surface().map(|surface| surface.id().protocol_id()),
+        pointer.surface().map(|surface| surface.id().protocol_id()),
+    )
+}
+
+fn set_focus(fixture: &mut Fixture, surface: &TestSurface) {
+    fixture.set_keyboard_focus(Some(surface));
+    fixture.set_pointer_focus(Some(surface));
+}
+
+#[test]
+fn focus_is_refused_for_other_surfaces() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    set_focus(&mut fixture, &surface);
+
+    // Locking clears the focus.
+    let lock = lock(&mut fixture.client);
+    fixture.assert_no_error();
+    assert_eq!(focus(&mut fixture), (None, None));
+
+    set_focus(&mut fixture, &surface);
+    assert_eq!(focus(&mut fixture), (None, None));
+
+    let lock_surface = fixture.create_surface();
+    get_lock_surface(&mut fixture, lock, &lock_surface);
+    fixture.assert_no_error();
+    set_focus(&mut fixture, &lock_surface);
+    assert_eq!(
+        focus(&mut fixture),
+        (Some(lock_surface.id), Some(lock_surface.id))
+    );
+}
+
+#[test]
+fn session_stays_locked_after_lock_client_disconnects() {
+    let mut fixture = Fixture::new();
+    let mut lock_client = TestClient::connect(&mut fixture.server);
+    lock(&mut lock_client);
+    fixture.dispatch();
+    let entity = fixture.server.state.locks[0];
+
+    drop(lock_client);
+    fixture.dispatch();
+    assert!(SessionLockManager::is_locked(&mut fixture.server.state.ecs));
+    let abandoned = fixture
+        .world()
+        .query_one_mut::<&SessionLock>(entity)
+        .unwrap()
+        .is_abandoned();
+    assert!(abandoned);
+
+    let surface = fixture.create_surface();
+    set_focus(&mut fixture, &surface);
+    assert_eq!(focus(&mut fixture), (None, None));
+}
+
+#[test]
+fn second_lock_is_finished() {
+    let mut fixture = Fixture::new();
+    let first = lock(&mut fixture.client);
+    let second = lock(&mut fixture.client);
+
+    assert!(fixture.opcodes(first).is_empty());
+    assert_eq!(fixture.opcodes(second), vec![FINISHED]);
+    assert_eq!(fixture.server.state.locks.len(), 1);
+}
+
+#[test]
+fn abandoned_lock_is_replaced() {
+    let mut fixture = Fixture::new();
+    let mut lock_client = TestClient::connect(&mut fixture.server);
+    lock(&mut lock_client);
+    fixture.dispatch();
+    drop(lock_client);
+    fixture.dispatch();
+
+    let lock = lock(&mut fixture.client);
+    fixture.assert_no_error();
+    let locks = fixture.server.state.locks.clone();
+    assert_eq!(locks.len(), 2);
+    assert!(!fixture.world().contains(locks[0]));
+
+    SessionLockManager::confirm_lock(&mut fixture.server.state.ecs, locks[1]);
+    assert_eq!(fixture.opcodes(lock), vec![LOCKED]);
+}
+
+#[test]
+fn surface_with_attached_buffer_is_error() {
+    let mut fixture = Fixture::new();
+    let lock = lock(&mut fixture.client);
+    let surface = fixture.create_surface();
+
+    // The buffer is attached, but not committed yet.
+    let mut pool = fixture.create_pool(4 * 4 * 4);
+    let buffer = fixture.create_buffer(&mut pool, 4, 4);
+    fixture.attach(surface.id, buffer);
+
+    get_lock_surface(&mut fixture, lock, &surface);
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(ext_session_lock_v1::Error::AlreadyConstructed as u32)
+    );
+}
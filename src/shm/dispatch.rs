@@ -0,0 +1,179 @@
+use std::sync::Arc;
+
+use wayland_server::{
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_shm::{self, WlShm},
+        wl_shm_pool::{self, WlShmPool},
+    },
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource, WEnum,
+};
+
+use crate::{compositor::BufferDimensions, EcsAccess, EntityData};
+
+use super::{Shm, ShmBuffer, ShmPool, ShmPoolData, FORMATS};
+
+impl<State> GlobalDispatch<WlShm, (), State> for Shm
+where
+    State: GlobalDispatch<WlShm, ()> + Dispatch<WlShm, ()>,
+{
+    fn bind(
+        _state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<WlShm>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        let shm = data_init.init(resource, ());
+
+        for format in FORMATS {
+            shm.format(format);
+        }
+    }
+}
+
+impl<State> Dispatch<WlShm, (), State> for Shm
+where
+    State: Dispatch<WlShm, ()> + Dispatch<WlShmPool, ShmPoolData>,
+{
+    fn request(
+        _state: &mut State,
+        _client: &Client,
+        shm: &WlShm,
+        request: wl_shm::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            wl_shm::Request::CreatePool { id, fd, size } => {
+                if size <= 0 {
+                    shm.post_error(
+                        wl_shm::Error::InvalidStride,
+                        format!("Invalid pool size {size}"),
+                    );
+                    return;
+                }
+
+                let pool = match ShmPool::new(fd, size as usize) {
+                    Some(pool) => pool,
+                    None => {
+                        shm.post_error(
+                            wl_shm::Error::InvalidFd,
+                            "Failed to map the pool, or the file is smaller than the pool",
+                        );
+                        return;
+                    }
+                };
+
+                data_init.init(
+                    id,
+                    ShmPoolData {
+                        pool: Arc::new(pool),
+                    },
+                );
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<State> Dispatch<WlShmPool, ShmPoolData, State> for Shm
+where
+    State: Dispatch<WlShmPool, ShmPoolData> + Dispatch<WlBuffer, EntityData> + EcsAccess,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        pool: &WlShmPool,
+        request: wl_shm_pool::Request,
+        data: &ShmPoolData,
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            wl_shm_pool::Request::CreateBuffer {
+                id,
+                offset,
+                width,
+                height,
+                stride,
+                format,
+            } => {
+                let format = match format {
+                    WEnum::Value(format) if FORMATS.contains(&format) => format,
+                    _ => {
+                        pool.post_error(
+                            wl_shm::Error::InvalidFormat,
+                            format!("Unsupported format {format:?}"),
+                        );
+                        return;
+                    }
+                };
+
+                // Every supported format uses 4 bytes per pixel.
+                let fits = offset >= 0
+                    && width > 0
+                    && height > 0
+                    && matches!(width.checked_mul(4), Some(min) if stride >= min)
+                    && offset as usize + stride as usize * height as usize <= data.pool.size();
+
+                if !fits {
+                    pool.post_error(
+                        wl_shm::Error::InvalidStride,
+                        format!(
+                            "Invalid buffer (offset {offset}, {width}x{height}, stride {stride})"
+                        ),
+                    );
+                    return;
+                }
+
+                let entity = state.ecs().world().reserve_entity();
+                data_init.init(id, EntityData(entity));
+                state
+                    .ecs()
+                    .world()
+                    .insert(
+                        entity,
+                        (
+                            BufferDimensions::new((width, height).into()),
+                            ShmBuffer {
+                                pool: data.pool.clone(),
+                                offset: offset as usize,
+                                width,
+                                height,
+                                stride,
+                                format,
+                            },
+                        ),
+                    )
+                    .expect("Entity was reserved");
+            }
+
+            wl_shm_pool::Request::Resize { size } => {
+                if size <= 0 || (size as usize) < data.pool.size() {
+                    pool.post_error(
+                        wl_shm::Error::InvalidStride,
+                        format!("Pools can only grow, got size {size}"),
+                    );
+                    return;
+                }
+
+                if !data.pool.resize(size as usize) {
+                    pool.post_error(
+                        wl_shm::Error::InvalidFd,
+                        "Failed to resize the pool, or the file is smaller than the pool",
+                    );
+                }
+            }
+
+            wl_shm_pool::Request::Destroy => {
+                // Buffers keep the pool alive.
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
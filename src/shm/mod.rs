@@ -1,17 +1,48 @@
-use wayland_server::{
-    protocol::{
-        wl_shm::{self, WlShm},
-        wl_shm_pool::{self, WlShmPool},
+//! Implementation of shared memory buffers.
+//!
+//! Clients share memory with the compositor by sending a file descriptor, which is mapped as a pool. Buffers
+//! are created at an offset into a pool. Every shm buffer is an entity with a [`BufferDimensions`] and a
+//! [`ShmBuffer`], which gives access to the contents of the buffer.
+//!
+//! The contents are owned by the client and may change at any time. A client may also truncate the file of a
+//! pool, which makes accesses to the mapping raise `SIGBUS`. [`ShmBuffer::with_data`] recovers from this and
+//! returns [`Truncated`] instead.
+//!
+//! [`BufferDimensions`]: crate::compositor::BufferDimensions
+
+mod dispatch;
+mod sigbus;
+#[cfg(test)]
+mod tests;
+
+pub use self::sigbus::Truncated;
+
+use std::{
+    fmt, mem,
+    os::unix::io::{AsRawFd, OwnedFd},
+    ptr, slice,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
     },
-    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New,
 };
 
+use wayland_server::{
+    protocol::wl_shm::{self, WlShm},
+    DisplayHandle, GlobalDispatch,
+};
+
+use crate::EcsAccess;
+
+/// The formats every client can use.
+const FORMATS: [wl_shm::Format; 2] = [wl_shm::Format::Argb8888, wl_shm::Format::Xrgb8888];
+
 pub struct Shm {}
 
 impl Shm {
     pub fn new<State>(display: &mut DisplayHandle) -> Self
     where
-        State: GlobalDispatch<WlShm, ()> + Dispatch<WlShm, ()> + 'static,
+        State: GlobalDispatch<WlShm, ()> + EcsAccess,
     {
         let _global = display.create_global::<State, WlShm, ()>(1, ());
 
@@ -19,58 +50,211 @@ impl Shm {
     }
 }
 
-impl<State> GlobalDispatch<WlShm, (), State> for Shm
-where
-    State: GlobalDispatch<WlShm, ()> + Dispatch<WlShm, ()>,
-{
-    fn bind(
-        _state: &mut State,
-        _handle: &DisplayHandle,
-        _client: &Client,
-        resource: New<WlShm>,
-        _global_data: &(),
-        data_init: &mut DataInit<'_, State>,
-    ) {
-        let shm = data_init.init(resource, ());
-        shm.format(wl_shm::Format::Argb8888);
-        shm.format(wl_shm::Format::Xrgb8888);
+/// User data of a `wl_shm_pool`.
+#[derive(Debug)]
+pub struct ShmPoolData {
+    pool: Arc<ShmPool>,
+}
+
+/// A shared memory buffer.
+///
+/// Buffers only ever use the 32-bit formats advertised by the [`Shm`] global, so every pixel is 4 bytes.
+#[derive(Debug, Clone)]
+pub struct ShmBuffer {
+    pool: Arc<ShmPool>,
+    offset: usize,
+    width: i32,
+    height: i32,
+    stride: i32,
+    format: wl_shm::Format,
+}
+
+impl ShmBuffer {
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
     }
+
+    /// The number of bytes between the start of two rows.
+    pub fn stride(&self) -> i32 {
+        self.stride
+    }
+
+    pub fn format(&self) -> wl_shm::Format {
+        self.format
+    }
+
+    /// Accesses the contents of the buffer.
+    ///
+    /// Returns [`Truncated`] if the client truncated the file of the pool while it was accessed, in which case
+    /// the contents passed to `f` are partly zeroed. Once truncated, every access to the pool fails. Accessing
+    /// another buffer of the same pool for writing from within `f` deadlocks.
+    pub fn with_data<T>(&self, f: impl FnOnce(&[u8]) -> T) -> Result<T, Truncated> {
+        let mapping = self.pool.mapping.read().unwrap();
+        self.pool.check_truncated()?;
+
+        // SAFETY: The buffer was validated to be within the pool, which can only grow. Faults caused by the file
+        // shrinking afterwards are handled by the guard.
+        let result = unsafe {
+            let ptr = mapping.ptr.add(self.offset);
+            sigbus::guard(ptr, self.len(), || {
+                f(slice::from_raw_parts(ptr, self.len()))
+            })
+        };
+        self.pool.record(result)
+    }
+
+    /// Accesses the contents of the buffer for writing, such as when copying the screen into it.
+    ///
+    /// Like [`ShmBuffer::with_data`], this returns [`Truncated`] if the client truncated the file of the pool.
+    /// Accessing any buffer of the same pool from within `f` deadlocks.
+    pub fn with_data_mut<T>(&self, f: impl FnOnce(&mut [u8]) -> T) -> Result<T, Truncated> {
+        // The write lock is taken for exclusive access to the contents, not to modify the mapping.
+        #[allow(clippy::readonly_write_lock)]
+        let mapping = self.pool.mapping.write().unwrap();
+        self.pool.check_truncated()?;
+
+        // SAFETY: The buffer was validated to be within the pool, which can only grow. Holding the write lock
+        // ensures the compositor does not access the pool elsewhere. Faults caused by the file shrinking
+        // afterwards are handled by the guard.
+        let result = unsafe {
+            let ptr = mapping.ptr.add(self.offset);
+            sigbus::guard(ptr, self.len(), || {
+                f(slice::from_raw_parts_mut(ptr, self.len()))
+            })
+        };
+        self.pool.record(result)
+    }
+
+    fn len(&self) -> usize {
+        self.stride as usize * self.height as usize
+    }
+}
+
+/// A memory mapped file descriptor.
+struct ShmPool {
+    fd: OwnedFd,
+    mapping: RwLock<Mapping>,
+    /// Whether an access faulted because the file was truncated. The faulting pages no longer share the
+    /// contents with the client.
+    truncated: AtomicBool,
+}
+
+struct Mapping {
+    ptr: *mut u8,
+    size: usize,
+}
+
+// SAFETY: The mapping is only accessed through the lock of the pool.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl ShmPool {
+    fn new(fd: OwnedFd, size: usize) -> Option<Self> {
+        if !fits_in_file(&fd, size) {
+            return None;
+        }
+
+        // SAFETY: Mapping a file descriptor has no effect on memory which is already in use.
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd.as_raw_fd(),
+                0,
+            )
+        };
+
+        if ptr == libc::MAP_FAILED {
+            return None;
+        }
+
+        Some(Self {
+            fd,
+            mapping: RwLock::new(Mapping {
+                ptr: ptr.cast(),
+                size,
+            }),
+            truncated: AtomicBool::new(false),
+        })
+    }
+
+    fn check_truncated(&self) -> Result<(), Truncated> {
+        match self.truncated.load(Ordering::Acquire) {
+            true => Err(Truncated),
+            false => Ok(()),
+        }
+    }
+
+    /// Remembers a faulted access, so later accesses fail as well.
+    fn record<T>(&self, result: Result<T, Truncated>) -> Result<T, Truncated> {
+        if result.is_err() {
+            self.truncated.store(true, Ordering::Release);
+        }
+
+        result
+    }
+
+    fn size(&self) -> usize {
+        self.mapping.read().unwrap().size
+    }
+
+    /// Grows the mapping to a new size.
+    fn resize(&self, size: usize) -> bool {
+        if !fits_in_file(&self.fd, size) {
+            return false;
+        }
+
+        let mut mapping = self.mapping.write().unwrap();
+
+        // SAFETY: The old mapping is not referenced outside of the lock, which is held.
+        let ptr =
+            unsafe { libc::mremap(mapping.ptr.cast(), mapping.size, size, libc::MREMAP_MAYMOVE) };
+
+        if ptr == libc::MAP_FAILED {
+            return false;
+        }
+
+        mapping.ptr = ptr.cast();
+        mapping.size = size;
+        true
+    }
+}
+
+/// Returns whether the file of a pool is at least `size` bytes long.
+///
+/// Mapping beyond the end of the file succeeds, but accessing that memory raises `SIGBUS`.
+fn fits_in_file(fd: &OwnedFd, size: usize) -> bool {
+    // SAFETY: An all-zero stat is a valid value which is overwritten by fstat.
+    let mut stat: libc::stat = unsafe { mem::zeroed() };
+    // SAFETY: The file descriptor is owned by the pool and the stat is valid for writes.
+    if unsafe { libc::fstat(fd.as_raw_fd(), &mut stat) } != 0 {
+        return false;
+    }
+
+    stat.st_size >= 0 && stat.st_size as u64 >= size as u64
 }
 
-impl<State> Dispatch<WlShm, (), State> for Shm
-where
-    State: Dispatch<WlShm, ()> + Dispatch<WlShmPool, ()>,
-{
-    fn request(
-        state: &mut State,
-        client: &Client,
-        resource: &WlShm,
-        request: wl_shm::Request,
-        data: &(),
-        dhandle: &DisplayHandle,
-        data_init: &mut DataInit<'_, State>,
-    ) {
-        match request {
-            wl_shm::Request::CreatePool { id, fd, size } => {
-                // TODO: This could be an entity actually.
-                let _pool = data_init.init(id, ());
-            }
-
-            _ => unreachable!(),
+impl Drop for ShmPool {
+    fn drop(&mut self) {
+        let mapping = self.mapping.get_mut().unwrap();
+        // SAFETY: Buffers keep the pool alive, so nothing references the mapping anymore.
+        unsafe {
+            libc::munmap(mapping.ptr.cast(), mapping.size);
         }
     }
 }
 
-impl<State> Dispatch<WlShmPool, (), State> for Shm {
-    fn request(
-        state: &mut State,
-        client: &Client,
-        resource: &WlShmPool,
-        request: wl_shm_pool::Request,
-        data: &(),
-        dhandle: &DisplayHandle,
-        data_init: &mut DataInit<'_, State>,
-    ) {
-        todo!()
+impl fmt::Debug for ShmPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShmPool")
+            .field("fd", &self.fd)
+            .field("size", &self.size())
+            .finish()
     }
 }
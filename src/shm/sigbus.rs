@@ -0,0 +1,177 @@
+//! Recovery from `SIGBUS` while accessing the memory of a pool.
+//!
+//! A client may truncate the file of a pool at any time, which makes accesses to the truncated part of the
+//! mapping raise `SIGBUS`. While the compositor accesses a pool, a signal handler replaces the faulting pages
+//! with anonymous memory so the access can finish, and the access is reported as failed afterwards.
+
+use std::{
+    cell::Cell,
+    mem, ptr,
+    sync::{Once, OnceLock},
+};
+
+/// A range of memory the current thread is accessing and whether an access to it faulted.
+struct Access {
+    start: usize,
+    end: usize,
+    faulted: Cell<bool>,
+    /// The access this one is nested in, for example when copying between two buffers.
+    outer: *const Access,
+}
+
+impl Drop for Access {
+    fn drop(&mut self) {
+        // Pops the access even if the closure panics, so the handler never follows a dangling pointer.
+        ACCESS.with(|current| current.set(self.outer));
+    }
+}
+
+thread_local! {
+    /// The innermost access of the current thread.
+    static ACCESS: Cell<*const Access> = const { Cell::new(ptr::null()) };
+}
+
+static INSTALL: Once = Once::new();
+static PREVIOUS_ACTION: OnceLock<SigAction> = OnceLock::new();
+
+/// The handler which was installed before ours, so unrelated faults can be forwarded to it.
+struct SigAction(libc::sigaction);
+
+// SAFETY: The action is only read after it was initialized.
+unsafe impl Send for SigAction {}
+unsafe impl Sync for SigAction {}
+
+/// The access faulted because the client truncated the file of the pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Truncated;
+
+/// Runs `f`, which accesses `len` bytes of a pool mapping at `ptr`, recovering from `SIGBUS`.
+///
+/// Returns [`Truncated`] if any access within the range faulted, in which case the data `f` saw is partly
+/// zeroed and must be discarded.
+pub(super) fn guard<T>(ptr: *const u8, len: usize, f: impl FnOnce() -> T) -> Result<T, Truncated> {
+    INSTALL.call_once(install);
+
+    let access = Access {
+        start: ptr as usize,
+        end: ptr as usize + len,
+        faulted: Cell::new(false),
+        outer: ACCESS.with(Cell::get),
+    };
+    ACCESS.with(|current| current.set(&access));
+    let result = f();
+
+    match access.faulted.get() {
+        true => Err(Truncated),
+        false => Ok(result),
+    }
+}
+
+fn install() {
+    // SAFETY: The action is fully initialized before it is installed and the handler only calls functions
+    // which are safe to call from a signal handler.
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) = handler;
+        action.sa_sigaction = handler as libc::sighandler_t;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_NODEFER;
+        libc::sigemptyset(&mut action.sa_mask);
+
+        let mut previous: libc::sigaction = mem::zeroed();
+        if libc::sigaction(libc::SIGBUS, &action, &mut previous) == 0 {
+            let _ = PREVIOUS_ACTION.set(SigAction(previous));
+        }
+    }
+}
+
+extern "C" fn handler(signal: libc::c_int, info: *mut libc::siginfo_t, context: *mut libc::c_void) {
+    // SAFETY: The kernel passes a valid siginfo to handlers installed with SA_SIGINFO.
+    let addr = unsafe { (*info).si_addr() } as usize;
+
+    let mut access = ACCESS.with(Cell::get);
+    let mut recovered = false;
+
+    // SAFETY: Accesses live on the stack of this thread until they are popped, so every access in the chain
+    // outlives the handler running on the same thread.
+    while let Some(current) = unsafe { access.as_ref() } {
+        if (current.start..current.end).contains(&addr) {
+            // SAFETY: The faulting page belongs to a pool mapping, which is replaced by anonymous memory of the
+            // same size. The mapping is unmapped as a whole once the pool is dropped.
+            let mapped = unsafe {
+                let page_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+                let page = addr & !(page_size - 1);
+                libc::mmap(
+                    page as *mut libc::c_void,
+                    page_size,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_FIXED | libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                    -1,
+                    0,
+                )
+            };
+
+            current.faulted.set(true);
+            recovered = mapped != libc::MAP_FAILED;
+            break;
+        }
+
+        access = current.outer;
+    }
+
+    if !recovered {
+        forward(signal, info, context);
+    }
+}
+
+/// Hands a fault which is not caused by a truncated pool to the previous handler.
+fn forward(signal: libc::c_int, info: *mut libc::siginfo_t, context: *mut libc::c_void) {
+    let previous = PREVIOUS_ACTION.get().map(|action| action.0);
+
+    match previous {
+        Some(action)
+            if action.sa_sigaction != libc::SIG_DFL && action.sa_sigaction != libc::SIG_IGN =>
+        {
+            // SAFETY: The previous handler was installed for this signal with the same flags.
+            unsafe {
+                if action.sa_flags & libc::SA_SIGINFO != 0 {
+                    let handler: extern "C" fn(
+                        libc::c_int,
+                        *mut libc::siginfo_t,
+                        *mut libc::c_void,
+                    ) = mem::transmute(action.sa_sigaction);
+                    handler(signal, info, context);
+                } else {
+                    let handler: extern "C" fn(libc::c_int) = mem::transmute(action.sa_sigaction);
+                    handler(signal);
+                }
+            }
+        }
+
+        // Restore the default action, so the fault kills the process once the instruction is retried.
+        _ => {
+            // SAFETY: The default action is a valid action for SIGBUS.
+            unsafe {
+                let mut action: libc::sigaction = mem::zeroed();
+                action.sa_sigaction = libc::SIG_DFL;
+                libc::sigaction(libc::SIGBUS, &action, ptr::null_mut());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, panic};
+
+    use super::{guard, ACCESS};
+
+    #[test]
+    fn access_is_popped_when_closure_panics() {
+        let data = [0u8; 16];
+        let result =
+            panic::catch_unwind(|| guard(data.as_ptr(), data.len(), || panic!("access failed")));
+
+        assert!(result.is_err());
+        assert!(ACCESS.with(Cell::get).is_null());
+    }
+}
@@ -0,0 +1,108 @@
+use std::os::unix::io::{AsRawFd, OwnedFd};
+
+use wayland_server::protocol::wl_shm;
+
+use crate::testing::{memfd, Arg, Fixture};
+
+use super::{ShmBuffer, Truncated};
+
+const PAGE_SIZE: usize = 4096;
+
+// wl_shm requests
+const CREATE_POOL: u16 = 0;
+
+// wl_shm_pool requests
+const CREATE_BUFFER: u16 = 0;
+const RESIZE: u16 = 2;
+
+fn create_pool(fixture: &mut Fixture, fd: &OwnedFd, size: usize) -> u32 {
+    let pool = fixture.client.new_id();
+    fixture.send(
+        fixture.shm,
+        CREATE_POOL,
+        &[
+            Arg::NewId(pool),
+            Arg::Fd(fd.as_raw_fd()),
+            Arg::Int(size as i32),
+        ],
+    );
+    pool
+}
+
+/// Creates a buffer covering the first page of a pool.
+fn create_buffer(fixture: &mut Fixture, pool: u32) -> ShmBuffer {
+    let buffer = fixture.client.new_id();
+    fixture.send(
+        pool,
+        CREATE_BUFFER,
+        &[
+            Arg::NewId(buffer),
+            Arg::Int(0),
+            Arg::Int(32),
+            Arg::Int(32),
+            Arg::Int(128),
+            Arg::Uint(wl_shm::Format::Argb8888 as u32),
+        ],
+    );
+    fixture.assert_no_error();
+
+    fixture
+        .world()
+        .query_mut::<&ShmBuffer>()
+        .into_iter()
+        .map(|(_, buffer)| buffer.clone())
+        .next()
+        .unwrap()
+}
+
+fn truncate(fd: &OwnedFd, size: usize) {
+    // SAFETY: The file descriptor is valid.
+    assert_eq!(unsafe { libc::ftruncate(fd.as_raw_fd(), size as _) }, 0);
+}
+
+#[test]
+fn pool_larger_than_file_is_error() {
+    let mut fixture = Fixture::new();
+    let fd = memfd(PAGE_SIZE);
+
+    create_pool(&mut fixture, &fd, 2 * PAGE_SIZE);
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(wl_shm::Error::InvalidFd as u32)
+    );
+}
+
+#[test]
+fn resize_checks_file_size() {
+    let mut fixture = Fixture::new();
+    let fd = memfd(PAGE_SIZE);
+    let pool = create_pool(&mut fixture, &fd, PAGE_SIZE);
+
+    truncate(&fd, 2 * PAGE_SIZE);
+    fixture.send(pool, RESIZE, &[Arg::Int(2 * PAGE_SIZE as i32)]);
+    fixture.assert_no_error();
+
+    fixture.send(pool, RESIZE, &[Arg::Int(4 * PAGE_SIZE as i32)]);
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(wl_shm::Error::InvalidFd as u32)
+    );
+}
+
+#[test]
+fn truncated_pool_is_recovered() {
+    let mut fixture = Fixture::new();
+    let fd = memfd(PAGE_SIZE);
+    let pool = create_pool(&mut fixture, &fd, PAGE_SIZE);
+    let buffer = create_buffer(&mut fixture, pool);
+
+    buffer.with_data_mut(|data| data.fill(0xff)).unwrap();
+    assert_eq!(buffer.with_data(|data| data[0]), Ok(0xff));
+
+    // Accessing the mapping beyond the end of the file raises SIGBUS.
+    truncate(&fd, 0);
+    assert_eq!(buffer.with_data(|data| data[0]), Err(Truncated));
+    // The pool no longer shares memory with the client, even if the file grows again.
+    truncate(&fd, PAGE_SIZE);
+    assert_eq!(buffer.with_data_mut(|data| data.fill(0xff)), Err(Truncated));
+}
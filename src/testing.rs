@@ -0,0 +1,1121 @@
+//! Helpers for tests which drive protocol implementations over the wire.
+//!
+//! [`TestClient`] speaks the wayland wire protocol directly on one end of a socket pair, so tests can send
+//! exact sequences of requests without a client library. [`TestState`] implements the handlers of the
+//! protocols under test.
+
+use std::{
+    io::{self, Read},
+    mem,
+    os::unix::{
+        io::{AsRawFd, OwnedFd, RawFd},
+        net::UnixStream,
+    },
+    ptr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use hecs::Entity;
+use smithay::utils::{Logical, Point};
+use wayland_protocols::ext::idle_notify::v1::server::{
+    ext_idle_notification_v1::ExtIdleNotificationV1, ext_idle_notifier_v1::ExtIdleNotifierV1,
+};
+use wayland_protocols::ext::session_lock::v1::server::{
+    ext_session_lock_manager_v1::ExtSessionLockManagerV1,
+    ext_session_lock_surface_v1::ExtSessionLockSurfaceV1, ext_session_lock_v1::ExtSessionLockV1,
+};
+use wayland_protocols::wp::{
+    fractional_scale::v1::server::{
+        wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
+        wp_fractional_scale_v1::WpFractionalScaleV1,
+    },
+    idle_inhibit::zv1::server::{
+        zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1,
+        zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1,
+    },
+    keyboard_shortcuts_inhibit::zv1::server::{
+        zwp_keyboard_shortcuts_inhibit_manager_v1::ZwpKeyboardShortcutsInhibitManagerV1,
+        zwp_keyboard_shortcuts_inhibitor_v1::ZwpKeyboardShortcutsInhibitorV1,
+    },
+    linux_dmabuf::zv1::server::{
+        zwp_linux_buffer_params_v1::ZwpLinuxBufferParamsV1,
+        zwp_linux_dmabuf_feedback_v1::ZwpLinuxDmabufFeedbackV1,
+        zwp_linux_dmabuf_v1::ZwpLinuxDmabufV1,
+    },
+    pointer_constraints::zv1::server::{
+        zwp_confined_pointer_v1::ZwpConfinedPointerV1, zwp_locked_pointer_v1::ZwpLockedPointerV1,
+        zwp_pointer_constraints_v1::ZwpPointerConstraintsV1,
+    },
+    presentation_time::server::{
+        wp_presentation::WpPresentation, wp_presentation_feedback::WpPresentationFeedback,
+    },
+    primary_selection::zv1::server::{
+        zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1,
+        zwp_primary_selection_device_v1::ZwpPrimarySelectionDeviceV1,
+        zwp_primary_selection_offer_v1::ZwpPrimarySelectionOfferV1,
+        zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1,
+    },
+    text_input::zv3::server::{
+        zwp_text_input_manager_v3::ZwpTextInputManagerV3, zwp_text_input_v3::ZwpTextInputV3,
+    },
+    viewporter::server::{wp_viewport::WpViewport, wp_viewporter::WpViewporter},
+};
+use wayland_protocols::xdg::{
+    decoration::zv1::server::{
+        zxdg_decoration_manager_v1::ZxdgDecorationManagerV1,
+        zxdg_toplevel_decoration_v1::{Mode, ZxdgToplevelDecorationV1},
+    },
+    shell::server::{xdg_surface::XdgSurface, xdg_toplevel::XdgToplevel, xdg_wm_base::XdgWmBase},
+};
+use wayland_protocols_misc::zwp_input_method_v2::server::{
+    zwp_input_method_keyboard_grab_v2::ZwpInputMethodKeyboardGrabV2,
+    zwp_input_method_manager_v2::ZwpInputMethodManagerV2, zwp_input_method_v2::ZwpInputMethodV2,
+    zwp_input_popup_surface_v2::ZwpInputPopupSurfaceV2,
+};
+use wayland_protocols_wlr::{
+    data_control::v1::server::{
+        zwlr_data_control_device_v1::ZwlrDataControlDeviceV1,
+        zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+        zwlr_data_control_offer_v1::ZwlrDataControlOfferV1,
+        zwlr_data_control_source_v1::ZwlrDataControlSourceV1,
+    },
+    foreign_toplevel::v1::server::{
+        zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1,
+        zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1,
+    },
+    layer_shell::v1::server::{
+        zwlr_layer_shell_v1::ZwlrLayerShellV1, zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
+    },
+};
+use wayland_server::{
+    delegate_dispatch, delegate_global_dispatch,
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_callback::WlCallback,
+        wl_compositor::WlCompositor,
+        wl_data_device::WlDataDevice,
+        wl_data_device_manager::WlDataDeviceManager,
+        wl_data_offer::WlDataOffer,
+        wl_data_source::WlDataSource,
+        wl_keyboard::{self, WlKeyboard},
+        wl_output::{self, WlOutput},
+        wl_pointer::{self, WlPointer},
+        wl_region::WlRegion,
+        wl_seat::WlSeat,
+        wl_shm::WlShm,
+        wl_shm_pool::WlShmPool,
+        wl_subcompositor::WlSubcompositor,
+        wl_subsurface::WlSubsurface,
+        wl_surface::WlSurface,
+        wl_touch::WlTouch,
+    },
+    Client, DataInit, Dispatch, Display, DisplayHandle, GlobalDispatch, New, Resource,
+};
+
+use crate::{
+    compositor::{Compositor, CompositorHandler, ManualBlocker, RegionData},
+    cursor_shape::{CursorShapeDeviceData, CursorShapeManager},
+    data_control::{DataControlHandler, DataControlManager},
+    data_device::{DataDeviceHandler, DataDeviceManager},
+    dmabuf::{
+        Dmabuf, DmabufBuffer, DmabufFeedback, DmabufFeedbackData, DmabufFormat, DmabufGlobalData,
+        DmabufHandler, DmabufParamsData, DmabufTranche, ImportError,
+    },
+    drm_syncobj::{DrmSyncobj, DrmSyncobjHandler, SyncTimeline, SyncTimelineData},
+    foreign_toplevel::{ForeignToplevelHandler, ForeignToplevelManager},
+    fractional_scale::{FractionalScaleHandler, FractionalScaleManager},
+    idle_inhibit::IdleInhibitManager,
+    idle_notify::IdleNotifier,
+    input_method::InputMethodManager,
+    keyboard_shortcuts_inhibit::KeyboardShortcutsInhibitManager,
+    layer_shell::{LayerShell, LayerShellHandler},
+    pointer_constraints::{PointerConstraintsHandler, PointerConstraintsManager},
+    presentation::{Presentation, PresentationGlobalData},
+    primary_selection::{PrimarySelectionHandler, PrimarySelectionManager},
+    protocols::cursor_shape_v1::{
+        wp_cursor_shape_device_v1::WpCursorShapeDeviceV1,
+        wp_cursor_shape_manager_v1::WpCursorShapeManagerV1,
+    },
+    protocols::ext_foreign_toplevel_list_v1::{
+        ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
+        ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1,
+    },
+    protocols::linux_drm_syncobj_v1::{
+        wp_linux_drm_syncobj_manager_v1::WpLinuxDrmSyncobjManagerV1,
+        wp_linux_drm_syncobj_surface_v1::WpLinuxDrmSyncobjSurfaceV1,
+        wp_linux_drm_syncobj_timeline_v1::WpLinuxDrmSyncobjTimelineV1,
+    },
+    seat::Seat,
+    session_lock::{SessionLockHandler, SessionLockManager},
+    shm::{Shm, ShmPoolData},
+    text_input::TextInputManager,
+    viewporter::Viewporter,
+    xdg_decoration::{XdgDecoration, XdgDecorationHandler},
+    xdg_shell::{XdgShell, XdgShellHandler},
+    Ecs, EcsAccess, EntityData,
+};
+
+// Requests used by the fixture.
+const WL_COMPOSITOR_CREATE_SURFACE: u16 = 0;
+const WL_COMPOSITOR_CREATE_REGION: u16 = 1;
+const WL_REGION_ADD: u16 = 1;
+const WL_SUBCOMPOSITOR_GET_SUBSURFACE: u16 = 1;
+const WL_SHM_CREATE_POOL: u16 = 0;
+const WL_SHM_POOL_CREATE_BUFFER: u16 = 0;
+const WL_SURFACE_ATTACH: u16 = 1;
+const WL_SURFACE_COMMIT: u16 = 6;
+const XDG_WM_BASE_GET_XDG_SURFACE: u16 = 2;
+const XDG_SURFACE_GET_TOPLEVEL: u16 = 1;
+
+/// An argument of a request.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Arg<'a> {
+    Int(i32),
+    Uint(u32),
+    Str(&'a str),
+    Object(u32),
+    NewId(u32),
+    Fd(RawFd),
+}
+
+/// An event received by a [`TestClient`].
+#[derive(Debug, Clone)]
+pub(crate) struct Event {
+    pub object: u32,
+    pub opcode: u16,
+    /// The arguments of the event, without file descriptors.
+    pub args: Vec<u8>,
+}
+
+impl Event {
+    /// Reads the 32-bit argument at a word offset.
+    pub fn uint(&self, word: usize) -> u32 {
+        let bytes = &self.args[word * 4..word * 4 + 4];
+        u32::from_ne_bytes(bytes.try_into().unwrap())
+    }
+
+    /// Reads the string argument at a word offset.
+    pub fn string(&self, word: usize) -> &str {
+        // The length includes the terminating nul byte.
+        let len = self.uint(word) as usize;
+        let start = (word + 1) * 4;
+        std::str::from_utf8(&self.args[start..start + len - 1]).unwrap()
+    }
+}
+
+/// A wayland client which writes requests to the wire by hand.
+pub(crate) struct TestClient {
+    socket: UnixStream,
+    next_id: u32,
+    registry: u32,
+    /// Globals announced by the registry, as name, interface and version.
+    globals: Vec<(u32, String, u32)>,
+    events: Vec<Event>,
+}
+
+impl TestClient {
+    /// Connects a client and reads the globals of the display.
+    pub fn connect(server: &mut TestServer) -> Self {
+        let (client_socket, server_socket) = UnixStream::pair().unwrap();
+        client_socket.set_nonblocking(true).unwrap();
+        server
+            .display
+            .handle()
+            .insert_client(server_socket, Arc::new(()))
+            .unwrap();
+
+        let mut client = Self {
+            socket: client_socket,
+            next_id: 2,
+            registry: 0,
+            globals: Vec::new(),
+            events: Vec::new(),
+        };
+
+        // wl_display.get_registry
+        client.registry = client.new_id();
+        client.send(1, 1, &[Arg::NewId(client.registry)]);
+        server.dispatch();
+        client.receive();
+
+        let registry = client.registry;
+        let mut globals = Vec::new();
+        client.events.retain(|event| {
+            // wl_registry.global
+            if event.object == registry && event.opcode == 0 {
+                let name = event.uint(0);
+                let len = event.uint(1) as usize;
+                let interface = String::from_utf8(event.args[8..8 + len - 1].to_vec()).unwrap();
+                let version = event.uint(2 + len.div_ceil(4));
+                globals.push((name, interface, version));
+                false
+            } else {
+                true
+            }
+        });
+        client.globals = globals;
+        client
+    }
+
+    /// Allocates the id of a new object.
+    pub fn new_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /// Binds a global by its interface name and returns the id of the new object.
+    pub fn bind(&mut self, interface: &str, version: u32) -> u32 {
+        let name = self
+            .globals
+            .iter()
+            .find(|(_, global, _)| global == interface)
+            .unwrap_or_else(|| panic!("No {interface} global"))
+            .0;
+        let id = self.new_id();
+
+        // wl_registry.bind takes an untyped new id, which is the interface, version and id.
+        self.send(
+            self.registry,
+            0,
+            &[
+                Arg::Uint(name),
+                Arg::Str(interface),
+                Arg::Uint(version),
+                Arg::NewId(id),
+            ],
+        );
+        id
+    }
+
+    /// Sends a request.
+    pub fn send(&mut self, object: u32, opcode: u16, args: &[Arg<'_>]) {
+        let mut message = vec![0u8; 8];
+        let mut fds = Vec::new();
+
+        for arg in args {
+            match *arg {
+                Arg::Int(value) => message.extend_from_slice(&value.to_ne_bytes()),
+                Arg::Uint(value) | Arg::Object(value) | Arg::NewId(value) => {
+                    message.extend_from_slice(&value.to_ne_bytes())
+                }
+                Arg::Str(value) => {
+                    message.extend_from_slice(&(value.len() as u32 + 1).to_ne_bytes());
+                    message.extend_from_slice(value.as_bytes());
+                    message.push(0);
+                    pad(&mut message);
+                }
+                Arg::Fd(fd) => fds.push(fd),
+            }
+        }
+
+        let header = ((message.len() as u32) << 16) | opcode as u32;
+        message[..4].copy_from_slice(&object.to_ne_bytes());
+        message[4..8].copy_from_slice(&header.to_ne_bytes());
+
+        send_with_fds(self.socket.as_raw_fd(), &message, &fds).unwrap();
+    }
+
+    /// Reads the events which the server has flushed.
+    pub fn receive(&mut self) {
+        let mut data = Vec::new();
+        let mut buf = [0u8; 4096];
+
+        loop {
+            match self.socket.read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => data.extend_from_slice(&buf[..len]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => panic!("Failed to read events: {err}"),
+            }
+        }
+
+        let mut offset = 0;
+        while offset + 8 <= data.len() {
+            let object = u32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap());
+            let header = u32::from_ne_bytes(data[offset + 4..offset + 8].try_into().unwrap());
+            let size = (header >> 16) as usize;
+
+            self.events.push(Event {
+                object,
+                opcode: header as u16,
+                args: data[offset + 8..offset + size].to_vec(),
+            });
+            offset += size;
+        }
+    }
+
+    /// The received events which were not taken yet, in the order they were sent.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Takes the received events which were sent to an object.
+    pub fn take_events(&mut self, object: u32) -> Vec<Event> {
+        let (events, rest) = mem::take(&mut self.events)
+            .into_iter()
+            .partition(|event| event.object == object);
+        self.events = rest;
+        events
+    }
+
+    /// Returns the code of the protocol error the client was killed with, if any.
+    pub fn protocol_error(&mut self) -> Option<u32> {
+        self.receive();
+
+        // wl_display.error carries the object, the code and a message.
+        self.take_events(1)
+            .into_iter()
+            .find(|event| event.opcode == 0)
+            .map(|event| event.uint(1))
+    }
+}
+
+fn pad(message: &mut Vec<u8>) {
+    message.resize(message.len().next_multiple_of(4), 0);
+}
+
+/// Writes a message, passing file descriptors as ancillary data.
+fn send_with_fds(socket: RawFd, data: &[u8], fds: &[RawFd]) -> io::Result<()> {
+    let mut iov = libc::iovec {
+        iov_base: data.as_ptr() as *mut _,
+        iov_len: data.len(),
+    };
+
+    // SAFETY: Only sizes are computed.
+    let space = unsafe { libc::CMSG_SPACE(mem::size_of_val(fds) as u32) } as usize;
+    let mut control = vec![0u8; space];
+
+    // SAFETY: msghdr is plain data, for which zeroes are valid.
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+
+    if !fds.is_empty() {
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = space as _;
+
+        // SAFETY: The control buffer has space for a header with the file descriptors.
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of_val(fds) as u32) as _;
+            ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(cmsg).cast(), fds.len());
+        }
+    }
+
+    // SAFETY: The message only points to buffers which outlive the call.
+    let sent = unsafe { libc::sendmsg(socket, &msg, 0) };
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    assert_eq!(sent as usize, data.len(), "Short write");
+    Ok(())
+}
+
+/// Creates an anonymous file of a size, filled with zeroes.
+pub(crate) fn memfd(size: usize) -> OwnedFd {
+    use std::os::unix::io::FromRawFd;
+
+    // SAFETY: The name is a valid C string.
+    let fd = unsafe { libc::memfd_create(c"smithay-ecs-test".as_ptr(), 0) };
+    assert!(fd >= 0, "memfd_create failed");
+
+    // SAFETY: The file descriptor was just created and is owned by nothing else.
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    // SAFETY: The file descriptor is valid.
+    assert_eq!(unsafe { libc::ftruncate(fd.as_raw_fd(), size as _) }, 0);
+    fd
+}
+
+/// The fourcc codes of formats used in tests.
+pub(crate) const ARGB8888: u32 = u32::from_le_bytes(*b"AR24");
+pub(crate) const XRGB8888: u32 = u32::from_le_bytes(*b"XR24");
+
+/// Creates dmabuf feedback advertising a single format with the linear modifier.
+pub(crate) fn dmabuf_feedback(format: u32) -> DmabufFeedback {
+    DmabufFeedback::new(
+        0,
+        vec![DmabufTranche {
+            target_device: 0,
+            scanout: false,
+            formats: vec![DmabufFormat {
+                format,
+                modifier: 0,
+            }],
+        }],
+    )
+    .unwrap()
+}
+
+/// A server with one connected client, which is where most tests start.
+///
+/// The client binds `wl_compositor`, `wl_subcompositor` and `wl_shm`, which tests use to create surfaces and
+/// buffers.
+pub(crate) struct Fixture {
+    pub server: TestServer,
+    pub client: TestClient,
+    pub compositor: u32,
+    pub subcompositor: u32,
+    pub shm: u32,
+    /// The `xdg_wm_base`, which is bound by the first [`Fixture::create_toplevel`].
+    wm_base: Option<u32>,
+}
+
+impl Fixture {
+    pub fn new() -> Self {
+        Self::with_compositor_version(4)
+    }
+
+    pub fn with_compositor_version(version: u32) -> Self {
+        let mut server = TestServer::new();
+        let mut client = TestClient::connect(&mut server);
+        let compositor = client.bind("wl_compositor", version);
+        let subcompositor = client.bind("wl_subcompositor", 1);
+        let shm = client.bind("wl_shm", 1);
+        server.dispatch();
+
+        Self {
+            server,
+            client,
+            compositor,
+            subcompositor,
+            shm,
+            wm_base: None,
+        }
+    }
+
+    /// The seat announced to the client.
+    pub fn seat(&self) -> Entity {
+        self.server.state.seat
+    }
+
+    pub fn world(&mut self) -> &mut hecs::World {
+        &mut self.server.state.ecs.world
+    }
+
+    /// Creates a surface.
+    pub fn create_surface(&mut self) -> TestSurface {
+        let id = self.client.new_id();
+        self.send(
+            self.compositor,
+            WL_COMPOSITOR_CREATE_SURFACE,
+            &[Arg::NewId(id)],
+        );
+        self.server.dispatch();
+
+        let surface = self.server.state.surfaces.last().unwrap().clone();
+        assert_eq!(surface.id().protocol_id(), id);
+        let entity = surface.data::<EntityData>().unwrap().0;
+
+        TestSurface {
+            id,
+            surface,
+            entity,
+        }
+    }
+
+    /// Makes a surface a subsurface of a parent and returns the `wl_subsurface`.
+    pub fn create_subsurface(&mut self, surface: &TestSurface, parent: &TestSurface) -> u32 {
+        let id = self.client.new_id();
+        self.send(
+            self.subcompositor,
+            WL_SUBCOMPOSITOR_GET_SUBSURFACE,
+            &[
+                Arg::NewId(id),
+                Arg::Object(surface.id),
+                Arg::Object(parent.id),
+            ],
+        );
+        id
+    }
+
+    /// Creates a surface with the `xdg_toplevel` role.
+    pub fn create_toplevel(&mut self) -> TestToplevel {
+        let wm_base = match self.wm_base {
+            Some(wm_base) => wm_base,
+            None => *self.wm_base.insert(self.client.bind("xdg_wm_base", 4)),
+        };
+        let surface = self.create_surface();
+
+        let xdg_surface = self.client.new_id();
+        self.send(
+            wm_base,
+            XDG_WM_BASE_GET_XDG_SURFACE,
+            &[Arg::NewId(xdg_surface), Arg::Object(surface.id)],
+        );
+        let toplevel = self.client.new_id();
+        self.send(
+            xdg_surface,
+            XDG_SURFACE_GET_TOPLEVEL,
+            &[Arg::NewId(toplevel)],
+        );
+        self.assert_no_error();
+
+        TestToplevel {
+            surface,
+            xdg_surface,
+            toplevel,
+        }
+    }
+
+    /// Creates a region from rectangles of `x`, `y`, `width` and `height`.
+    pub fn create_region(&mut self, rects: &[(i32, i32, i32, i32)]) -> u32 {
+        let region = self.client.new_id();
+        self.send(
+            self.compositor,
+            WL_COMPOSITOR_CREATE_REGION,
+            &[Arg::NewId(region)],
+        );
+        for &(x, y, width, height) in rects {
+            self.send(
+                region,
+                WL_REGION_ADD,
+                &[Arg::Int(x), Arg::Int(y), Arg::Int(width), Arg::Int(height)],
+            );
+        }
+        region
+    }
+
+    /// Creates a shm pool backed by an anonymous file.
+    pub fn create_pool(&mut self, size: usize) -> TestPool {
+        let fd = memfd(size);
+        let id = self.client.new_id();
+        self.send(
+            self.shm,
+            WL_SHM_CREATE_POOL,
+            &[
+                Arg::NewId(id),
+                Arg::Fd(fd.as_raw_fd()),
+                Arg::Int(size as i32),
+            ],
+        );
+        self.server.dispatch();
+
+        TestPool {
+            id,
+            offset: 0,
+            _fd: fd,
+        }
+    }
+
+    /// Creates an argb8888 buffer after the buffers created from a pool before.
+    pub fn create_buffer(&mut self, pool: &mut TestPool, width: i32, height: i32) -> u32 {
+        let id = self.client.new_id();
+        self.send(
+            pool.id,
+            WL_SHM_POOL_CREATE_BUFFER,
+            &[
+                Arg::NewId(id),
+                Arg::Int(pool.offset as i32),
+                Arg::Int(width),
+                Arg::Int(height),
+                Arg::Int(width * 4),
+                Arg::Uint(0),
+            ],
+        );
+        pool.offset += (width * height * 4) as usize;
+        id
+    }
+
+    /// Attaches a buffer at the origin, or removes the buffer if `buffer` is 0.
+    pub fn attach(&mut self, surface: u32, buffer: u32) {
+        self.send(
+            surface,
+            WL_SURFACE_ATTACH,
+            &[Arg::Object(buffer), Arg::Int(0), Arg::Int(0)],
+        );
+    }
+
+    pub fn send(&mut self, object: u32, opcode: u16, args: &[Arg<'_>]) {
+        self.client.send(object, opcode, args);
+    }
+
+    /// Dispatches the requests sent so far and reads the events sent in response.
+    pub fn dispatch(&mut self) {
+        self.server.dispatch();
+        self.client.receive();
+    }
+
+    /// Commits a surface, which must not cause a protocol error.
+    pub fn commit(&mut self, surface: u32) {
+        self.send(surface, WL_SURFACE_COMMIT, &[]);
+        self.assert_no_error();
+    }
+
+    /// Dispatches the requests sent so far and returns the code of the protocol error they caused, if any.
+    pub fn protocol_error(&mut self) -> Option<u32> {
+        self.server.dispatch();
+        self.client.protocol_error()
+    }
+
+    pub fn assert_no_error(&mut self) {
+        assert_eq!(self.protocol_error(), None);
+    }
+
+    /// Dispatches the requests sent so far, which must not cause a protocol error, and takes the events sent to
+    /// an object.
+    pub fn events(&mut self, object: u32) -> Vec<Event> {
+        self.dispatch();
+        assert_eq!(self.client.protocol_error(), None);
+        self.client.take_events(object)
+    }
+
+    /// Like [`Fixture::events`], but only returns the opcodes of the events.
+    pub fn opcodes(&mut self, object: u32) -> Vec<u16> {
+        self.events(object)
+            .into_iter()
+            .map(|event| event.opcode)
+            .collect()
+    }
+
+    /// Adds a [`ManualBlocker`] to the next commit of a surface.
+    pub fn add_blocker(&mut self, surface: &TestSurface) -> ManualBlocker {
+        let blocker = ManualBlocker::new();
+        Compositor::add_blocker::<TestState>(
+            &mut self.server.state.ecs,
+            &surface.surface,
+            blocker.clone(),
+        );
+        blocker
+    }
+
+    pub fn release(&mut self, blocker: &ManualBlocker) {
+        blocker.release(&mut self.server.state);
+        self.dispatch();
+    }
+
+    pub fn set_keyboard_focus(&mut self, surface: Option<&TestSurface>) {
+        let display = self.server.display.handle();
+        let seat = self.seat();
+        let surface = surface.map(|surface| surface.surface.clone());
+        Seat::set_keyboard_focus(&mut self.server.state, &display, seat, surface);
+        self.dispatch();
+    }
+
+    /// Presses or releases a key and returns the serial of the event, if a surface has keyboard focus.
+    pub fn key(&mut self, key: u32, state: wl_keyboard::KeyState) -> Option<u32> {
+        let seat = self.seat();
+        let serial =
+            Seat::keyboard_key(&mut self.server.state.ecs, seat, Duration::ZERO, key, state);
+        self.dispatch();
+        serial
+    }
+
+    /// Presses or releases a button and returns the serial of the event, if a surface has pointer focus.
+    pub fn button(&mut self, button: u32, state: wl_pointer::ButtonState) -> Option<u32> {
+        let seat = self.seat();
+        let serial = Seat::pointer_button(
+            &mut self.server.state.ecs,
+            seat,
+            Duration::ZERO,
+            button,
+            state,
+        );
+        self.dispatch();
+        serial
+    }
+
+    /// Moves the pointer over the origin of a surface.
+    pub fn set_pointer_focus(&mut self, surface: Option<&TestSurface>) {
+        let display = self.server.display.handle();
+        let seat = self.seat();
+        let focus = surface.map(|surface| (surface.surface.clone(), Point::default()));
+        Seat::set_pointer_focus(&mut self.server.state, &display, seat, focus);
+        self.dispatch();
+    }
+}
+
+/// A surface created by [`Fixture::create_surface`].
+#[derive(Debug, Clone)]
+pub(crate) struct TestSurface {
+    /// The protocol id of the surface.
+    pub id: u32,
+    pub surface: WlSurface,
+    pub entity: Entity,
+}
+
+/// A toplevel created by [`Fixture::create_toplevel`].
+#[derive(Debug, Clone)]
+pub(crate) struct TestToplevel {
+    pub surface: TestSurface,
+    pub xdg_surface: u32,
+    pub toplevel: u32,
+}
+
+/// A shm pool created by [`Fixture::create_pool`].
+pub(crate) struct TestPool {
+    pub id: u32,
+    /// The offset of the next buffer.
+    offset: usize,
+    _fd: OwnedFd,
+}
+
+/// The server side of a test.
+pub(crate) struct TestServer {
+    pub display: Display<TestState>,
+    pub state: TestState,
+}
+
+impl TestServer {
+    pub fn new() -> Self {
+        let display = Display::new().unwrap();
+        let mut handle = display.handle();
+
+        let mut ecs = Ecs::new();
+        let seat = Seat::new::<TestState>(&mut handle, &mut ecs, "seat0").entity();
+
+        let state = TestState {
+            ecs,
+            compositor: Compositor::new::<TestState>(&mut handle),
+            seat,
+            surfaces: Vec::new(),
+            reject_dmabufs: false,
+            timelines: Vec::new(),
+            output_scales: Vec::new(),
+            toplevel_requests: Vec::new(),
+            locks: Vec::new(),
+        };
+        Shm::new::<TestState>(&mut handle);
+        Dmabuf::new::<TestState>(&mut handle, dmabuf_feedback(ARGB8888));
+        DrmSyncobj::new::<TestState>(&mut handle);
+        Viewporter::new::<TestState>(&mut handle);
+        FractionalScaleManager::new::<TestState>(&mut handle);
+        XdgShell::new::<TestState>(&mut handle);
+        XdgDecoration::new::<TestState>(&mut handle);
+        LayerShell::new::<TestState>(&mut handle);
+        ForeignToplevelManager::new::<TestState>(&mut handle);
+        DataDeviceManager::new::<TestState>(&mut handle);
+        PrimarySelectionManager::new::<TestState>(&mut handle);
+        DataControlManager::new::<TestState>(&mut handle);
+        PointerConstraintsManager::new::<TestState>(&mut handle);
+        IdleNotifier::new::<TestState>(&mut handle);
+        TextInputManager::new::<TestState>(&mut handle);
+        InputMethodManager::new::<TestState>(&mut handle);
+        CursorShapeManager::new::<TestState>(&mut handle);
+        IdleInhibitManager::new::<TestState>(&mut handle);
+        KeyboardShortcutsInhibitManager::new::<TestState>(&mut handle);
+        SessionLockManager::new::<TestState>(&mut handle);
+        handle.create_global::<TestState, WlOutput, ()>(4, ());
+        // CLOCK_MONOTONIC
+        Presentation::new::<TestState>(&mut handle, 1);
+
+        Self { display, state }
+    }
+
+    /// Dispatches the requests of every client and flushes the events.
+    pub fn dispatch(&mut self) {
+        self.display.dispatch_clients(&mut self.state).unwrap();
+        self.display.flush_clients().unwrap();
+    }
+}
+
+pub(crate) struct TestState {
+    pub ecs: Ecs,
+    pub compositor: Compositor,
+    /// The seat announced to every client.
+    pub seat: Entity,
+    /// Every surface created by a client, oldest first.
+    pub surfaces: Vec<WlSurface>,
+    /// Whether the renderer fails to import dmabufs.
+    pub reject_dmabufs: bool,
+    /// Every timeline imported by a client, oldest first.
+    pub timelines: Vec<Arc<SoftwareTimeline>>,
+    /// The scales of the outputs every surface overlaps.
+    pub output_scales: Vec<f64>,
+    /// The names of the foreign toplevel requests routed to the handler, oldest first.
+    pub toplevel_requests: Vec<&'static str>,
+    /// The locks passed to the session lock handler, oldest first.
+    pub locks: Vec<Entity>,
+}
+
+/// A [`SyncTimeline`] in memory, standing in for a DRM syncobj.
+#[derive(Debug, Default)]
+pub(crate) struct SoftwareTimeline {
+    /// The latest signalled point, which signals every point before it.
+    signalled: Mutex<u64>,
+}
+
+impl SyncTimeline for SoftwareTimeline {
+    fn is_signalled(&self, point: u64) -> bool {
+        *self.signalled.lock().unwrap() >= point
+    }
+
+    fn signal(&self, point: u64) {
+        let mut signalled = self.signalled.lock().unwrap();
+        *signalled = (*signalled).max(point);
+    }
+}
+
+impl EcsAccess for TestState {
+    fn ecs(&mut self) -> &mut Ecs {
+        &mut self.ecs
+    }
+}
+
+impl CompositorHandler for TestState {
+    fn compositor(&mut self) -> &mut Compositor {
+        &mut self.compositor
+    }
+
+    fn new_surface(&mut self, surface: WlSurface) {
+        self.surfaces.push(surface);
+    }
+
+    fn commit(&mut self, _surface: &WlSurface) {}
+}
+
+impl DrmSyncobjHandler for TestState {
+    fn import_timeline(&mut self, _fd: OwnedFd) -> Option<Arc<dyn SyncTimeline>> {
+        let timeline = Arc::new(SoftwareTimeline::default());
+        self.timelines.push(timeline.clone());
+        Some(timeline)
+    }
+}
+
+impl FractionalScaleHandler for TestState {
+    fn output_scales(&mut self, _surface: &WlSurface) -> Vec<f64> {
+        self.output_scales.clone()
+    }
+}
+
+impl XdgShellHandler for TestState {
+    fn new_toplevel(&mut self, _toplevel: XdgToplevel) {}
+}
+
+impl XdgDecorationHandler for TestState {
+    fn request_mode(&mut self, _surface: &WlSurface, _mode: Option<Mode>) {}
+}
+
+impl LayerShellHandler for TestState {
+    fn new_layer_surface(
+        &mut self,
+        _surface: &WlSurface,
+        _output: Option<WlOutput>,
+        _namespace: String,
+    ) {
+    }
+}
+
+impl ForeignToplevelHandler for TestState {
+    fn activate(&mut self, _surface: &WlSurface, _seat: &WlSeat) {
+        self.toplevel_requests.push("activate");
+    }
+
+    fn close(&mut self, _surface: &WlSurface) {
+        self.toplevel_requests.push("close");
+    }
+
+    fn set_minimized(&mut self, _surface: &WlSurface, minimized: bool) {
+        self.toplevel_requests.push(match minimized {
+            true => "minimize",
+            false => "unminimize",
+        });
+    }
+
+    fn set_maximized(&mut self, _surface: &WlSurface, maximized: bool) {
+        self.toplevel_requests.push(match maximized {
+            true => "maximize",
+            false => "unmaximize",
+        });
+    }
+
+    fn set_fullscreen(
+        &mut self,
+        _surface: &WlSurface,
+        fullscreen: bool,
+        _output: Option<WlOutput>,
+    ) {
+        self.toplevel_requests.push(match fullscreen {
+            true => "fullscreen",
+            false => "unfullscreen",
+        });
+    }
+}
+
+impl DataDeviceHandler for TestState {
+    fn send_selection(&mut self, _seat: Entity, _mime_type: String, _fd: OwnedFd) {}
+
+    fn start_drag(&mut self, _seat: Entity) {}
+}
+
+impl PrimarySelectionHandler for TestState {
+    fn send_primary_selection(&mut self, _seat: Entity, _mime_type: String, _fd: OwnedFd) {}
+}
+
+impl DataControlHandler for TestState {}
+
+impl PointerConstraintsHandler for TestState {
+    fn cursor_position_hint(
+        &mut self,
+        _seat: Entity,
+        _surface: &WlSurface,
+        _location: Point<f64, Logical>,
+    ) {
+    }
+}
+
+impl DmabufHandler for TestState {
+    fn dmabuf_imported(&mut self, _dmabuf: &DmabufBuffer) -> Result<(), ImportError> {
+        match self.reject_dmabufs {
+            true => Err(ImportError),
+            false => Ok(()),
+        }
+    }
+}
+
+impl SessionLockHandler for TestState {
+    fn lock(&mut self, lock: Entity) {
+        self.locks.push(lock);
+    }
+
+    fn unlock(&mut self) {}
+
+    fn new_lock_surface(&mut self, _surface: &WlSurface, _output: &WlOutput) {}
+}
+
+// Outputs are implemented by the compositor, tests only need objects to pass to requests.
+impl GlobalDispatch<WlOutput, ()> for TestState {
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<WlOutput>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<WlOutput, ()> for TestState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &WlOutput,
+        _request: wl_output::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+delegate_global_dispatch!(TestState: [WlCompositor: ()] => Compositor);
+delegate_dispatch!(TestState: [WlCompositor: ()] => Compositor);
+delegate_dispatch!(TestState: [WlRegion: RegionData] => Compositor);
+delegate_dispatch!(TestState: [WlSurface: EntityData] => Compositor);
+delegate_dispatch!(TestState: [WlCallback: ()] => Compositor);
+delegate_dispatch!(TestState: [WlBuffer: EntityData] => Compositor);
+
+delegate_global_dispatch!(TestState: [WlSubcompositor: ()] => Compositor);
+delegate_dispatch!(TestState: [WlSubcompositor: ()] => Compositor);
+delegate_dispatch!(TestState: [WlSubsurface: EntityData] => Compositor);
+
+delegate_global_dispatch!(TestState: [WlShm: ()] => Shm);
+delegate_dispatch!(TestState: [WlShm: ()] => Shm);
+delegate_dispatch!(TestState: [WlShmPool: ShmPoolData] => Shm);
+
+delegate_global_dispatch!(TestState: [ZwpLinuxDmabufV1: DmabufGlobalData] => Dmabuf);
+delegate_dispatch!(TestState: [ZwpLinuxDmabufV1: DmabufGlobalData] => Dmabuf);
+delegate_dispatch!(TestState: [ZwpLinuxBufferParamsV1: DmabufParamsData] => Dmabuf);
+delegate_dispatch!(TestState: [ZwpLinuxDmabufFeedbackV1: DmabufFeedbackData] => Dmabuf);
+
+delegate_global_dispatch!(TestState: [WpLinuxDrmSyncobjManagerV1: ()] => DrmSyncobj);
+delegate_dispatch!(TestState: [WpLinuxDrmSyncobjManagerV1: ()] => DrmSyncobj);
+delegate_dispatch!(TestState: [WpLinuxDrmSyncobjSurfaceV1: EntityData] => DrmSyncobj);
+delegate_dispatch!(TestState: [WpLinuxDrmSyncobjTimelineV1: SyncTimelineData] => DrmSyncobj);
+
+delegate_global_dispatch!(TestState: [WpViewporter: ()] => Viewporter);
+delegate_dispatch!(TestState: [WpViewporter: ()] => Viewporter);
+delegate_dispatch!(TestState: [WpViewport: EntityData] => Viewporter);
+
+delegate_global_dispatch!(TestState: [WpFractionalScaleManagerV1: ()] => FractionalScaleManager);
+delegate_dispatch!(TestState: [WpFractionalScaleManagerV1: ()] => FractionalScaleManager);
+delegate_dispatch!(TestState: [WpFractionalScaleV1: EntityData] => FractionalScaleManager);
+
+delegate_global_dispatch!(TestState: [XdgWmBase: ()] => XdgShell);
+delegate_dispatch!(TestState: [XdgWmBase: ()] => XdgShell);
+delegate_dispatch!(TestState: [XdgSurface: EntityData] => XdgShell);
+delegate_dispatch!(TestState: [XdgToplevel: EntityData] => XdgShell);
+
+delegate_global_dispatch!(TestState: [ZxdgDecorationManagerV1: ()] => XdgDecoration);
+delegate_dispatch!(TestState: [ZxdgDecorationManagerV1: ()] => XdgDecoration);
+delegate_dispatch!(TestState: [ZxdgToplevelDecorationV1: EntityData] => XdgDecoration);
+
+delegate_global_dispatch!(TestState: [ZwlrLayerShellV1: ()] => LayerShell);
+delegate_dispatch!(TestState: [ZwlrLayerShellV1: ()] => LayerShell);
+delegate_dispatch!(TestState: [ZwlrLayerSurfaceV1: EntityData] => LayerShell);
+
+delegate_global_dispatch!(TestState: [ZwlrForeignToplevelManagerV1: ()] => ForeignToplevelManager);
+delegate_dispatch!(TestState: [ZwlrForeignToplevelManagerV1: EntityData] => ForeignToplevelManager);
+delegate_dispatch!(TestState: [ZwlrForeignToplevelHandleV1: EntityData] => ForeignToplevelManager);
+delegate_global_dispatch!(TestState: [ExtForeignToplevelListV1: ()] => ForeignToplevelManager);
+delegate_dispatch!(TestState: [ExtForeignToplevelListV1: EntityData] => ForeignToplevelManager);
+delegate_dispatch!(TestState: [ExtForeignToplevelHandleV1: EntityData] => ForeignToplevelManager);
+
+delegate_global_dispatch!(TestState: [WlSeat: EntityData] => Seat);
+delegate_dispatch!(TestState: [WlSeat: EntityData] => Seat);
+delegate_dispatch!(TestState: [WlPointer: EntityData] => Seat);
+delegate_dispatch!(TestState: [WlKeyboard: EntityData] => Seat);
+delegate_dispatch!(TestState: [WlTouch: EntityData] => Seat);
+
+delegate_global_dispatch!(TestState: [WlDataDeviceManager: ()] => DataDeviceManager);
+delegate_dispatch!(TestState: [WlDataDeviceManager: ()] => DataDeviceManager);
+delegate_dispatch!(TestState: [WlDataSource: EntityData] => DataDeviceManager);
+delegate_dispatch!(TestState: [WlDataDevice: EntityData] => DataDeviceManager);
+delegate_dispatch!(TestState: [WlDataOffer: EntityData] => DataDeviceManager);
+
+delegate_global_dispatch!(TestState: [ZwpPrimarySelectionDeviceManagerV1: ()] => PrimarySelectionManager);
+delegate_dispatch!(TestState: [ZwpPrimarySelectionDeviceManagerV1: ()] => PrimarySelectionManager);
+delegate_dispatch!(TestState: [ZwpPrimarySelectionSourceV1: EntityData] => PrimarySelectionManager);
+delegate_dispatch!(TestState: [ZwpPrimarySelectionDeviceV1: EntityData] => PrimarySelectionManager);
+delegate_dispatch!(TestState: [ZwpPrimarySelectionOfferV1: EntityData] => PrimarySelectionManager);
+
+delegate_global_dispatch!(TestState: [ZwlrDataControlManagerV1: ()] => DataControlManager);
+delegate_dispatch!(TestState: [ZwlrDataControlManagerV1: ()] => DataControlManager);
+delegate_dispatch!(TestState: [ZwlrDataControlSourceV1: EntityData] => DataControlManager);
+delegate_dispatch!(TestState: [ZwlrDataControlDeviceV1: EntityData] => DataControlManager);
+delegate_dispatch!(TestState: [ZwlrDataControlOfferV1: EntityData] => DataControlManager);
+
+delegate_global_dispatch!(TestState: [ZwpPointerConstraintsV1: ()] => PointerConstraintsManager);
+delegate_dispatch!(TestState: [ZwpPointerConstraintsV1: ()] => PointerConstraintsManager);
+delegate_dispatch!(TestState: [ZwpLockedPointerV1: EntityData] => PointerConstraintsManager);
+delegate_dispatch!(TestState: [ZwpConfinedPointerV1: EntityData] => PointerConstraintsManager);
+
+delegate_global_dispatch!(TestState: [ExtIdleNotifierV1: ()] => IdleNotifier);
+delegate_dispatch!(TestState: [ExtIdleNotifierV1: ()] => IdleNotifier);
+delegate_dispatch!(TestState: [ExtIdleNotificationV1: EntityData] => IdleNotifier);
+
+delegate_global_dispatch!(TestState: [ZwpTextInputManagerV3: ()] => TextInputManager);
+delegate_dispatch!(TestState: [ZwpTextInputManagerV3: ()] => TextInputManager);
+delegate_dispatch!(TestState: [ZwpTextInputV3: EntityData] => TextInputManager);
+
+delegate_global_dispatch!(TestState: [ZwpInputMethodManagerV2: ()] => InputMethodManager);
+delegate_dispatch!(TestState: [ZwpInputMethodManagerV2: ()] => InputMethodManager);
+delegate_dispatch!(TestState: [ZwpInputMethodV2: EntityData] => InputMethodManager);
+delegate_dispatch!(TestState: [ZwpInputPopupSurfaceV2: EntityData] => InputMethodManager);
+delegate_dispatch!(TestState: [ZwpInputMethodKeyboardGrabV2: EntityData] => InputMethodManager);
+
+delegate_global_dispatch!(TestState: [WpCursorShapeManagerV1: ()] => CursorShapeManager);
+delegate_dispatch!(TestState: [WpCursorShapeManagerV1: ()] => CursorShapeManager);
+delegate_dispatch!(TestState: [WpCursorShapeDeviceV1: CursorShapeDeviceData] => CursorShapeManager);
+
+delegate_global_dispatch!(TestState: [ZwpIdleInhibitManagerV1: ()] => IdleInhibitManager);
+delegate_dispatch!(TestState: [ZwpIdleInhibitManagerV1: ()] => IdleInhibitManager);
+delegate_dispatch!(TestState: [ZwpIdleInhibitorV1: EntityData] => IdleInhibitManager);
+
+delegate_global_dispatch!(TestState: [ZwpKeyboardShortcutsInhibitManagerV1: ()] => KeyboardShortcutsInhibitManager);
+delegate_dispatch!(TestState: [ZwpKeyboardShortcutsInhibitManagerV1: ()] => KeyboardShortcutsInhibitManager);
+delegate_dispatch!(TestState: [ZwpKeyboardShortcutsInhibitorV1: EntityData] => KeyboardShortcutsInhibitManager);
+
+delegate_global_dispatch!(TestState: [ExtSessionLockManagerV1: ()] => SessionLockManager);
+delegate_dispatch!(TestState: [ExtSessionLockManagerV1: ()] => SessionLockManager);
+delegate_dispatch!(TestState: [ExtSessionLockV1: EntityData] => SessionLockManager);
+delegate_dispatch!(TestState: [ExtSessionLockSurfaceV1: EntityData] => SessionLockManager);
+
+delegate_global_dispatch!(TestState: [WpPresentation: PresentationGlobalData] => Presentation);
+delegate_dispatch!(TestState: [WpPresentation: ()] => Presentation);
+delegate_dispatch!(TestState: [WpPresentationFeedback: EntityData] => Presentation);
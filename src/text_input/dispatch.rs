@@ -0,0 +1,238 @@
+use smithay::utils::Rectangle;
+use wayland_backend::server::{ClientId, ObjectId};
+use wayland_protocols::wp::text_input::zv3::server::{
+    zwp_text_input_manager_v3::{self, ZwpTextInputManagerV3},
+    zwp_text_input_v3::{self, ZwpTextInputV3},
+};
+use wayland_server::{
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource, WEnum,
+};
+
+use crate::{
+    input_method,
+    seat::{KeyboardFocus, Seat},
+    EcsAccess, EntityData,
+};
+
+use super::{
+    focus_changed, PendingTextInput, SurroundingText, TextInput, TextInputManager, TextInputState,
+};
+
+impl<State> GlobalDispatch<ZwpTextInputManagerV3, (), State> for TextInputManager
+where
+    State:
+        GlobalDispatch<ZwpTextInputManagerV3, ()> + Dispatch<ZwpTextInputManagerV3, ()> + EcsAccess,
+{
+    fn bind(
+        _state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwpTextInputManagerV3>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<State> Dispatch<ZwpTextInputManagerV3, (), State> for TextInputManager
+where
+    State: Dispatch<ZwpTextInputManagerV3, ()> + Dispatch<ZwpTextInputV3, EntityData> + EcsAccess,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        _resource: &ZwpTextInputManagerV3,
+        request: zwp_text_input_manager_v3::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zwp_text_input_manager_v3::Request::GetTextInput { id, seat } => {
+                let seat = Seat::entity_of(&seat);
+                let entity = state.ecs().world().reserve_entity();
+                let object = data_init.init(id, EntityData(entity));
+                let world = state.ecs().world();
+
+                // The text input enters the focused surface immediately if it belongs to the client.
+                let focus = world
+                    .query_one_mut::<&KeyboardFocus>(seat)
+                    .ok()
+                    .and_then(|focus| focus.surface().cloned())
+                    .filter(|surface| surface.id().same_client_as(&object.id()));
+
+                if let Some(surface) = &focus {
+                    object.enter(surface);
+                }
+
+                world
+                    .insert_one(
+                        entity,
+                        TextInput {
+                            object,
+                            seat,
+                            focus,
+                            enabled: false,
+                            commits: 0,
+                            pending: PendingTextInput::default(),
+                        },
+                    )
+                    .expect("Entity was reserved");
+
+                Seat::add_focus_system::<State>(state.ecs(), seat, focus_changed::<State>);
+            }
+
+            zwp_text_input_manager_v3::Request::Destroy => {
+                // Text inputs are unaffected by the manager being destroyed.
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<State> Dispatch<ZwpTextInputV3, EntityData, State> for TextInputManager
+where
+    State: Dispatch<ZwpTextInputV3, EntityData> + EcsAccess,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        _resource: &ZwpTextInputV3,
+        request: zwp_text_input_v3::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        let world = state.ecs().world();
+        let text_input = world
+            .query_one_mut::<&mut TextInput>(data.0)
+            .expect("Text input exists until destroyed");
+        let pending = &mut text_input.pending;
+
+        match request {
+            zwp_text_input_v3::Request::Enable => {
+                // Enabling resets all state of the text input.
+                pending.enable = Some(true);
+                pending.state = TextInputState::default();
+            }
+
+            zwp_text_input_v3::Request::Disable => {
+                pending.enable = Some(false);
+            }
+
+            zwp_text_input_v3::Request::SetSurroundingText {
+                text,
+                cursor,
+                anchor,
+            } => {
+                pending.state.surrounding_text = Some(SurroundingText {
+                    text,
+                    cursor,
+                    anchor,
+                });
+            }
+
+            zwp_text_input_v3::Request::SetTextChangeCause { cause } => {
+                if let WEnum::Value(cause) = cause {
+                    pending.state.change_cause = cause;
+                }
+            }
+
+            zwp_text_input_v3::Request::SetContentType { hint, purpose } => {
+                if let (WEnum::Value(hint), WEnum::Value(purpose)) = (hint, purpose) {
+                    pending.state.content_hint = hint;
+                    pending.state.content_purpose = purpose;
+                }
+            }
+
+            zwp_text_input_v3::Request::SetCursorRectangle {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                pending.state.cursor_rectangle =
+                    Some(Rectangle::from_loc_and_size((x, y), (width, height)));
+            }
+
+            zwp_text_input_v3::Request::Commit => {
+                text_input.commits = text_input.commits.wrapping_add(1);
+
+                let seat = text_input.seat;
+                let was_enabled = text_input.enabled;
+                let enable = text_input.pending.enable.take();
+                let committed = text_input.pending.state.clone();
+                let surface = text_input
+                    .focus
+                    .as_ref()
+                    .map(|surface| surface.data::<EntityData>().unwrap().0);
+
+                let surface = match surface {
+                    Some(surface) => surface,
+                    // Text inputs without focus cannot be enabled.
+                    None => return,
+                };
+
+                let mut enabled = enable.unwrap_or(was_enabled);
+
+                // Only one text input of a seat can be enabled at a time, enabling another one is ignored.
+                if enabled && !was_enabled {
+                    enabled =
+                        !world
+                            .query_mut::<&TextInput>()
+                            .into_iter()
+                            .any(|(entity, text_input)| {
+                                entity != data.0 && text_input.seat == seat && text_input.enabled
+                            });
+                }
+
+                world
+                    .query_one_mut::<&mut TextInput>(data.0)
+                    .expect("Text input exists until destroyed")
+                    .enabled = enabled;
+
+                match (was_enabled, enabled) {
+                    (false, false) => (),
+
+                    (true, false) => {
+                        let _ = world.remove_one::<TextInputState>(surface);
+                        input_method::deactivate(world, seat);
+                    }
+
+                    (was_enabled, true) => {
+                        world
+                            .insert_one(surface, committed.clone())
+                            .expect("Surface exists while focused");
+
+                        if was_enabled {
+                            input_method::send_state(world, seat, &committed);
+                        } else {
+                            input_method::activate(world, seat, &committed);
+                        }
+                    }
+                }
+            }
+
+            zwp_text_input_v3::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        let world = state.ecs().world();
+
+        if let Ok(mut text_input) = world.remove_one::<TextInput>(data.0) {
+            if let Some(surface) = text_input.disable() {
+                let _ = world.remove_one::<TextInputState>(surface);
+                input_method::deactivate(world, text_input.seat);
+            }
+        }
+
+        let _ = world.despawn(data.0);
+    }
+}
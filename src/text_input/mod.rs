@@ -0,0 +1,206 @@
+//! Implementation of the text input protocol.
+//!
+//! Text inputs let clients receive text from an [input method](crate::input_method) instead of key events, which
+//! is needed to enter text in languages with more characters than keys. Every `zwp_text_input_v3` is an entity
+//! with a [`TextInput`] belonging to a [seat](crate::seat) entity. A text input enters the surface which has the
+//! keyboard focus of its seat if the surface belongs to the same client.
+//!
+//! # Text input state
+//!
+//! The surrounding text, content type and cursor rectangle are double-buffered state of the text input which is
+//! applied by `zwp_text_input_v3.commit`. While a text input is enabled, its committed state is available as a
+//! [`TextInputState`] which can be queried from the [`WlSurface`] the text input entered. The state is removed
+//! once the text input is disabled or leaves the surface.
+//!
+//! The committed state is forwarded to the input method of the seat, and the text the input method produces is
+//! sent to the enabled text input of the focused surface.
+
+mod dispatch;
+#[cfg(test)]
+mod tests;
+
+use std::mem;
+
+use hecs::Entity;
+use smithay::utils::{Logical, Rectangle};
+use wayland_protocols::wp::text_input::zv3::server::{
+    zwp_text_input_manager_v3::ZwpTextInputManagerV3,
+    zwp_text_input_v3::{ChangeCause, ContentHint, ContentPurpose, ZwpTextInputV3},
+};
+use wayland_server::{protocol::wl_surface::WlSurface, DisplayHandle, GlobalDispatch, Resource};
+
+use crate::{input_method, seat::KeyboardFocus, EcsAccess, EntityData};
+
+pub struct TextInputManager {}
+
+impl TextInputManager {
+    pub fn new<State>(display: &mut DisplayHandle) -> Self
+    where
+        State: GlobalDispatch<ZwpTextInputManagerV3, ()> + EcsAccess,
+    {
+        let _global = display.create_global::<State, ZwpTextInputManagerV3, ()>(1, ());
+        Self {}
+    }
+}
+
+/// The committed state of the enabled text input of a [`WlSurface`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextInputState {
+    pub(crate) surrounding_text: Option<SurroundingText>,
+    pub(crate) change_cause: ChangeCause,
+    pub(crate) content_hint: ContentHint,
+    pub(crate) content_purpose: ContentPurpose,
+    pub(crate) cursor_rectangle: Option<Rectangle<i32, Logical>>,
+}
+
+impl Default for TextInputState {
+    fn default() -> Self {
+        Self {
+            surrounding_text: None,
+            change_cause: ChangeCause::InputMethod,
+            content_hint: ContentHint::None,
+            content_purpose: ContentPurpose::Normal,
+            cursor_rectangle: None,
+        }
+    }
+}
+
+impl TextInputState {
+    /// The text around the cursor, or [`None`] if the client does not support sending it.
+    pub fn surrounding_text(&self) -> Option<&SurroundingText> {
+        self.surrounding_text.as_ref()
+    }
+
+    /// What caused the last change of the surrounding text.
+    pub fn change_cause(&self) -> ChangeCause {
+        self.change_cause
+    }
+
+    pub fn content_hint(&self) -> ContentHint {
+        self.content_hint
+    }
+
+    pub fn content_purpose(&self) -> ContentPurpose {
+        self.content_purpose
+    }
+
+    /// The rectangle of the cursor in surface-local coordinates.
+    ///
+    /// Input method popups are placed relative to this rectangle.
+    pub fn cursor_rectangle(&self) -> Option<Rectangle<i32, Logical>> {
+        self.cursor_rectangle
+    }
+}
+
+/// The text around the cursor of a text input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SurroundingText {
+    pub text: String,
+    /// The byte offset of the cursor in the text.
+    pub cursor: i32,
+    /// The byte offset of the other end of the selection, which is the cursor if nothing is selected.
+    pub anchor: i32,
+}
+
+/// A text input of a client.
+#[derive(Debug)]
+pub struct TextInput {
+    pub(crate) object: ZwpTextInputV3,
+    pub(crate) seat: Entity,
+    /// The surface the text input entered.
+    pub(crate) focus: Option<WlSurface>,
+    pub(crate) enabled: bool,
+    /// The number of commit requests, which is used as the serial of done events.
+    pub(crate) commits: u32,
+    pending: PendingTextInput,
+}
+
+impl TextInput {
+    /// The seat entity of the text input.
+    pub fn seat(&self) -> Entity {
+        self.seat
+    }
+
+    /// Returns whether the client enabled the text input.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Disables the text input.
+    ///
+    /// Returns the surface entity whose [`TextInputState`] must be removed if the text input was enabled.
+    fn disable(&mut self) -> Option<Entity> {
+        if !mem::take(&mut self.enabled) {
+            return None;
+        }
+
+        self.focus
+            .as_ref()
+            .map(|surface| surface.data::<EntityData>().unwrap().0)
+    }
+}
+
+#[derive(Debug, Default)]
+struct PendingTextInput {
+    /// An enable or disable request which was not committed yet.
+    enable: Option<bool>,
+    state: TextInputState,
+}
+
+/// Returns the enabled text input of the surface which has the keyboard focus of a seat.
+pub(crate) fn active_text_input(world: &mut hecs::World, seat: Entity) -> Option<Entity> {
+    let focus = world
+        .query_one_mut::<&KeyboardFocus>(seat)
+        .ok()?
+        .surface()
+        .cloned()?;
+
+    world
+        .query_mut::<&TextInput>()
+        .into_iter()
+        .find(|(_, text_input)| {
+            text_input.seat == seat
+                && text_input.enabled
+                && text_input.focus.as_ref() == Some(&focus)
+        })
+        .map(|(entity, _)| entity)
+}
+
+/// Moves the text inputs of a seat to the surface which has keyboard focus.
+fn focus_changed<State: EcsAccess>(state: &mut State, _display: &DisplayHandle, seat: Entity) {
+    let world = state.ecs().world();
+    let focus = world
+        .query_one_mut::<&KeyboardFocus>(seat)
+        .ok()
+        .and_then(|focus| focus.surface().cloned());
+
+    let mut disabled = Vec::new();
+
+    for (_, text_input) in world
+        .query_mut::<&mut TextInput>()
+        .into_iter()
+        .filter(|(_, text_input)| text_input.seat == seat && text_input.focus != focus)
+    {
+        // Leaving a surface implicitly disables the text input.
+        disabled.extend(text_input.disable());
+
+        if let Some(previous) = text_input.focus.take() {
+            text_input.object.leave(&previous);
+        }
+
+        if let Some(surface) = &focus {
+            if surface.id().same_client_as(&text_input.object.id()) {
+                text_input.object.enter(surface);
+                text_input.focus = Some(surface.clone());
+            }
+        }
+    }
+
+    for surface in &disabled {
+        let _ = world.remove_one::<TextInputState>(*surface);
+    }
+
+    if !disabled.is_empty() {
+        input_method::deactivate(world, seat);
+    }
+}
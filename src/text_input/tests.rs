@@ -0,0 +1,181 @@
+use crate::testing::{Arg, Fixture, TestSurface};
+
+use super::TextInputState;
+
+// zwp_text_input_manager_v3 requests
+const GET_TEXT_INPUT: u16 = 1;
+
+// zwp_text_input_v3 requests
+const ENABLE: u16 = 1;
+const DISABLE: u16 = 2;
+const SET_SURROUNDING_TEXT: u16 = 3;
+const COMMIT: u16 = 7;
+
+// zwp_text_input_v3 events
+const ENTER: u16 = 0;
+const LEAVE: u16 = 1;
+const COMMIT_STRING: u16 = 3;
+const DONE: u16 = 5;
+
+// zwp_input_method_manager_v2 requests
+const GET_INPUT_METHOD: u16 = 0;
+
+// zwp_input_method_v2 requests
+const IM_COMMIT_STRING: u16 = 0;
+const IM_COMMIT: u16 = 3;
+
+fn get_text_input(fixture: &mut Fixture) -> u32 {
+    let manager = fixture.client.bind("zwp_text_input_manager_v3", 1);
+    let seat = fixture.client.bind("wl_seat", 7);
+    let text_input = fixture.client.new_id();
+    fixture.send(
+        manager,
+        GET_TEXT_INPUT,
+        &[Arg::NewId(text_input), Arg::Object(seat)],
+    );
+    fixture.assert_no_error();
+    text_input
+}
+
+fn enable(fixture: &mut Fixture, text_input: u32) {
+    fixture.send(text_input, ENABLE, &[]);
+    fixture.send(text_input, COMMIT, &[]);
+    fixture.assert_no_error();
+}
+
+fn has_state(fixture: &mut Fixture, surface: &TestSurface) -> bool {
+    fixture
+        .world()
+        .query_one_mut::<&TextInputState>(surface.entity)
+        .is_ok()
+}
+
+/// The enter and leave events of a text input, with the id of the surface.
+fn focus_events(fixture: &mut Fixture, text_input: u32) -> Vec<(u16, u32)> {
+    fixture
+        .events(text_input)
+        .into_iter()
+        .filter(|event| matches!(event.opcode, ENTER | LEAVE))
+        .map(|event| (event.opcode, event.uint(0)))
+        .collect()
+}
+
+#[test]
+fn text_input_follows_keyboard_focus() {
+    let mut fixture = Fixture::new();
+    let first = fixture.create_surface();
+    let second = fixture.create_surface();
+    let text_input = get_text_input(&mut fixture);
+
+    fixture.set_keyboard_focus(Some(&first));
+    assert_eq!(
+        focus_events(&mut fixture, text_input),
+        vec![(ENTER, first.id)]
+    );
+
+    fixture.set_keyboard_focus(Some(&second));
+    assert_eq!(
+        focus_events(&mut fixture, text_input),
+        vec![(LEAVE, first.id), (ENTER, second.id)]
+    );
+
+    fixture.set_keyboard_focus(None);
+    assert_eq!(
+        focus_events(&mut fixture, text_input),
+        vec![(LEAVE, second.id)]
+    );
+}
+
+#[test]
+fn text_input_enters_focused_surface_on_creation() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    fixture.set_keyboard_focus(Some(&surface));
+
+    let text_input = get_text_input(&mut fixture);
+    assert_eq!(
+        focus_events(&mut fixture, text_input),
+        vec![(ENTER, surface.id)]
+    );
+}
+
+#[test]
+fn state_is_applied_on_commit_and_removed_on_leave() {
+    let mut fixture = Fixture::new();
+    let first = fixture.create_surface();
+    let second = fixture.create_surface();
+    let text_input = get_text_input(&mut fixture);
+    fixture.set_keyboard_focus(Some(&first));
+
+    fixture.send(text_input, ENABLE, &[]);
+    fixture.send(
+        text_input,
+        SET_SURROUNDING_TEXT,
+        &[Arg::Str("hello"), Arg::Int(5), Arg::Int(5)],
+    );
+    fixture.dispatch();
+    assert!(!has_state(&mut fixture, &first));
+
+    fixture.send(text_input, COMMIT, &[]);
+    fixture.assert_no_error();
+    let text = fixture
+        .world()
+        .query_one_mut::<&TextInputState>(first.entity)
+        .unwrap()
+        .surrounding_text()
+        .map(|text| text.text.clone());
+    assert_eq!(text.as_deref(), Some("hello"));
+
+    // Leaving the surface disables the text input.
+    fixture.set_keyboard_focus(Some(&second));
+    assert!(!has_state(&mut fixture, &first));
+    assert!(!has_state(&mut fixture, &second));
+}
+
+#[test]
+fn disable_removes_state() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    let text_input = get_text_input(&mut fixture);
+    fixture.set_keyboard_focus(Some(&surface));
+    enable(&mut fixture, text_input);
+    assert!(has_state(&mut fixture, &surface));
+
+    fixture.send(text_input, DISABLE, &[]);
+    fixture.send(text_input, COMMIT, &[]);
+    fixture.assert_no_error();
+    assert!(!has_state(&mut fixture, &surface));
+}
+
+#[test]
+fn done_carries_number_of_commits() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    let text_input = get_text_input(&mut fixture);
+    fixture.set_keyboard_focus(Some(&surface));
+
+    let manager = fixture.client.bind("zwp_input_method_manager_v2", 1);
+    let seat = fixture.client.bind("wl_seat", 7);
+    let input_method = fixture.client.new_id();
+    fixture.send(
+        manager,
+        GET_INPUT_METHOD,
+        &[Arg::Object(seat), Arg::NewId(input_method)],
+    );
+
+    // Enabling is the first commit, updating the state the second.
+    enable(&mut fixture, text_input);
+    fixture.send(text_input, COMMIT, &[]);
+    fixture.events(text_input);
+
+    fixture.send(input_method, IM_COMMIT_STRING, &[Arg::Str("é")]);
+    fixture.send(input_method, IM_COMMIT, &[Arg::Uint(2)]);
+
+    let events = fixture.events(text_input);
+    assert_eq!(events.len(), 2);
+    assert_eq!(
+        (events[0].opcode, events[0].string(0)),
+        (COMMIT_STRING, "é")
+    );
+    assert_eq!((events[1].opcode, events[1].uint(0)), (DONE, 2));
+}
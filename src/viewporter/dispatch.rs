@@ -0,0 +1,189 @@
+use smithay::utils::Rectangle;
+use wayland_backend::server::{ClientId, ObjectId};
+use wayland_protocols::wp::viewporter::server::{
+    wp_viewport::{self, WpViewport},
+    wp_viewporter::{self, WpViewporter},
+};
+use wayland_server::{
+    protocol::wl_surface::WlSurface, Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch,
+    New, Resource,
+};
+
+use crate::{
+    compositor::{Compositor, CompositorHandler},
+    EntityData,
+};
+
+use super::{pre_commit, ViewportObject, Viewporter};
+
+impl<State> GlobalDispatch<WpViewporter, (), State> for Viewporter
+where
+    State: GlobalDispatch<WpViewporter, ()> + Dispatch<WpViewporter, ()> + CompositorHandler,
+{
+    fn bind(
+        _state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<WpViewporter>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<State> Dispatch<WpViewporter, (), State> for Viewporter
+where
+    State: Dispatch<WpViewporter, ()> + Dispatch<WpViewport, EntityData> + CompositorHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        viewporter: &WpViewporter,
+        request: wp_viewporter::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            wp_viewporter::Request::Destroy => {
+                // wp_viewport objects are unaffected by the global being destroyed.
+            }
+
+            wp_viewporter::Request::GetViewport { id, surface } => {
+                let entity = surface.data::<EntityData>().unwrap().0;
+                let world = state.ecs().world();
+
+                match world.query_one_mut::<&ViewportObject>(entity) {
+                    Ok(ViewportObject(Some(_))) => {
+                        viewporter.post_error(
+                            wp_viewporter::Error::ViewportExists,
+                            "Surface already has a viewport",
+                        );
+                        return;
+                    }
+
+                    Ok(ViewportObject(None)) => (),
+
+                    // The commit system is only added the first time, since it outlives the viewport.
+                    Err(_) => {
+                        world
+                            .insert_one(entity, ViewportObject::default())
+                            .expect("Surface must be a valid entity if dispatched");
+                        Compositor::add_pre_commit::<State>(
+                            state.ecs(),
+                            &surface,
+                            pre_commit::<State>,
+                        );
+                    }
+                }
+
+                let viewport = data_init.init(id, EntityData(entity));
+                let object = state
+                    .ecs()
+                    .world()
+                    .query_one_mut::<&mut ViewportObject>(entity)
+                    .unwrap();
+                object.0 = Some(viewport);
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<State> Dispatch<WpViewport, EntityData, State> for Viewporter
+where
+    State: Dispatch<WpViewport, EntityData> + CompositorHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        viewport: &WpViewport,
+        request: wp_viewport::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        if let wp_viewport::Request::Destroy = request {
+            // handled by Dispatch::destroyed
+            return;
+        }
+
+        let surface = state
+            .ecs()
+            .world()
+            .query_one_mut::<&WlSurface>(data.0)
+            .expect("Surface must be a valid entity if dispatched")
+            .clone();
+
+        if !surface.is_alive() {
+            viewport.post_error(
+                wp_viewport::Error::NoSurface,
+                "The surface of the viewport was destroyed",
+            );
+            return;
+        }
+
+        match request {
+            wp_viewport::Request::SetSource {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let source = if x == -1.0 && y == -1.0 && width == -1.0 && height == -1.0 {
+                    None
+                } else if x < 0.0 || y < 0.0 || width <= 0.0 || height <= 0.0 {
+                    viewport.post_error(
+                        wp_viewport::Error::BadValue,
+                        format!("Invalid source rectangle ({x}, {y}, {width}x{height})"),
+                    );
+                    return;
+                } else {
+                    Some(Rectangle::from_loc_and_size((x, y), (width, height)))
+                };
+
+                Compositor::pending_viewport::<State>(state.ecs(), &surface).source = source;
+            }
+
+            wp_viewport::Request::SetDestination { width, height } => {
+                let destination = if width == -1 && height == -1 {
+                    None
+                } else if width <= 0 || height <= 0 {
+                    viewport.post_error(
+                        wp_viewport::Error::BadValue,
+                        format!("Invalid destination size {width}x{height}"),
+                    );
+                    return;
+                } else {
+                    Some((width, height).into())
+                };
+
+                Compositor::pending_viewport::<State>(state.ecs(), &surface).destination =
+                    destination;
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        let (surface, object) = match state
+            .ecs()
+            .world()
+            .query_one_mut::<(&WlSurface, &mut ViewportObject)>(data.0)
+        {
+            Ok(components) => components,
+            Err(_) => return,
+        };
+        object.0 = None;
+
+        // Quoting wp_viewport::destroy:
+        // > The wl_surface.commit request will apply the removal of the viewport from the wl_surface.
+        if surface.is_alive() {
+            let surface = surface.clone();
+            *Compositor::pending_viewport::<State>(state.ecs(), &surface) = Default::default();
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Implementation of the viewporter protocol.
+//!
+//! A viewport crops the buffer of a surface to a source rectangle and scales the result to a destination size,
+//! decoupling the size of the surface from the size of the buffer.
+//!
+//! The viewport is double-buffered state of the surface. Once committed, it is available as a [`Viewport`]
+//! which can always be queried from a [`WlSurface`]. Surfaces without a `wp_viewport` have a viewport with
+//! neither a source rectangle nor a destination size.
+//!
+//! The committed viewport is taken into account when the [`SurfaceDamage`](crate::compositor::SurfaceDamage)
+//! is converted into buffer coordinates and by [`Compositor::surface_size`].
+
+mod dispatch;
+#[cfg(test)]
+mod tests;
+
+use smithay::utils::{Logical, Rectangle, Size};
+use wayland_protocols::wp::viewporter::server::{
+    wp_viewport::{self, WpViewport},
+    wp_viewporter::WpViewporter,
+};
+use wayland_server::{protocol::wl_surface::WlSurface, DisplayHandle, GlobalDispatch, Resource};
+
+use crate::{compositor::Compositor, compositor::CompositorHandler, EntityData};
+
+pub struct Viewporter {}
+
+impl Viewporter {
+    pub fn new<State>(display: &mut DisplayHandle) -> Self
+    where
+        State: GlobalDispatch<WpViewporter, ()> + CompositorHandler,
+    {
+        let _global = display.create_global::<State, WpViewporter, ()>(1, ());
+        Self {}
+    }
+}
+
+/// The viewport of a [`WlSurface`].
+///
+/// This can always be queried if the surface is alive.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub(crate) source: Option<Rectangle<f64, Logical>>,
+    pub(crate) destination: Option<Size<i32, Logical>>,
+}
+
+impl Viewport {
+    /// The rectangle of the buffer which is shown, in surface-local coordinates before the viewport is applied.
+    ///
+    /// Returns [`None`] if the whole buffer is shown.
+    pub fn source(&self) -> Option<Rectangle<f64, Logical>> {
+        self.source
+    }
+
+    /// The size the source rectangle is scaled to.
+    ///
+    /// Returns [`None`] if the source rectangle is not scaled.
+    pub fn destination(&self) -> Option<Size<i32, Logical>> {
+        self.destination
+    }
+
+    /// Returns whether the viewport changes how the buffer is shown.
+    pub fn is_set(&self) -> bool {
+        self.source.is_some() || self.destination.is_some()
+    }
+
+    /// The size of the surface after the viewport is applied.
+    ///
+    /// `buffer_size` is the size of the buffer in surface-local coordinates, after the buffer scale and
+    /// transform are applied.
+    pub fn surface_size(&self, buffer_size: Size<i32, Logical>) -> Size<i32, Logical> {
+        match (self.destination, self.source) {
+            (Some(destination), _) => destination,
+            (None, Some(source)) => (source.size.w as i32, source.size.h as i32).into(),
+            (None, None) => buffer_size,
+        }
+    }
+
+    /// Maps a rectangle of the surface into the buffer, in surface-local coordinates before the viewport is
+    /// applied.
+    ///
+    /// Returns the corners of the rectangle, which may have fractional coordinates.
+    pub(crate) fn surface_to_source(
+        &self,
+        rect: Rectangle<i32, Logical>,
+        buffer_size: Size<i32, Logical>,
+    ) -> ((f64, f64), (f64, f64)) {
+        let source = self.source.unwrap_or_else(|| {
+            Rectangle::from_loc_and_size((0.0, 0.0), (buffer_size.w as f64, buffer_size.h as f64))
+        });
+        let destination = self.surface_size(buffer_size);
+
+        let scale_x = source.size.w / destination.w as f64;
+        let scale_y = source.size.h / destination.h as f64;
+
+        let x1 = source.loc.x + rect.loc.x as f64 * scale_x;
+        let y1 = source.loc.y + rect.loc.y as f64 * scale_y;
+        let x2 = source.loc.x + (rect.loc.x + rect.size.w) as f64 * scale_x;
+        let y2 = source.loc.y + (rect.loc.y + rect.size.h) as f64 * scale_y;
+
+        ((x1, y1), (x2, y2))
+    }
+}
+
+/// The `wp_viewport` of a surface.
+///
+/// This is inserted together with the commit system and is never removed.
+#[derive(Debug, Default)]
+struct ViewportObject(Option<WpViewport>);
+
+/// Validates the pending viewport against the buffer which will be attached.
+fn pre_commit<State: CompositorHandler>(state: &mut State, surface: &WlSurface) {
+    let entity = surface.data::<EntityData>().unwrap().0;
+
+    let object = match state
+        .ecs()
+        .world()
+        .query_one_mut::<&ViewportObject>(entity)
+        .ok()
+        .and_then(|object| object.0.clone())
+    {
+        Some(object) => object,
+        // The pending viewport is reset when the object is destroyed.
+        None => return,
+    };
+
+    let viewport = *Compositor::pending_viewport::<State>(state.ecs(), surface);
+
+    if let Some(source) = viewport.source {
+        if viewport.destination.is_none()
+            && (source.size.w.fract() != 0.0 || source.size.h.fract() != 0.0)
+        {
+            object.post_error(
+                wp_viewport::Error::BadSize,
+                "Source size must be integer if no destination size is set",
+            );
+            Compositor::discard_commit::<State>(state.ecs(), surface);
+            return;
+        }
+
+        if let Some(size) = Compositor::pending_buffer_size::<State>(state.ecs(), surface) {
+            if source.loc.x + source.size.w > size.w as f64
+                || source.loc.y + source.size.h > size.h as f64
+            {
+                object.post_error(
+                    wp_viewport::Error::OutOfBuffer,
+                    format!(
+                        "Source rectangle ({}, {}, {}x{}) extends outside of the buffer ({}x{})",
+                        source.loc.x, source.loc.y, source.size.w, source.size.h, size.w, size.h
+                    ),
+                );
+                Compositor::discard_commit::<State>(state.ecs(), surface);
+            }
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use wayland_protocols::wp::viewporter::server::wp_viewport;
+use wayland_server::protocol::wl_output::Transform;
+
+use crate::testing::{Arg, Fixture, TestSurface};
+
+use super::Viewport;
+
+// wl_surface requests
+const DESTROY: u16 = 0;
+const COMMIT: u16 = 6;
+const SET_BUFFER_TRANSFORM: u16 = 7;
+const SET_BUFFER_SCALE: u16 = 8;
+
+// wp_viewporter requests
+const GET_VIEWPORT: u16 = 1;
+
+// wp_viewport requests
+const SET_SOURCE: u16 = 1;
+const SET_DESTINATION: u16 = 2;
+
+fn get_viewport(fixture: &mut Fixture, surface: &TestSurface) -> u32 {
+    let viewporter = fixture.client.bind("wp_viewporter", 1);
+    let id = fixture.client.new_id();
+    fixture.send(
+        viewporter,
+        GET_VIEWPORT,
+        &[Arg::NewId(id), Arg::Object(surface.id)],
+    );
+    fixture.assert_no_error();
+    id
+}
+
+/// Sends a source rectangle, with each value converted to wl_fixed.
+fn set_source(fixture: &mut Fixture, viewport: u32, x: f64, y: f64, width: f64, height: f64) {
+    let fixed = |value: f64| Arg::Int((value * 256.0) as i32);
+    fixture.send(
+        viewport,
+        SET_SOURCE,
+        &[fixed(x), fixed(y), fixed(width), fixed(height)],
+    );
+}
+
+fn viewport(fixture: &mut Fixture, surface: &TestSurface) -> Viewport {
+    *fixture
+        .world()
+        .query_one_mut::<&Viewport>(surface.entity)
+        .unwrap()
+}
+
+#[test]
+fn viewport_is_applied_on_commit() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    let object = get_viewport(&mut fixture, &surface);
+
+    fixture.send(object, SET_DESTINATION, &[Arg::Int(20), Arg::Int(10)]);
+    fixture.dispatch();
+    assert!(!viewport(&mut fixture, &surface).is_set());
+
+    fixture.commit(surface.id);
+    assert_eq!(
+        viewport(&mut fixture, &surface).destination(),
+        Some((20, 10).into())
+    );
+}
+
+#[test]
+fn negative_source_is_bad_value() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    let viewport = get_viewport(&mut fixture, &surface);
+
+    set_source(&mut fixture, viewport, -1.0, 0.0, 4.0, 4.0);
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(wp_viewport::Error::BadValue as u32)
+    );
+}
+
+#[test]
+fn empty_destination_is_bad_value() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    let viewport = get_viewport(&mut fixture, &surface);
+
+    fixture.send(viewport, SET_DESTINATION, &[Arg::Int(0), Arg::Int(10)]);
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(wp_viewport::Error::BadValue as u32)
+    );
+}
+
+#[test]
+fn fractional_source_without_destination_is_bad_size() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    let viewport = get_viewport(&mut fixture, &surface);
+
+    set_source(&mut fixture, viewport, 0.0, 0.0, 2.5, 2.0);
+    fixture.send(surface.id, COMMIT, &[]);
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(wp_viewport::Error::BadSize as u32)
+    );
+}
+
+#[test]
+fn fractional_source_with_destination_is_allowed() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    let viewport = get_viewport(&mut fixture, &surface);
+
+    set_source(&mut fixture, viewport, 0.0, 0.0, 2.5, 2.0);
+    fixture.send(viewport, SET_DESTINATION, &[Arg::Int(5), Arg::Int(4)]);
+    fixture.commit(surface.id);
+}
+
+#[test]
+fn source_outside_of_transformed_buffer_is_out_of_buffer() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    let viewport = get_viewport(&mut fixture, &surface);
+    let mut pool = fixture.create_pool(8 * 4 * 4);
+    let buffer = fixture.create_buffer(&mut pool, 8, 4);
+
+    // The 8x4 buffer is 4x2 at a scale of 2, which is 2x4 once rotated.
+    fixture.attach(surface.id, buffer);
+    fixture.send(surface.id, SET_BUFFER_SCALE, &[Arg::Int(2)]);
+    fixture.send(
+        surface.id,
+        SET_BUFFER_TRANSFORM,
+        &[Arg::Int(Transform::_90 as i32)],
+    );
+    set_source(&mut fixture, viewport, 0.0, 0.0, 2.0, 4.0);
+    fixture.commit(surface.id);
+
+    // The same source rectangle fits the size of the buffer in pixels, but not the rotated surface.
+    set_source(&mut fixture, viewport, 0.0, 0.0, 4.0, 2.0);
+    fixture.send(surface.id, COMMIT, &[]);
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(wp_viewport::Error::OutOfBuffer as u32)
+    );
+}
+
+#[test]
+fn request_after_surface_is_destroyed_is_no_surface() {
+    let mut fixture = Fixture::new();
+    let surface = fixture.create_surface();
+    let viewport = get_viewport(&mut fixture, &surface);
+
+    fixture.send(surface.id, DESTROY, &[]);
+    fixture.send(viewport, SET_DESTINATION, &[Arg::Int(20), Arg::Int(10)]);
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(wp_viewport::Error::NoSurface as u32)
+    );
+}
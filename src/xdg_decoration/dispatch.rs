@@ -0,0 +1,149 @@
+use wayland_backend::server::{ClientId, ObjectId};
+use wayland_protocols::xdg::decoration::zv1::server::{
+    zxdg_decoration_manager_v1::{self, ZxdgDecorationManagerV1},
+    zxdg_toplevel_decoration_v1::{self, ZxdgToplevelDecorationV1},
+};
+use wayland_server::{
+    protocol::wl_surface::WlSurface, Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch,
+    New, Resource, WEnum,
+};
+
+use crate::{compositor::Buffer, EntityData};
+
+use super::{DecorationMode, XdgDecoration, XdgDecorationHandler};
+
+impl<State> GlobalDispatch<ZxdgDecorationManagerV1, (), State> for XdgDecoration
+where
+    State: GlobalDispatch<ZxdgDecorationManagerV1, ()>
+        + Dispatch<ZxdgDecorationManagerV1, ()>
+        + XdgDecorationHandler,
+{
+    fn bind(
+        _state: &mut State,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZxdgDecorationManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<State> Dispatch<ZxdgDecorationManagerV1, (), State> for XdgDecoration
+where
+    State: Dispatch<ZxdgDecorationManagerV1, ()>
+        + Dispatch<ZxdgToplevelDecorationV1, EntityData>
+        + XdgDecorationHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        _resource: &ZxdgDecorationManagerV1,
+        request: zxdg_decoration_manager_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, State>,
+    ) {
+        match request {
+            zxdg_decoration_manager_v1::Request::Destroy => {
+                // Decoration objects are unaffected by the global being destroyed.
+            }
+
+            zxdg_decoration_manager_v1::Request::GetToplevelDecoration { id, toplevel } => {
+                let entity = toplevel.data::<EntityData>().unwrap().0;
+                let decoration = data_init.init(id, EntityData(entity));
+                let world = state.ecs().world();
+
+                if world.query_one_mut::<&DecorationMode>(entity).is_ok() {
+                    decoration.post_error(
+                        zxdg_toplevel_decoration_v1::Error::AlreadyConstructed,
+                        "Toplevel already has a decoration object",
+                    );
+                    return;
+                }
+
+                let buffer = world
+                    .query_one_mut::<&Buffer>(entity)
+                    .expect("Surface must be a valid entity if dispatched");
+
+                if buffer.current_buffer().is_some() {
+                    decoration.post_error(
+                        zxdg_toplevel_decoration_v1::Error::UnconfiguredBuffer,
+                        "Toplevel already has a buffer attached",
+                    );
+                    return;
+                }
+
+                world
+                    .insert_one(
+                        entity,
+                        DecorationMode {
+                            object: decoration,
+                            requested: None,
+                            mode: None,
+                        },
+                    )
+                    .expect("Surface must be a valid entity if dispatched");
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<State> Dispatch<ZxdgToplevelDecorationV1, EntityData, State> for XdgDecoration
+where
+    State: Dispatch<ZxdgToplevelDecorationV1, EntityData> + XdgDecorationHandler,
+{
+    fn request(
+        state: &mut State,
+        _client: &Client,
+        _resource: &ZxdgToplevelDecorationV1,
+        request: zxdg_toplevel_decoration_v1::Request,
+        data: &EntityData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, State>,
+    ) {
+        let requested = match request {
+            zxdg_toplevel_decoration_v1::Request::Destroy => {
+                // handled by Dispatch::destroyed
+                return;
+            }
+
+            zxdg_toplevel_decoration_v1::Request::SetMode { mode } => match mode {
+                WEnum::Value(mode) => Some(mode),
+                // Unknown modes are treated as having no preference.
+                WEnum::Unknown(_) => None,
+            },
+
+            zxdg_toplevel_decoration_v1::Request::UnsetMode => None,
+
+            _ => unreachable!(),
+        };
+
+        let (surface, decoration) = state
+            .ecs()
+            .world()
+            .query_one_mut::<(&WlSurface, &mut DecorationMode)>(data.0)
+            .expect("Decoration exists until destroyed");
+        decoration.requested = requested;
+
+        let surface = surface.clone();
+        state.request_mode(&surface, requested);
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, resource: ObjectId, data: &EntityData) {
+        // A duplicate decoration object which caused a protocol error does not own the component.
+        let owned = state
+            .ecs()
+            .world()
+            .query_one_mut::<&DecorationMode>(data.0)
+            .map(|decoration| decoration.object.id() == resource)
+            .unwrap_or(false);
+
+        if owned {
+            let _ = state.ecs().world().remove_one::<DecorationMode>(data.0);
+        }
+    }
+}
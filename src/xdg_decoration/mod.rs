@@ -0,0 +1,86 @@
+//! Implementation of the xdg-decoration protocol.
+//!
+//! Clients use this protocol to negotiate whether the compositor draws the decorations of a toplevel. The
+//! decoration state of a toplevel is stored in a [`DecorationMode`], which can be queried from the
+//! [`WlSurface`] of the toplevel once the client created a decoration object.
+//!
+//! Whenever the client requests a mode, [`XdgDecorationHandler::request_mode`] is called. The compositor
+//! responds by calling [`XdgDecoration::set_mode`], which may also be used at any other time to force a mode.
+//! The mode is sent as part of a configure of the toplevel.
+
+mod dispatch;
+#[cfg(test)]
+mod tests;
+
+use wayland_protocols::xdg::decoration::zv1::server::{
+    zxdg_decoration_manager_v1::ZxdgDecorationManagerV1,
+    zxdg_toplevel_decoration_v1::{self, ZxdgToplevelDecorationV1},
+};
+use wayland_server::{protocol::wl_surface::WlSurface, DisplayHandle, GlobalDispatch, Resource};
+
+use crate::{
+    xdg_shell::{XdgShell, XdgShellHandler},
+    Ecs, EntityData,
+};
+
+pub use zxdg_toplevel_decoration_v1::Mode;
+
+pub trait XdgDecorationHandler: XdgShellHandler {
+    /// The client requested a decoration mode for a toplevel.
+    ///
+    /// [`None`] means the client has no preference. The compositor must respond by calling
+    /// [`XdgDecoration::set_mode`].
+    fn request_mode(&mut self, surface: &WlSurface, mode: Option<Mode>);
+}
+
+pub struct XdgDecoration {}
+
+impl XdgDecoration {
+    pub fn new<State>(display: &mut DisplayHandle) -> Self
+    where
+        State: GlobalDispatch<ZxdgDecorationManagerV1, ()> + XdgDecorationHandler,
+    {
+        let _global = display.create_global::<State, ZxdgDecorationManagerV1, ()>(1, ());
+        Self {}
+    }
+
+    /// Sets the decoration mode of a toplevel and sends it to the client with a configure.
+    ///
+    /// The current [`ToplevelConfigure`](crate::xdg_shell::ToplevelConfigure) of the toplevel is sent along with
+    /// the mode. Returns the serial of the configure, or [`None`] if the toplevel has no decoration object.
+    pub fn set_mode(ecs: &mut Ecs, surface: &WlSurface, mode: Mode) -> Option<u32> {
+        let entity = surface.data::<EntityData>()?.0;
+        let decoration = ecs
+            .world
+            .query_one_mut::<&mut DecorationMode>(entity)
+            .ok()?;
+
+        decoration.object.configure(mode);
+        decoration.mode = Some(mode);
+        XdgShell::send_toplevel_configure(ecs, surface)
+    }
+}
+
+/// The decoration mode of a toplevel.
+#[derive(Debug)]
+pub struct DecorationMode {
+    pub(crate) object: ZxdgToplevelDecorationV1,
+    requested: Option<Mode>,
+    mode: Option<Mode>,
+}
+
+impl DecorationMode {
+    /// The mode the client prefers.
+    ///
+    /// Returns [`None`] if the client has no preference.
+    pub fn requested(&self) -> Option<Mode> {
+        self.requested
+    }
+
+    /// The mode most recently sent to the client.
+    ///
+    /// Returns [`None`] if the compositor has not sent a mode yet.
+    pub fn mode(&self) -> Option<Mode> {
+        self.mode
+    }
+}
@@ -0,0 +1,70 @@
+use wayland_protocols::xdg::decoration::zv1::server::zxdg_toplevel_decoration_v1;
+
+use crate::testing::{Arg, Fixture, TestToplevel};
+
+use super::{Mode, XdgDecoration};
+
+// zxdg_decoration_manager_v1 requests
+const GET_TOPLEVEL_DECORATION: u16 = 1;
+
+// xdg_toplevel requests
+const TOPLEVEL_DESTROY: u16 = 0;
+
+// zxdg_toplevel_decoration_v1, xdg_toplevel and xdg_surface events
+const CONFIGURE: u16 = 0;
+
+/// Creates a toplevel with a decoration object and returns it with the `zxdg_toplevel_decoration_v1`.
+fn create_decorated_toplevel(fixture: &mut Fixture) -> (TestToplevel, u32) {
+    let manager = fixture.client.bind("zxdg_decoration_manager_v1", 1);
+    let toplevel = fixture.create_toplevel();
+
+    let decoration = fixture.client.new_id();
+    fixture.send(
+        manager,
+        GET_TOPLEVEL_DECORATION,
+        &[Arg::NewId(decoration), Arg::Object(toplevel.toplevel)],
+    );
+    fixture.assert_no_error();
+
+    (toplevel, decoration)
+}
+
+#[test]
+fn set_mode_sends_full_configure() {
+    let mut fixture = Fixture::new();
+    let (toplevel, decoration) = create_decorated_toplevel(&mut fixture);
+
+    let serial = XdgDecoration::set_mode(
+        &mut fixture.server.state.ecs,
+        &toplevel.surface.surface,
+        Mode::ServerSide,
+    )
+    .unwrap();
+
+    let events = fixture.events(decoration);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].opcode, CONFIGURE);
+    assert_eq!(events[0].uint(0), Mode::ServerSide as u32);
+
+    // The mode only takes effect with a configure of the toplevel and of the xdg_surface.
+    let events = fixture.client.take_events(toplevel.toplevel);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].opcode, CONFIGURE);
+
+    let events = fixture.client.take_events(toplevel.xdg_surface);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].opcode, CONFIGURE);
+    assert_eq!(events[0].uint(0), serial);
+}
+
+#[test]
+fn destroying_toplevel_before_decoration_is_error() {
+    let mut fixture = Fixture::new();
+    let (toplevel, _) = create_decorated_toplevel(&mut fixture);
+
+    fixture.send(toplevel.toplevel, TOPLEVEL_DESTROY, &[]);
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(zxdg_toplevel_decoration_v1::Error::Orphaned as u32)
+    );
+}
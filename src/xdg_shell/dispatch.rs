@@ -1,16 +1,30 @@
-use wayland_protocols::xdg::shell::server::{
-    xdg_surface::{self, XdgSurface},
-    xdg_toplevel::{self, XdgToplevel},
-    xdg_wm_base::{self, XdgWmBase},
+use std::sync::atomic::Ordering;
+
+use smithay::utils::Rectangle;
+use wayland_backend::server::{ClientId, ObjectId};
+use wayland_protocols::xdg::{
+    decoration::zv1::server::zxdg_toplevel_decoration_v1,
+    shell::server::{
+        xdg_surface::{self, XdgSurface},
+        xdg_toplevel::{self, XdgToplevel},
+        xdg_wm_base::{self, XdgWmBase},
+    },
+};
+use wayland_server::{
+    protocol::wl_surface::WlSurface, Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch,
+    Resource,
 };
-use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, Resource};
 
 use crate::{
-    compositor::{AlreadyHasRole, Role},
+    compositor::{AlreadyHasRole, Compositor, CompositorEvent, CompositorHandler, Role},
+    xdg_decoration::DecorationMode,
     EntityData,
 };
 
-use super::{XdgShell, XdgShellHandler};
+use super::{
+    post_commit, pre_commit, AppId, Title, ToplevelConfigure, XdgShell, XdgShellHandler,
+    XdgSurfaceConfigure,
+};
 
 impl<State> GlobalDispatch<XdgWmBase, (), State> for XdgShell
 where
@@ -30,7 +44,7 @@ where
 
 impl<State> Dispatch<XdgWmBase, (), State> for XdgShell
 where
-    State: Dispatch<XdgSurface, EntityData> + XdgShellHandler,
+    State: Dispatch<XdgSurface, EntityData> + XdgShellHandler + CompositorHandler,
 {
     fn request(
         state: &mut State,
@@ -54,9 +68,27 @@ where
                 }
 
                 let entity = surface.data::<EntityData>().unwrap().0;
-                data_init.init(id, EntityData(entity));
-
-                // TODO: Add xdg_surface data to the entity
+                state.ecs().push_event(CompositorEvent::RoleAssigned {
+                    surface: surface.clone(),
+                    entity,
+                    role: XdgShell::SURFACE_ROLE,
+                });
+                let object = data_init.init(id, EntityData(entity));
+                state
+                    .ecs()
+                    .world()
+                    .insert_one(
+                        entity,
+                        XdgSurfaceConfigure {
+                            object,
+                            pending: Vec::new(),
+                            last_acked: None,
+                            committed: Default::default(),
+                        },
+                    )
+                    .expect("Surface must be a valid entity if dispatched");
+                Compositor::add_pre_commit::<State>(state.ecs(), &surface, pre_commit::<State>);
+                Compositor::add_post_commit::<State>(state.ecs(), &surface, post_commit::<State>);
             }
 
             xdg_wm_base::Request::Pong { serial } => todo!(),
@@ -68,7 +100,7 @@ where
 
 impl<State> Dispatch<XdgSurface, EntityData, State> for XdgShell
 where
-    State: Dispatch<XdgToplevel, EntityData> + XdgShellHandler,
+    State: Dispatch<XdgToplevel, EntityData> + XdgShellHandler + CompositorHandler,
 {
     fn request(
         state: &mut State,
@@ -80,19 +112,54 @@ where
         data_init: &mut DataInit<'_, State>,
     ) {
         match request {
-            xdg_surface::Request::Destroy => todo!(),
+            xdg_surface::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
 
             xdg_surface::Request::GetToplevel { id } => {
+                let has_toplevel = state
+                    .ecs()
+                    .world()
+                    .query_one_mut::<&XdgToplevel>(data.0)
+                    .is_ok();
                 let role = state.ecs().query_one_mut::<&mut Role, _>(resource).unwrap();
 
                 // xdg_surface's role is special as creating a toplevel actually replaces the role.
-                if role.role() != Some(XdgShell::SURFACE_ROLE) {
-                    panic!("TODO: There is supposed to be an error for this?")
+                match role.role() {
+                    Some(XdgShell::SURFACE_ROLE) => role.replace_role(XdgShell::TOPLEVEL_ROLE),
+
+                    // The surface keeps its role once the toplevel is destroyed, so it may become a toplevel again.
+                    Some(XdgShell::TOPLEVEL_ROLE) if !has_toplevel => (),
+
+                    Some(XdgShell::TOPLEVEL_ROLE) => {
+                        resource.post_error(
+                            xdg_surface::Error::AlreadyConstructed,
+                            "Surface already has a toplevel",
+                        );
+                        return;
+                    }
+
+                    _ => panic!("TODO: There is supposed to be an error for this?"),
                 }
 
-                role.replace_role(XdgShell::TOPLEVEL_ROLE);
+                let surface = state
+                    .ecs()
+                    .world()
+                    .query_one_mut::<&WlSurface>(data.0)
+                    .unwrap()
+                    .clone();
+                state.ecs().push_event(CompositorEvent::RoleAssigned {
+                    surface,
+                    entity: data.0,
+                    role: XdgShell::TOPLEVEL_ROLE,
+                });
 
                 let toplevel = data_init.init(id, EntityData(data.0));
+                state
+                    .ecs()
+                    .world()
+                    .insert(data.0, (toplevel.clone(), ToplevelConfigure::default()))
+                    .expect("Surface must be a valid entity if dispatched");
 
                 // TODO: Add xdg_surface data to the entity
                 state.new_toplevel(toplevel);
@@ -109,15 +176,70 @@ where
                 y,
                 width,
                 height,
-            } => todo!(),
+            } => {
+                // Quoting xdg_surface::set_window_geometry:
+                // > The width and height must be greater than zero.
+                if width <= 0 || height <= 0 {
+                    resource.post_error(
+                        xdg_surface::Error::InvalidSize,
+                        format!("Invalid window geometry size {width}x{height}"),
+                    );
+                    return;
+                }
+
+                let surface = state
+                    .ecs()
+                    .world()
+                    .query_one_mut::<&WlSurface>(data.0)
+                    .unwrap()
+                    .clone();
+                *Compositor::pending_window_geometry::<State>(state.ecs(), &surface) =
+                    Some(Rectangle::from_loc_and_size((x, y), (width, height)));
+            }
 
-            xdg_surface::Request::AckConfigure { serial } => todo!(),
+            xdg_surface::Request::AckConfigure { serial } => {
+                let configure = state
+                    .ecs()
+                    .world()
+                    .query_one_mut::<&mut XdgSurfaceConfigure>(data.0)
+                    .expect("Surface must be a valid entity if dispatched");
+
+                // Acknowledging a configure also acknowledges every configure sent before it.
+                let index = match configure.pending.iter().position(|&s| s == serial) {
+                    Some(index) => index,
+                    None => {
+                        resource.post_error(
+                            xdg_surface::Error::InvalidSerial,
+                            format!("Serial {serial} does not belong to a pending configure"),
+                        );
+                        return;
+                    }
+                };
+
+                configure.pending.drain(..=index);
+                configure.last_acked = Some(serial);
+            }
             _ => todo!(),
         }
     }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        // A destroyed xdg_surface can never acknowledge a configure, so nothing should wait for it.
+        if let Ok(configure) = state
+            .ecs()
+            .world()
+            .remove_one::<XdgSurfaceConfigure>(data.0)
+        {
+            configure.committed.destroyed.store(true, Ordering::Release);
+            Compositor::apply_transactions(state);
+        }
+    }
 }
 
-impl<State> Dispatch<XdgToplevel, EntityData, State> for XdgShell {
+impl<State> Dispatch<XdgToplevel, EntityData, State> for XdgShell
+where
+    State: Dispatch<XdgToplevel, EntityData> + XdgShellHandler,
+{
     fn request(
         state: &mut State,
         client: &Client,
@@ -127,6 +249,49 @@ impl<State> Dispatch<XdgToplevel, EntityData, State> for XdgShell {
         dhandle: &DisplayHandle,
         data_init: &mut DataInit<'_, State>,
     ) {
-        todo!()
+        match request {
+            xdg_toplevel::Request::Destroy => {
+                // handled by Dispatch::destroyed
+            }
+
+            xdg_toplevel::Request::SetTitle { title } => {
+                state
+                    .ecs()
+                    .world()
+                    .insert_one(data.0, Title(title))
+                    .expect("Surface must be a valid entity if dispatched");
+            }
+
+            xdg_toplevel::Request::SetAppId { app_id } => {
+                state
+                    .ecs()
+                    .world()
+                    .insert_one(data.0, AppId(app_id))
+                    .expect("Surface must be a valid entity if dispatched");
+            }
+
+            // Window management requests such as moving, resizing or maximizing are not implemented yet. They
+            // are only hints, so ignoring them leaves the toplevel in a valid state.
+            _ => (),
+        }
+    }
+
+    fn destroyed(state: &mut State, _client: ClientId, _resource: ObjectId, data: &EntityData) {
+        // The surface keeps its role, but is no longer a toplevel. The title and app id are removed separately,
+        // as the client may not have set them.
+        let world = state.ecs().world();
+
+        // Quoting zxdg_toplevel_decoration_v1:
+        // > The xdg_toplevel_decoration object must be destroyed before its xdg_toplevel.
+        if let Ok(decoration) = world.query_one_mut::<&DecorationMode>(data.0) {
+            decoration.object.post_error(
+                zxdg_toplevel_decoration_v1::Error::Orphaned,
+                "Toplevel was destroyed before its decoration object",
+            );
+        }
+
+        let _ = world.remove::<(XdgToplevel, ToplevelConfigure)>(data.0);
+        let _ = world.remove_one::<Title>(data.0);
+        let _ = world.remove_one::<AppId>(data.0);
     }
 }
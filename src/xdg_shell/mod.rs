@@ -1,9 +1,24 @@
-use wayland_protocols::xdg::shell::server::{xdg_toplevel::XdgToplevel, xdg_wm_base::XdgWmBase};
-use wayland_server::{DisplayHandle, GlobalDispatch};
+use smithay::utils::{Logical, Size};
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc,
+};
+use wayland_protocols::xdg::shell::server::{
+    xdg_surface::XdgSurface,
+    xdg_toplevel::{self, XdgToplevel},
+    xdg_wm_base::XdgWmBase,
+};
 
-use crate::EcsAccess;
+use wayland_server::{protocol::wl_surface::WlSurface, DisplayHandle, GlobalDispatch, Resource};
+
+use crate::{
+    compositor::{Blocker, Compositor, CompositorHandler},
+    next_serial, Ecs, EcsAccess, EntityData,
+};
 
 mod dispatch;
+#[cfg(test)]
+mod tests;
 
 pub trait XdgShellHandler: EcsAccess {
     fn new_toplevel(&mut self, toplevel: XdgToplevel);
@@ -22,4 +37,193 @@ impl XdgShell {
 
     pub const SURFACE_ROLE: &str = "xdg_surface";
     pub const TOPLEVEL_ROLE: &str = "xdg_toplevel";
+
+    /// Sends a configure event to the xdg_surface of a surface.
+    ///
+    /// Role specific configure events, such as the decoration mode, must be sent before this. Returns the serial
+    /// of the configure, or [`None`] if the surface is not an xdg_surface.
+    pub fn send_configure(ecs: &mut Ecs, surface: &WlSurface) -> Option<u32> {
+        let entity = surface.data::<EntityData>()?.0;
+        let configure = ecs
+            .world
+            .query_one_mut::<&mut XdgSurfaceConfigure>(entity)
+            .ok()?;
+
+        let serial = next_serial();
+        configure.pending.push(serial);
+        configure.object.configure(serial);
+        Some(serial)
+    }
+
+    /// Sends the [`ToplevelConfigure`] of a toplevel to the client, followed by a configure of its xdg_surface.
+    ///
+    /// Role specific configure events of other protocols, such as the decoration mode, must be sent before this.
+    /// Returns the serial of the configure, or [`None`] if the surface is not a toplevel.
+    pub fn send_toplevel_configure(ecs: &mut Ecs, surface: &WlSurface) -> Option<u32> {
+        let entity = surface.data::<EntityData>()?.0;
+        let (toplevel, configure) = ecs
+            .world
+            .query_one_mut::<(&XdgToplevel, &ToplevelConfigure)>(entity)
+            .ok()?;
+
+        let states = configure
+            .states
+            .iter()
+            .flat_map(|&state| (state as u32).to_ne_bytes())
+            .collect();
+        toplevel.configure(configure.size.w, configure.size.h, states);
+        Self::send_configure(ecs, surface)
+    }
+
+    /// Creates a [`Blocker`] which is released once the client commits a state acknowledging a configure.
+    ///
+    /// Adding the blocker to the next commit of other surfaces makes their state appear together with the state
+    /// the client drew in response to the configure. The blocker is also released if the xdg_surface is
+    /// destroyed. Returns [`None`] if the surface is not an xdg_surface.
+    pub fn configure_blocker(
+        ecs: &mut Ecs,
+        surface: &WlSurface,
+        serial: u32,
+    ) -> Option<ConfigureBlocker> {
+        let entity = surface.data::<EntityData>()?.0;
+        let configure = ecs
+            .world
+            .query_one_mut::<&XdgSurfaceConfigure>(entity)
+            .ok()?;
+
+        Some(ConfigureBlocker {
+            committed: configure.committed.clone(),
+            serial,
+        })
+    }
+}
+
+/// The configure state of an xdg_surface.
+///
+/// This can be queried from a [`WlSurface`] once the surface has become an xdg_surface.
+#[derive(Debug)]
+pub struct XdgSurfaceConfigure {
+    object: XdgSurface,
+    /// Serials of configure events which were not acknowledged yet, oldest first.
+    pending: Vec<u32>,
+    last_acked: Option<u32>,
+    committed: Arc<CommittedConfigure>,
+}
+
+impl XdgSurfaceConfigure {
+    /// The serial of the most recently acknowledged configure.
+    pub fn last_acked(&self) -> Option<u32> {
+        self.last_acked
+    }
+
+    /// Returns whether the client acknowledged every configure event that was sent.
+    pub fn is_acked(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// The state of a toplevel which is sent with each configure.
+///
+/// This is inserted on the [`WlSurface`] of a toplevel when it is created. The compositor changes it and then
+/// calls [`XdgShell::send_toplevel_configure`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ToplevelConfigure {
+    /// The size the toplevel should have. A width or height of zero lets the client decide.
+    pub size: Size<i32, Logical>,
+    pub states: Vec<xdg_toplevel::State>,
+}
+
+/// The configure which was acknowledged by the latest commit of an xdg_surface.
+#[derive(Debug, Default)]
+struct CommittedConfigure {
+    /// The serial of the last acknowledged configure, or 0 before the first one.
+    serial: AtomicU32,
+    destroyed: AtomicBool,
+}
+
+/// A [`Blocker`] waiting for a commit which acknowledges a configure.
+///
+/// This is created by [`XdgShell::configure_blocker`].
+#[derive(Debug, Clone)]
+pub struct ConfigureBlocker {
+    committed: Arc<CommittedConfigure>,
+    serial: u32,
+}
+
+impl Blocker for ConfigureBlocker {
+    fn is_released(&self) -> bool {
+        if self.committed.destroyed.load(Ordering::Acquire) {
+            return true;
+        }
+
+        // Serials wrap around, so the difference is compared instead of the serials.
+        let committed = self.committed.serial.load(Ordering::Acquire);
+        committed != 0 && committed.wrapping_sub(self.serial) as i32 >= 0
+    }
+}
+
+/// The serial of the configure which a commit acknowledged.
+struct AckedConfigure(u32);
+
+/// Records the configure acknowledged by a commit in the commit state.
+fn pre_commit<State: EcsAccess>(state: &mut State, surface: &WlSurface) {
+    let entity = surface.data::<EntityData>().unwrap().0;
+
+    let serial = match state
+        .ecs()
+        .world()
+        .query_one_mut::<&XdgSurfaceConfigure>(entity)
+    {
+        Ok(configure) => configure.last_acked,
+        Err(_) => None,
+    };
+
+    if let Some(serial) = serial {
+        Compositor::insert_commit_state::<State, _>(state.ecs(), surface, AckedConfigure(serial));
+    }
+}
+
+/// Releases the [`ConfigureBlocker`]s of the acknowledged configure once the commit is applied.
+fn post_commit<State: CompositorHandler>(state: &mut State, surface: &WlSurface) {
+    let serial = match Compositor::take_commit_state::<State, AckedConfigure>(state.ecs(), surface)
+    {
+        Some(AckedConfigure(serial)) => serial,
+        None => return,
+    };
+    let entity = surface.data::<EntityData>().unwrap().0;
+
+    if let Ok(configure) = state
+        .ecs()
+        .world()
+        .query_one_mut::<&XdgSurfaceConfigure>(entity)
+    {
+        configure.committed.serial.store(serial, Ordering::Release);
+        // Transactions waiting for this configure may be queued before the one being applied.
+        Compositor::apply_transactions(state);
+    }
+}
+
+/// The title of a toplevel, as set by the client.
+///
+/// This is inserted on the toplevel's [`WlSurface`] the first time the client sets a title.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Title(pub(crate) String);
+
+impl Title {
+    pub fn get(&self) -> &str {
+        &self.0
+    }
+}
+
+/// The application id of a toplevel, as set by the client.
+///
+/// This is usually the name of the desktop file of the application. Like [`Title`], this is inserted the first
+/// time the client sets it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppId(pub(crate) String);
+
+impl AppId {
+    pub fn get(&self) -> &str {
+        &self.0
+    }
 }
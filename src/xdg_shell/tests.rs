@@ -0,0 +1,199 @@
+use smithay::utils::{Logical, Rectangle};
+use wayland_protocols::xdg::shell::server::{xdg_surface, xdg_toplevel};
+
+use crate::{
+    compositor::{Blocker, SurfaceGeometry},
+    testing::{Arg, Fixture, TestToplevel},
+};
+
+use super::{ToplevelConfigure, XdgShell, XdgSurfaceConfigure};
+
+// xdg_surface requests
+const SET_WINDOW_GEOMETRY: u16 = 3;
+const ACK_CONFIGURE: u16 = 4;
+
+// xdg_toplevel requests
+const SET_MAX_SIZE: u16 = 7;
+const SET_MAXIMIZED: u16 = 9;
+const SET_MINIMIZED: u16 = 13;
+
+// xdg_surface and xdg_toplevel events
+const CONFIGURE: u16 = 0;
+
+fn configure(fixture: &mut Fixture, toplevel: &TestToplevel) -> u32 {
+    let serial =
+        XdgShell::send_toplevel_configure(&mut fixture.server.state.ecs, &toplevel.surface.surface)
+            .unwrap();
+    fixture.dispatch();
+    serial
+}
+
+fn is_acked(fixture: &mut Fixture, toplevel: &TestToplevel) -> bool {
+    fixture
+        .world()
+        .query_one_mut::<&XdgSurfaceConfigure>(toplevel.surface.entity)
+        .unwrap()
+        .is_acked()
+}
+
+#[test]
+fn toplevel_configure_sends_size_and_states() {
+    let mut fixture = Fixture::new();
+    let toplevel = fixture.create_toplevel();
+    *fixture
+        .world()
+        .query_one_mut::<&mut ToplevelConfigure>(toplevel.surface.entity)
+        .unwrap() = ToplevelConfigure {
+        size: (640, 480).into(),
+        states: vec![xdg_toplevel::State::Activated],
+    };
+
+    let serial = configure(&mut fixture, &toplevel);
+
+    let events = fixture.client.take_events(toplevel.toplevel);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].opcode, CONFIGURE);
+    assert_eq!(events[0].uint(0), 640);
+    assert_eq!(events[0].uint(1), 480);
+    // The states array is its length in bytes followed by the states.
+    assert_eq!(events[0].uint(2), 4);
+    assert_eq!(events[0].uint(3), xdg_toplevel::State::Activated as u32);
+
+    let events = fixture.client.take_events(toplevel.xdg_surface);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].opcode, CONFIGURE);
+    assert_eq!(events[0].uint(0), serial);
+}
+
+#[test]
+fn ack_configure_acknowledges_earlier_configures() {
+    let mut fixture = Fixture::new();
+    let toplevel = fixture.create_toplevel();
+    configure(&mut fixture, &toplevel);
+    let serial = configure(&mut fixture, &toplevel);
+    assert!(!is_acked(&mut fixture, &toplevel));
+
+    fixture.send(toplevel.xdg_surface, ACK_CONFIGURE, &[Arg::Uint(serial)]);
+    fixture.assert_no_error();
+    assert!(is_acked(&mut fixture, &toplevel));
+}
+
+#[test]
+fn configure_blocker_is_released_when_acking_commit_is_applied() {
+    let mut fixture = Fixture::new();
+    let toplevel = fixture.create_toplevel();
+    let serial = configure(&mut fixture, &toplevel);
+    let configure_blocker = XdgShell::configure_blocker(
+        &mut fixture.server.state.ecs,
+        &toplevel.surface.surface,
+        serial,
+    )
+    .unwrap();
+
+    fixture.send(toplevel.xdg_surface, ACK_CONFIGURE, &[Arg::Uint(serial)]);
+    let blocker = fixture.add_blocker(&toplevel.surface);
+    fixture.commit(toplevel.surface.id);
+    assert!(!configure_blocker.is_released());
+
+    fixture.release(&blocker);
+    assert!(configure_blocker.is_released());
+}
+
+#[test]
+fn ack_configure_with_unknown_serial_is_error() {
+    let mut fixture = Fixture::new();
+    let toplevel = fixture.create_toplevel();
+    let serial = configure(&mut fixture, &toplevel);
+
+    fixture.send(
+        toplevel.xdg_surface,
+        ACK_CONFIGURE,
+        &[Arg::Uint(serial.wrapping_add(1))],
+    );
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(xdg_surface::Error::InvalidSerial as u32)
+    );
+}
+
+fn set_window_geometry(
+    fixture: &mut Fixture,
+    toplevel: &TestToplevel,
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+) {
+    fixture.send(
+        toplevel.xdg_surface,
+        SET_WINDOW_GEOMETRY,
+        &[Arg::Int(x), Arg::Int(y), Arg::Int(w), Arg::Int(h)],
+    );
+}
+
+fn window_geometry(fixture: &mut Fixture, toplevel: &TestToplevel) -> Rectangle<i32, Logical> {
+    fixture
+        .world()
+        .query_one_mut::<&SurfaceGeometry>(toplevel.surface.entity)
+        .unwrap()
+        .window_geometry()
+}
+
+#[test]
+fn window_geometry_is_clamped_to_bounding_box() {
+    let mut fixture = Fixture::new();
+    let toplevel = fixture.create_toplevel();
+    let serial = configure(&mut fixture, &toplevel);
+    fixture.send(toplevel.xdg_surface, ACK_CONFIGURE, &[Arg::Uint(serial)]);
+    let mut pool = fixture.create_pool(10 * 8 * 4);
+    let buffer = fixture.create_buffer(&mut pool, 10, 8);
+    fixture.attach(toplevel.surface.id, buffer);
+    fixture.commit(toplevel.surface.id);
+
+    // Without a window geometry the whole surface is the window.
+    assert_eq!(
+        window_geometry(&mut fixture, &toplevel),
+        Rectangle::from_loc_and_size((0, 0), (10, 8))
+    );
+
+    set_window_geometry(&mut fixture, &toplevel, 1, 2, 4, 4);
+    fixture.commit(toplevel.surface.id);
+    assert_eq!(
+        window_geometry(&mut fixture, &toplevel),
+        Rectangle::from_loc_and_size((1, 2), (4, 4))
+    );
+
+    set_window_geometry(&mut fixture, &toplevel, -5, 2, 100, 100);
+    fixture.commit(toplevel.surface.id);
+    assert_eq!(
+        window_geometry(&mut fixture, &toplevel),
+        Rectangle::from_loc_and_size((0, 2), (10, 6))
+    );
+}
+
+#[test]
+fn non_positive_window_geometry_size_is_error() {
+    let mut fixture = Fixture::new();
+    let toplevel = fixture.create_toplevel();
+
+    set_window_geometry(&mut fixture, &toplevel, 0, 0, 0, 10);
+    assert_eq!(
+        fixture.protocol_error(),
+        Some(xdg_surface::Error::InvalidSize as u32)
+    );
+}
+
+#[test]
+fn unhandled_toplevel_requests_are_ignored() {
+    let mut fixture = Fixture::new();
+    let toplevel = fixture.create_toplevel();
+
+    fixture.send(
+        toplevel.toplevel,
+        SET_MAX_SIZE,
+        &[Arg::Int(800), Arg::Int(600)],
+    );
+    fixture.send(toplevel.toplevel, SET_MAXIMIZED, &[]);
+    fixture.send(toplevel.toplevel, SET_MINIMIZED, &[]);
+    fixture.assert_no_error();
+}